@@ -0,0 +1,60 @@
+// Parser throughput on a stream of packets of every length, a byte at a
+// time and in bulk. Run with `cargo bench --bench parser`.
+
+extern crate robo;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use robo::l0::comm::*;
+
+const STREAM_LEN: usize = 4 << 20;
+
+fn stream() -> Vec<u8> {
+    let mut bytes = vec![SYNC_ACK, 1];
+    let mut seq: PacketSeq = 1;
+    let mut len = 0;
+    while bytes.len() < STREAM_LEN {
+        let pkt = Packet { seq, code: 0x81, data: (0..len as u8).collect() };
+        pkt.encode(&mut bytes).unwrap();
+        seq = seq.next();
+        len = (len + 1) % (PACKET_MAX_DATA_LEN + 1);
+    }
+    bytes
+}
+
+// The best of a few runs of f, which returns the packets it parsed.
+fn bench<F: FnMut(&[u8]) -> usize>(name: &str, bytes: &[u8], mut f: F) {
+    let mut best = Duration::MAX;
+    let mut packets = 0;
+    for _ in 0..5 {
+        let start = Instant::now();
+        packets = f(black_box(bytes));
+        best = best.min(start.elapsed());
+    }
+    let mbps = bytes.len() as f64 / best.as_secs_f64() / 1e6;
+    println!("{:12} {:8.1} MB/s  {} packets in {:?}", name, mbps, packets, best);
+}
+
+fn main() {
+    let bytes = stream();
+    bench("parse_frame", &bytes, |bytes| {
+        let mut p = Parser::new();
+        bytes.iter().filter(|b| p.parse_frame(**b).packet).count()
+    });
+    bench("parse_bytes", &bytes, |mut bytes| {
+        let mut p = Parser::new();
+        let mut packets = 0;
+        while !bytes.is_empty() {
+            let (n, s) = p.parse_bytes(bytes);
+            if s.packet {
+                packets += 1;
+            }
+            bytes = &bytes[n..];
+        }
+        packets
+    });
+    bench("parse", &bytes, |bytes| {
+        let mut p = Parser::new();
+        bytes.iter().filter(|b| p.parse(**b).packet.is_some()).count()
+    });
+}
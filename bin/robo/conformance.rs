@@ -0,0 +1,24 @@
+use std::io;
+use std::time::Duration;
+use clap::{Arg, ArgMatches, Command};
+use robo::conformance::Harness;
+use port;
+
+pub fn command() -> Command {
+    Command::new("conformance")
+        .about("Check a device's l0 protocol implementation")
+        .args(port::args())
+        .arg(Arg::new("timeout").long("timeout").value_name("MS").default_value("500")
+            .value_parser(clap::value_parser!(u64))
+            .help("Response timeout, must exceed the device's receive timeout"))
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let timeout = Duration::from_millis(*m.get_one::<u64>("timeout").unwrap());
+    let report = Harness::new(port::open_serial(m)?).with_timeout(timeout).run_all()?;
+    print!("{}", report);
+    if !report.passed() {
+        return Err(io::Error::other("device failed conformance"));
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::time::{Duration, Instant};
+use clap::{ArgMatches, Command};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+use robo::fmt::display::Summary;
+use robo::l0::comm::*;
+use robo::l2::schema::Schema;
+use robo::mission::Step;
+use port;
+use repl;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+const FRAME_PERIOD: Duration = Duration::from_millis(100);
+
+pub fn command() -> Command {
+    Command::new("dash")
+        .about("Terminal dashboard of a link")
+        .args(port::args())
+}
+
+#[derive(Default)]
+struct CodeStats {
+    arrivals: VecDeque<Instant>, // within the rate window.
+    total: u64,
+    latest: String,
+}
+
+struct Dashboard {
+    schema: Schema,
+    codes: BTreeMap<u8, CodeStats>,
+    input: String,
+    status: String,
+}
+
+impl Dashboard {
+    fn record(&mut self, pkt: &Packet, now: Instant) {
+        let c = self.codes.entry(pkt.code).or_default();
+        c.arrivals.push_back(now);
+        c.total += 1;
+        c.latest = Summary::packet(pkt).with_schema(&self.schema).to_string();
+    }
+
+    fn expire(&mut self, now: Instant) {
+        for c in self.codes.values_mut() {
+            while c.arrivals.front().is_some_and(|t| now.duration_since(*t) > RATE_WINDOW) {
+                c.arrivals.pop_front();
+            }
+        }
+    }
+
+    // Sends what the input box holds: mission send steps, one or more.
+    fn submit<T: io::Read + io::Write>(&mut self, ep: &mut Endpoint<T>) {
+        let line = self.input.trim().to_string();
+        self.input.clear();
+        if line.is_empty() {
+            return;
+        }
+        let result = repl::parse(&self.schema, &line).and_then(|mission| {
+            let mut sent = 0;
+            for step in mission.steps {
+                match step {
+                    Step::Send(pkt) => {
+                        self.schema.validate(&pkt)?;
+                        ep.send(pkt)?;
+                        sent += 1;
+                    },
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "only send works here")),
+                }
+            }
+            Ok(sent)
+        });
+        self.status = match result {
+            Ok(_) => format!("sent: {}", line),
+            Err(e) => format!("error: {}", e),
+        };
+    }
+
+    fn draw<T: io::Read + io::Write>(&self, f: &mut Frame, ep: &Endpoint<T>) {
+        let [link, table, input, status] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ]).areas(f.area());
+
+        let s = ep.stats();
+        let state = match ep.expected_seq() {
+            Some(seq) => format!("{} (seq 0x{:02x})", ep.phase(), seq),
+            None => ep.phase().to_string(),
+        };
+        f.render_widget(Paragraph::new(format!(
+            "{} {}  tx {} pkts / {} B  rx {} pkts / {} B  resyncs {}  timeout {} ms",
+            ep.device(), state, s.packets_sent, s.bytes_sent, s.packets_received, s.bytes_received, s.resyncs,
+            ep.timeout().as_millis(),
+        )).block(Block::bordered().title("link")), link);
+
+        let rows = self.codes.iter().map(|(code, c)| Row::new(vec![
+            format!("0x{:02x}", code),
+            self.schema.get(*code).map(|set| set.name()).unwrap_or("").to_string(),
+            format!("{}/s", c.arrivals.len()),
+            c.total.to_string(),
+            c.latest.clone(),
+        ]));
+        let widths = [
+            Constraint::Length(6),
+            Constraint::Length(12),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Fill(1),
+        ];
+        f.render_widget(Table::new(rows, widths)
+            .header(Row::new(vec!["code", "set", "rate", "total", "latest"]))
+            .block(Block::bordered().title("packets")), table);
+
+        f.render_widget(Paragraph::new(format!("> {}", self.input))
+            .block(Block::bordered().title("command (send CODE HEX.., Esc quits)")), input);
+        f.render_widget(Paragraph::new(self.status.as_str()), status);
+    }
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let schema = port::schema(m)?;
+    let mut ep = Endpoint::named(port::open_stream(m)?, m.get_one::<String>("port").unwrap())?;
+    let mut dash = Dashboard { schema, codes: BTreeMap::new(), input: String::new(), status: String::new() };
+    let mut terminal = ratatui::init();
+    let result = (|| -> io::Result<()> {
+        let mut next_frame = Instant::now();
+        loop {
+            let now = Instant::now();
+            for pkt in ep.poll()? {
+                dash.record(&pkt, now);
+            }
+            ep.check_timeout()?;
+            if now >= next_frame {
+                dash.expire(now);
+                terminal.draw(|f| dash.draw(f, &ep))?;
+                next_frame = now + FRAME_PERIOD;
+            }
+            if !event::poll(Duration::from_millis(5))? {
+                continue;
+            }
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Enter => dash.submit(&mut ep),
+                KeyCode::Backspace => {
+                    dash.input.pop();
+                },
+                KeyCode::Char(c) => dash.input.push(c),
+                _ => continue,
+            }
+            next_frame = Instant::now();
+        }
+    })();
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,25 @@
+use std::fs;
+use std::io;
+use clap::{Arg, ArgMatches, Command};
+use robo::fmt::cheader::CHeader;
+use port;
+
+pub fn command() -> Command {
+    Command::new("header")
+        .about("Generate a C header for firmware from the schema")
+        .arg(port::schema_arg().required(true))
+        .arg(Arg::new("prefix").long("prefix").default_value("robo"))
+        .arg(Arg::new("output").short('o').long("output").help("Write to a file instead of stdout"))
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let schema = port::schema(m)?;
+    let header = CHeader::new(&schema).with_prefix(m.get_one::<String>("prefix").unwrap()).to_string();
+    match m.get_one::<String>("output") {
+        Some(path) => fs::write(path, header),
+        None => {
+            print!("{}", header);
+            Ok(())
+        },
+    }
+}
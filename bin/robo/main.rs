@@ -0,0 +1,56 @@
+extern crate clap;
+#[cfg(feature = "tui")]
+extern crate ratatui;
+extern crate robo;
+extern crate rustyline;
+extern crate serde_json;
+extern crate serialport;
+
+mod conformance;
+#[cfg(feature = "tui")]
+mod dash;
+mod header;
+mod ping;
+mod port;
+mod repl;
+mod send;
+mod sniff;
+#[cfg(feature = "web")]
+mod web;
+
+use std::process;
+use clap::Command;
+
+fn main() {
+    let cli = Command::new("robo")
+        .about("Tools for talking to robo.rs devices")
+        .subcommand_required(true)
+        .subcommand(conformance::command())
+        .subcommand(header::command())
+        .subcommand(ping::command())
+        .subcommand(repl::command())
+        .subcommand(send::command())
+        .subcommand(sniff::command());
+    #[cfg(feature = "tui")]
+    let cli = cli.subcommand(dash::command());
+    #[cfg(feature = "web")]
+    let cli = cli.subcommand(web::command());
+    let matches = cli.get_matches();
+    let result = match matches.subcommand() {
+        Some(("conformance", m)) => conformance::run(m),
+        #[cfg(feature = "tui")]
+        Some(("dash", m)) => dash::run(m),
+        Some(("header", m)) => header::run(m),
+        Some(("ping", m)) => ping::run(m),
+        Some(("repl", m)) => repl::run(m),
+        Some(("send", m)) => send::run(m),
+        Some(("sniff", m)) => sniff::run(m),
+        #[cfg(feature = "web")]
+        Some(("web", m)) => web::run(m),
+        _ => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("robo: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,37 @@
+use std::io;
+use std::time::Duration;
+use clap::{Arg, ArgMatches, Command};
+use port;
+use send;
+
+pub fn command() -> Command {
+    Command::new("ping")
+        .about("Measure round trip time and throughput of a link")
+        .args(port::args())
+        .arg(Arg::new("count").long("count").short('c').default_value("10")
+            .value_parser(clap::value_parser!(u32)))
+        .arg(Arg::new("timeout").long("timeout").value_name("MS").default_value("1000")
+            .value_parser(clap::value_parser!(u64)))
+        .arg(Arg::new("throughput").long("throughput").value_name("SECS")
+            .value_parser(clap::value_parser!(u64))
+            .help("Also saturate the link, needs a device echoing --echo-code"))
+        .arg(Arg::new("echo-code").long("echo-code").default_value("0x0e").value_parser(port::parse_code))
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let timeout = Duration::from_millis(*m.get_one::<u64>("timeout").unwrap());
+    let mut ep = send::connect(m, timeout)?;
+    let stats = ep.ping(*m.get_one::<u32>("count").unwrap(), timeout)?;
+    println!("{} sent, {} received, {:.1}% loss", stats.sent, stats.received, stats.loss() * 100.0);
+    if stats.received > 0 {
+        println!("rtt min/avg/max = {:.3}/{:.3}/{:.3} ms", stats.min.as_secs_f64() * 1e3,
+            stats.avg.as_secs_f64() * 1e3, stats.max.as_secs_f64() * 1e3);
+    }
+    if let Some(secs) = m.get_one::<u64>("throughput") {
+        let code = *m.get_one::<u8>("echo-code").unwrap();
+        let t = ep.throughput_test(Duration::from_secs(*secs), code, timeout)?;
+        println!("{} packets, {} echoed, {:.1}% loss, goodput {:.0} B/s", t.sent, t.echoed,
+            t.loss() * 100.0, t.goodput());
+    }
+    Ok(())
+}
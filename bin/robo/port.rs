@@ -0,0 +1,73 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+use clap::{Arg, ArgMatches};
+use robo::l2::schema::Schema;
+use serialport;
+
+pub const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+pub type Serial = Box<dyn serialport::SerialPort>;
+
+pub trait Stream: io::Read + io::Write + Send {}
+
+impl<T: io::Read + io::Write + Send> Stream for T {}
+
+pub type Transport = Box<dyn Stream>;
+
+pub fn args() -> Vec<Arg> {
+    vec![
+        Arg::new("port").required(true).help("Serial port or tcp://HOST:PORT, sniff also reads capture files"),
+        Arg::new("baud").long("baud").default_value("115200").value_parser(clap::value_parser!(u32)),
+        schema_arg(),
+    ]
+}
+
+pub fn schema_arg() -> Arg {
+    Arg::new("schema").long("schema").value_name("CODE=SET,...")
+        .help("Message set of each code, e.g. 0x01=motor,0x81=encoder, or a file listing them")
+}
+
+pub fn schema(m: &ArgMatches) -> io::Result<Schema> {
+    match m.get_one::<String>("schema") {
+        Some(s) if Path::new(s).is_file() => fs::read_to_string(s)?.parse(),
+        Some(s) => s.parse(),
+        None => Ok(Schema::new()),
+    }
+}
+
+pub fn open_serial(m: &ArgMatches) -> io::Result<Serial> {
+    let path = m.get_one::<String>("port").unwrap();
+    let baud = *m.get_one::<u32>("baud").unwrap();
+    Ok(serialport::new(path.as_str(), baud).timeout(READ_TIMEOUT).open()?)
+}
+
+// A regular file is a capture, anything else a serial port.
+pub fn open_input(m: &ArgMatches) -> io::Result<Box<dyn io::Read>> {
+    let path = m.get_one::<String>("port").unwrap();
+    if Path::new(path).is_file() {
+        return Ok(Box::new(File::open(path)?));
+    }
+    Ok(Box::new(open_serial(m)?))
+}
+
+// A serial port, or a TCP connection when the port is tcp://HOST:PORT.
+pub fn open_stream(m: &ArgMatches) -> io::Result<Transport> {
+    let path = m.get_one::<String>("port").unwrap();
+    if let Some(addr) = path.strip_prefix("tcp://") {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        return Ok(Box::new(stream));
+    }
+    Ok(Box::new(open_serial(m)?))
+}
+
+pub fn parse_code(s: &str) -> Result<u8, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse(),
+    }.map_err(|e| e.to_string())
+}
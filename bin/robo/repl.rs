@@ -0,0 +1,224 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use clap::{Arg, ArgMatches, Command};
+use robo::fmt::display::Summary;
+use robo::l0::comm::{Endpoint, Packet};
+use robo::l2::Sender;
+use robo::l2::schema::Schema;
+use robo::mission::{Mission, Sequencer, SequencerEvent, SequencerState, Step};
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, ExternalPrinter, Helper};
+use port;
+use send;
+
+const COMMANDS: &[&str] = &["send", "wait", "expect", "goto", "run", "codes", "help", "quit"];
+
+const HELP: &str = "\
+send CODE [HEX..]                    send a packet, CODE may name a message set
+expect CODE [HEX..] timeout MS       wait for a packet starting with HEX
+wait MS
+run FILE                             run a mission script
+codes                                list the schema
+quit";
+
+pub fn command() -> Command {
+    Command::new("repl")
+        .about("Interactive session with a device")
+        .args(port::args())
+        .arg(Arg::new("script").long("script").value_name("FILE")
+            .help("Run a mission script before prompting"))
+}
+
+type Link = Arc<Mutex<Endpoint<port::Transport>>>;
+
+// Lets a sequencer send through the link the poller thread also reads.
+struct Shared(Link);
+
+impl Sender for Shared {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.0.lock().unwrap().send(pkt)
+    }
+}
+
+struct ReplHelper {
+    schema: Schema,
+    files: FilenameCompleter,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let head = &line[..pos];
+        let start = head.rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let word = &head[start..];
+        let toks: Vec<&str> = head[..start].split_whitespace().collect();
+        let candidates: Vec<String> = match toks.as_slice() {
+            [] => COMMANDS.iter().map(|c| c.to_string()).collect(),
+            ["send"] | ["expect"] => self.schema.codes()
+                .flat_map(|(code, set)| vec![set.name().to_string(), format!("0x{:02x}", code)])
+                .collect(),
+            ["run"] => return self.files.complete(line, pos, ctx),
+            _ => Vec::new(),
+        };
+        let pairs = candidates.into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair { display: c.clone(), replacement: c })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+// Parses mission text in which send and expect may name a message set of
+// the schema instead of giving its code.
+pub fn parse(schema: &Schema, text: &str) -> io::Result<Mission> {
+    let lines: Vec<String> = text.lines().map(|l| resolve(schema, l)).collect::<io::Result<_>>()?;
+    lines.join("\n").parse()
+}
+
+fn resolve(schema: &Schema, line: &str) -> io::Result<String> {
+    let mut toks: Vec<String> = line.split_whitespace().map(|t| t.to_string()).collect();
+    if toks.len() > 1 && (toks[0] == "send" || toks[0] == "expect") && port::parse_code(&toks[1]).is_err() {
+        let code = toks[1].parse().ok().and_then(|set| schema.code_of(set))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no code for {:?} in the schema", toks[1])))?;
+        toks[1] = format!("0x{:02x}", code);
+    }
+    Ok(toks.join(" "))
+}
+
+fn execute(link: &Link, packets: &mpsc::Receiver<Packet>, schema: &Schema, mission: Mission, trace: bool)
+    -> io::Result<()> {
+    for step in &mission.steps {
+        if let Step::Send(ref pkt) = *step {
+            schema.validate(pkt)?;
+        }
+    }
+    while packets.try_recv().is_ok() {}
+    let steps = mission.steps.clone();
+    let mut seq = Sequencer::new(mission, Shared(link.clone()));
+    if trace {
+        seq.on_event(move |e| if let SequencerEvent::StepStarted(i) = *e {
+            println!("{:3}: {}", i, steps[i]);
+        });
+    }
+    let start = Instant::now();
+    seq.start(start.elapsed())?;
+    while seq.state() == SequencerState::Running {
+        if let Ok(pkt) = packets.recv_timeout(Duration::from_millis(5)) {
+            seq.handle(&pkt, start.elapsed())?;
+        }
+        seq.poll(start.elapsed())?;
+    }
+    Ok(())
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".robo_history"))
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let schema = port::schema(m)?;
+    let link: Link = Arc::new(Mutex::new(send::connect(m, Duration::from_secs(1))?));
+    let readline_err = |e: ReadlineError| io::Error::other(e.to_string());
+    let mut rl = Editor::<ReplHelper, DefaultHistory>::new().map_err(readline_err)?;
+    rl.set_helper(Some(ReplHelper { schema: schema.clone(), files: FilenameCompleter::new() }));
+    if let Some(ref path) = history_path() {
+        let _ = rl.load_history(path);
+    }
+
+    // Prints incoming packets above the prompt and hands them to scripts.
+    // Without a terminal there is no prompt to keep clear.
+    let mut printer = rl.create_external_printer().ok();
+    let (tx, packets) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let poller = {
+        let (link, schema, stop) = (link.clone(), schema.clone(), stop.clone());
+        thread::spawn(move || -> io::Result<()> {
+            while !stop.load(Ordering::Relaxed) {
+                let pkts = {
+                    let mut link = link.lock().unwrap();
+                    link.check_timeout()?;
+                    link.poll()?
+                };
+                for pkt in pkts {
+                    let msg = format!("< {}", Summary::packet(&pkt).with_schema(&schema));
+                    match printer {
+                        Some(ref mut printer) => drop(printer.print(msg)),
+                        None => println!("{}", msg),
+                    }
+                    let _ = tx.send(pkt);
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            Ok(())
+        })
+    };
+
+    if let Some(path) = m.get_one::<String>("script") {
+        let mission = parse(&schema, &fs::read_to_string(path)?)?;
+        execute(&link, &packets, &schema, mission, true)?;
+    }
+    loop {
+        let line = match rl.readline("robo> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(readline_err(e)),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = rl.add_history_entry(line);
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        let result = match toks.as_slice() {
+            ["quit"] | ["exit"] => break,
+            ["help"] => {
+                println!("{}", HELP);
+                Ok(())
+            },
+            ["codes"] => {
+                for (code, set) in schema.codes() {
+                    println!("0x{:02x} {}", code, set.name());
+                }
+                Ok(())
+            },
+            ["run", path] => fs::read_to_string(path)
+                .and_then(|s| parse(&schema, &s))
+                .and_then(|mission| execute(&link, &packets, &schema, mission, true)),
+            _ => parse(&schema, line)
+                .and_then(|mission| execute(&link, &packets, &schema, mission, false)),
+        };
+        if let Err(e) = result {
+            println!("error: {}", e);
+        }
+    }
+
+    if let Some(ref path) = history_path() {
+        let _ = rl.save_history(path);
+    }
+    stop.store(true, Ordering::Relaxed);
+    poller.join().unwrap()
+}
@@ -0,0 +1,92 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use clap::{Arg, ArgMatches, Command};
+use robo::fmt::display::Summary;
+use robo::l0::comm::*;
+use serde_json;
+use port;
+
+pub fn command() -> Command {
+    Command::new("send")
+        .about("Send one packet, optionally waiting for a reply")
+        .args(port::args())
+        .arg(Arg::new("code").long("code").required(true).value_parser(port::parse_code))
+        .arg(Arg::new("payload").default_value("")
+            .help("Payload as hex bytes (\"01 00 64\") or a JSON array ([1, 0, 100])"))
+        .arg(Arg::new("expect").long("expect").value_parser(port::parse_code)
+            .help("Wait for a packet with this code and print it"))
+        .arg(Arg::new("timeout").long("timeout").value_name("MS").default_value("1000")
+            .value_parser(clap::value_parser!(u64)))
+}
+
+pub fn parse_payload(s: &str) -> io::Result<Vec<u8>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let s = s.trim();
+    if s.starts_with('[') {
+        return serde_json::from_str(s).map_err(|e| invalid(format!("bad JSON payload: {}", e)));
+    }
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace() && *b != b':' && *b != b',').collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(invalid("odd number of hex digits in payload".to_string()));
+    }
+    digits.chunks(2)
+        .map(|d| ::std::str::from_utf8(d).ok().and_then(|d| u8::from_str_radix(d, 16).ok())
+            .ok_or_else(|| invalid(format!("bad hex in payload {:?}", s))))
+        .collect()
+}
+
+// Polls until a packet matches or timeout expires.
+pub fn wait<T, F>(ep: &mut Endpoint<T>, timeout: Duration, mut f: F) -> io::Result<Option<Packet>>
+    where T: io::Read + io::Write, F: FnMut(&Packet) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        for pkt in ep.poll()? {
+            if f(&pkt) {
+                return Ok(Some(pkt));
+            }
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+pub fn connect(m: &ArgMatches, timeout: Duration) -> io::Result<Endpoint<port::Transport>> {
+    let mut ep = Endpoint::named(port::open_stream(m)?, m.get_one::<String>("port").unwrap())?;
+    let deadline = Instant::now() + timeout;
+    while !ep.is_ready() {
+        ep.poll()?;
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no sync from device"));
+        }
+    }
+    Ok(ep)
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let schema = port::schema(m)?;
+    let timeout = Duration::from_millis(*m.get_one::<u64>("timeout").unwrap());
+    let mut pkt = Packet::new_with(0, *m.get_one::<u8>("code").unwrap());
+    pkt.data = parse_payload(m.get_one::<String>("payload").unwrap())?;
+    if pkt.data.len() > PACKET_MAX_DATA_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("payload is {} bytes, at most {} fit a packet", pkt.data.len(), PACKET_MAX_DATA_LEN)));
+    }
+    schema.validate(&pkt)?;
+
+    let mut ep = connect(m, timeout)?;
+    ep.send(pkt)?;
+    let expect = match m.get_one::<u8>("expect") {
+        Some(code) => *code,
+        None => return Ok(()),
+    };
+    match wait(&mut ep, timeout, |p| p.code == expect)? {
+        Some(reply) => {
+            println!("{}", Summary::packet(&reply).with_schema(&schema));
+            Ok(())
+        },
+        None => Err(io::Error::new(io::ErrorKind::TimedOut, format!("no reply with code 0x{:02x}", expect))),
+    }
+}
@@ -0,0 +1,74 @@
+use std::io;
+use std::time::Instant;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use robo::l0::comm::*;
+use robo::fmt::display::{HexDump, Summary};
+use robo::fmt::wirelog::{Direction, WireLogWriter};
+use port;
+
+pub fn command() -> Command {
+    Command::new("sniff")
+        .about("Print the packets on a link as they pass")
+        .args(port::args())
+        .arg(Arg::new("code").long("code").action(ArgAction::Append).value_parser(port::parse_code)
+            .help("Only show packets with this code, may repeat"))
+        .arg(Arg::new("dump").long("dump").action(ArgAction::SetTrue)
+            .help("Show each packet as an annotated hex dump"))
+        .arg(Arg::new("jsonl").long("jsonl").action(ArgAction::SetTrue).conflicts_with("dump")
+            .help("Write a JSON lines wire log instead"))
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let schema = port::schema(m)?;
+    let dump = m.get_flag("dump");
+    let mut log = if m.get_flag("jsonl") { Some(WireLogWriter::new(io::stdout())) } else { None };
+    let codes: Vec<u8> = m.get_many::<u8>("code").map(|c| c.cloned().collect()).unwrap_or_default();
+    let mut input = port::open_input(m)?;
+    let mut parser = Parser::promiscuous();
+    let start = Instant::now();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for b in &buf[..n] {
+            let r = parser.parse(*b);
+            let elapsed = start.elapsed();
+            let ts = elapsed.as_secs_f64();
+            if let Some(ref mut log) = log {
+                if r.sync == SYNC_REQ || r.sync == SYNC_ACK {
+                    log.sync(elapsed, Direction::Rx, r.sync, *b)?;
+                }
+                if let Some(pkt) = r.packet.filter(|p| codes.is_empty() || codes.contains(&p.code)) {
+                    log.packet(elapsed, Direction::Rx, &pkt)?;
+                }
+                continue;
+            }
+            match r.sync {
+                SYNC_REQ => println!("{:10.3}  sync request seq={}", ts, b),
+                SYNC_ACK => println!("{:10.3}  sync ack seq={}", ts, b),
+                SUSPEND_REQ => println!("{:10.3}  suspend request seq={}", ts, b),
+                SUSPEND_ACK => println!("{:10.3}  suspend ack seq={}", ts, b),
+                EPOCH => println!("{:10.3}  epoch boot={}", ts, b),
+                FIN => println!("{:10.3}  fin seq={}", ts, b),
+                FIN_ACK => println!("{:10.3}  fin ack seq={}", ts, b),
+                FLOW_OFF => println!("{:10.3}  flow off seq={}", ts, b),
+                FLOW_ON => println!("{:10.3}  flow on seq={}", ts, b),
+                _ => (),
+            }
+            if let Some(pkt) = r.packet {
+                if !codes.is_empty() && !codes.contains(&pkt.code) {
+                    continue;
+                }
+                if dump {
+                    println!("{:10.3}\n{}", ts, HexDump::packet(&pkt).with_schema(&schema));
+                } else {
+                    println!("{:10.3}  {}", ts, Summary::packet(&pkt).with_schema(&schema));
+                }
+            }
+        }
+    }
+}
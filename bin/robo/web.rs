@@ -0,0 +1,39 @@
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use clap::{Arg, ArgMatches, Command};
+use robo::bridge::web::WebDashboard;
+use port;
+use send;
+
+const LINK_PERIOD: Duration = Duration::from_secs(1);
+
+pub fn command() -> Command {
+    Command::new("web")
+        .about("Serve a browser dashboard of a link")
+        .args(port::args())
+        .arg(Arg::new("listen").long("listen").default_value("127.0.0.1:8080"))
+}
+
+pub fn run(m: &ArgMatches) -> io::Result<()> {
+    let dash = Arc::new(WebDashboard::new().with_schema(port::schema(m)?));
+    let mut ep = send::connect(m, Duration::from_secs(1))?;
+    let addr = WebDashboard::serve(dash.clone(), m.get_one::<String>("listen").unwrap().as_str())?;
+    println!("dashboard at http://{}/", addr);
+    let mut last_link = Instant::now();
+    loop {
+        for pkt in ep.poll()? {
+            dash.publish_packet(&pkt);
+        }
+        ep.check_timeout()?;
+        for pkt in dash.take_commands() {
+            ep.send(pkt)?;
+        }
+        if last_link.elapsed() >= LINK_PERIOD {
+            dash.publish_link(ep.device(), ep.stats());
+            last_link = Instant::now();
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
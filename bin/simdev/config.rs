@@ -0,0 +1,292 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use robo::fmt::json::encode_message;
+use robo::l0::comm::PACKET_MAX_DATA_LEN;
+use robo::l2::schema::MessageSet;
+use serde_json::Value;
+
+// What the simulated device is, loaded from a JSON file:
+//
+//   {
+//     "name": "arm",
+//     "identity": {"code": 14, "text": "robotalks arm-sim 1.0"},
+//     "parameters": {"code": 13, "values": [{"id": 1, "value": 10, "min": 0, "max": 100}]},
+//     "echo": 15,
+//     "telemetry": [
+//       {"code": 131, "period_ms": 100, "set": "power",
+//        "message": {"message": "status", "voltage_mv": 12400, "current_ma": 300, "soc_pct": 80},
+//        "fields": [{"offset": 1, "width": 2, "sine": {"bias": 12000, "amplitude": 400, "period_ms": 5000}}]}
+//     ],
+//     "faults": [{"at_ms": 2000, "every_ms": 10000, "corrupt": {"bytes": 4, "mask": 16}}]
+//   }
+//
+// Requests come in on the codes given, with replies on their event codes.
+// Telemetry always goes out as events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub name: String,
+    pub identity: Option<Identity>,
+    pub parameters: Option<Parameters>,
+    pub echo: Option<u8>,
+    pub telemetry: Vec<Telemetry>,
+    pub faults: Vec<Fault>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub code: u8,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+    pub code: u8,
+    pub values: Vec<Parameter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameter {
+    pub id: u8,
+    pub value: i32,
+    pub min: i32,
+    pub max: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Telemetry {
+    pub code: u8,
+    pub period: Duration,
+    pub data: Vec<u8>,
+    pub fields: Vec<Generator>,
+}
+
+// Overwrites width bytes at offset, little-endian, before each send.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Generator {
+    pub offset: usize,
+    pub width: usize,
+    pub wave: Wave,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wave {
+    Counter { step: i64 },
+    Sine { bias: f64, amplitude: f64, period: Duration },
+    Noise { bias: f64, amplitude: f64 },
+    Parameter(u8), // the current value of a parameter.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fault {
+    pub at: Duration, // since the connection came up.
+    pub every: Option<Duration>,
+    pub kind: FaultKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultKind {
+    Drop(usize),                        // swallows the next bytes sent.
+    Corrupt { bytes: usize, mask: u8 }, // xors the next bytes sent.
+    Silence(Duration),                  // sends nothing for a while.
+    Reboot,                             // starts over with a new handshake.
+    Disconnect,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn int(v: &Value, key: &str, at: &str) -> io::Result<i64> {
+    v[key].as_i64().ok_or_else(|| invalid(format!("{}: {} must be an integer", at, key)))
+}
+
+fn opt_int(v: &Value, key: &str, at: &str, default: i64) -> io::Result<i64> {
+    if v[key].is_null() { Ok(default) } else { int(v, key, at) }
+}
+
+fn num(v: &Value, key: &str, at: &str, default: f64) -> io::Result<f64> {
+    match v[key] {
+        Value::Null => Ok(default),
+        ref n => n.as_f64().ok_or_else(|| invalid(format!("{}: {} must be a number", at, key))),
+    }
+}
+
+fn request_code(v: &Value, at: &str) -> io::Result<u8> {
+    match v.as_i64() {
+        Some(c @ 0..=0x0f) => Ok(c as u8),
+        _ => Err(invalid(format!("{}: {} is not a request code", at, v))),
+    }
+}
+
+fn code(v: &Value, at: &str) -> io::Result<u8> {
+    request_code(&v["code"], at)
+}
+
+fn millis(v: &Value, key: &str, at: &str) -> io::Result<Duration> {
+    let ms = int(v, key, at)?;
+    if ms < 0 {
+        return Err(invalid(format!("{}: {} must not be negative", at, key)));
+    }
+    Ok(Duration::from_millis(ms as u64))
+}
+
+fn parameter(v: &Value, at: &str) -> io::Result<Parameter> {
+    let p = Parameter {
+        id: int(v, "id", at)? as u8,
+        value: opt_int(v, "value", at, 0)? as i32,
+        min: opt_int(v, "min", at, i32::MIN as i64)? as i32,
+        max: opt_int(v, "max", at, i32::MAX as i64)? as i32,
+    };
+    if p.value < p.min || p.value > p.max {
+        return Err(invalid(format!("{}: value outside min..max", at)));
+    }
+    Ok(p)
+}
+
+fn generator(v: &Value, at: &str) -> io::Result<Generator> {
+    let wave = if let Some(w) = v.get("counter") {
+        Wave::Counter { step: opt_int(w, "step", at, 1)? }
+    } else if let Some(w) = v.get("sine") {
+        Wave::Sine { bias: num(w, "bias", at, 0.0)?, amplitude: num(w, "amplitude", at, 0.0)?,
+                     period: millis(w, "period_ms", at)? }
+    } else if let Some(w) = v.get("noise") {
+        Wave::Noise { bias: num(w, "bias", at, 0.0)?, amplitude: num(w, "amplitude", at, 0.0)? }
+    } else if let Some(id) = v.get("parameter") {
+        Wave::Parameter(id.as_u64().ok_or_else(|| invalid(format!("{}: parameter must be an id", at)))? as u8)
+    } else {
+        return Err(invalid(format!("{}: expected counter, sine, noise or parameter", at)));
+    };
+    let width = opt_int(v, "width", at, 1)?;
+    if ![1, 2, 4].contains(&width) {
+        return Err(invalid(format!("{}: width must be 1, 2 or 4", at)));
+    }
+    Ok(Generator { offset: int(v, "offset", at)? as usize, width: width as usize, wave })
+}
+
+fn telemetry(v: &Value, at: &str) -> io::Result<Telemetry> {
+    let data = match (v.get("data"), v.get("set")) {
+        (Some(data), _) => serde_json::from_value(data.clone())
+            .map_err(|e| invalid(format!("{}: data must be an array of bytes: {}", at, e)))?,
+        (None, Some(set)) => {
+            let set: MessageSet = set.as_str().unwrap_or("").parse()
+                .map_err(|e| invalid(format!("{}: {}", at, e)))?;
+            encode_message(set, &v["message"]).map_err(|e| invalid(format!("{}: {}", at, e)))?
+        },
+        (None, None) => Vec::new(),
+    };
+    if data.len() > PACKET_MAX_DATA_LEN {
+        return Err(invalid(format!("{}: payload longer than {} bytes", at, PACKET_MAX_DATA_LEN)));
+    }
+    let fields = match v.get("fields") {
+        Some(fs) => fs.as_array().ok_or_else(|| invalid(format!("{}: fields must be an array", at)))?
+            .iter().enumerate().map(|(i, f)| generator(f, &format!("{}.fields[{}]", at, i)))
+            .collect::<io::Result<Vec<_>>>()?,
+        None => Vec::new(),
+    };
+    if let Some(f) = fields.iter().find(|f| f.offset + f.width > data.len()) {
+        return Err(invalid(format!("{}: field at {} overruns the payload", at, f.offset)));
+    }
+    let period = millis(v, "period_ms", at)?;
+    if period == Duration::from_millis(0) {
+        return Err(invalid(format!("{}: period_ms must be positive", at)));
+    }
+    let code = match int(v, "code", at)? {
+        c @ 0..=0x0f | c @ 0x80..=0x8f => c as u8 | 0x80,
+        c => return Err(invalid(format!("{}: bad code {}", at, c))),
+    };
+    Ok(Telemetry { code, period, data, fields })
+}
+
+fn fault(v: &Value, at: &str) -> io::Result<Fault> {
+    let kind = if let Some(n) = v.get("drop") {
+        FaultKind::Drop(n.as_u64().ok_or_else(|| invalid(format!("{}: drop must be a byte count", at)))? as usize)
+    } else if let Some(c) = v.get("corrupt") {
+        FaultKind::Corrupt { bytes: opt_int(c, "bytes", at, 1)? as usize, mask: opt_int(c, "mask", at, 0xff)? as u8 }
+    } else if v.get("silence_ms").is_some() {
+        FaultKind::Silence(millis(v, "silence_ms", at)?)
+    } else if v.get("reboot").is_some() {
+        FaultKind::Reboot
+    } else if v.get("disconnect").is_some() {
+        FaultKind::Disconnect
+    } else {
+        return Err(invalid(format!("{}: expected drop, corrupt, silence_ms, reboot or disconnect", at)));
+    };
+    let every = if v.get("every_ms").is_some() { Some(millis(v, "every_ms", at)?) } else { None };
+    if every == Some(Duration::from_millis(0)) {
+        return Err(invalid(format!("{}: every_ms must be positive", at)));
+    }
+    Ok(Fault { at: millis(v, "at_ms", at)?, every, kind })
+}
+
+fn list<T, F>(v: &Value, key: &str, f: F) -> io::Result<Vec<T>> where F: Fn(&Value, &str) -> io::Result<T> {
+    match v.get(key) {
+        Some(items) => items.as_array().ok_or_else(|| invalid(format!("{} must be an array", key)))?
+            .iter().enumerate().map(|(i, item)| f(item, &format!("{}[{}]", key, i))).collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> io::Result<Config> {
+        let text = fs::read_to_string(path)?;
+        let v: Value = serde_json::from_str(&text)
+            .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+        Config::from_json(&v)
+    }
+
+    pub fn from_json(v: &Value) -> io::Result<Config> {
+        let identity = match v.get("identity") {
+            Some(i) => Some(Identity {
+                code: code(i, "identity")?,
+                text: i["text"].as_str().ok_or_else(|| invalid("identity: missing text".to_string()))?.to_string(),
+            }),
+            None => None,
+        };
+        let parameters = match v.get("parameters") {
+            Some(p) => Some(Parameters { code: code(p, "parameters")?, values: list(p, "values", parameter)? }),
+            None => None,
+        };
+        let echo = match v.get("echo") {
+            Some(c) => Some(request_code(c, "echo")?),
+            None => None,
+        };
+        let config = Config {
+            name: v["name"].as_str().unwrap_or("simdev").to_string(),
+            identity,
+            parameters,
+            echo,
+            telemetry: list(v, "telemetry", telemetry)?,
+            faults: list(v, "faults", fault)?,
+        };
+        for t in &config.telemetry {
+            for f in &t.fields {
+                if let Wave::Parameter(id) = f.wave {
+                    if config.parameter(id).is_none() {
+                        return Err(invalid(format!("telemetry 0x{:02x}: no parameter {}", t.code, id)));
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn parameter(&self, id: u8) -> Option<&Parameter> {
+        self.parameters.as_ref()?.values.iter().find(|p| p.id == id)
+    }
+}
+
+// Answers identity requests and echoes, nothing else.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            name: "simdev".to_string(),
+            identity: Some(Identity { code: 0x0e, text: "robo.rs simdev".to_string() }),
+            parameters: None,
+            echo: Some(0x0f),
+            telemetry: Vec::new(),
+            faults: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::f64::consts::PI;
+use std::io;
+use std::io::{Read, Write};
+use std::time::Duration;
+use robo::l0::comm::{Endpoint, Packet, PACKET_MAX_DATA_LEN};
+use config::{Config, FaultKind, Telemetry, Wave};
+
+// Parameter ops, the first payload byte of requests and replies.
+pub const PARAM_GET: u8 = 0x01;  // [op, id] -> [op, id, value: i32]
+pub const PARAM_SET: u8 = 0x02;  // [op, id, value: i32] -> [op, id, value: i32]
+pub const PARAM_ERROR: u8 = 0xff; // [op, id], for unknown ids and bad requests.
+
+// Damages what the device sends as the fault script says.
+pub struct Faulty<T> {
+    inner: T,
+    drop: usize,
+    corrupt: usize,
+    mask: u8,
+    silent: bool,
+}
+
+impl<T> Faulty<T> {
+    pub fn new(inner: T) -> Self {
+        Faulty { inner, drop: 0, corrupt: 0, mask: 0, silent: false }
+    }
+}
+
+impl<T: Read> Read for Faulty<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for Faulty<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = Vec::with_capacity(buf.len());
+        for b in buf {
+            if self.silent {
+                continue;
+            }
+            if self.drop > 0 {
+                self.drop -= 1;
+            } else if self.corrupt > 0 {
+                self.corrupt -= 1;
+                out.push(b ^ self.mask);
+            } else {
+                out.push(*b);
+            }
+        }
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// A device as described by a Config, speaking the wire protocol over a
+// transport. Time is passed to step as the time since the connection came
+// up, and the fault script restarts with each connection.
+pub struct Device<T> {
+    endpoint: Endpoint<Faulty<T>>,
+    config: Config,
+    verbose: bool,
+    params: Vec<(u8, i32)>,
+    last: Vec<Option<Duration>>,
+    counters: Vec<i64>,
+    faults: Vec<Option<Duration>>, // next time each fault fires.
+    silent_until: Option<Duration>,
+    rng: u64,
+}
+
+impl<T: Read + Write> Device<T> {
+    pub fn new(transport: T, config: &Config) -> io::Result<Self> {
+        Ok(Device {
+            endpoint: Endpoint::with_epoch(Faulty::new(transport), &config.name, 0)?,
+            verbose: false,
+            params: Device::<T>::initial_params(config),
+            last: vec![None; config.telemetry.len()],
+            counters: vec![0; config.telemetry.iter().map(|t| t.fields.len()).sum()],
+            faults: config.faults.iter().map(|f| Some(f.at)).collect(),
+            silent_until: None,
+            rng: 0x2545_f491_4f6c_dd1d,
+            config: config.clone(),
+        })
+    }
+
+    // Logs unhandled requests and faults to stderr.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    // Back to the configured parameters, with a new handshake.
+    pub fn reboot(&mut self) -> io::Result<()> {
+        self.params = Device::<T>::initial_params(&self.config);
+        self.last = vec![None; self.last.len()];
+        self.endpoint.restart()
+    }
+
+    fn initial_params(config: &Config) -> Vec<(u8, i32)> {
+        config.parameters.iter().flat_map(|p| p.values.iter()).map(|p| (p.id, p.value)).collect()
+    }
+
+    fn param(&self, id: u8) -> Option<i32> {
+        self.params.iter().find(|p| p.0 == id).map(|p| p.1)
+    }
+
+    // Returns false once a disconnect fault fires.
+    pub fn step(&mut self, now: Duration) -> io::Result<bool> {
+        for pkt in self.endpoint.poll()? {
+            self.handle(pkt)?;
+        }
+        if !self.inject(now)? {
+            return Ok(false);
+        }
+        if self.silent_until.is_some_and(|t| now >= t) {
+            self.silent_until = None;
+            self.endpoint.transport().silent = false;
+        }
+        if self.endpoint.is_ready() {
+            for i in 0..self.config.telemetry.len() {
+                let due = self.last[i].is_none_or(|t| now >= t + self.config.telemetry[i].period);
+                if due {
+                    self.last[i] = Some(now);
+                    let pkt = self.telemetry(i, now);
+                    self.endpoint.send(pkt)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn reply(&mut self, code: u8, data: Vec<u8>) -> io::Result<()> {
+        self.endpoint.send(Packet { seq: 0, code: code | 0x80, data })
+    }
+
+    fn handle(&mut self, pkt: Packet) -> io::Result<()> {
+        let identity = self.config.identity.as_ref().map(|i| i.code);
+        let params = self.config.parameters.as_ref().map(|p| p.code);
+        if Some(pkt.code) == identity {
+            let mut text = self.config.identity.as_ref().unwrap().text.clone().into_bytes();
+            text.truncate(PACKET_MAX_DATA_LEN);
+            self.reply(pkt.code, text)
+        } else if Some(pkt.code) == params {
+            let data = self.parameter(&pkt.data);
+            self.reply(pkt.code, data)
+        } else if Some(pkt.code) == self.config.echo {
+            self.reply(pkt.code, pkt.data)
+        } else {
+            if self.verbose {
+                eprintln!("simdev: unhandled packet 0x{:02x} {:02x?}", pkt.code, pkt.data);
+            }
+            Ok(())
+        }
+    }
+
+    fn parameter(&mut self, req: &[u8]) -> Vec<u8> {
+        let id = req.get(1).cloned().unwrap_or(0);
+        let value = match (req.first().cloned(), self.config.parameter(id).cloned()) {
+            (Some(PARAM_GET), Some(_)) if req.len() == 2 => self.param(id),
+            (Some(PARAM_SET), Some(p)) if req.len() == 6 => {
+                let v = i32::from_le_bytes([req[2], req[3], req[4], req[5]]).max(p.min).min(p.max);
+                self.params.iter_mut().find(|p| p.0 == id).unwrap().1 = v;
+                Some(v)
+            },
+            _ => None,
+        };
+        match value {
+            Some(v) => {
+                let mut data = vec![req[0], id];
+                data.extend_from_slice(&v.to_le_bytes());
+                data
+            },
+            None => vec![PARAM_ERROR, id],
+        }
+    }
+
+    fn noise(&mut self) -> f64 {
+        // xorshift64, repeatable from run to run.
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+    }
+
+    fn telemetry(&mut self, i: usize, now: Duration) -> Packet {
+        let t: Telemetry = self.config.telemetry[i].clone();
+        let first: usize = self.config.telemetry[..i].iter().map(|t| t.fields.len()).sum();
+        let mut data = t.data;
+        for (j, f) in t.fields.iter().enumerate() {
+            let v = match f.wave {
+                Wave::Counter { step } => {
+                    let c = &mut self.counters[first + j];
+                    let v = *c;
+                    *c = c.wrapping_add(step);
+                    v
+                },
+                Wave::Sine { bias, amplitude, period } => {
+                    let phase = now.as_secs_f64() / period.as_secs_f64().max(1e-3);
+                    (bias + amplitude * (2.0 * PI * phase).sin()).round() as i64
+                },
+                Wave::Noise { bias, amplitude } => (bias + amplitude * self.noise()).round() as i64,
+                Wave::Parameter(id) => self.param(id).unwrap_or(0) as i64,
+            };
+            data[f.offset..f.offset + f.width].copy_from_slice(&v.to_le_bytes()[..f.width]);
+        }
+        Packet { seq: 0, code: t.code, data }
+    }
+
+    // Fires the faults that are due, false for a disconnect.
+    fn inject(&mut self, now: Duration) -> io::Result<bool> {
+        for i in 0..self.faults.len() {
+            match self.faults[i] {
+                Some(at) if now >= at => {},
+                _ => continue,
+            }
+            let fault = self.config.faults[i];
+            self.faults[i] = fault.every.map(|every| now + every);
+            if self.verbose {
+                eprintln!("simdev: {:?} at {:?}", fault.kind, now);
+            }
+            match fault.kind {
+                FaultKind::Drop(n) => self.endpoint.transport().drop += n,
+                FaultKind::Corrupt { bytes, mask } => {
+                    let t = self.endpoint.transport();
+                    t.corrupt += bytes;
+                    t.mask = mask;
+                },
+                FaultKind::Silence(d) => {
+                    self.silent_until = Some(now + d);
+                    self.endpoint.transport().silent = true;
+                },
+                FaultKind::Reboot => self.reboot()?,
+                FaultKind::Disconnect => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
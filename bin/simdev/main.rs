@@ -0,0 +1,154 @@
+// A configurable device for testing host applications without hardware:
+//
+//   robo-simdev device.json --listen 127.0.0.1:7000
+//   robo-simdev device.json --pty --link /tmp/ttyROBO
+//
+// See config.rs for the file format. Besides telemetry, the device answers
+// identity requests with its identity text, parameter requests with the ops
+// in device.rs and echo requests with their payload, and damages its output
+// or drops the link as its fault script says.
+
+extern crate clap;
+extern crate robo;
+extern crate serde_json;
+extern crate serialport;
+
+mod config;
+mod device;
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use device::Device;
+
+const READ_TIMEOUT: Duration = Duration::from_millis(1);
+
+// A TCP connection that reports the peer closing it as an error rather
+// than an empty read.
+struct Conn(TcpStream);
+
+impl Read for Conn {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf)? {
+            0 if !buf.is_empty() => Err(io::ErrorKind::UnexpectedEof.into()),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for Conn {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn closed(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset |
+        io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe)
+}
+
+fn serve_tcp(config: &Config, m: &ArgMatches) -> io::Result<()> {
+    let listener = TcpListener::bind(m.get_one::<String>("listen").unwrap().as_str())?;
+    // CI scripts wait for this line, and learn the port when it was 0.
+    println!("listening on {}", listener.local_addr()?);
+    io::stdout().flush()?;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let peer = stream.peer_addr()?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut dev = Device::new(Conn(stream), config)?.verbose(m.get_flag("verbose"));
+        let start = Instant::now();
+        let result = loop {
+            match dev.step(start.elapsed()) {
+                Ok(true) => {},
+                Ok(false) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        match result {
+            Err(ref e) if closed(e) => {},
+            Err(e) => return Err(e),
+            Ok(()) => {},
+        }
+        if m.get_flag("verbose") {
+            eprintln!("simdev: {} disconnected", peer);
+        }
+        if m.get_flag("once") {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// The device sits on the master side; hosts open the slave. A PTY can't be
+// hung up, so disconnect faults reboot the device instead.
+#[cfg(unix)]
+fn serve_pty(config: &Config, m: &ArgMatches) -> io::Result<()> {
+    use serialport::{SerialPort, TTYPort};
+    let (mut master, slave) = TTYPort::pair()?;
+    master.set_timeout(READ_TIMEOUT)?;
+    let path = slave.name().ok_or_else(|| io::Error::other("PTY without a name"))?;
+    if let Some(link) = m.get_one::<PathBuf>("link") {
+        let _ = ::std::fs::remove_file(link);
+        ::std::os::unix::fs::symlink(&path, link)?;
+    }
+    println!("device at {}", path);
+    io::stdout().flush()?;
+    let mut dev = Device::new(master, config)?.verbose(m.get_flag("verbose"));
+    let start = Instant::now();
+    loop {
+        if !dev.step(start.elapsed())? {
+            dev.reboot()?;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn serve_pty(_: &Config, _: &ArgMatches) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "PTYs need a Unix host"))
+}
+
+fn run(m: &ArgMatches) -> io::Result<()> {
+    let config = match m.get_one::<PathBuf>("config") {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    if m.get_flag("pty") {
+        serve_pty(&config, m)
+    } else {
+        serve_tcp(&config, m)
+    }
+}
+
+fn main() {
+    let m = Command::new("robo-simdev")
+        .about("Emulates a robo.rs device over TCP or a PTY")
+        .arg(Arg::new("config").value_parser(clap::value_parser!(PathBuf))
+            .help("Device description in JSON; without it the device only answers identity and echo requests"))
+        .arg(Arg::new("listen").long("listen").value_name("ADDR").default_value("127.0.0.1:7000")
+            .help("Address to accept connections on, one at a time"))
+        .arg(Arg::new("pty").long("pty").action(ArgAction::SetTrue).conflicts_with("listen")
+            .help("Serve on a pseudo-terminal instead, printing its path"))
+        .arg(Arg::new("link").long("link").value_name("PATH").requires("pty")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Symlink the PTY here"))
+        .arg(Arg::new("once").long("once").action(ArgAction::SetTrue)
+            .help("Exit when the first connection closes"))
+        .arg(Arg::new("verbose").long("verbose").short('v').action(ArgAction::SetTrue)
+            .help("Log unhandled requests, faults and disconnects to stderr"))
+        .get_matches();
+    if let Err(e) = run(&m) {
+        eprintln!("robo-simdev: {}", e);
+        process::exit(1);
+    }
+}
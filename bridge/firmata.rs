@@ -0,0 +1,209 @@
+use std::io;
+use std::io::Write;
+use l0::comm::Packet;
+use l2::{Message, Sender};
+use l2::adc::{AdcMessage, MAX_CHANNELS};
+
+const ANALOG_MESSAGE: u8 = 0xe0;
+const DIGITAL_MESSAGE: u8 = 0x90;
+const REPORT_ANALOG: u8 = 0xc0;
+const REPORT_DIGITAL: u8 = 0xd0;
+const START_SYSEX: u8 = 0xf0;
+const SET_PIN_MODE: u8 = 0xf4;
+const SET_DIGITAL_PIN_VALUE: u8 = 0xf5;
+const END_SYSEX: u8 = 0xf7;
+const REPORT_VERSION: u8 = 0xf9;
+const EXTENDED_ANALOG: u8 = 0x6f;
+const SAMPLING_INTERVAL: u8 = 0x7a;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinMode {
+    Input = 0x00,
+    Output = 0x01,
+    Analog = 0x02,
+    Pwm = 0x03,
+    Servo = 0x04,
+    InputPullup = 0x0b,
+}
+
+enum State {
+    Idle,
+    Message { cmd: u8, data: Vec<u8> },
+    Sysex,
+}
+
+// Drives an Arduino running StandardFirmata through the adc message set, so
+// it can sit behind the same Sender as a native device. Packets with the
+// adc code are translated to Firmata commands on the writer, and reports
+// fed back from the board come out as AdcMessage::Sample packets.
+//
+// Firmata only streams analog inputs, so Read enables reporting on the
+// channel until the next value arrives. Digital outputs and PWM have no
+// message set here and are driven directly.
+pub struct FirmataAdapter<W> {
+    writer: W,
+    adc_code: u8,
+    streaming: u16,
+    pending_reads: u16,
+    ports: [u8; 16],
+    version: Option<(u8, u8)>,
+    state: State,
+}
+
+impl<W: Write> FirmataAdapter<W> {
+    pub fn new(writer: W, adc_code: u8) -> Self {
+        FirmataAdapter {
+            writer,
+            adc_code,
+            streaming: 0,
+            pending_reads: 0,
+            ports: [0; 16],
+            version: None,
+            state: State::Idle,
+        }
+    }
+
+    pub fn writer(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    // Protocol version, once the board has reported it.
+    pub fn version(&self) -> Option<(u8, u8)> {
+        self.version
+    }
+
+    pub fn set_pin_mode(&mut self, pin: u8, mode: PinMode) -> io::Result<()> {
+        self.writer.write_all(&[SET_PIN_MODE, pin & 0x7f, mode as u8])
+    }
+
+    pub fn digital_write(&mut self, pin: u8, value: bool) -> io::Result<()> {
+        self.writer.write_all(&[SET_DIGITAL_PIN_VALUE, pin & 0x7f, value as u8])
+    }
+
+    // Duty cycle on a pin in Pwm mode, 0 to 255 on most boards.
+    pub fn analog_write(&mut self, pin: u8, value: u16) -> io::Result<()> {
+        if pin < 16 {
+            return self.writer.write_all(&[ANALOG_MESSAGE | pin, value as u8 & 0x7f, (value >> 7) as u8 & 0x7f]);
+        }
+        self.writer.write_all(&[START_SYSEX, EXTENDED_ANALOG, pin & 0x7f,
+            value as u8 & 0x7f, (value >> 7) as u8 & 0x7f, (value >> 14) as u8 & 0x7f, END_SYSEX])
+    }
+
+    // Enables reports of the port holding pin, then digital_read follows
+    // the board.
+    pub fn report_digital(&mut self, pin: u8, enable: bool) -> io::Result<()> {
+        self.writer.write_all(&[REPORT_DIGITAL | (pin >> 3) & 0x0f, enable as u8])
+    }
+
+    pub fn digital_read(&self, pin: u8) -> Option<bool> {
+        self.ports.get((pin >> 3) as usize).map(|p| p & (1 << (pin & 7)) != 0)
+    }
+
+    fn report_analog(&mut self, mask: u16, enable: bool) -> io::Result<()> {
+        for ch in (0..MAX_CHANNELS as u8).filter(|ch| mask & (1 << ch) != 0) {
+            self.writer.write_all(&[REPORT_ANALOG | ch, enable as u8])?;
+        }
+        Ok(())
+    }
+
+    fn handle_adc(&mut self, msg: &AdcMessage) -> io::Result<()> {
+        match *msg {
+            AdcMessage::Read { channel } if (channel as usize) < MAX_CHANNELS => {
+                self.pending_reads |= 1 << channel;
+                self.report_analog(1 << channel, true)
+            },
+            AdcMessage::StartSampling { mask, rate_hz } => {
+                let interval = 1000 / rate_hz.max(1);
+                self.writer.write_all(&[START_SYSEX, SAMPLING_INTERVAL,
+                    interval as u8 & 0x7f, (interval >> 7) as u8 & 0x7f, END_SYSEX])?;
+                self.report_analog(self.streaming & !mask, false)?;
+                self.streaming = mask;
+                self.report_analog(mask, true)
+            },
+            AdcMessage::StopSampling => {
+                let mask = self.streaming & !self.pending_reads;
+                self.streaming = 0;
+                self.report_analog(mask, false)
+            },
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported adc message")),
+        }
+    }
+
+    // Parses bytes from the board, returning the packets they complete.
+    pub fn feed(&mut self, data: &[u8]) -> io::Result<Vec<Packet>> {
+        let mut out = Vec::new();
+        for b in data {
+            if let Some(msg) = self.parse(*b) {
+                out.push(msg.to_packet(self.adc_code));
+            }
+        }
+        // one-shot reads are done once answered.
+        let done = self.pending_reads & !self.streaming;
+        let answered = out.iter().fold(0u16, |mask, pkt| match AdcMessage::from_packet(pkt) {
+            Some(AdcMessage::Sample { channel, .. }) => mask | (1 << channel),
+            _ => mask,
+        });
+        self.report_analog(done & answered, false)?;
+        self.pending_reads &= !answered;
+        Ok(out)
+    }
+
+    fn parse(&mut self, b: u8) -> Option<AdcMessage> {
+        if b == START_SYSEX {
+            self.state = State::Sysex;
+            return None;
+        }
+        if let State::Sysex = self.state {
+            // sysex replies aren't needed for the adapter's messages.
+            if b == END_SYSEX {
+                self.state = State::Idle;
+            }
+            return None;
+        }
+        if b & 0x80 != 0 {
+            self.state = State::Message { cmd: b, data: Vec::with_capacity(2) };
+            return None;
+        }
+        let (cmd, value) = match self.state {
+            State::Message { cmd, ref mut data } => {
+                data.push(b);
+                if data.len() < 2 {
+                    return None;
+                }
+                (cmd, data[0] as u16 | (data[1] as u16) << 7)
+            },
+            _ => return None,
+        };
+        self.state = State::Idle;
+        match cmd & 0xf0 {
+            ANALOG_MESSAGE => {
+                let channel = cmd & 0x0f;
+                if (self.streaming | self.pending_reads) & (1 << channel) == 0 {
+                    return None;
+                }
+                Some(AdcMessage::Sample { channel, raw: value })
+            },
+            DIGITAL_MESSAGE => {
+                self.ports[(cmd & 0x0f) as usize] = value as u8;
+                None
+            },
+            _ if cmd == REPORT_VERSION => {
+                self.version = Some((value as u8 & 0x7f, (value >> 7) as u8));
+                None
+            },
+            _ => None,
+        }
+    }
+}
+
+impl<W: Write> Sender for FirmataAdapter<W> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        if pkt.code != self.adc_code {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no firmata mapping for packet code"));
+        }
+        let msg = AdcMessage::from_packet(&pkt)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed adc message"))?;
+        self.handle_adc(&msg)?;
+        self.writer.flush()
+    }
+}
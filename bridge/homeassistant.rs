@@ -0,0 +1,217 @@
+use std::io;
+use serde_json::Value;
+use l0::comm::Packet;
+use l2::{Message, Sender};
+use l2::mode::{Mode, ModeMessage};
+use l2::power::BatteryState;
+
+pub const DISCOVERY_PREFIX: &str = "homeassistant";
+
+// The MQTT client side, e.g. a rumqttc client.
+pub trait MqttPublisher {
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()>;
+}
+
+const MODES: [Mode; 5] = [Mode::Disabled, Mode::Teleop, Mode::Autonomous, Mode::Estop, Mode::Fault];
+
+pub fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Disabled => "disabled",
+        Mode::Teleop => "teleop",
+        Mode::Autonomous => "autonomous",
+        Mode::Estop => "estop",
+        Mode::Fault => "fault",
+    }
+}
+
+enum Kind {
+    Sensor { device_class: Option<String>, unit: Option<String> },
+    Switch { on: Packet, off: Packet },
+    Button { press: Packet },
+    Select { options: Vec<(String, Packet)> },
+}
+
+struct Entity {
+    object_id: String,
+    name: String,
+    kind: Kind,
+}
+
+impl Entity {
+    fn component(&self) -> &'static str {
+        match self.kind {
+            Kind::Sensor { .. } => "sensor",
+            Kind::Switch { .. } => "switch",
+            Kind::Button { .. } => "button",
+            Kind::Select { .. } => "select",
+        }
+    }
+}
+
+// Announces robot telemetry and commands to Home Assistant through MQTT
+// discovery. Sensors publish their state under the node's base topic, and
+// switches, buttons and selects map their command payloads to packets for
+// the device.
+pub struct HomeAssistant {
+    node_id: String,
+    device_name: String,
+    base: String,
+    entities: Vec<Entity>,
+}
+
+impl HomeAssistant {
+    // node_id must be unique per robot on the broker.
+    pub fn new(node_id: &str, device_name: &str) -> Self {
+        HomeAssistant {
+            node_id: node_id.to_string(),
+            device_name: device_name.to_string(),
+            base: format!("robo/{}", node_id),
+            entities: Vec::new(),
+        }
+    }
+
+    pub fn sensor(mut self, object_id: &str, name: &str, device_class: Option<&str>, unit: Option<&str>) -> Self {
+        self.push(object_id, name, Kind::Sensor {
+            device_class: device_class.map(|s| s.to_string()),
+            unit: unit.map(|s| s.to_string()),
+        });
+        self
+    }
+
+    // Battery voltage, current and charge, fed by publish_battery.
+    pub fn with_battery(self) -> Self {
+        self.sensor("battery", "Battery", Some("battery"), Some("%"))
+            .sensor("battery_voltage", "Battery voltage", Some("voltage"), Some("V"))
+            .sensor("battery_current", "Battery current", Some("current"), Some("A"))
+    }
+
+    // A mode sensor fed by publish_mode and a select requesting modes with
+    // packets of mode_code.
+    pub fn with_mode(mut self, mode_code: u8) -> Self {
+        self = self.sensor("mode", "Mode", Some("enum"), None);
+        let options = MODES.iter()
+            .map(|m| (mode_name(*m).to_string(), ModeMessage::Request { mode: *m }.to_packet(mode_code)))
+            .collect();
+        self.push("mode_request", "Request mode", Kind::Select { options });
+        self
+    }
+
+    pub fn switch(mut self, object_id: &str, name: &str, on: Packet, off: Packet) -> Self {
+        self.push(object_id, name, Kind::Switch { on, off });
+        self
+    }
+
+    pub fn button(mut self, object_id: &str, name: &str, press: Packet) -> Self {
+        self.push(object_id, name, Kind::Button { press });
+        self
+    }
+
+    fn push(&mut self, object_id: &str, name: &str, kind: Kind) {
+        self.entities.retain(|e| e.object_id != object_id);
+        self.entities.push(Entity { object_id: object_id.to_string(), name: name.to_string(), kind });
+    }
+
+    pub fn state_topic(&self, object_id: &str) -> String {
+        format!("{}/{}/state", self.base, object_id)
+    }
+
+    pub fn command_topic(&self, object_id: &str) -> String {
+        format!("{}/{}/set", self.base, object_id)
+    }
+
+    pub fn availability_topic(&self) -> String {
+        format!("{}/availability", self.base)
+    }
+
+    // Topics to subscribe to for handle_command.
+    pub fn command_topics(&self) -> Vec<String> {
+        self.entities.iter()
+            .filter(|e| !matches!(e.kind, Kind::Sensor { .. }))
+            .map(|e| self.command_topic(&e.object_id))
+            .collect()
+    }
+
+    // Retained discovery config per entity, as (topic, payload).
+    pub fn discovery(&self) -> Vec<(String, Value)> {
+        self.entities.iter().map(|e| {
+            let topic = format!("{}/{}/{}/{}/config", DISCOVERY_PREFIX, e.component(), self.node_id, e.object_id);
+            let mut config = json!({
+                "name": e.name,
+                "unique_id": format!("{}_{}", self.node_id, e.object_id),
+                "availability_topic": self.availability_topic(),
+                "device": { "identifiers": [self.node_id], "name": self.device_name, "manufacturer": "robo.rs" },
+            });
+            match e.kind {
+                Kind::Sensor { ref device_class, ref unit } => {
+                    config["state_topic"] = json!(self.state_topic(&e.object_id));
+                    if let Some(ref c) = *device_class {
+                        config["device_class"] = json!(c);
+                    }
+                    if let Some(ref u) = *unit {
+                        config["unit_of_measurement"] = json!(u);
+                        config["state_class"] = json!("measurement");
+                    }
+                    if e.object_id == "mode" {
+                        config["options"] = json!(MODES.iter().map(|m| mode_name(*m)).collect::<Vec<_>>());
+                    }
+                },
+                Kind::Switch { .. } => {
+                    config["command_topic"] = json!(self.command_topic(&e.object_id));
+                    config["optimistic"] = json!(true);
+                },
+                Kind::Button { .. } => {
+                    config["command_topic"] = json!(self.command_topic(&e.object_id));
+                },
+                Kind::Select { ref options } => {
+                    config["command_topic"] = json!(self.command_topic(&e.object_id));
+                    config["options"] = json!(options.iter().map(|o| o.0.clone()).collect::<Vec<_>>());
+                },
+            }
+            (topic, config)
+        }).collect()
+    }
+
+    // Publishes the discovery configs and marks the device online.
+    pub fn announce<P: MqttPublisher>(&self, mqtt: &mut P) -> io::Result<()> {
+        for (topic, config) in self.discovery() {
+            mqtt.publish(&topic, config.to_string().as_bytes(), true)?;
+        }
+        mqtt.publish(&self.availability_topic(), b"online", true)
+    }
+
+    pub fn publish_state<P: MqttPublisher>(&self, mqtt: &mut P, object_id: &str, value: &str) -> io::Result<()> {
+        mqtt.publish(&self.state_topic(object_id), value.as_bytes(), false)
+    }
+
+    pub fn publish_battery<P: MqttPublisher>(&self, mqtt: &mut P, state: &BatteryState) -> io::Result<()> {
+        self.publish_state(mqtt, "battery", &format!("{:.0}", state.soc))?;
+        self.publish_state(mqtt, "battery_voltage", &format!("{:.2}", state.voltage))?;
+        self.publish_state(mqtt, "battery_current", &format!("{:.2}", state.current))
+    }
+
+    pub fn publish_mode<P: MqttPublisher>(&self, mqtt: &mut P, mode: Mode) -> io::Result<()> {
+        self.publish_state(mqtt, "mode", mode_name(mode))
+    }
+
+    // Sends the packet for a command from Home Assistant. Returns false if
+    // the topic or payload doesn't belong to any entity.
+    pub fn handle_command<S: Sender>(&self, topic: &str, payload: &[u8], sender: &mut S) -> io::Result<bool> {
+        let payload = String::from_utf8_lossy(payload);
+        let entity = match self.entities.iter().find(|e| self.command_topic(&e.object_id) == topic) {
+            Some(e) => e,
+            None => return Ok(false),
+        };
+        let pkt = match entity.kind {
+            Kind::Switch { ref on, .. } if payload == "ON" => on,
+            Kind::Switch { ref off, .. } if payload == "OFF" => off,
+            Kind::Button { ref press } if payload == "PRESS" => press,
+            Kind::Select { ref options } => match options.iter().find(|o| o.0 == payload) {
+                Some(o) => &o.1,
+                None => return Ok(false),
+            },
+            _ => return Ok(false),
+        };
+        sender.send(pkt.clone())?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,328 @@
+use kinematics::Twist;
+use l0::comm::{Crc, SoftCrc16};
+use l2::imu::{Quaternion, Vec3};
+use l2::mode::Mode;
+
+const MAGIC_V1: u8 = 0xfe;
+const MAGIC_V2: u8 = 0xfd;
+const V2_SIGNED: u8 = 0x01;
+const SIGNATURE_LEN: usize = 13;
+
+const MSG_HEARTBEAT: u32 = 0;
+const MSG_ATTITUDE: u32 = 30;
+const MSG_MANUAL_CONTROL: u32 = 69;
+
+// MAV_TYPE_GROUND_ROVER and MAV_AUTOPILOT_GENERIC.
+pub const TYPE_GROUND_ROVER: u8 = 10;
+pub const AUTOPILOT_GENERIC: u8 = 0;
+
+// MAV_MODE_FLAG bits.
+pub const MODE_FLAG_CUSTOM: u8 = 0x01;
+pub const MODE_FLAG_AUTO: u8 = 0x04;
+pub const MODE_FLAG_MANUAL_INPUT: u8 = 0x40;
+pub const MODE_FLAG_ARMED: u8 = 0x80;
+
+// MAV_STATE values.
+pub const STATE_STANDBY: u8 = 3;
+pub const STATE_ACTIVE: u8 = 4;
+pub const STATE_CRITICAL: u8 = 5;
+pub const STATE_EMERGENCY: u8 = 6;
+
+// MANUAL_CONTROL axes span -1000 to 1000.
+pub const MANUAL_FULL_SCALE: f64 = 1000.0;
+
+// The subset of the common dialect the bridge speaks. Fields are listed in
+// wire order, which sorts them by size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MavMessage {
+    Heartbeat {
+        custom_mode: u32,
+        mav_type: u8,
+        autopilot: u8,
+        base_mode: u8,
+        system_status: u8,
+    },
+    Attitude {
+        time_boot_ms: u32,
+        roll: f32,
+        pitch: f32,
+        yaw: f32,
+        rollspeed: f32,
+        pitchspeed: f32,
+        yawspeed: f32,
+    },
+    ManualControl {
+        x: i16,
+        y: i16,
+        z: i16,
+        r: i16,
+        buttons: u16,
+        target: u8,
+    },
+}
+
+impl MavMessage {
+    pub fn id(&self) -> u32 {
+        match *self {
+            MavMessage::Heartbeat { .. } => MSG_HEARTBEAT,
+            MavMessage::Attitude { .. } => MSG_ATTITUDE,
+            MavMessage::ManualControl { .. } => MSG_MANUAL_CONTROL,
+        }
+    }
+
+    pub fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            MavMessage::Heartbeat { custom_mode, mav_type, autopilot, base_mode, system_status } => {
+                data.extend_from_slice(&custom_mode.to_le_bytes());
+                data.extend_from_slice(&[mav_type, autopilot, base_mode, system_status, 3]);
+            },
+            MavMessage::Attitude { time_boot_ms, roll, pitch, yaw, rollspeed, pitchspeed, yawspeed } => {
+                data.extend_from_slice(&time_boot_ms.to_le_bytes());
+                for v in &[roll, pitch, yaw, rollspeed, pitchspeed, yawspeed] {
+                    data.extend_from_slice(&v.to_le_bytes());
+                }
+            },
+            MavMessage::ManualControl { x, y, z, r, buttons, target } => {
+                for v in &[x, y, z, r] {
+                    data.extend_from_slice(&v.to_le_bytes());
+                }
+                data.extend_from_slice(&buttons.to_le_bytes());
+                data.push(target);
+            },
+        }
+    }
+
+    // MAVLink 2 strips trailing zero bytes, so short payloads are zero
+    // extended; extension fields past the known ones are ignored.
+    pub fn decode(id: u32, payload: &[u8]) -> Option<Self> {
+        let len = payload_len(id)?;
+        let mut p = payload.to_vec();
+        if p.len() < len {
+            p.resize(len, 0);
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([p[i], p[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]]);
+        let f32_at = |i: usize| f32::from_bits(u32_at(i));
+        let msg = match id {
+            MSG_HEARTBEAT => MavMessage::Heartbeat {
+                custom_mode: u32_at(0),
+                mav_type: p[4],
+                autopilot: p[5],
+                base_mode: p[6],
+                system_status: p[7],
+            },
+            MSG_ATTITUDE => MavMessage::Attitude {
+                time_boot_ms: u32_at(0),
+                roll: f32_at(4),
+                pitch: f32_at(8),
+                yaw: f32_at(12),
+                rollspeed: f32_at(16),
+                pitchspeed: f32_at(20),
+                yawspeed: f32_at(24),
+            },
+            _ => MavMessage::ManualControl {
+                x: u16_at(0) as i16,
+                y: u16_at(2) as i16,
+                z: u16_at(4) as i16,
+                r: u16_at(6) as i16,
+                buttons: u16_at(8),
+                target: p[10],
+            },
+        };
+        Some(msg)
+    }
+}
+
+fn payload_len(id: u32) -> Option<usize> {
+    match id {
+        MSG_HEARTBEAT => Some(9),
+        MSG_ATTITUDE => Some(28),
+        MSG_MANUAL_CONTROL => Some(11),
+        _ => None,
+    }
+}
+
+// Seeds the checksum per message so both ends agree on the field layout.
+fn crc_extra(id: u32) -> Option<u8> {
+    match id {
+        MSG_HEARTBEAT => Some(50),
+        MSG_ATTITUDE => Some(39),
+        MSG_MANUAL_CONTROL => Some(243),
+        _ => None,
+    }
+}
+
+// CRC-16/MCRF4XX, which SoftCrc16 computes, or a peripheral can.
+fn checksum<C: Crc>(crc: &mut C, data: &[u8], extra: u8) -> u16 {
+    crc.reset();
+    crc.update(data);
+    crc.update(&[extra]);
+    crc.finish()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MavFrame {
+    pub seq: u8,
+    pub sysid: u8,
+    pub compid: u8,
+    pub msg: MavMessage,
+}
+
+impl MavFrame {
+    // Encodes as a MAVLink 1 frame, which ground stations accept from
+    // vehicles that don't announce MAVLink 2 support.
+    pub fn encode(&self, data: &mut Vec<u8>) {
+        self.encode_with(&mut SoftCrc16::new(), data)
+    }
+
+    pub fn encode_with<C: Crc>(&self, crc: &mut C, data: &mut Vec<u8>) {
+        let start = data.len();
+        data.extend_from_slice(&[MAGIC_V1, 0, self.seq, self.sysid, self.compid, self.msg.id() as u8]);
+        self.msg.encode(data);
+        data[start + 1] = (data.len() - start - 6) as u8;
+        let crc = checksum(crc, &data[start + 1..], crc_extra(self.msg.id()).unwrap_or(0));
+        data.extend_from_slice(&crc.to_le_bytes());
+    }
+}
+
+// Extracts frames from a byte stream, accepting both protocol versions.
+// Frames with unknown ids or bad checksums are skipped.
+#[derive(Default)]
+pub struct MavParser<C = SoftCrc16> {
+    buf: Vec<u8>,
+    errors: u64,
+    crc: C,
+}
+
+impl MavParser {
+    pub fn new() -> Self {
+        MavParser::default()
+    }
+}
+
+impl<C: Crc> MavParser<C> {
+    pub fn with_crc(crc: C) -> Self {
+        MavParser { buf: Vec::new(), errors: 0, crc }
+    }
+
+    // Frames dropped for a bad checksum.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn parse(&mut self, b: u8) -> Option<MavFrame> {
+        if self.buf.is_empty() && b != MAGIC_V1 && b != MAGIC_V2 {
+            return None;
+        }
+        self.buf.push(b);
+        let total = self.frame_len()?;
+        if self.buf.len() < total {
+            return None;
+        }
+        let frame = self.decode();
+        self.buf.clear();
+        frame
+    }
+
+    fn frame_len(&self) -> Option<usize> {
+        let len = *self.buf.get(1)? as usize;
+        if self.buf[0] == MAGIC_V1 {
+            return Some(6 + len + 2);
+        }
+        let incompat = *self.buf.get(2)?;
+        let signature = if incompat & V2_SIGNED != 0 { SIGNATURE_LEN } else { 0 };
+        Some(10 + len + 2 + signature)
+    }
+
+    fn decode(&mut self) -> Option<MavFrame> {
+        let buf = &self.buf;
+        let len = buf[1] as usize;
+        let (header, seq, sysid, compid, id) = if buf[0] == MAGIC_V1 {
+            (6, buf[2], buf[3], buf[4], buf[5] as u32)
+        } else {
+            let id = buf[7] as u32 | (buf[8] as u32) << 8 | (buf[9] as u32) << 16;
+            (10, buf[4], buf[5], buf[6], id)
+        };
+        let extra = crc_extra(id)?;
+        let end = header + len;
+        let crc = u16::from_le_bytes([buf[end], buf[end + 1]]);
+        if checksum(&mut self.crc, &buf[1..end], extra) != crc {
+            self.errors += 1;
+            return None;
+        }
+        let msg = MavMessage::decode(id, &buf[header..end])?;
+        Some(MavFrame { seq, sysid, compid, msg })
+    }
+}
+
+// Builds outgoing frames with a running sequence number.
+pub struct MavEncoder<C = SoftCrc16> {
+    sysid: u8,
+    compid: u8,
+    seq: u8,
+    crc: C,
+}
+
+impl MavEncoder {
+    pub fn new(sysid: u8, compid: u8) -> Self {
+        MavEncoder::with_crc(sysid, compid, SoftCrc16::new())
+    }
+}
+
+impl<C: Crc> MavEncoder<C> {
+    pub fn with_crc(sysid: u8, compid: u8, crc: C) -> Self {
+        MavEncoder { sysid, compid, seq: 0, crc }
+    }
+
+    pub fn encode(&mut self, msg: MavMessage) -> Vec<u8> {
+        let frame = MavFrame { seq: self.seq, sysid: self.sysid, compid: self.compid, msg };
+        self.seq = self.seq.wrapping_add(1);
+        let mut data = Vec::new();
+        frame.encode_with(&mut self.crc, &mut data);
+        data
+    }
+}
+
+// Vehicle heartbeat for the current mode; the mode itself travels as the
+// custom mode.
+pub fn heartbeat(mode: Mode) -> MavMessage {
+    let (base_mode, system_status) = match mode {
+        Mode::Disabled => (0, STATE_STANDBY),
+        Mode::Teleop => (MODE_FLAG_ARMED | MODE_FLAG_MANUAL_INPUT, STATE_ACTIVE),
+        Mode::Autonomous => (MODE_FLAG_ARMED | MODE_FLAG_AUTO, STATE_ACTIVE),
+        Mode::Fault => (0, STATE_CRITICAL),
+        Mode::Estop => (0, STATE_EMERGENCY),
+    };
+    MavMessage::Heartbeat {
+        custom_mode: mode as u32,
+        mav_type: TYPE_GROUND_ROVER,
+        autopilot: AUTOPILOT_GENERIC,
+        base_mode: base_mode | MODE_FLAG_CUSTOM,
+        system_status,
+    }
+}
+
+// time_boot_ms is device time, which wraps after about 49 days.
+pub fn attitude(orientation: &Quaternion, gyro: &Vec3, time_boot_ms: u32) -> MavMessage {
+    MavMessage::Attitude {
+        time_boot_ms,
+        roll: orientation.roll() as f32,
+        pitch: orientation.pitch() as f32,
+        yaw: orientation.yaw() as f32,
+        rollspeed: gyro.x as f32,
+        pitchspeed: gyro.y as f32,
+        yawspeed: gyro.z as f32,
+    }
+}
+
+// Maps a MANUAL_CONTROL stick command to a body twist. MAVLink has y to
+// the right and r clockwise, so both flip for the crate's convention.
+pub fn twist_from_manual(msg: &MavMessage, max_linear: f64, max_angular: f64) -> Option<Twist> {
+    match *msg {
+        MavMessage::ManualControl { x, y, r, .. } => {
+            let axis = |v: i16| (v as f64 / MANUAL_FULL_SCALE).clamp(-1.0, 1.0);
+            Some(Twist::holonomic(axis(x) * max_linear, -axis(y) * max_linear, -axis(r) * max_angular))
+        },
+        _ => None,
+    }
+}
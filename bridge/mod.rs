@@ -0,0 +1,13 @@
+pub mod firmata;
+#[cfg(feature = "homeassistant")]
+pub mod homeassistant;
+pub mod mavlink;
+pub mod rerun;
+pub mod ros2;
+#[cfg(feature = "rosbridge")]
+pub mod rosbridge;
+#[cfg(feature = "web")]
+pub mod web;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,94 @@
+use std::io;
+use std::time::Duration;
+use l2::camera::{Frame, ImageFormat};
+use l2::imu::{ImuSample, Quaternion};
+use l2::lidar::LaserScan;
+use nav::odometry::PoseEstimate;
+
+// The Rerun archetypes the sink produces, in the SDK's units: f32
+// positions in meters and xyzw quaternions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Archetype<'a> {
+    Transform3D { translation: [f32; 3], rotation_xyzw: [f32; 4] },
+    Points3D { positions: Vec<[f32; 3]> },
+    Scalar(f64),
+    Image { width: u32, height: u32, grey: &'a [u8] },
+    EncodedImage { media_type: &'static str, data: &'a [u8] },
+}
+
+// The Rerun side: implemented over a rerun::RecordingStream, setting the
+// timeline to time and logging the archetype at entity. The SDK pulls in a
+// large dependency tree, so the crate stays independent of it.
+pub trait RerunLogger {
+    fn log(&mut self, entity: &str, time: Duration, data: Archetype) -> io::Result<()>;
+}
+
+// Logs telemetry under one entity tree: the pose at world/robot, scans and
+// camera frames as its children so they follow it in the 3D view, and IMU
+// components as scalar plots.
+pub struct RerunSink<L> {
+    logger: L,
+    root: String,
+}
+
+impl<L: RerunLogger> RerunSink<L> {
+    pub fn new(logger: L) -> Self {
+        RerunSink { logger, root: "world/robot".to_string() }
+    }
+
+    pub fn with_root(mut self, root: &str) -> Self {
+        self.root = root.to_string();
+        self
+    }
+
+    pub fn logger(&self) -> &L {
+        &self.logger
+    }
+
+    pub fn log_pose(&mut self, estimate: &PoseEstimate) -> io::Result<()> {
+        let p = estimate.pose;
+        let q = Quaternion::from_yaw(p.theta);
+        self.logger.log(&self.root, estimate.ts, Archetype::Transform3D {
+            translation: [p.x as f32, p.y as f32, 0.0],
+            rotation_xyzw: [q.x as f32, q.y as f32, q.z as f32, q.w as f32],
+        })
+    }
+
+    // ts is host time, the sample's own timestamp is device time.
+    pub fn log_imu(&mut self, sample: &ImuSample, ts: Duration) -> io::Result<()> {
+        let series = [
+            ("accel/x", sample.accel.x), ("accel/y", sample.accel.y), ("accel/z", sample.accel.z),
+            ("gyro/x", sample.gyro.x), ("gyro/y", sample.gyro.y), ("gyro/z", sample.gyro.z),
+        ];
+        for (name, v) in series.iter() {
+            self.logger.log(&format!("imu/{}", name), ts, Archetype::Scalar(*v))?;
+        }
+        Ok(())
+    }
+
+    // Valid returns as points in the robot frame.
+    pub fn log_scan(&mut self, scan: &LaserScan) -> io::Result<()> {
+        let positions = scan.ranges.iter().enumerate()
+            .filter(|&(_, r)| r.is_finite())
+            .map(|(i, r)| {
+                let (s, c) = scan.angle(i).sin_cos();
+                [(r * c) as f32, (r * s) as f32, 0.0]
+            })
+            .collect();
+        let entity = format!("{}/scan", self.root);
+        self.logger.log(&entity, scan.ts, Archetype::Points3D { positions })
+    }
+
+    pub fn log_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let data = match frame.format {
+            ImageFormat::Grey8 => Archetype::Image {
+                width: frame.width as u32,
+                height: frame.height as u32,
+                grey: &frame.data,
+            },
+            ImageFormat::Jpeg => Archetype::EncodedImage { media_type: "image/jpeg", data: &frame.data },
+        };
+        let entity = format!("{}/camera", self.root);
+        self.logger.log(&entity, frame.ts, data)
+    }
+}
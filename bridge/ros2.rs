@@ -0,0 +1,171 @@
+use std::io;
+use std::time::Duration;
+use kinematics::{DriveClient, DriveKinematics, Twist};
+use l2::Sender;
+use l2::imu::{ImuSample, Quaternion, Vec3};
+use l2::power;
+use nav::odometry::PoseEstimate;
+
+// Message types mirroring their ROS 2 definitions field by field, so the
+// node side only has to copy fields into the generated r2r/rclrs types.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub stamp: Duration,
+    pub frame_id: String,
+}
+
+// sensor_msgs/Imu
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imu {
+    pub header: Header,
+    pub orientation: Quaternion,
+    pub orientation_covariance: [f64; 9],
+    pub angular_velocity: Vec3,
+    pub angular_velocity_covariance: [f64; 9],
+    pub linear_acceleration: Vec3,
+    pub linear_acceleration_covariance: [f64; 9],
+}
+
+// geometry_msgs/Twist
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TwistMsg {
+    pub linear: Vec3,
+    pub angular: Vec3,
+}
+
+// nav_msgs/Odometry, with the pose and twist covariances over
+// (x, y, z, roll, pitch, yaw).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Odometry {
+    pub header: Header,
+    pub child_frame_id: String,
+    pub position: Vec3,
+    pub orientation: Quaternion,
+    pub pose_covariance: [f64; 36],
+    pub twist: TwistMsg,
+    pub twist_covariance: [f64; 36],
+}
+
+// sensor_msgs/BatteryState, the fields this crate knows about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryState {
+    pub header: Header,
+    pub voltage: f32,
+    pub current: f32,    // negative while discharging, as in ROS.
+    pub percentage: f32, // 0 to 1.
+    pub present: bool,
+}
+
+// The ROS side: an r2r or rclrs node implements this to publish on its
+// topics. Building against ROS requires a sourced ROS 2 installation, so
+// the crate itself stays independent of either binding.
+pub trait RosNode {
+    fn publish_imu(&mut self, topic: &str, msg: &Imu) -> io::Result<()>;
+    fn publish_odometry(&mut self, topic: &str, msg: &Odometry) -> io::Result<()>;
+    fn publish_battery(&mut self, topic: &str, msg: &BatteryState) -> io::Result<()>;
+}
+
+// -1 in the first element marks an unknown orientation.
+const UNKNOWN: [f64; 9] = [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+
+pub fn imu_msg(sample: &ImuSample, stamp: Duration, frame_id: &str) -> Imu {
+    Imu {
+        header: Header { stamp, frame_id: frame_id.to_string() },
+        orientation: Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 },
+        orientation_covariance: UNKNOWN,
+        angular_velocity: sample.gyro,
+        angular_velocity_covariance: [0.0; 9],
+        linear_acceleration: sample.accel,
+        linear_acceleration_covariance: [0.0; 9],
+    }
+}
+
+pub fn odometry_msg(estimate: &PoseEstimate, twist: &Twist, frame_id: &str, child_frame_id: &str) -> Odometry {
+    // map (x, y, theta) onto (x, y, yaw) of the 6x6 covariance.
+    let index = [0, 1, 5];
+    let mut pose_covariance = [0.0; 36];
+    for (i, row) in estimate.covariance.iter().enumerate() {
+        for (j, v) in row.iter().enumerate() {
+            pose_covariance[index[i] * 6 + index[j]] = *v;
+        }
+    }
+    let pose = estimate.pose;
+    Odometry {
+        header: Header { stamp: estimate.ts, frame_id: frame_id.to_string() },
+        child_frame_id: child_frame_id.to_string(),
+        position: Vec3::new(pose.x, pose.y, 0.0),
+        orientation: Quaternion::from_yaw(pose.theta),
+        pose_covariance,
+        twist: TwistMsg {
+            linear: Vec3::new(twist.vx, twist.vy, 0.0),
+            angular: Vec3::new(0.0, 0.0, twist.omega),
+        },
+        twist_covariance: [0.0; 36],
+    }
+}
+
+pub fn battery_msg(state: &power::BatteryState, frame_id: &str) -> BatteryState {
+    BatteryState {
+        header: Header { stamp: state.ts, frame_id: frame_id.to_string() },
+        voltage: state.voltage as f32,
+        current: -state.current as f32,
+        percentage: (state.soc / 100.0) as f32,
+        present: true,
+    }
+}
+
+// A cmd_vel message as a body twist; z, roll and pitch are ignored.
+pub fn twist_from_msg(msg: &TwistMsg) -> Twist {
+    Twist::holonomic(msg.linear.x, msg.linear.y, msg.angular.z)
+}
+
+// Publishes telemetry on the conventional topics and forwards cmd_vel to
+// the drive.
+pub struct Ros2Bridge<N> {
+    node: N,
+    base_frame: String,
+    odom_frame: String,
+}
+
+impl<N: RosNode> Ros2Bridge<N> {
+    pub fn new(node: N) -> Self {
+        Ros2Bridge {
+            node,
+            base_frame: "base_link".to_string(),
+            odom_frame: "odom".to_string(),
+        }
+    }
+
+    pub fn with_frames(mut self, odom_frame: &str, base_frame: &str) -> Self {
+        self.odom_frame = odom_frame.to_string();
+        self.base_frame = base_frame.to_string();
+        self
+    }
+
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+
+    // Publishes on "imu", stamped with host time ts.
+    pub fn publish_imu(&mut self, sample: &ImuSample, ts: Duration) -> io::Result<()> {
+        let msg = imu_msg(sample, ts, &self.base_frame);
+        self.node.publish_imu("imu", &msg)
+    }
+
+    pub fn publish_odometry(&mut self, estimate: &PoseEstimate, twist: &Twist) -> io::Result<()> {
+        let msg = odometry_msg(estimate, twist, &self.odom_frame, &self.base_frame);
+        self.node.publish_odometry("odom", &msg)
+    }
+
+    pub fn publish_battery(&mut self, state: &power::BatteryState) -> io::Result<()> {
+        let msg = battery_msg(state, &self.base_frame);
+        self.node.publish_battery("battery_state", &msg)
+    }
+
+    // Called from the node's cmd_vel subscription.
+    pub fn cmd_vel<K, S>(&mut self, msg: &TwistMsg, client: &mut DriveClient<K, S>) -> io::Result<()>
+        where K: DriveKinematics, S: Sender {
+        client.drive(&twist_from_msg(msg))
+    }
+}
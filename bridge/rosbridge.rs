@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use tungstenite::{self, Message, WebSocket};
+use kinematics::Twist;
+use l2::imu::{Quaternion, Vec3};
+use super::ros2::{BatteryState, Header, Imu, Odometry};
+
+// What the server exposes to clients. Telemetry flows the other way, through
+// Server::publish.
+pub trait Backend {
+    // A client publishing on a topic it advertised, e.g. cmd_vel.
+    fn publish(&mut self, topic: &str, msg: &Value) -> io::Result<()>;
+    fn call_service(&mut self, service: &str, args: &Value) -> io::Result<Value>;
+}
+
+struct Subscription {
+    id: Option<String>,
+    throttle: Duration,
+    last: Option<Duration>,
+}
+
+// The rosbridge v2 protocol state of one client: advertise, unadvertise,
+// publish, subscribe, unsubscribe and call_service. Fragmentation and
+// compression aren't supported.
+#[derive(Default)]
+pub struct Session {
+    subscriptions: HashMap<String, Subscription>,
+    advertised: HashSet<String>,
+}
+
+fn status(level: &str, id: Option<&Value>, msg: &str) -> String {
+    let mut v = json!({ "op": "status", "level": level, "msg": msg });
+    if let Some(id) = id {
+        v["id"] = id.clone();
+    }
+    v.to_string()
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    pub fn is_subscribed(&self, topic: &str) -> bool {
+        self.subscriptions.contains_key(topic)
+    }
+
+    // Handles one text frame from the client, returning the frames to send
+    // back.
+    pub fn handle<B: Backend>(&mut self, text: &str, backend: &mut B) -> Vec<String> {
+        let req: Value = match serde_json::from_str(text) {
+            Ok(v) => v,
+            Err(_) => return vec![status("error", None, "invalid json")],
+        };
+        let id = req.get("id");
+        let op = req["op"].as_str().unwrap_or("");
+        let topic = req["topic"].as_str();
+        match (op, topic) {
+            ("advertise", Some(topic)) => {
+                self.advertised.insert(topic.to_string());
+            },
+            ("unadvertise", Some(topic)) => {
+                self.advertised.remove(topic);
+            },
+            ("publish", Some(topic)) => {
+                if !self.advertised.contains(topic) {
+                    return vec![status("error", id, "topic not advertised")];
+                }
+                if let Err(e) = backend.publish(topic, &req["msg"]) {
+                    return vec![status("error", id, &e.to_string())];
+                }
+            },
+            ("subscribe", Some(topic)) => {
+                let throttle = req["throttle_rate"].as_u64().unwrap_or(0);
+                self.subscriptions.insert(topic.to_string(), Subscription {
+                    id: id.and_then(|v| v.as_str()).map(|s| s.to_string()),
+                    throttle: Duration::from_millis(throttle),
+                    last: None,
+                });
+            },
+            ("unsubscribe", Some(topic)) => {
+                self.subscriptions.remove(topic);
+            },
+            ("call_service", _) => {
+                let service = req["service"].as_str().unwrap_or("");
+                let args = req.get("args").cloned().unwrap_or_else(|| json!({}));
+                let (values, result) = match backend.call_service(service, &args) {
+                    Ok(v) => (v, true),
+                    Err(e) => (Value::String(e.to_string()), false),
+                };
+                let mut resp = json!({
+                    "op": "service_response",
+                    "service": service,
+                    "values": values,
+                    "result": result,
+                });
+                if let Some(id) = id {
+                    resp["id"] = id.clone();
+                }
+                return vec![resp.to_string()];
+            },
+            _ => return vec![status("error", id, &format!("unsupported op {:?}", op))],
+        }
+        Vec::new()
+    }
+
+    // The publish frame for msg if the client subscribed to topic and its
+    // throttle rate allows one at now.
+    pub fn outgoing(&mut self, topic: &str, msg: &Value, now: Duration) -> Option<String> {
+        let sub = self.subscriptions.get_mut(topic)?;
+        if let Some(last) = sub.last {
+            if now < last + sub.throttle {
+                return None;
+            }
+        }
+        sub.last = Some(now);
+        let mut v = json!({ "op": "publish", "topic": topic, "msg": msg });
+        if let Some(ref id) = sub.id {
+            v["id"] = Value::String(id.clone());
+        }
+        Some(v.to_string())
+    }
+}
+
+struct Client {
+    ws: WebSocket<TcpStream>,
+    session: Session,
+    closed: bool,
+}
+
+type Clients = Arc<Mutex<Vec<Arc<Mutex<Client>>>>>;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+fn ws_error(e: tungstenite::Error) -> io::Error {
+    match e {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+// rosbridge over WebSocket, so web tools like Foxglove or roslibjs can
+// connect without a ROS install. Each client is served on its own thread.
+pub struct Server<B> {
+    listener: TcpListener,
+    backend: Arc<Mutex<B>>,
+    clients: Clients,
+    start: Instant,
+}
+
+impl<B: Backend + Send + 'static> Server<B> {
+    pub fn bind<A: ToSocketAddrs>(addr: A, backend: B) -> io::Result<Self> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            backend: Arc::new(Mutex::new(backend)),
+            clients: Arc::new(Mutex::new(Vec::new())),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn clients(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    // Blocks for the next connection, completes the handshake and serves
+    // the client in the background.
+    pub fn accept(&self) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        let ws = tungstenite::accept(stream)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        ws.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+        let client = Arc::new(Mutex::new(Client { ws, session: Session::new(), closed: false }));
+        self.clients.lock().unwrap().push(client.clone());
+        let backend = self.backend.clone();
+        let clients = self.clients.clone();
+        thread::spawn(move || {
+            serve(&client, &backend);
+            clients.lock().unwrap().retain(|c| !Arc::ptr_eq(c, &client));
+        });
+        Ok(())
+    }
+
+    // Sends msg to every client subscribed to topic, returning how many
+    // it went to.
+    pub fn publish(&self, topic: &str, msg: &Value) -> usize {
+        let now = self.start.elapsed();
+        let clients = self.clients.lock().unwrap().clone();
+        let mut sent = 0;
+        for client in clients {
+            let mut c = client.lock().unwrap();
+            if let Some(text) = c.session.outgoing(topic, msg, now) {
+                if c.ws.send(Message::Text(text)).is_ok() {
+                    sent += 1;
+                } else {
+                    c.closed = true;
+                }
+            }
+        }
+        sent
+    }
+}
+
+fn serve<B: Backend>(client: &Mutex<Client>, backend: &Mutex<B>) {
+    loop {
+        let mut c = client.lock().unwrap();
+        if c.closed {
+            return;
+        }
+        let text = match c.ws.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return,
+            Ok(_) => continue,
+            Err(e) => {
+                let e = ws_error(e);
+                match e.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                        drop(c);
+                        thread::yield_now();
+                        continue;
+                    },
+                    _ => return,
+                }
+            },
+        };
+        let replies = {
+            let mut backend = backend.lock().unwrap();
+            c.session.handle(&text, &mut *backend)
+        };
+        for reply in replies {
+            if c.ws.send(Message::Text(reply)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// JSON renderings of the ROS 2 message types, as rosbridge clients expect.
+
+fn header_json(h: &Header) -> Value {
+    json!({
+        "stamp": { "sec": h.stamp.as_secs(), "nanosec": h.stamp.subsec_nanos() },
+        "frame_id": h.frame_id,
+    })
+}
+
+fn vec3_json(v: &Vec3) -> Value {
+    json!({ "x": v.x, "y": v.y, "z": v.z })
+}
+
+fn quat_json(q: &Quaternion) -> Value {
+    json!({ "x": q.x, "y": q.y, "z": q.z, "w": q.w })
+}
+
+pub fn imu_json(m: &Imu) -> Value {
+    json!({
+        "header": header_json(&m.header),
+        "orientation": quat_json(&m.orientation),
+        "orientation_covariance": m.orientation_covariance.to_vec(),
+        "angular_velocity": vec3_json(&m.angular_velocity),
+        "angular_velocity_covariance": m.angular_velocity_covariance.to_vec(),
+        "linear_acceleration": vec3_json(&m.linear_acceleration),
+        "linear_acceleration_covariance": m.linear_acceleration_covariance.to_vec(),
+    })
+}
+
+pub fn odometry_json(m: &Odometry) -> Value {
+    json!({
+        "header": header_json(&m.header),
+        "child_frame_id": m.child_frame_id,
+        "pose": {
+            "pose": { "position": vec3_json(&m.position), "orientation": quat_json(&m.orientation) },
+            "covariance": m.pose_covariance.to_vec(),
+        },
+        "twist": {
+            "twist": { "linear": vec3_json(&m.twist.linear), "angular": vec3_json(&m.twist.angular) },
+            "covariance": m.twist_covariance.to_vec(),
+        },
+    })
+}
+
+pub fn battery_json(m: &BatteryState) -> Value {
+    json!({
+        "header": header_json(&m.header),
+        "voltage": m.voltage,
+        "current": m.current,
+        "percentage": m.percentage,
+        "present": m.present,
+    })
+}
+
+// A geometry_msgs/Twist published on cmd_vel; missing fields are zero.
+pub fn twist_from_json(msg: &Value) -> Option<Twist> {
+    if !msg.is_object() {
+        return None;
+    }
+    let f = |v: &Value| v.as_f64().unwrap_or(0.0);
+    Some(Twist::holonomic(f(&msg["linear"]["x"]), f(&msg["linear"]["y"]), f(&msg["angular"]["z"])))
+}
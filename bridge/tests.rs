@@ -0,0 +1,485 @@
+#![cfg(test)]
+
+use std::io;
+use std::time::Duration;
+use kinematics::Twist;
+use l2::imu::{ImuSample, Vec3};
+use l2::power;
+use nav::Pose2;
+use nav::odometry::PoseEstimate;
+use super::ros2::*;
+
+fn assert_near(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+}
+
+#[derive(Default)]
+struct Node {
+    imu: Vec<(String, Imu)>,
+}
+
+impl RosNode for Node {
+    fn publish_imu(&mut self, topic: &str, msg: &Imu) -> io::Result<()> {
+        self.imu.push((topic.to_string(), msg.clone()));
+        Ok(())
+    }
+
+    fn publish_odometry(&mut self, _: &str, _: &Odometry) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn publish_battery(&mut self, _: &str, _: &BatteryState) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn ros2_imu() {
+    let mut bridge = Ros2Bridge::new(Node::default());
+    let sample = ImuSample {
+        accel: Vec3::new(0.0, 0.0, 9.8),
+        gyro: Vec3::new(0.0, 0.0, 0.5),
+        ts: Duration::from_millis(3),
+    };
+    bridge.publish_imu(&sample, Duration::from_millis(10)).unwrap();
+    let (ref topic, ref m) = bridge.node().imu[0];
+    assert_eq!(topic, "imu");
+    assert_eq!(m.header.frame_id, "base_link");
+    assert_eq!(m.header.stamp, Duration::from_millis(10));
+    assert_eq!(m.orientation_covariance[0], -1.0);
+    assert_eq!(m.angular_velocity, sample.gyro);
+}
+
+#[test]
+fn ros2_odometry() {
+    let mut covariance = [[0.0; 3]; 3];
+    covariance[0][0] = 0.1;
+    covariance[1][1] = 0.2;
+    covariance[2][2] = 0.3;
+    covariance[0][2] = 0.05;
+    let estimate = PoseEstimate {
+        pose: Pose2::new(1.0, 2.0, ::std::f64::consts::FRAC_PI_2),
+        covariance,
+        ts: Duration::from_secs(1),
+    };
+    let m = odometry_msg(&estimate, &Twist::holonomic(0.5, 0.0, 0.1), "odom", "base_link");
+    assert_eq!(m.position, Vec3::new(1.0, 2.0, 0.0));
+    assert_near(m.orientation.yaw(), ::std::f64::consts::FRAC_PI_2);
+    assert_eq!(m.pose_covariance[0], 0.1);
+    assert_eq!(m.pose_covariance[7], 0.2);
+    assert_eq!(m.pose_covariance[35], 0.3);
+    assert_eq!(m.pose_covariance[5], 0.05);
+    assert_eq!(m.twist.angular.z, 0.1);
+}
+
+#[test]
+fn ros2_battery_and_cmd_vel() {
+    let state = power::BatteryState { voltage: 11.1, current: 2.0, soc: 80.0, ts: Duration::from_secs(2) };
+    let m = battery_msg(&state, "base_link");
+    assert_near(m.percentage as f64, 0.8);
+    assert_near(m.current as f64, -2.0);
+
+    let t = twist_from_msg(&TwistMsg { linear: Vec3::new(0.3, 0.0, 1.0), angular: Vec3::new(0.0, 0.0, -0.2) });
+    assert_eq!(t, Twist::holonomic(0.3, 0.0, -0.2));
+}
+
+#[test]
+fn mavlink_heartbeat_frame() {
+    use l2::mode::Mode;
+    use super::mavlink::*;
+
+    let mut enc = MavEncoder::new(1, 1);
+    let data = enc.encode(heartbeat(Mode::Teleop));
+    assert_eq!(data, vec![
+        0xfe, 0x09, 0x00, 0x01, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x0a, 0x00, 0xc1, 0x04, 0x03, 0x01, 0x23,
+    ]);
+    assert_eq!(enc.encode(heartbeat(Mode::Teleop))[2], 1);
+}
+
+#[test]
+fn mavlink_parse() {
+    use l2::imu::Quaternion;
+    use super::mavlink::*;
+
+    let msg = attitude(&Quaternion::from_yaw(1.0), &Vec3::new(0.0, 0.0, 0.5), 1234);
+    let mut data = vec![0x00, 0x55];
+    data.extend(MavEncoder::new(2, 3).encode(msg));
+    let mut parser = MavParser::new();
+    let frames: Vec<MavFrame> = data.iter().filter_map(|b| parser.parse(*b)).collect();
+    assert_eq!(frames, vec![MavFrame { seq: 0, sysid: 2, compid: 3, msg }]);
+
+    // MAVLink 2 manual control from a ground station, trailing zeros
+    // stripped from the payload.
+    let data = [
+        0xfd, 0x08, 0x00, 0x00, 0x07, 0xff, 0xbe, 0x45, 0x00, 0x00,
+        0xf4, 0x01, 0x00, 0x00, 0x00, 0x00, 0x06, 0xff, 0x4e, 0x45,
+    ];
+    let frame = data.iter().filter_map(|b| parser.parse(*b)).next().unwrap();
+    assert_eq!(frame.seq, 7);
+    assert_eq!(frame.msg, MavMessage::ManualControl { x: 500, y: 0, z: 0, r: -250, buttons: 0, target: 0 });
+    let t = twist_from_manual(&frame.msg, 2.0, 1.0).unwrap();
+    assert_eq!(t, Twist::holonomic(1.0, 0.0, 0.25));
+
+    let mut bad = data;
+    bad[12] ^= 1;
+    assert!(bad.iter().filter_map(|b| parser.parse(*b)).next().is_none());
+    assert_eq!(parser.errors(), 1);
+}
+
+#[test]
+fn mavlink_crc_offload() {
+    use l0::comm::{Crc, SoftCrc16};
+    use l2::mode::Mode;
+    use super::mavlink::*;
+
+    // Stands in for a peripheral, which is fed whole slices.
+    #[derive(Default)]
+    struct Peripheral {
+        crc: SoftCrc16,
+    }
+    impl Crc for Peripheral {
+        fn reset(&mut self) {
+            self.crc.reset();
+        }
+        fn update(&mut self, bytes: &[u8]) {
+            self.crc.update(bytes);
+        }
+        fn finish(&mut self) -> u16 {
+            self.crc.finish()
+        }
+    }
+
+    let mut enc = MavEncoder::with_crc(1, 1, Peripheral::default());
+    let data = enc.encode(heartbeat(Mode::Teleop));
+    assert_eq!(data, MavEncoder::new(1, 1).encode(heartbeat(Mode::Teleop)));
+    let mut parser = MavParser::with_crc(Peripheral::default());
+    assert!(data.iter().filter_map(|b| parser.parse(*b)).next().is_some());
+    assert_eq!(parser.errors(), 0);
+}
+
+#[test]
+fn firmata_adc() {
+    use l2::{Message, Sender};
+    use l2::adc::AdcMessage;
+    use super::firmata::*;
+
+    let mut adapter = FirmataAdapter::new(Vec::new(), 0x20);
+    assert!(adapter.feed(&[0xf9, 0x02, 0x05]).unwrap().is_empty());
+    assert_eq!(adapter.version(), Some((2, 5)));
+
+    adapter.send(AdcMessage::Read { channel: 2 }.to_packet(0x20)).unwrap();
+    assert_eq!(adapter.writer().split_off(0), vec![0xc2, 0x01]);
+    // reports from channels nobody asked for are dropped.
+    let pkts = adapter.feed(&[0xe1, 0x10, 0x00, 0xe2, 0x7f, 0x07]).unwrap();
+    assert_eq!(pkts, vec![AdcMessage::Sample { channel: 2, raw: 1023 }.to_packet(0x20)]);
+    assert_eq!(adapter.writer().split_off(0), vec![0xc2, 0x00]);
+
+    adapter.send(AdcMessage::StartSampling { mask: 0b11, rate_hz: 50 }.to_packet(0x20)).unwrap();
+    assert_eq!(adapter.writer().split_off(0), vec![0xf0, 0x7a, 0x14, 0x00, 0xf7, 0xc0, 0x01, 0xc1, 0x01]);
+    // sysex replies in between are skipped.
+    let pkts = adapter.feed(&[0xe0, 0x05, 0xf0, 0x79, 0x02, 0xf7, 0x00, 0xe1, 0x06, 0x00]).unwrap();
+    assert_eq!(pkts.len(), 1);
+    adapter.send(AdcMessage::StopSampling.to_packet(0x20)).unwrap();
+    assert_eq!(adapter.writer().split_off(0), vec![0xc0, 0x00, 0xc1, 0x00]);
+
+    assert!(adapter.send(AdcMessage::Read { channel: 0 }.to_packet(0x21)).is_err());
+}
+
+#[test]
+fn firmata_digital_and_pwm() {
+    use super::firmata::*;
+
+    let mut adapter = FirmataAdapter::new(Vec::new(), 0x20);
+    adapter.set_pin_mode(9, PinMode::Pwm).unwrap();
+    adapter.analog_write(9, 200).unwrap();
+    adapter.analog_write(20, 200).unwrap();
+    adapter.digital_write(13, true).unwrap();
+    assert_eq!(adapter.writer().split_off(0), vec![
+        0xf4, 0x09, 0x03, 0xe9, 0x48, 0x01,
+        0xf0, 0x6f, 0x14, 0x48, 0x01, 0x00, 0xf7,
+        0xf5, 0x0d, 0x01,
+    ]);
+
+    adapter.report_digital(10, true).unwrap();
+    assert_eq!(adapter.writer().split_off(0), vec![0xd1, 0x01]);
+    adapter.feed(&[0x91, 0x04, 0x00]).unwrap();
+    assert_eq!(adapter.digital_read(10), Some(true));
+    assert_eq!(adapter.digital_read(11), Some(false));
+}
+
+#[cfg(feature = "rosbridge")]
+#[derive(Default)]
+struct Services {
+    published: Vec<(String, ::serde_json::Value)>,
+}
+
+#[cfg(feature = "rosbridge")]
+impl super::rosbridge::Backend for Services {
+    fn publish(&mut self, topic: &str, msg: &::serde_json::Value) -> io::Result<()> {
+        self.published.push((topic.to_string(), msg.clone()));
+        Ok(())
+    }
+
+    fn call_service(&mut self, service: &str, args: &::serde_json::Value) -> io::Result<::serde_json::Value> {
+        match service {
+            "/set_mode" => Ok(json!({ "success": args["mode"] == "teleop" })),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "no such service")),
+        }
+    }
+}
+
+#[cfg(feature = "rosbridge")]
+#[test]
+fn rosbridge_session() {
+    use serde_json::{self, Value};
+    use super::rosbridge::*;
+
+    let mut backend = Services::default();
+    let mut session = Session::new();
+    let parse = |s: &str| serde_json::from_str::<Value>(s).unwrap();
+
+    let r = session.handle(r#"{"op":"publish","topic":"/cmd_vel","msg":{}}"#, &mut backend);
+    assert_eq!(parse(&r[0])["level"], "error");
+    session.handle(r#"{"op":"advertise","topic":"/cmd_vel","type":"geometry_msgs/Twist"}"#, &mut backend);
+    let r = session.handle(r#"{"op":"publish","topic":"/cmd_vel","msg":{"linear":{"x":0.5},"angular":{"z":-0.1}}}"#, &mut backend);
+    assert!(r.is_empty());
+    assert_eq!(twist_from_json(&backend.published[0].1), Some(Twist::holonomic(0.5, 0.0, -0.1)));
+
+    let r = session.handle(r#"{"op":"call_service","id":"c1","service":"/set_mode","args":{"mode":"teleop"}}"#, &mut backend);
+    let v = parse(&r[0]);
+    assert_eq!(v["id"], "c1");
+    assert_eq!(v["result"], true);
+    assert_eq!(v["values"]["success"], true);
+    let r = session.handle(r#"{"op":"call_service","service":"/nope"}"#, &mut backend);
+    assert_eq!(parse(&r[0])["result"], false);
+
+    let msg = battery_json(&battery_msg(
+        &power::BatteryState { voltage: 12.0, current: 1.0, soc: 50.0, ts: Duration::from_secs(3) }, "base_link"));
+    assert!(session.outgoing("/battery_state", &msg, Duration::from_secs(0)).is_none());
+    session.handle(r#"{"op":"subscribe","id":"s1","topic":"/battery_state","throttle_rate":100}"#, &mut backend);
+    let v = parse(&session.outgoing("/battery_state", &msg, Duration::from_millis(0)).unwrap());
+    assert_eq!(v["op"], "publish");
+    assert_eq!(v["id"], "s1");
+    assert_eq!(v["msg"]["percentage"], 0.5);
+    assert_eq!(v["msg"]["header"]["stamp"]["sec"], 3);
+    assert!(session.outgoing("/battery_state", &msg, Duration::from_millis(50)).is_none());
+    assert!(session.outgoing("/battery_state", &msg, Duration::from_millis(100)).is_some());
+
+    session.handle(r#"{"op":"unsubscribe","topic":"/battery_state"}"#, &mut backend);
+    assert!(!session.is_subscribed("/battery_state"));
+}
+
+#[cfg(feature = "rosbridge")]
+#[test]
+fn rosbridge_server() {
+    use std::thread;
+    use tungstenite::{self, Message};
+    use super::rosbridge::*;
+
+    let server = Server::bind("127.0.0.1:0", Services::default()).unwrap();
+    let url = format!("ws://{}", server.local_addr().unwrap());
+    let client = thread::spawn(move || {
+        let (mut ws, _) = tungstenite::connect(url).unwrap();
+        ws.send(Message::Text(r#"{"op":"subscribe","topic":"/odom"}"#.to_string())).unwrap();
+        ws.send(Message::Text(r#"{"op":"bogus"}"#.to_string())).unwrap();
+        let status = ws.read().unwrap().into_text().unwrap();
+        let msg = ws.read().unwrap().into_text().unwrap();
+        (status, msg)
+    });
+    server.accept().unwrap();
+    // the status reply shows the subscribe was handled.
+    while server.publish("/odom", &json!({ "x": 1 })) == 0 {
+        thread::sleep(Duration::from_millis(5));
+    }
+    let (status, msg) = client.join().unwrap();
+    assert!(status.contains("unsupported op"));
+    assert!(msg.contains("\"topic\":\"/odom\""));
+}
+
+#[cfg(feature = "homeassistant")]
+#[derive(Default)]
+struct Broker {
+    messages: Vec<(String, String, bool)>,
+}
+
+#[cfg(feature = "homeassistant")]
+impl super::homeassistant::MqttPublisher for Broker {
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> io::Result<()> {
+        self.messages.push((topic.to_string(), String::from_utf8(payload.to_vec()).unwrap(), retain));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "homeassistant")]
+#[test]
+fn homeassistant_discovery() {
+    use l0::comm::Packet;
+    use l2::Message;
+    use l2::mode::{Mode, ModeMessage};
+    use super::homeassistant::*;
+
+    let ha = HomeAssistant::new("rover1", "Rover")
+        .with_battery()
+        .with_mode(0x30)
+        .button("horn", "Horn", Packet::new_with(0, 0x40));
+    let mut broker = Broker::default();
+    ha.announce(&mut broker).unwrap();
+    assert_eq!(broker.messages.len(), 7);
+    assert!(broker.messages.iter().all(|m| m.2));
+    let (ref topic, ref payload, _) = broker.messages[0];
+    assert_eq!(topic, "homeassistant/sensor/rover1/battery/config");
+    let config: ::serde_json::Value = ::serde_json::from_str(payload).unwrap();
+    assert_eq!(config["state_topic"], "robo/rover1/battery/state");
+    assert_eq!(config["unit_of_measurement"], "%");
+    assert_eq!(config["unique_id"], "rover1_battery");
+    assert_eq!(broker.messages[6], ("robo/rover1/availability".to_string(), "online".to_string(), true));
+
+    broker.messages.clear();
+    ha.publish_battery(&mut broker, &power::BatteryState { voltage: 11.84, current: 1.5, soc: 76.4, ts: Duration::from_secs(0) }).unwrap();
+    ha.publish_mode(&mut broker, Mode::Teleop).unwrap();
+    let values: Vec<&str> = broker.messages.iter().map(|m| m.1.as_str()).collect();
+    assert_eq!(values, vec!["76", "11.84", "1.50", "teleop"]);
+
+    assert_eq!(ha.command_topics(), vec!["robo/rover1/mode_request/set", "robo/rover1/horn/set"]);
+    let mut sent = Vec::new();
+    assert!(ha.handle_command("robo/rover1/mode_request/set", b"autonomous", &mut sent).unwrap());
+    assert!(ha.handle_command("robo/rover1/horn/set", b"PRESS", &mut sent).unwrap());
+    assert!(!ha.handle_command("robo/rover1/mode_request/set", b"flying", &mut sent).unwrap());
+    assert!(!ha.handle_command("robo/rover1/battery/set", b"PRESS", &mut sent).unwrap());
+    assert_eq!(sent, vec![
+        ModeMessage::Request { mode: Mode::Autonomous }.to_packet(0x30),
+        Packet::new_with(0, 0x40),
+    ]);
+}
+
+#[test]
+fn rerun_sink() {
+    use l2::camera::{Frame, ImageFormat};
+    use l2::lidar::LaserScan;
+    use super::rerun::*;
+
+    #[derive(Default)]
+    struct Logger {
+        logged: Vec<(String, Duration, String)>,
+    }
+
+    impl RerunLogger for Logger {
+        fn log(&mut self, entity: &str, time: Duration, data: Archetype) -> io::Result<()> {
+            let summary = match data {
+                Archetype::Points3D { ref positions } => format!("{} points", positions.len()),
+                Archetype::Image { width, height, .. } => format!("{}x{}", width, height),
+                ref other => format!("{:?}", other),
+            };
+            self.logged.push((entity.to_string(), time, summary));
+            Ok(())
+        }
+    }
+
+    let mut sink = RerunSink::new(Logger::default());
+    let estimate = PoseEstimate {
+        pose: Pose2::new(1.0, 2.0, 0.0),
+        covariance: [[0.0; 3]; 3],
+        ts: Duration::from_secs(1),
+    };
+    sink.log_pose(&estimate).unwrap();
+    let scan = LaserScan {
+        seq: 1,
+        angle_min: 0.0,
+        angle_increment: ::std::f64::consts::FRAC_PI_2,
+        ranges: vec![1.0, f64::NAN, 2.0],
+        ts: Duration::from_secs(2),
+        period: None,
+    };
+    sink.log_scan(&scan).unwrap();
+    let frame = Frame {
+        seq: 1,
+        format: ImageFormat::Grey8,
+        width: 4,
+        height: 2,
+        device_ts: Duration::from_secs(0),
+        ts: Duration::from_secs(3),
+        data: vec![0; 8],
+    };
+    sink.log_frame(&frame).unwrap();
+    sink.log_imu(&ImuSample { accel: Vec3::new(0.0, 0.0, 9.8), gyro: Vec3::new(0.0, 0.0, 0.0), ts: Duration::from_secs(0) },
+        Duration::from_secs(4)).unwrap();
+
+    let logged = &sink.logger().logged;
+    assert_eq!(logged[0].0, "world/robot");
+    assert_eq!(logged[0].2, "Transform3D { translation: [1.0, 2.0, 0.0], rotation_xyzw: [0.0, 0.0, 0.0, 1.0] }");
+    assert_eq!(logged[1], ("world/robot/scan".to_string(), Duration::from_secs(2), "2 points".to_string()));
+    assert_eq!(logged[2], ("world/robot/camera".to_string(), Duration::from_secs(3), "4x2".to_string()));
+    assert_eq!(logged[5], ("imu/accel/z".to_string(), Duration::from_secs(4), "Scalar(9.8)".to_string()));
+}
+
+#[cfg(feature = "web")]
+fn http(addr: ::std::net::SocketAddr, request: &str) -> String {
+    use std::io::{Read, Write};
+    let mut s = ::std::net::TcpStream::connect(addr).unwrap();
+    s.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    s.write_all(request.as_bytes()).unwrap();
+    let mut resp = Vec::new();
+    let mut buf = [0u8; 1024];
+    // event streams never end, so stop once the first event is in.
+    while let Ok(n) = s.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        resp.extend_from_slice(&buf[..n]);
+        if resp.ends_with(b"\n\n") && resp.windows(5).any(|w| w == b"data:") {
+            break;
+        }
+    }
+    String::from_utf8_lossy(&resp).into_owned()
+}
+
+#[cfg(feature = "web")]
+#[test]
+fn web_dashboard() {
+    use std::sync::Arc;
+    use l0::comm::Packet;
+    use l2::schema::{MessageSet, Schema};
+    use super::web::WebDashboard;
+
+    let dash = Arc::new(WebDashboard::new().with_schema(Schema::new().with(0x02, MessageSet::Mode)));
+    let addr = WebDashboard::serve(dash.clone(), "127.0.0.1:0").unwrap();
+    dash.publish_packet(&Packet { seq: 3, code: 0x81, data: vec![1, 2] });
+
+    let page = http(addr, "GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\n") && page.contains("<title>robo</title>"));
+
+    let events = http(addr, "GET /events HTTP/1.1\r\nHost: x\r\n\r\n");
+    assert!(events.contains("text/event-stream"), "{}", events);
+    assert!(events.contains(r#"data: {"topic":"0x81","value":{"code":129,"data":"0102","decoded":null,"seq":3}}"#),
+        "{}", events);
+
+    let post = |body: &str| http(addr, &format!(
+        "POST /command HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}", body.len(), body));
+    assert!(post(r#"{"code": 1, "data": "0164"}"#).starts_with("HTTP/1.1 204"));
+    assert!(post(r#"{"code": 2, "data": [255]}"#).starts_with("HTTP/1.1 400"));
+    assert!(post(r#"{"code": 3, "data": "zz"}"#).starts_with("HTTP/1.1 400"));
+    assert_eq!(dash.take_commands(), vec![Packet { seq: 0, code: 1, data: vec![1, 0x64] }]);
+    assert!(dash.take_commands().is_empty());
+    assert!(dash.take_error().is_none());
+
+    #[cfg(feature = "rosbridge")]
+    {
+        use tungstenite::Message;
+        let stream = ::std::net::TcpStream::connect(addr).unwrap();
+        let (mut ws, _) = ::tungstenite::client(format!("ws://{}/ws", addr), stream).unwrap();
+        assert!(ws.read().unwrap().to_text().unwrap().starts_with(r#"{"topic":"0x81""#));
+        ws.send(Message::text(r#"{"code": 5, "data": [1]}"#)).unwrap();
+        dash.publish("battery", json!(0.5));
+        assert_eq!(ws.read().unwrap().to_text().unwrap(), r#"{"topic":"battery","value":0.5}"#);
+        let mut commands = Vec::new();
+        for _ in 0..100 {
+            commands.extend(dash.take_commands());
+            if !commands.is_empty() {
+                break;
+            }
+            ::std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(commands, vec![Packet { seq: 0, code: 5, data: vec![1] }]);
+    }
+}
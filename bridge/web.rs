@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::thread;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use futures_util::future::{self, Future, FutureExt, Ready};
+use futures_util::{Stream, StreamExt};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use l0::comm::{LinkStats, Packet, PACKET_MAX_DATA_LEN};
+use l2::schema::Schema;
+
+const INDEX: &str = include_str!("web.html");
+
+// Telemetry feed of one client, starting with the latest value of every
+// topic.
+struct Feed(mpsc::UnboundedReceiver<String>);
+
+impl Stream for Feed {
+    type Item = String;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<String>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+// A browser dashboard: GET / serves the page, /events streams telemetry
+// as server-sent events and /ws as WebSocket text frames. Commands come in
+// as JSON, {"code": 1, "data": "0164"}, posted to /command or sent over the
+// WebSocket, and wait in a queue until the application takes them.
+//
+// Telemetry is published as {"topic": ..., "value": ...} events; there is
+// no hub in the tree to subscribe to, so the application publishes what it
+// wants shown.
+pub struct WebDashboard {
+    schema: Schema,
+    latest: Mutex<BTreeMap<String, String>>,
+    clients: Mutex<Vec<mpsc::UnboundedSender<String>>>,
+    commands: Mutex<Vec<Packet>>,
+    error: Mutex<Option<io::Error>>,
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_command(text: &str) -> Option<Packet> {
+    let v: Value = serde_json::from_str(text).ok()?;
+    let code = v["code"].as_u64().filter(|c| *c <= 0xff)? as u8;
+    let data: Vec<u8> = match v["data"] {
+        Value::Null => Vec::new(),
+        Value::String(ref s) if s.is_ascii() && s.len() % 2 == 0 => (0..s.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+            .collect::<Option<_>>()?,
+        Value::Array(ref a) => a.iter()
+            .map(|b| b.as_u64().filter(|b| *b <= 0xff).map(|b| b as u8))
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    if data.len() > PACKET_MAX_DATA_LEN {
+        return None;
+    }
+    let mut pkt = Packet::new_with(0, code);
+    pkt.data = data;
+    Some(pkt)
+}
+
+impl Default for WebDashboard {
+    fn default() -> Self {
+        WebDashboard::new()
+    }
+}
+
+impl WebDashboard {
+    pub fn new() -> Self {
+        WebDashboard {
+            schema: Schema::new(),
+            latest: Mutex::new(BTreeMap::new()),
+            clients: Mutex::new(Vec::new()),
+            commands: Mutex::new(Vec::new()),
+            error: Mutex::new(None),
+        }
+    }
+
+    // Decodes published packets and validates commands.
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn publish(&self, topic: &str, value: Value) {
+        let event = json!({ "topic": topic, "value": value }).to_string();
+        self.latest.lock().unwrap().insert(topic.to_string(), event.clone());
+        self.clients.lock().unwrap().retain(|c| c.send(event.clone()).is_ok());
+    }
+
+    // Publishes under the name of the packet's message set, or its code.
+    pub fn publish_packet(&self, pkt: &Packet) {
+        let topic = match self.schema.get(pkt.code) {
+            Some(set) => set.name().to_string(),
+            None => format!("0x{:02x}", pkt.code),
+        };
+        self.publish(&topic, json!({
+            "seq": pkt.seq,
+            "code": pkt.code,
+            "data": hex(&pkt.data),
+            "decoded": self.schema.describe(pkt),
+        }));
+    }
+
+    pub fn publish_link(&self, device: &str, stats: &LinkStats) {
+        self.publish(&format!("link/{}", device), json!({
+            "packets_sent": stats.packets_sent,
+            "packets_received": stats.packets_received,
+            "bytes_sent": stats.bytes_sent,
+            "bytes_received": stats.bytes_received,
+            "resyncs": stats.resyncs,
+            "overflows": stats.overflows,
+        }));
+    }
+
+    // Commands received since the last call, oldest first.
+    pub fn take_commands(&self) -> Vec<Packet> {
+        self.commands.lock().unwrap().drain(..).collect()
+    }
+
+    fn command(&self, text: &str) -> bool {
+        match parse_command(text) {
+            Some(pkt) if self.schema.validate(&pkt).is_ok() => {
+                self.commands.lock().unwrap().push(pkt);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn subscribe(&self) -> Feed {
+        let (tx, rx) = mpsc::unbounded_channel();
+        for event in self.latest.lock().unwrap().values() {
+            let _ = tx.send(event.clone());
+        }
+        self.clients.lock().unwrap().push(tx);
+        Feed(rx)
+    }
+
+    // Serves the dashboard from a background thread, returning the bound
+    // address. Should the server stop, its error is kept for take_error.
+    pub fn serve<A: ToSocketAddrs>(dashboard: Arc<WebDashboard>, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let rt = tokio::runtime::Builder::new_current_thread().enable_io().enable_time().build()?;
+        let listener = {
+            let _ctx = rt.enter();
+            tokio::net::TcpListener::from_std(listener)?
+        };
+        let app = Router::new()
+            .route("/", get(index))
+            .route("/events", get(events))
+            .route("/ws", get(websocket))
+            .route("/command", post(command))
+            .with_state(dashboard.clone());
+        thread::spawn(move || {
+            if let Err(e) = rt.block_on(IntoFuture::into_future(axum::serve(listener, app))) {
+                *dashboard.error.lock().unwrap() = Some(e);
+            }
+        });
+        Ok(local)
+    }
+
+    // The error the server stopped on, if it did.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+type Shared = State<Arc<WebDashboard>>;
+
+fn index() -> Ready<Html<&'static str>> {
+    future::ready(Html(INDEX))
+}
+
+fn events(State(dash): Shared) -> Ready<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let feed = dash.subscribe().map(|e| Ok(Event::default().data(e)));
+    future::ready(Sse::new(feed).keep_alive(KeepAlive::default()))
+}
+
+fn command(State(dash): Shared, body: String) -> Ready<StatusCode> {
+    future::ready(if dash.command(&body) { StatusCode::NO_CONTENT } else { StatusCode::BAD_REQUEST })
+}
+
+fn websocket(State(dash): Shared, ws: WebSocketUpgrade) -> Ready<Response> {
+    future::ready(ws.on_upgrade(move |socket| client(dash, socket)))
+}
+
+fn client(dash: Arc<WebDashboard>, socket: WebSocket) -> impl Future<Output = ()> {
+    let (sink, stream) = socket.split();
+    let outgoing = dash.subscribe().map(|e| Ok(Message::Text(e))).forward(sink);
+    let incoming = stream
+        .take_while(|m| future::ready(matches!(m, Ok(ref m) if !matches!(m, Message::Close(_)))))
+        .for_each(move |m| {
+            if let Ok(Message::Text(text)) = m {
+                dash.command(&text);
+            }
+            future::ready(())
+        });
+    future::select(outgoing, incoming).map(|_| ())
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use l0::comm::*;
+
+// The checks a peer implementation of the l0 protocol should pass. Each
+// starts from a fresh handshake, so they can run in any order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    Handshake,       // answers a sync request with an ack.
+    Resync,          // answers another sync request once synchronized.
+    SkipsGarbage,    // ignores junk before a sync request.
+    Packets,         // takes every length form and the seq wrap silently.
+    InvalidSeq,      // asks for a resync after a skipped seq.
+    InvalidLength,   // asks for a resync on a length byte >= 0x80.
+    ReceiveTimeout,  // asks for a resync when a packet stops midway.
+}
+
+impl Scenario {
+    pub const ALL: &'static [Scenario] = &[
+        Scenario::Handshake,
+        Scenario::Resync,
+        Scenario::SkipsGarbage,
+        Scenario::Packets,
+        Scenario::InvalidSeq,
+        Scenario::InvalidLength,
+        Scenario::ReceiveTimeout,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::Handshake => "handshake",
+            Scenario::Resync => "resync",
+            Scenario::SkipsGarbage => "skips_garbage",
+            Scenario::Packets => "packets",
+            Scenario::InvalidSeq => "invalid_seq",
+            Scenario::InvalidLength => "invalid_length",
+            Scenario::ReceiveTimeout => "receive_timeout",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub scenario: Scenario,
+    pub failure: Option<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.outcomes.iter().all(|o| o.passed())
+    }
+
+    pub fn get(&self, scenario: Scenario) -> Option<&Outcome> {
+        self.outcomes.iter().find(|o| o.scenario == scenario)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for o in &self.outcomes {
+            match o.failure {
+                None => writeln!(f, "PASS {}", o.scenario.name())?,
+                Some(ref why) => writeln!(f, "FAIL {}: {}", o.scenario.name(), why)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// What the peer did, as seen by the harness parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Seen {
+    Request,
+    Ack,
+    Packet,
+    Invalid(u8), // a byte that broke the framing of what the peer sent.
+}
+
+type Pump = Box<dyn FnMut()>;
+
+// Runs scenarios against a peer at the other end of transport, playing the
+// host side with raw bytes. Reads may fail with WouldBlock or TimedOut when
+// nothing arrived. An in-process peer is driven by the pump, which is
+// called whenever the harness waits for it.
+pub struct Harness<T> {
+    transport: T,
+    parser: Parser,
+    seq: PacketSeq,
+    last: u8, // the previous byte from the peer.
+    timeout: Duration,
+    pump: Option<Pump>,
+}
+
+fn fail<T>(msg: String) -> Result<T, String> {
+    Err(msg)
+}
+
+impl<T: Read + Write> Harness<T> {
+    pub fn new(transport: T) -> Self {
+        Harness {
+            transport,
+            parser: Parser::new(),
+            seq: 1,
+            last: 0,
+            timeout: Duration::from_millis(500),
+            pump: None,
+        }
+    }
+
+    // How long to wait for a response, and for silence where none is
+    // expected. Must exceed the peer's receive timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_pump<F: FnMut() + 'static>(mut self, pump: F) -> Self {
+        self.pump = Some(Box::new(pump));
+        self
+    }
+
+    pub fn run(&mut self, scenarios: &[Scenario]) -> io::Result<Report> {
+        let mut report = Report::default();
+        for s in scenarios {
+            let failure = self.scenario(*s)?.err();
+            report.outcomes.push(Outcome { scenario: *s, failure });
+        }
+        Ok(report)
+    }
+
+    pub fn run_all(&mut self) -> io::Result<Report> {
+        self.run(Scenario::ALL)
+    }
+
+    // The outer result carries transport errors, the inner one the failure.
+    fn scenario(&mut self, scenario: Scenario) -> io::Result<Result<(), String>> {
+        self.seq = if scenario == Scenario::Packets { 0xec } else { 1 };
+        match scenario {
+            Scenario::Handshake => self.handshake(),
+            Scenario::Resync => {
+                if let Err(why) = self.handshake()? {
+                    return Ok(Err(why));
+                }
+                self.seq = 0x40;
+                self.handshake()
+            },
+            Scenario::SkipsGarbage => {
+                self.write(&[0x01, 0x02, 0x80, 0xf0, 0x55])?;
+                self.handshake()
+            },
+            Scenario::Packets => {
+                if let Err(why) = self.handshake()? {
+                    return Ok(Err(why));
+                }
+                for len in &[0usize, 1, 6, 7, 20, PACKET_MAX_DATA_LEN] {
+                    for code in &[0x01u8, 0x81] {
+                        let mut pkt = Packet::new_with(self.seq, *code);
+                        pkt.data = (0..*len as u8).collect();
+                        pkt.encode(&mut self.transport)?;
+                        self.seq = self.seq.next();
+                    }
+                }
+                self.expect_silence()
+            },
+            Scenario::InvalidSeq => self.expect_resync(|h| vec![h.seq.next(), 0x01]),
+            Scenario::InvalidLength => self.expect_resync(|h| vec![h.seq, 0x71, 0x80]),
+            Scenario::ReceiveTimeout => self.expect_resync(|h| vec![h.seq, 0x31, 0x01]),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.transport.write_all(bytes)?;
+        self.transport.flush()
+    }
+
+    // Reads what the peer sent, answering its sync requests.
+    fn read(&mut self) -> io::Result<Vec<Seen>> {
+        if let Some(ref mut pump) = self.pump {
+            pump();
+        } else {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let mut seen = Vec::new();
+        let mut buf = [0u8; 64];
+        loop {
+            let n = match self.transport.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "peer closed the link")),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e),
+            };
+            for b in &buf[..n] {
+                let r = self.parser.parse(*b);
+                if r.sync == SYNC_ACK {
+                    seen.push(Seen::Request);
+                    let seq = self.seq;
+                    self.write(&[SYNC_ACK, seq])?;
+                } else if r.sync == SYNC_REQ {
+                    seen.push(Seen::Invalid(*b));
+                } else if r.packet.is_some() {
+                    seen.push(Seen::Packet);
+                } else if self.last == SYNC_ACK && r.state.is_ready() && !r.state.is_receiving() {
+                    seen.push(Seen::Ack);
+                }
+                self.last = *b;
+            }
+        }
+        Ok(seen)
+    }
+
+    // Waits until the peer does what pred looks for.
+    fn wait_for<F: Fn(&Seen) -> bool>(&mut self, pred: F) -> io::Result<Option<Seen>> {
+        let deadline = Instant::now() + self.timeout;
+        while Instant::now() < deadline {
+            let seen = self.read()?;
+            if let Some(s) = seen.into_iter().find(|s| pred(s) || matches!(s, Seen::Invalid(_))) {
+                return Ok(Some(s));
+            }
+        }
+        Ok(None)
+    }
+
+    fn handshake(&mut self) -> io::Result<Result<(), String>> {
+        self.parser.reset();
+        let seq = self.seq;
+        self.write(&[SYNC_REQ, seq])?;
+        Ok(match self.wait_for(|s| *s == Seen::Ack)? {
+            Some(Seen::Ack) => Ok(()),
+            Some(Seen::Invalid(b)) => fail(format!("peer sent 0x{:02x} out of place during the handshake", b)),
+            _ => fail(format!("no sync ack within {:?}", self.timeout)),
+        })
+    }
+
+    fn expect_silence(&mut self) -> io::Result<Result<(), String>> {
+        Ok(match self.wait_for(|s| *s == Seen::Request)? {
+            None => Ok(()),
+            Some(Seen::Invalid(b)) => fail(format!("peer sent 0x{:02x} out of place", b)),
+            Some(_) => fail("peer asked for a resync".to_string()),
+        })
+    }
+
+    fn expect_resync<F: Fn(&Self) -> Vec<u8>>(&mut self, bytes: F) -> io::Result<Result<(), String>> {
+        if let Err(why) = self.handshake()? {
+            return Ok(Err(why));
+        }
+        let bytes = bytes(self);
+        self.write(&bytes)?;
+        Ok(match self.wait_for(|s| *s == Seen::Request)? {
+            Some(Seen::Request) => Ok(()),
+            Some(Seen::Invalid(b)) => fail(format!("peer sent 0x{:02x} out of place", b)),
+            _ => fail(format!("no sync request within {:?}", self.timeout)),
+        })
+    }
+}
@@ -0,0 +1,6 @@
+mod harness;
+
+pub use self::harness::*;
+
+#[cfg(test)]
+mod tests;
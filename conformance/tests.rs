@@ -0,0 +1,31 @@
+#![cfg(test)]
+
+use std::time::Duration;
+use l0::comm::Endpoint;
+use sim::Loopback;
+use super::*;
+
+#[test]
+fn endpoint_conformance() {
+    let (host, dev) = Loopback::pair();
+    let mut peer = Endpoint::named(dev, "peer").unwrap();
+    let mut h = Harness::new(host)
+        .with_timeout(Duration::from_millis(20))
+        .with_pump(move || { peer.poll().unwrap(); });
+    let report = h.run_all().unwrap();
+    for s in Scenario::ALL {
+        // Endpoint leaves receive timeouts to its owner.
+        assert_eq!(report.get(*s).unwrap().passed(), *s != Scenario::ReceiveTimeout, "{}", report);
+    }
+    assert!(report.to_string().contains("PASS handshake\n"));
+    assert!(report.to_string().contains("FAIL receive_timeout: no sync request within 20ms\n"));
+}
+
+#[test]
+fn silent_peer_fails() {
+    let (host, _dev) = Loopback::pair();
+    let mut h = Harness::new(host).with_timeout(Duration::from_millis(5));
+    let report = h.run(&[Scenario::Handshake, Scenario::Packets]).unwrap();
+    assert!(!report.passed());
+    assert_eq!(report.outcomes[1].failure.as_ref().unwrap(), "no sync ack within 5ms");
+}
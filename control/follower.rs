@@ -0,0 +1,106 @@
+use std::io;
+use kinematics::{DriveClient, DriveKinematics, Twist};
+use l2::Sender;
+use nav::{Point2, Pose2};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowerEvent {
+    WaypointReached(usize),
+    Completed,
+}
+
+// Pure-pursuit follower: steers towards the first waypoint farther than the
+// lookahead distance, on the arc through the current pose.
+pub struct Follower {
+    waypoints: Vec<Point2>,
+    next: usize,
+    lookahead: f64,
+    speed: f64,
+    max_angular: f64,
+    tolerance: f64,
+    completed: bool,
+}
+
+impl Follower {
+    pub fn new(waypoints: Vec<Point2>, lookahead: f64, speed: f64) -> Self {
+        Follower {
+            completed: waypoints.is_empty(),
+            waypoints,
+            next: 0,
+            lookahead,
+            speed,
+            max_angular: f64::INFINITY,
+            tolerance: lookahead / 4.0,
+        }
+    }
+
+    pub fn with_max_angular(mut self, max_angular: f64) -> Self {
+        self.max_angular = max_angular;
+        self
+    }
+
+    // Distance to the final waypoint at which the path is complete.
+    pub fn with_goal_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn waypoints(&self) -> &[Point2] {
+        &self.waypoints
+    }
+
+    // Fraction of waypoints reached.
+    pub fn progress(&self) -> f64 {
+        if self.waypoints.is_empty() {
+            1.0
+        } else {
+            self.next as f64 / self.waypoints.len() as f64
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    // Computes the twist for the current pose, and the events since the last
+    // update. A zero twist is returned once completed.
+    pub fn update(&mut self, pose: &Pose2) -> (Twist, Vec<FollowerEvent>) {
+        let mut events = Vec::new();
+        if self.completed {
+            return (Twist::zero(), events);
+        }
+        let here = pose.position();
+        let last = self.waypoints.len() - 1;
+        while self.next < last && here.distance(&self.waypoints[self.next]) <= self.lookahead {
+            events.push(FollowerEvent::WaypointReached(self.next));
+            self.next += 1;
+        }
+        let goal_dist = here.distance(&self.waypoints[last]);
+        if self.next == last && goal_dist <= self.tolerance {
+            events.push(FollowerEvent::WaypointReached(last));
+            events.push(FollowerEvent::Completed);
+            self.next = self.waypoints.len();
+            self.completed = true;
+            return (Twist::zero(), events);
+        }
+
+        let target = pose.to_local(&self.waypoints[self.next]);
+        let d2 = target.x * target.x + target.y * target.y;
+        let curvature = if d2 > 0.0 { 2.0 * target.y / d2 } else { 0.0 };
+        let speed = self.speed * (goal_dist / self.lookahead).min(1.0);
+        let omega = (speed * curvature).max(-self.max_angular).min(self.max_angular);
+        (Twist::new(speed, omega), events)
+    }
+
+    // Updates and sends the resulting twist through the drive client.
+    pub fn drive<K, S>(&mut self, pose: &Pose2, client: &mut DriveClient<K, S>) -> io::Result<Vec<FollowerEvent>>
+        where K: DriveKinematics, S: Sender {
+        let (twist, events) = self.update(pose);
+        if self.completed {
+            client.stop()?;
+        } else {
+            client.drive(&twist)?;
+        }
+        Ok(events)
+    }
+}
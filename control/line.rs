@@ -0,0 +1,45 @@
+use std::io;
+use std::time::Duration;
+use kinematics::{DriveClient, DriveKinematics, Twist};
+use l2::Sender;
+use l2::line::{LineEstimator, LineMessage};
+use super::pid::Pid;
+
+// Steers along a line with a PID on the estimated line position; the PID
+// output is the angular velocity. When the line is lost the robot keeps
+// turning towards the side it was last seen on.
+pub struct LineFollower {
+    estimator: LineEstimator,
+    pid: Pid,
+    speed: f64,
+}
+
+impl LineFollower {
+    pub fn new(estimator: LineEstimator, pid: Pid, speed: f64) -> Self {
+        let mut pid = pid;
+        pid.set_setpoint(0.0);
+        LineFollower { estimator, pid, speed }
+    }
+
+    pub fn estimator(&self) -> &LineEstimator {
+        &self.estimator
+    }
+
+    pub fn update(&mut self, msg: &LineMessage, dt: Duration) -> Twist {
+        let pos = match self.estimator.update(msg) {
+            Some(pos) => pos,
+            None => match self.estimator.last() {
+                Some(last) => last.signum(),
+                None => return Twist::zero(),
+            },
+        };
+        // a line to the right (positive) needs a clockwise (negative) turn.
+        Twist::new(self.speed, self.pid.update(pos, dt))
+    }
+
+    pub fn drive<K, S>(&mut self, msg: &LineMessage, dt: Duration, client: &mut DriveClient<K, S>) -> io::Result<()>
+        where K: DriveKinematics, S: Sender {
+        let twist = self.update(msg, dt);
+        client.drive(&twist)
+    }
+}
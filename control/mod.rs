@@ -0,0 +1,7 @@
+pub mod follower;
+pub mod line;
+pub mod pid;
+pub mod ramp;
+
+#[cfg(test)]
+mod tests;
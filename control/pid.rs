@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PidGains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+}
+
+impl PidGains {
+    pub fn new(kp: f64, ki: f64, kd: f64) -> Self {
+        PidGains { kp, ki, kd }
+    }
+}
+
+// Discrete PID controller holding its own setpoint, so it can be fed
+// directly from telemetry callbacks via update().
+//
+// The derivative acts on the measurement (no kick on setpoint changes) and
+// is low-pass filtered with time constant d_filter. The integral is kept in
+// output units, which makes gain changes bumpless, and stops accumulating
+// while the output is saturated (anti-windup).
+#[derive(Debug, Clone)]
+pub struct Pid {
+    gains: PidGains,
+    setpoint: f64,
+    out_min: f64,
+    out_max: f64,
+    d_filter: f64,
+    integral: f64,
+    derivative: f64,
+    last: Option<(f64, f64)>, // (error, measurement)
+}
+
+impl Pid {
+    pub fn new(gains: PidGains) -> Self {
+        Pid {
+            gains,
+            setpoint: 0.0,
+            out_min: f64::NEG_INFINITY,
+            out_max: f64::INFINITY,
+            d_filter: 0.0,
+            integral: 0.0,
+            derivative: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn with_output_limits(mut self, min: f64, max: f64) -> Self {
+        self.out_min = min;
+        self.out_max = max;
+        self
+    }
+
+    // Derivative low-pass time constant in seconds, 0 disables filtering.
+    pub fn with_derivative_filter(mut self, tau: f64) -> Self {
+        self.d_filter = tau;
+        self
+    }
+
+    pub fn gains(&self) -> &PidGains {
+        &self.gains
+    }
+
+    // Changes gains without a step in the output: the integral absorbs the
+    // difference in proportional and derivative contributions.
+    pub fn set_gains(&mut self, gains: PidGains) {
+        if let Some((error, _)) = self.last {
+            let before = self.gains.kp * error - self.gains.kd * self.derivative;
+            let after = gains.kp * error - gains.kd * self.derivative;
+            self.integral += before - after;
+        }
+        self.gains = gains;
+    }
+
+    pub fn setpoint(&self) -> f64 {
+        self.setpoint
+    }
+
+    pub fn set_setpoint(&mut self, setpoint: f64) {
+        self.setpoint = setpoint;
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.derivative = 0.0;
+        self.last = None;
+    }
+
+    // Advances the controller by dt with a new measurement, returns the
+    // clamped output.
+    pub fn update(&mut self, measurement: f64, dt: Duration) -> f64 {
+        let dt = dt.as_secs_f64();
+        let error = self.setpoint - measurement;
+        if let Some((_, last_measurement)) = self.last {
+            if dt > 0.0 {
+                let raw = (measurement - last_measurement) / dt;
+                let alpha = dt / (self.d_filter + dt);
+                self.derivative += alpha * (raw - self.derivative);
+            }
+        }
+        self.last = Some((error, measurement));
+
+        let pd = self.gains.kp * error - self.gains.kd * self.derivative;
+        let mut integral = self.integral + self.gains.ki * error * dt;
+        // while saturated, the integral may only shrink towards the limit.
+        if pd + integral > self.out_max {
+            integral = integral.min(self.integral.max(self.out_max - pd));
+        } else if pd + integral < self.out_min {
+            integral = integral.max(self.integral.min(self.out_min - pd));
+        }
+        self.integral = integral;
+        (pd + integral).max(self.out_min).min(self.out_max)
+    }
+}
@@ -0,0 +1,96 @@
+use std::io;
+use std::time::Duration;
+use kinematics::{DriveClient, DriveKinematics, Twist};
+use l2::Sender;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisLimits {
+    pub max_accel: f64, // units per second squared
+    pub max_jerk: f64,  // units per second cubed
+}
+
+impl AxisLimits {
+    pub fn new(max_accel: f64, max_jerk: f64) -> Self {
+        AxisLimits { max_accel, max_jerk }
+    }
+
+    pub fn unlimited() -> Self {
+        AxisLimits::new(f64::INFINITY, f64::INFINITY)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    limits: AxisLimits,
+    value: f64,
+    accel: f64,
+}
+
+impl Axis {
+    fn new(limits: AxisLimits) -> Self {
+        Axis { limits, value: 0.0, accel: 0.0 }
+    }
+
+    fn step(&mut self, target: f64, dt: f64) -> f64 {
+        let err = target - self.value;
+        if dt <= 0.0 || err == 0.0 {
+            self.accel = 0.0;
+            return self.value;
+        }
+        // the fastest acceleration that still allows easing into the target
+        // within the jerk limit.
+        let want = (err.abs() / dt)
+            .min(self.limits.max_accel)
+            .min((2.0 * self.limits.max_jerk * err.abs()).sqrt())
+            .copysign(err);
+        let jerk = self.limits.max_jerk * dt;
+        self.accel += (want - self.accel).max(-jerk).min(jerk);
+        self.value += self.accel * dt;
+        if (target - self.value) * err <= 0.0 {
+            self.value = target;
+            self.accel = 0.0;
+        }
+        self.value
+    }
+}
+
+// Slews a commanded twist towards the requested one within per-axis
+// acceleration and jerk limits.
+pub struct Ramp {
+    axes: [Axis; 3],
+}
+
+impl Ramp {
+    pub fn new(vx: AxisLimits, vy: AxisLimits, omega: AxisLimits) -> Self {
+        Ramp { axes: [Axis::new(vx), Axis::new(vy), Axis::new(omega)] }
+    }
+
+    pub fn current(&self) -> Twist {
+        Twist::holonomic(self.axes[0].value, self.axes[1].value, self.axes[2].value)
+    }
+
+    // Forgets the current motion, e.g. after the robot was stopped externally.
+    pub fn reset(&mut self) {
+        for axis in self.axes.iter_mut() {
+            axis.value = 0.0;
+            axis.accel = 0.0;
+        }
+    }
+
+    // Advances by dt towards target, returns the twist to command now.
+    pub fn step(&mut self, target: &Twist, dt: Duration) -> Twist {
+        let dt = dt.as_secs_f64();
+        Twist::holonomic(
+            self.axes[0].step(target.vx, dt),
+            self.axes[1].step(target.vy, dt),
+            self.axes[2].step(target.omega, dt),
+        )
+    }
+
+    pub fn drive<K, S>(&mut self, target: &Twist, dt: Duration, client: &mut DriveClient<K, S>) -> io::Result<Twist>
+        where K: DriveKinematics, S: Sender {
+        let twist = self.step(target, dt);
+        client.drive(&twist)?;
+        Ok(twist)
+    }
+}
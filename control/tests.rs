@@ -0,0 +1,162 @@
+#![cfg(test)]
+
+use std::f64::consts::PI;
+use std::time::Duration;
+use l0::comm::Packet;
+use kinematics::{DriveClient, Twist};
+use kinematics::diff_drive::DiffDrive;
+use nav::{Point2, Pose2};
+use l2::line::*;
+use super::follower::*;
+use super::line::*;
+use super::pid::*;
+use super::ramp::*;
+
+fn assert_near(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+const DT: Duration = Duration::from_millis(100);
+
+#[test]
+fn test_pid_terms() {
+    let mut pid = Pid::new(PidGains::new(2.0, 1.0, 0.5));
+    pid.set_setpoint(1.0);
+    // p = 2, i = 0.1, no derivative on first sample.
+    assert_near(pid.update(0.0, DT), 2.1);
+    // p = 1, i = 0.15, d = -0.5 * 5.
+    assert_near(pid.update(0.5, DT), 1.0 + 0.15 - 2.5);
+}
+
+#[test]
+fn test_pid_output_clamp_and_anti_windup() {
+    let mut pid = Pid::new(PidGains::new(0.0, 10.0, 0.0)).with_output_limits(-1.0, 1.0);
+    pid.set_setpoint(10.0);
+    for _ in 0..100 {
+        assert!(pid.update(0.0, DT) <= 1.0);
+    }
+    // the integral didn't wind up, so it unwinds immediately.
+    pid.set_setpoint(-10.0);
+    assert!(pid.update(0.0, DT) < 0.0);
+}
+
+#[test]
+fn test_pid_derivative_filter() {
+    let mut pid = Pid::new(PidGains::new(0.0, 0.0, 1.0)).with_derivative_filter(0.1);
+    pid.update(0.0, DT);
+    // raw derivative 10, filtered by alpha = 0.5.
+    assert_near(pid.update(1.0, DT), -5.0);
+}
+
+#[test]
+fn test_pid_bumpless_retune() {
+    let mut pid = Pid::new(PidGains::new(1.0, 0.5, 0.0));
+    pid.set_setpoint(2.0);
+    pid.update(0.0, DT);
+    let out = pid.update(1.0, Duration::from_secs(0));
+    pid.set_gains(PidGains::new(3.0, 0.5, 0.0));
+    assert_near(pid.update(1.0, Duration::from_secs(0)), out);
+    pid.reset();
+    assert_near(pid.update(2.0, DT), 0.0);
+}
+
+#[test]
+fn test_follower_straight_line() {
+    let mut f = Follower::new(vec![Point2::new(1.0, 0.0), Point2::new(2.0, 0.0)], 0.5, 1.0);
+    let (t, events) = f.update(&Pose2::new(0.0, 0.0, 0.0));
+    assert_near(t.vx, 1.0);
+    assert_near(t.omega, 0.0);
+    assert!(events.is_empty());
+
+    let (_, events) = f.update(&Pose2::new(0.6, 0.0, 0.0));
+    assert_eq!(events, vec![FollowerEvent::WaypointReached(0)]);
+    assert_near(f.progress(), 0.5);
+
+    let (t, _) = f.update(&Pose2::new(1.8, 0.0, 0.0));
+    assert_near(t.vx, 0.4);
+
+    let (t, events) = f.update(&Pose2::new(1.95, 0.0, 0.0));
+    assert_eq!(t, Twist::zero());
+    assert_eq!(events, vec![FollowerEvent::WaypointReached(1), FollowerEvent::Completed]);
+    assert!(f.is_completed());
+    assert_near(f.progress(), 1.0);
+}
+
+#[test]
+fn test_follower_turns_towards_target() {
+    let mut f = Follower::new(vec![Point2::new(0.0, 2.0)], 0.5, 1.0).with_max_angular(0.5);
+    let (t, _) = f.update(&Pose2::new(0.0, 0.0, 0.0));
+    assert_near(t.omega, 0.5);
+    let (t, _) = f.update(&Pose2::new(0.0, 0.0, PI));
+    assert_near(t.omega, -0.5);
+}
+
+#[test]
+fn test_follower_drive() {
+    let mut sent: Vec<Packet> = Vec::new();
+    let mut c = DriveClient::new(DiffDrive::new(0.5, 0.1), &mut sent, 1);
+    let mut f = Follower::new(vec![Point2::new(1.0, 0.0)], 0.5, 1.0);
+    f.drive(&Pose2::new(0.0, 0.0, 0.0), &mut c).unwrap();
+    let events = f.drive(&Pose2::new(1.0, 0.0, 0.0), &mut c).unwrap();
+    assert_eq!(events.last(), Some(&FollowerEvent::Completed));
+    assert_eq!(c.last_command(), Some(&Twist::zero()));
+}
+
+#[test]
+fn test_ramp_acceleration_limit() {
+    let mut r = Ramp::new(AxisLimits::new(1.0, f64::INFINITY), AxisLimits::unlimited(), AxisLimits::unlimited());
+    let target = Twist::holonomic(1.0, 0.5, -0.5);
+    let t = r.step(&target, DT);
+    assert_near(t.vx, 0.1);
+    assert_near(t.vy, 0.5);
+    assert_near(t.omega, -0.5);
+    for _ in 0..20 {
+        r.step(&target, DT);
+    }
+    assert_eq!(r.current(), target);
+    r.reset();
+    assert_eq!(r.current(), Twist::zero());
+}
+
+#[test]
+fn test_ramp_jerk_limit() {
+    let mut r = Ramp::new(AxisLimits::new(1.0, 5.0), AxisLimits::unlimited(), AxisLimits::unlimited());
+    let target = Twist::new(1.0, 0.0);
+    // acceleration builds up by at most 0.5 per step.
+    assert_near(r.step(&target, DT).vx, 0.05);
+    assert_near(r.step(&target, DT).vx, 0.15);
+    let mut last = 0.15;
+    for _ in 0..40 {
+        let v = r.step(&target, DT).vx;
+        assert!(v >= last && v <= 1.0);
+        last = v;
+    }
+    assert_near(last, 1.0);
+}
+
+#[test]
+fn test_ramp_drive() {
+    let mut sent: Vec<Packet> = Vec::new();
+    let mut c = DriveClient::new(DiffDrive::new(0.5, 0.1), &mut sent, 1);
+    let mut r = Ramp::new(AxisLimits::new(1.0, f64::INFINITY), AxisLimits::unlimited(), AxisLimits::unlimited());
+    let t = r.drive(&Twist::new(1.0, 0.0), DT, &mut c).unwrap();
+    assert_eq!(c.last_command(), Some(&t));
+}
+
+#[test]
+fn test_line_follower() {
+    let mut f = LineFollower::new(LineEstimator::new(), Pid::new(PidGains::new(2.0, 0.0, 0.0)), 0.3);
+    let reading = |samples: Vec<u8>| LineMessage { bits: 8, samples };
+    // nothing seen yet.
+    assert_eq!(f.update(&reading(vec![255; 5]), DT), Twist::zero());
+    let t = f.update(&reading(vec![255, 255, 0, 255, 255]), DT);
+    assert_near(t.vx, 0.3);
+    assert_near(t.omega, 0.0);
+    // line under the right half turns clockwise.
+    let t = f.update(&reading(vec![255, 255, 255, 0, 0]), DT);
+    assert_near(f.estimator().last().unwrap(), 0.75);
+    assert_near(t.omega, -1.5);
+    // lost on the right keeps turning right, harder.
+    let t = f.update(&reading(vec![255; 5]), DT);
+    assert_near(t.omega, -2.0);
+}
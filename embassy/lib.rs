@@ -0,0 +1,140 @@
+// The device side of a robo.rs link for async firmware on embassy: the
+// EmbeddedLink of the robo crate over an embedded-io-async UART, with
+// embassy-time running the receive timer. It lives in its own crate
+// because robo is built with the 2015 edition, which has no async.
+
+#![cfg_attr(not(test), no_std)]
+
+use embassy_time::{with_deadline, Duration, Instant};
+use embedded_io_async::{ErrorType, Read, Write};
+use robo::l0::comm::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkError<E> {
+    Io(E),
+    NotReady, // the handshake isn't done.
+    TooLong,  // payload longer than PACKET_MAX_DATA_LEN.
+    Closed,   // the UART reached end of stream.
+}
+
+impl<E> From<E> for LinkError<E> {
+    fn from(e: E) -> Self {
+        LinkError::Io(e)
+    }
+}
+
+pub type LinkResult<T, U> = Result<T, LinkError<<U as ErrorType>::Error>>;
+
+// Never allocates: packets are parsed in place and borrowed from the link
+// until the next recv. A frame stalled for longer than timeout is dropped
+// and the link resyncs.
+pub struct EmbassyLink<U> {
+    uart: U,
+    parser: Parser,
+    seq: PacketSeq,
+    ready: bool,
+    timeout: Duration,
+    deadline: Option<Instant>, // of the receive timer, while running.
+    buf: [u8; 32],
+    pos: usize,
+    len: usize,
+}
+
+impl<U: Read + Write> EmbassyLink<U> {
+    // Starts the handshake by sending a sync request.
+    pub async fn new(uart: U, timeout: Duration) -> LinkResult<Self, U> {
+        let mut link = EmbassyLink {
+            uart,
+            parser: Parser::new(),
+            seq: 1,
+            ready: false,
+            timeout,
+            deadline: None,
+            buf: [0; 32],
+            pos: 0,
+            len: 0,
+        };
+        let s = link.parser.reset_status();
+        link.apply(s).await?;
+        Ok(link)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn uart(&mut self) -> &mut U {
+        &mut self.uart
+    }
+
+    pub fn release(self) -> U {
+        self.uart
+    }
+
+    async fn apply(&mut self, s: ParseStatus) -> LinkResult<(), U> {
+        self.ready = s.state.is_ready();
+        match s.timer_action() {
+            TimerAction::Restart => self.deadline = Some(Instant::now() + self.timeout),
+            TimerAction::Stop => self.deadline = None,
+            TimerAction::NoChange => {},
+        }
+        if s.sync != 0 {
+            self.uart.write_all(&[s.sync, self.seq]).await?;
+        }
+        Ok(())
+    }
+
+    // Waits for the next packet, answering sync requests on the way.
+    pub async fn recv(&mut self) -> LinkResult<Frame<'_>, U> {
+        loop {
+            while self.pos < self.len {
+                let s = self.parser.parse_frame(self.buf[self.pos]);
+                self.pos += 1;
+                self.apply(s).await?;
+                if s.packet {
+                    return Ok(self.parser.frame());
+                }
+            }
+            let read = self.uart.read(&mut self.buf);
+            let n = match self.deadline {
+                Some(deadline) => match with_deadline(deadline, read).await {
+                    Ok(n) => n?,
+                    Err(_) => {
+                        let s = self.parser.timeout_status();
+                        self.apply(s).await?;
+                        continue;
+                    },
+                },
+                None => read.await?,
+            };
+            if n == 0 {
+                return Err(LinkError::Closed);
+            }
+            self.pos = 0;
+            self.len = n;
+        }
+    }
+
+    // Assigns the next sequence number and writes the packet.
+    pub async fn send(&mut self, code: u8, data: &[u8]) -> LinkResult<(), U> {
+        if !self.ready {
+            return Err(LinkError::NotReady);
+        }
+        if data.len() > PACKET_MAX_DATA_LEN {
+            return Err(LinkError::TooLong);
+        }
+        let (head, n) = frame_head(self.seq, code, data.len());
+        self.seq = self.seq.next();
+        self.uart.write_all(&head[..n]).await?;
+        self.uart.write_all(data).await?;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> LinkResult<(), U> {
+        Ok(self.uart.flush().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use embassy_futures::block_on;
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{ErrorType, Read, Write};
+use super::*;
+
+#[derive(Default)]
+struct Uart {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+}
+
+impl ErrorType for Uart {
+    type Error = Infallible;
+}
+
+impl Read for Uart {
+    // Waits forever once nothing is left, like an idle line.
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        if self.rx.is_empty() {
+            core::future::pending::<()>().await;
+        }
+        let n = buf.len().min(self.rx.len());
+        for (i, b) in self.rx.drain(..n).enumerate() {
+            buf[i] = b;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Uart {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        self.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+#[test]
+fn test_embassy_link() {
+    block_on(async {
+        let mut link = EmbassyLink::new(Uart::default(), Duration::from_millis(10)).await.unwrap();
+        assert_eq!(link.uart().tx.split_off(0), vec![SYNC_REQ, 1]);
+        assert_eq!(link.send(0x81, &[]).await, Err(LinkError::NotReady));
+
+        link.uart().rx.extend(&[SYNC_ACK, 1, 1, 0x11, 7, 2, 0x01]);
+        assert_eq!(link.recv().await.unwrap(), Frame { seq: 1, code: 0x01, data: &[7] });
+        assert!(link.is_ready());
+        assert_eq!(link.recv().await.unwrap(), Frame { seq: 2, code: 0x01, data: &[] });
+        link.send(0x81, &[1, 2]).await.unwrap();
+        assert_eq!(link.send(0x81, &[0; PACKET_MAX_DATA_LEN + 1]).await, Err(LinkError::TooLong));
+        assert_eq!(link.uart().tx.split_off(0), vec![1, 0xa1, 1, 2]);
+
+        // a frame cut short times out and resyncs.
+        link.uart().rx.extend(&[3, 0x72]);
+        assert!(with_timeout(Duration::from_millis(15), link.recv()).await.is_err());
+        assert!(!link.is_ready());
+        assert_eq!(link.uart().tx[..2], [SYNC_REQ, 2]);
+    });
+}
@@ -0,0 +1,123 @@
+// C API for the l0 parser and packet encoder, declared in ffi/robo_ffi.h. The
+// caller owns all memory: the parser lives in storage it provides, and
+// nothing here allocates, so the same code runs in firmware as on the
+// host. Build the library to link with
+//
+//   cargo rustc --lib --release --crate-type staticlib --features ffi
+//
+// and for firmware, which provides the panic handler, add
+// --no-default-features and the target.
+
+use core::mem;
+use core::ptr;
+use core::slice;
+use l0::comm::{frame_head, ParseStatus, Parser, PACKET_MAX_DATA_LEN};
+
+// Opaque to C.
+pub struct RoboParser(Parser);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoboParseStatus {
+    pub sync: u8,   // sync byte to send, followed by the sender's seq; 0 for none.
+    pub state: u8,  // ROBO_STATE_* bits.
+    pub packet: u8, // 1 when a packet is complete, see robo_parser_frame.
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RoboFrame {
+    pub seq: u8,
+    pub code: u8,
+    pub len: u8,
+    pub data: *const u8, // valid until the parser is next used.
+}
+
+impl From<ParseStatus> for RoboParseStatus {
+    fn from(s: ParseStatus) -> Self {
+        RoboParseStatus { sync: s.sync, state: s.state, packet: s.packet as u8 }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn robo_parser_size() -> usize {
+    mem::size_of::<RoboParser>()
+}
+
+#[no_mangle]
+pub extern "C" fn robo_parser_align() -> usize {
+    mem::align_of::<RoboParser>()
+}
+
+/// Places a parser in storage, which must be robo_parser_size() bytes
+/// aligned to robo_parser_align(). Returns NULL otherwise. A parser that
+/// isn't promiscuous starts by asking for a sync request to be sent.
+///
+/// # Safety
+/// storage must be valid for writes of size bytes and outlive the parser.
+#[no_mangle]
+pub unsafe extern "C" fn robo_parser_new(storage: *mut u8, size: usize, promiscuous: bool) -> *mut RoboParser {
+    if storage.is_null() || size < robo_parser_size() || !(storage as usize).is_multiple_of(robo_parser_align()) {
+        return ptr::null_mut();
+    }
+    let p = storage as *mut RoboParser;
+    p.write(RoboParser(if promiscuous { Parser::promiscuous() } else { Parser::new() }));
+    p
+}
+
+/// # Safety
+/// parser must come from robo_parser_new.
+#[no_mangle]
+pub unsafe extern "C" fn robo_parser_parse(parser: *mut RoboParser, b: u8) -> RoboParseStatus {
+    (*parser).0.parse_frame(b).into()
+}
+
+/// Called when the receive timer runs out mid-frame.
+///
+/// # Safety
+/// parser must come from robo_parser_new.
+#[no_mangle]
+pub unsafe extern "C" fn robo_parser_timeout(parser: *mut RoboParser) -> RoboParseStatus {
+    (*parser).0.timeout_status().into()
+}
+
+/// # Safety
+/// parser must come from robo_parser_new.
+#[no_mangle]
+pub unsafe extern "C" fn robo_parser_reset(parser: *mut RoboParser) -> RoboParseStatus {
+    (*parser).0.reset_status().into()
+}
+
+/// The packet completed by the last byte parsed.
+///
+/// # Safety
+/// parser must come from robo_parser_new.
+#[no_mangle]
+pub unsafe extern "C" fn robo_parser_frame(parser: *const RoboParser) -> RoboFrame {
+    let f = (*parser).0.frame();
+    RoboFrame { seq: f.seq, code: f.code, len: f.data.len() as u8, data: f.data.as_ptr() }
+}
+
+/// Writes a packet to out, returning its length, or 0 when the payload is
+/// longer than ROBO_MAX_DATA_LEN or out can't hold the packet.
+///
+/// # Safety
+/// data must be valid for len bytes and out for cap bytes; data may be
+/// NULL when len is 0.
+#[no_mangle]
+pub unsafe extern "C" fn robo_packet_encode(seq: u8, code: u8, data: *const u8, len: usize,
+                                            out: *mut u8, cap: usize) -> usize {
+    let (head, n) = frame_head(seq, code, len);
+    if len > PACKET_MAX_DATA_LEN || n + len > cap || out.is_null() || (len > 0 && data.is_null()) {
+        return 0;
+    }
+    let out = slice::from_raw_parts_mut(out, cap);
+    out[..n].copy_from_slice(&head[..n]);
+    if len > 0 {
+        out[n..n + len].copy_from_slice(slice::from_raw_parts(data, len));
+    }
+    n + len
+}
+
+#[cfg(test)]
+mod tests;
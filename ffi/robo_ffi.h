@@ -0,0 +1,83 @@
+/* C API for the robo.rs l0 parser and packet encoder. Nothing allocates:
+ * the parser lives in storage the caller provides.
+ *
+ *   static uint8_t storage[ROBO_PARSER_STORAGE_SIZE] ROBO_PARSER_ALIGNED;
+ *   robo_parser_t *p = robo_parser_new(storage, sizeof(storage), false);
+ *   robo_parse_status_t s = robo_parser_reset(p);   // sync request to send
+ *   ...
+ *   s = robo_parser_parse(p, byte);
+ *   if (s.sync) { uint8_t out[2] = { s.sync, my_seq }; uart_write(out, 2); }
+ *   if (s.packet) { robo_frame_t f = robo_parser_frame(p); handle(f.code, f.data, f.len); }
+ */
+#ifndef ROBO_FFI_H
+#define ROBO_FFI_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ROBO_SYNC_REQ 0xff
+#define ROBO_SYNC_ACK 0xfe
+#define ROBO_WAKE 0xfd
+#define ROBO_SUSPEND_REQ 0xfc
+#define ROBO_SUSPEND_ACK 0xfb
+#define ROBO_EPOCH 0xfa
+#define ROBO_FIN 0xf9
+#define ROBO_FIN_ACK 0xf8
+#define ROBO_FLOW_OFF 0xf7
+#define ROBO_FLOW_ON 0xf6
+#define ROBO_MAX_DATA_LEN 127
+#define ROBO_MAX_PACKET_LEN (3 + ROBO_MAX_DATA_LEN)
+
+#define ROBO_STATE_READY 0x01
+#define ROBO_STATE_RECV 0x02
+#define ROBO_STATE_SUSPENDED 0x04
+#define ROBO_STATE_CLOSING 0x08
+#define ROBO_STATE_CLOSED 0x10
+#define ROBO_STATE_FLOW_OFF 0x20
+
+/* Enough for the parser on 32 and 64-bit targets; robo_parser_size() has
+   the exact figure. */
+#define ROBO_PARSER_STORAGE_SIZE 192
+#define ROBO_PARSER_ALIGNED __attribute__((aligned(8)))
+
+typedef struct robo_parser robo_parser_t;
+
+typedef struct {
+    uint8_t sync;   /* sync byte to send, followed by the sender's seq; 0 for none */
+    uint8_t state;  /* ROBO_STATE_* bits */
+    uint8_t packet; /* 1 when a packet is complete, see robo_parser_frame */
+} robo_parse_status_t;
+
+typedef struct {
+    uint8_t seq;
+    uint8_t code;
+    uint8_t len;
+    const uint8_t *data; /* valid until the parser is next used */
+} robo_frame_t;
+
+size_t robo_parser_size(void);
+size_t robo_parser_align(void);
+
+/* NULL unless storage holds robo_parser_size() bytes aligned to
+   robo_parser_align(). */
+robo_parser_t *robo_parser_new(void *storage, size_t size, bool promiscuous);
+robo_parse_status_t robo_parser_parse(robo_parser_t *parser, uint8_t byte);
+robo_parse_status_t robo_parser_timeout(robo_parser_t *parser);
+robo_parse_status_t robo_parser_reset(robo_parser_t *parser);
+robo_frame_t robo_parser_frame(const robo_parser_t *parser);
+
+/* Returns the packet length written to out, 0 if the payload is too long
+   or out too small. */
+size_t robo_packet_encode(uint8_t seq, uint8_t code, const uint8_t *data, size_t len,
+                          uint8_t *out, size_t cap);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ROBO_FFI_H */
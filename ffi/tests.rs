@@ -0,0 +1,68 @@
+#![cfg(test)]
+
+use std::ptr;
+use std::slice;
+use l0::comm::{SYNC_ACK, SYNC_REQ, SYNC_STATE_READY};
+use super::*;
+
+// ROBO_PARSER_STORAGE_SIZE in robo_ffi.h.
+const STORAGE_SIZE: usize = 192;
+
+#[repr(align(8))]
+struct Storage([u8; STORAGE_SIZE]);
+
+#[test]
+fn parser_in_caller_storage() {
+    assert!(robo_parser_size() <= STORAGE_SIZE);
+    assert!(robo_parser_align() <= 8);
+    let mut storage = Storage([0; STORAGE_SIZE]);
+    unsafe {
+        assert!(robo_parser_new(storage.0.as_mut_ptr(), 8, false).is_null());
+        assert!(robo_parser_new(storage.0.as_mut_ptr().add(1), STORAGE_SIZE - 1, false).is_null());
+        let p = robo_parser_new(storage.0.as_mut_ptr(), STORAGE_SIZE, false);
+        assert!(!p.is_null());
+        assert_eq!(robo_parser_reset(p).sync, SYNC_REQ);
+        robo_parser_parse(p, SYNC_REQ);
+        let s = robo_parser_parse(p, 5);
+        assert_eq!(s, RoboParseStatus { sync: SYNC_ACK, state: SYNC_STATE_READY, packet: 0 });
+
+        let payload = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut buf = [0u8; 3 + 127];
+        let n = robo_packet_encode(5, 0x81, payload.as_ptr(), payload.len(), buf.as_mut_ptr(), buf.len());
+        assert_eq!(&buf[..n], &[5, 0xf1, 8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut last = RoboParseStatus { sync: 0, state: 0, packet: 0 };
+        for b in &buf[..n] {
+            last = robo_parser_parse(p, *b);
+        }
+        assert_eq!(last.packet, 1);
+        let f = robo_parser_frame(p);
+        assert_eq!((f.seq, f.code), (5, 0x81));
+        assert_eq!(slice::from_raw_parts(f.data, f.len as usize), &payload);
+
+        assert_eq!(robo_packet_encode(1, 2, ptr::null(), 0, buf.as_mut_ptr(), 2), 2);
+        assert_eq!(&buf[..2], &[1, 2]);
+        assert_eq!(robo_packet_encode(1, 2, payload.as_ptr(), payload.len(), buf.as_mut_ptr(), 10), 0);
+        assert_eq!(robo_packet_encode(1, 2, buf.as_ptr(), 128, buf.as_mut_ptr(), buf.len()), 0);
+    }
+}
+
+// The header names every control byte and state bit the parser reports.
+#[test]
+fn header_matches_parser() {
+    use l0::comm::*;
+    let defines: Vec<(&str, u8)> = include_str!("robo_ffi.h").lines()
+        .filter_map(|l| l.strip_prefix("#define ROBO_"))
+        .filter_map(|l| {
+            let (name, value) = l.split_once(' ')?;
+            Some((name, u8::from_str_radix(value.strip_prefix("0x")?, 16).ok()?))
+        }).collect();
+    let expected = [
+        ("SYNC_REQ", SYNC_REQ), ("SYNC_ACK", SYNC_ACK), ("WAKE", WAKE),
+        ("SUSPEND_REQ", SUSPEND_REQ), ("SUSPEND_ACK", SUSPEND_ACK), ("EPOCH", EPOCH),
+        ("FIN", FIN), ("FIN_ACK", FIN_ACK), ("FLOW_OFF", FLOW_OFF), ("FLOW_ON", FLOW_ON),
+        ("STATE_READY", SYNC_STATE_READY), ("STATE_RECV", SYNC_STATE_RECV),
+        ("STATE_SUSPENDED", SYNC_STATE_SUSPENDED), ("STATE_CLOSING", SYNC_STATE_CLOSING),
+        ("STATE_CLOSED", SYNC_STATE_CLOSED), ("STATE_FLOW_OFF", SYNC_STATE_FLOW_OFF),
+    ];
+    assert_eq!(defines, expected);
+}
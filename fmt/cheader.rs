@@ -0,0 +1,173 @@
+use std::fmt;
+use l0::comm::{EPOCH, FIN, FIN_ACK, FLOW_OFF, FLOW_ON, PACKET_MAX_DATA_LEN, SUSPEND_ACK, SUSPEND_REQ, SYNC_ACK, SYNC_REQ, WAKE};
+use l2::layout::{Count, Field, FieldType, Layout};
+use l2::schema::{MessageSet, Schema};
+
+const KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+    "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while",
+];
+
+// A C header for firmware speaking to a host with this schema: the packet
+// codes, the op and a packed struct of every message of the sets in use,
+// and macros for payload lengths. Structs overlay payloads directly only on
+// little-endian targets.
+pub struct CHeader<'a> {
+    schema: &'a Schema,
+    prefix: String,
+}
+
+fn c_name(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+impl<'a> CHeader<'a> {
+    pub fn new(schema: &'a Schema) -> Self {
+        CHeader { schema, prefix: "robo".to_string() }
+    }
+
+    // Prepended to every name, upper case for macros. Defaults to "robo".
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn upper(&self, parts: &[&str]) -> String {
+        let mut s = self.prefix.to_uppercase();
+        for p in parts {
+            s.push('_');
+            s.push_str(&p.to_uppercase());
+        }
+        s
+    }
+
+    fn type_name(&self, set: MessageSet, name: &str) -> String {
+        format!("{}_{}_{}_t", self.prefix, set.name(), name)
+    }
+
+    fn c_type(&self, set: MessageSet, ty: &FieldType) -> String {
+        match *ty {
+            FieldType::U8 => "uint8_t".to_string(),
+            FieldType::I8 => "int8_t".to_string(),
+            FieldType::U16 => "uint16_t".to_string(),
+            FieldType::I16 => "int16_t".to_string(),
+            FieldType::U32 => "uint32_t".to_string(),
+            FieldType::I32 => "int32_t".to_string(),
+            FieldType::Struct(name, _) => self.type_name(set, name),
+        }
+    }
+
+    fn fields(&self, f: &mut fmt::Formatter, set: MessageSet, fields: &[Field]) -> fmt::Result {
+        for field in fields {
+            let ty = self.c_type(set, &field.ty);
+            let name = c_name(field.name);
+            match field.count {
+                Count::One => writeln!(f, "    {} {};", ty, name)?,
+                Count::Fixed(n) => writeln!(f, "    {} {}[{}];", ty, name, n)?,
+                Count::Rest => writeln!(f, "    {} {}[];", ty, name)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Struct types used by the layouts, innermost first.
+    fn nested(layouts: &[Layout]) -> Vec<(&'static str, &'static [Field])> {
+        fn walk(fields: &[Field], found: &mut Vec<(&'static str, &'static [Field])>) {
+            for f in fields {
+                if let FieldType::Struct(name, inner) = f.ty {
+                    walk(inner, found);
+                    if !found.iter().any(|&(n, _)| n == name) {
+                        found.push((name, inner));
+                    }
+                }
+            }
+        }
+        let mut found = Vec::new();
+        for l in layouts {
+            walk(l.fields, &mut found);
+        }
+        found
+    }
+
+    fn set(&self, f: &mut fmt::Formatter, set: MessageSet) -> fmt::Result {
+        writeln!(f, "/* {} */\n", set.name())?;
+        for l in set.layouts() {
+            writeln!(f, "#define {} 0x{:02x}", self.upper(&[set.name(), l.name]), l.op)?;
+        }
+        writeln!(f)?;
+        for l in set.layouts() {
+            let len = self.upper(&[set.name(), l.name, "len"]);
+            match l.rest() {
+                Some(rest) => writeln!(f, "#define {}(n) ({} + (n) * {})", len, l.min_len(), rest.ty.size())?,
+                None => writeln!(f, "#define {} {}", len, l.min_len())?,
+            }
+        }
+        writeln!(f, "\n#pragma pack(push, 1)")?;
+        for (name, fields) in CHeader::nested(set.layouts()) {
+            writeln!(f, "\ntypedef struct {{")?;
+            self.fields(f, set, fields)?;
+            writeln!(f, "}} {};", self.type_name(set, name))?;
+        }
+        for l in set.layouts() {
+            writeln!(f, "\ntypedef struct {{\n    uint8_t op; /* {} */", self.upper(&[set.name(), l.name]))?;
+            self.fields(f, set, l.fields)?;
+            writeln!(f, "}} {};", self.type_name(set, l.name))?;
+        }
+        writeln!(f, "\n#pragma pack(pop)\n")?;
+        for l in set.layouts() {
+            let len = self.upper(&[set.name(), l.name, "len"]);
+            writeln!(f, "_Static_assert(sizeof({}) == {}{}, \"{} {} layout\");",
+                self.type_name(set, l.name), len, if l.rest().is_some() { "(0)" } else { "" }, set.name(), l.name)?;
+        }
+        writeln!(f)
+    }
+}
+
+impl<'a> fmt::Display for CHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let guard = self.upper(&["h"]);
+        writeln!(f, "/* Generated by robo from the schema \"{}\". Do not edit. */\n", self.schema)?;
+        writeln!(f, "#ifndef {0}\n#define {0}\n\n#include <stdint.h>\n", guard)?;
+
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["sync_req"]), SYNC_REQ)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["sync_ack"]), SYNC_ACK)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["suspend_req"]), SUSPEND_REQ)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["suspend_ack"]), SUSPEND_ACK)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["wake"]), WAKE)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["epoch"]), EPOCH)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["fin"]), FIN)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["fin_ack"]), FIN_ACK)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["flow_off"]), FLOW_OFF)?;
+        writeln!(f, "#define {} 0x{:02x}", self.upper(&["flow_on"]), FLOW_ON)?;
+        writeln!(f, "#define {} {}\n", self.upper(&["max_data_len"]), PACKET_MAX_DATA_LEN)?;
+        writeln!(f, "/* The code byte of a packet, carrying the payload length when it is below 7;")?;
+        writeln!(f, "   otherwise a length byte follows. */")?;
+        writeln!(f, "#define {}(code, len) (((code) & 0x8f) | ((len) < 7 ? (len) << 4 : 0x70))",
+            self.upper(&["code_byte"]))?;
+        writeln!(f, "#define {}(code) (((code) & 0x80) != 0)\n", self.upper(&["is_event"]))?;
+
+        let mut sets: Vec<MessageSet> = Vec::new();
+        for (code, set) in self.schema.codes() {
+            let shared = self.schema.codes().filter(|&(_, s)| s == set).count() > 1;
+            let name = if shared {
+                self.upper(&["code", set.name(), &format!("{:02x}", code)])
+            } else {
+                self.upper(&["code", set.name()])
+            };
+            writeln!(f, "#define {} 0x{:02x}", name, code)?;
+            if !sets.contains(&set) {
+                sets.push(set);
+            }
+        }
+        writeln!(f)?;
+        for set in sets {
+            self.set(f, set)?;
+        }
+        writeln!(f, "#endif /* {} */", guard)
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use l0::comm::{Packet, EPOCH, FIN, FIN_ACK, FLOW_OFF, FLOW_ON, SUSPEND_ACK, SUSPEND_REQ, SYNC_ACK, SYNC_REQ};
+use l2::schema::Schema;
+
+const BYTES_PER_LINE: usize = 16;
+
+// Renders the bytes of a frame one field per line, hex on the left and what
+// the field means on the right:
+//
+//   01                         seq 1
+//   61                         code 0x01 request, len 6
+//   01 00 64 00 9c ff          motor SetVelocities { first: 0, ... }
+//
+// Payloads are decoded when a schema assigns a message set to the code.
+pub struct HexDump<'a> {
+    bytes: Vec<u8>,
+    schema: Option<&'a Schema>,
+}
+
+impl<'a> HexDump<'a> {
+    pub fn packet(pkt: &Packet) -> Self {
+        let mut bytes = Vec::with_capacity(pkt.data.len() + 3);
+        pkt.encode(&mut bytes).expect("write to vec");
+        HexDump { bytes, schema: None }
+    }
+
+    // Raw bytes from the wire, starting at a frame boundary: either a
+    // packet or a sync request or ack.
+    pub fn frame(bytes: &[u8]) -> Self {
+        HexDump { bytes: bytes.to_vec(), schema: None }
+    }
+
+    pub fn with_schema(mut self, schema: &'a Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    fn field(&self, f: &mut fmt::Formatter, bytes: &[u8], note: &str) -> fmt::Result {
+        let mut lines = bytes.chunks(BYTES_PER_LINE);
+        let first = lines.next().unwrap_or(&[]);
+        writeln!(f, "{:<width$}  {}", hex(first), note, width = BYTES_PER_LINE * 3 - 1)?;
+        for line in lines {
+            writeln!(f, "{}", hex(line))?;
+        }
+        Ok(())
+    }
+
+    fn payload(&self, code: u8, data: &[u8]) -> String {
+        let set = match self.schema.and_then(|s| s.get(code)) {
+            Some(set) => set,
+            None => return "payload".to_string(),
+        };
+        match set.describe(data) {
+            Some(msg) => format!("{} {}", set.name(), msg),
+            None => format!("{} (malformed)", set.name()),
+        }
+    }
+}
+
+// One line per packet, for logs and consoles:
+//
+//   seq=1   code=0x01 request len=6   motor SetVelocities { first: 0, ... }
+//
+// Without a schema for the code, the payload shows as hex.
+pub struct Summary<'a> {
+    pkt: &'a Packet,
+    schema: Option<&'a Schema>,
+}
+
+impl<'a> Summary<'a> {
+    pub fn packet(pkt: &'a Packet) -> Self {
+        Summary { pkt, schema: None }
+    }
+
+    pub fn with_schema(mut self, schema: &'a Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+}
+
+impl<'a> fmt::Display for Summary<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pkt = self.pkt;
+        let kind = if pkt.code & 0x80 != 0 { "event" } else { "request" };
+        write!(f, "seq={:<3} code=0x{:02x} {:<7} len={:<3}", pkt.seq, pkt.code, kind, pkt.data.len())?;
+        match self.schema.and_then(|s| s.get(pkt.code).map(|set| (set, s.describe(pkt)))) {
+            Some((set, Some(msg))) => write!(f, " {} {}", set.name(), msg),
+            Some((set, None)) => write!(f, " {} (malformed) {}", set.name(), hex(&pkt.data)),
+            None => write!(f, " {}", hex(&pkt.data)),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+impl<'a> fmt::Display for HexDump<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.bytes[..];
+        match b.first() {
+            None => return Ok(()),
+            Some(&EPOCH) => {
+                self.field(f, &b[..1], "epoch")?;
+                if let Some(epoch) = b.get(1) {
+                    self.field(f, &b[1..2], &format!("boot {}", epoch))?;
+                }
+                return Ok(());
+            },
+            Some(&SYNC_REQ) | Some(&SYNC_ACK) | Some(&SUSPEND_REQ) | Some(&SUSPEND_ACK) | Some(&FIN) | Some(&FIN_ACK) |
+            Some(&FLOW_OFF) | Some(&FLOW_ON) => {
+                let kind = match b[0] {
+                    SYNC_REQ => "sync request",
+                    SYNC_ACK => "sync ack",
+                    SUSPEND_REQ => "suspend request",
+                    SUSPEND_ACK => "suspend ack",
+                    FIN => "fin",
+                    FIN_ACK => "fin ack",
+                    FLOW_OFF => "flow off",
+                    _ => "flow on",
+                };
+                self.field(f, &b[..1], kind)?;
+                if let Some(seq) = b.get(1) {
+                    self.field(f, &b[1..2], &format!("seq {}", seq))?;
+                }
+                return Ok(());
+            },
+            Some(seq) => self.field(f, &b[..1], &format!("seq {}", seq))?,
+        }
+        let head = match b.get(1) {
+            Some(head) => *head,
+            None => return Ok(()),
+        };
+        let code = head & 0x8f;
+        let kind = if head & 0x80 != 0 { "event" } else { "request" };
+        let mut len = ((head >> 4) & 0x07) as usize;
+        let mut at = 2;
+        if len < 7 {
+            self.field(f, &b[1..2], &format!("code 0x{:02x} {}, len {}", code, kind, len))?;
+        } else {
+            self.field(f, &b[1..2], &format!("code 0x{:02x} {}, long", code, kind))?;
+            len = match b.get(2) {
+                Some(n) => *n as usize,
+                None => return Ok(()),
+            };
+            self.field(f, &b[2..3], &format!("len {}", len))?;
+            at = 3;
+        }
+        let data = &b[at..b.len().min(at + len)];
+        if data.len() < len {
+            return self.field(f, data, &format!("truncated, {} of {} bytes", data.len(), len));
+        }
+        if !data.is_empty() {
+            self.field(f, data, &self.payload(code, data))?;
+        }
+        if b.len() > at + len {
+            self.field(f, &b[at + len..], "trailing")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::io;
+use serde_json::{Map, Value};
+use l2::layout::{Count, Field, FieldType};
+use l2::schema::MessageSet;
+
+// Messages as JSON objects keyed by field name, with "message" naming the
+// op, laid out by l2::layout:
+//
+//   {"message":"status","voltage_mv":12400,"current_ma":300,"soc_pct":80}
+//
+// Fixed and trailing arrays are JSON arrays, nested structs objects.
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_value(data: &[u8], pos: &mut usize, ty: &FieldType) -> Option<Value> {
+    if let FieldType::Struct(_, fields) = *ty {
+        return read_fields(data, pos, fields).map(Value::Object);
+    }
+    let n = ty.size();
+    let b = data.get(*pos..*pos + n)?;
+    *pos += n;
+    let u = b.iter().rev().fold(0u64, |acc, b| acc << 8 | *b as u64);
+    Some(match *ty {
+        FieldType::I8 => json!(u as i8),
+        FieldType::I16 => json!(u as i16),
+        FieldType::I32 => json!(u as i32),
+        _ => json!(u),
+    })
+}
+
+fn read_fields(data: &[u8], pos: &mut usize, fields: &[Field]) -> Option<Map<String, Value>> {
+    let mut obj = Map::new();
+    for f in fields {
+        let value = match f.count {
+            Count::One => read_value(data, pos, &f.ty)?,
+            Count::Fixed(n) => Value::Array((0..n).map(|_| read_value(data, pos, &f.ty)).collect::<Option<_>>()?),
+            Count::Rest => {
+                let mut items = Vec::new();
+                while *pos < data.len() {
+                    items.push(read_value(data, pos, &f.ty)?);
+                }
+                Value::Array(items)
+            },
+        };
+        obj.insert(f.name.to_string(), value);
+    }
+    Some(obj)
+}
+
+fn write_value(data: &mut Vec<u8>, value: &Value, ty: &FieldType, name: &str) -> io::Result<()> {
+    if let FieldType::Struct(_, fields) = *ty {
+        return write_fields(data, value, fields);
+    }
+    let v = value.as_i64().ok_or_else(|| invalid(format!("{} must be an integer", name)))?;
+    data.extend_from_slice(&v.to_le_bytes()[..ty.size()]);
+    Ok(())
+}
+
+fn write_fields(data: &mut Vec<u8>, msg: &Value, fields: &[Field]) -> io::Result<()> {
+    for f in fields {
+        let value = msg.get(f.name).ok_or_else(|| invalid(format!("missing {}", f.name)))?;
+        match f.count {
+            Count::One => write_value(data, value, &f.ty, f.name)?,
+            _ => {
+                let items = value.as_array().ok_or_else(|| invalid(format!("{} must be an array", f.name)))?;
+                for item in items {
+                    write_value(data, item, &f.ty, f.name)?;
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+// None unless data is a valid message of the set.
+pub fn decode_message(set: MessageSet, data: &[u8]) -> Option<Value> {
+    set.describe(data)?;
+    let layout = set.layouts().iter().find(|l| Some(&l.op) == data.first())?;
+    let mut obj = read_fields(data, &mut 1, layout.fields)?;
+    obj.insert("message".to_string(), json!(layout.name));
+    Some(Value::Object(obj))
+}
+
+pub fn encode_message(set: MessageSet, msg: &Value) -> io::Result<Vec<u8>> {
+    let name = msg["message"].as_str().ok_or_else(|| invalid("missing message".to_string()))?;
+    let layout = set.layouts().iter().find(|l| l.name == name)
+        .ok_or_else(|| invalid(format!("no {} message {:?}", set.name(), name)))?;
+    let mut data = vec![layout.op];
+    write_fields(&mut data, msg, layout.fields)?;
+    match set.describe(&data) {
+        Some(_) => Ok(data),
+        None => Err(invalid(format!("not a valid {} {} message", set.name(), name))),
+    }
+}
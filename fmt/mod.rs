@@ -0,0 +1,9 @@
+pub mod cheader;
+pub mod display;
+#[cfg(feature = "serde_json")]
+pub mod json;
+#[cfg(feature = "wirelog")]
+pub mod wirelog;
+
+#[cfg(test)]
+mod tests;
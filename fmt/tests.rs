@@ -0,0 +1,123 @@
+#![cfg(test)]
+
+use l0::comm::Packet;
+use l2::schema::{MessageSet, Schema};
+use super::cheader::CHeader;
+use super::display::{HexDump, Summary};
+
+#[test]
+fn hex_dump_annotates_fields() {
+    let mut pkt = Packet::new_with(1, 0x01);
+    pkt.data = vec![0x01, 0x00, 0x64, 0x00, 0x9c, 0xff];
+    let schema = Schema::new().with(0x01, MessageSet::Motor);
+    let dump = HexDump::packet(&pkt).with_schema(&schema).to_string();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("01 ") && lines[0].ends_with("seq 1"));
+    assert!(lines[1].starts_with("61 ") && lines[1].ends_with("code 0x01 request, len 6"));
+    assert!(lines[2].starts_with("01 00 64 00 9c ff "));
+    assert!(lines[2].contains("motor SetVelocities"));
+
+    // Long payloads take a length byte and wrap at 16 bytes.
+    let mut pkt = Packet::new_with(2, 0x82);
+    pkt.data = (0..20).collect();
+    let dump = HexDump::packet(&pkt).to_string();
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[1].ends_with("code 0x82 event, long"));
+    assert!(lines[2].starts_with("14 ") && lines[2].ends_with("len 20"));
+    assert!(lines[3].ends_with("payload"));
+    assert_eq!(lines[4], "10 11 12 13");
+
+    let dump = HexDump::frame(&[0xfe, 0x05]).to_string();
+    assert_eq!(dump.lines().map(|l| l.split("  ").last().unwrap().trim()).collect::<Vec<_>>(),
+        vec!["sync ack", "seq 5"]);
+    let dump = HexDump::frame(&[0x03, 0x31, 0x01, 0xaa]).to_string();
+    assert!(dump.lines().nth(1).unwrap().ends_with("len 3"));
+    assert!(dump.lines().nth(2).unwrap().ends_with("truncated, 2 of 3 bytes"));
+}
+
+#[test]
+fn summary_one_line() {
+    let mut pkt = Packet::new_with(1, 0x01);
+    pkt.data = vec![0x01, 0x00, 0x64, 0x00, 0x9c, 0xff];
+    let schema = Schema::new().with(0x01, MessageSet::Motor);
+    let line = Summary::packet(&pkt).with_schema(&schema).to_string();
+    assert!(line.starts_with("seq=1   code=0x01 request len=6   motor SetVelocities"));
+    assert_eq!(Summary::packet(&Packet { seq: 12, code: 0x82, data: vec![1, 0xab] }).to_string(),
+        "seq=12  code=0x82 event   len=2   01 ab");
+    pkt.data.truncate(1);
+    assert!(Summary::packet(&pkt).with_schema(&schema).to_string().ends_with("motor (malformed) 01"));
+}
+
+#[cfg(feature = "wirelog")]
+#[test]
+fn wire_log_roundtrip() {
+    use std::time::Duration;
+    use l0::comm::SYNC_ACK;
+    use super::wirelog::*;
+
+    let mut pkt = Packet::new_with(3, 0x81);
+    pkt.data = vec![0x01, 0x64];
+    let mut w = WireLogWriter::new(Vec::new());
+    w.sync(Duration::from_millis(5), Direction::Rx, SYNC_ACK, 7).unwrap();
+    w.packet(Duration::from_millis(125), Direction::Tx, &pkt).unwrap();
+    let text = String::from_utf8(w.into_inner()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], r#"{"dir":"rx","seq":7,"sync":"ack","ts":0.005}"#);
+    assert_eq!(lines[1], r#"{"code":129,"data":"0164","dir":"tx","len":2,"seq":3,"ts":0.125}"#);
+
+    let recs: Vec<WireRecord> = WireLogReader::new(format!("{}\n\n", text).as_bytes())
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(recs, vec![
+        WireRecord::sync(Duration::from_millis(5), Direction::Rx, SYNC_ACK, 7),
+        WireRecord::packet(Duration::from_millis(125), Direction::Tx, &pkt),
+    ]);
+    assert_eq!(recs[0].sync_byte(), SYNC_ACK);
+
+    let err = WireLogReader::new(&b"\n{\"ts\":1,\"dir\":\"up\",\"seq\":1}\n"[..]).next().unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "line 2: dir must be rx or tx");
+}
+
+#[test]
+fn c_header_from_schema() {
+    let schema: Schema = "0x01=motor\n0x81=encoder # telemetry\n0x83=touch,0x84=motor".parse().unwrap();
+    assert_eq!(schema.to_string(), "0x01=motor,0x81=encoder,0x83=touch,0x84=motor");
+    let header = CHeader::new(&schema).with_prefix("bot").to_string();
+    for line in &[
+        "#ifndef BOT_H",
+        "#define BOT_CODE_MOTOR_01 0x01",
+        "#define BOT_CODE_MOTOR_84 0x84",
+        "#define BOT_CODE_ENCODER 0x81",
+        "#define BOT_ENCODER_COUNT 0x01",
+        "#define BOT_ENCODER_COUNT_LEN 6",
+        "#define BOT_MOTOR_SET_VELOCITIES_LEN(n) (2 + (n) * 2)",
+        "    int16_t velocities[];",
+        "} bot_encoder_count_t;",
+        "    uint8_t switch_;",
+        "_Static_assert(sizeof(bot_encoder_count_t) == BOT_ENCODER_COUNT_LEN, \"encoder count layout\");",
+    ] {
+        assert!(header.lines().any(|l| l == *line), "missing {:?} in\n{}", line, header);
+    }
+    assert_eq!(header.matches("} bot_motor_stop_t;").count(), 1);
+    assert!(header.trim_end().ends_with("#endif /* BOT_H */"));
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn json_messages() {
+    use super::json::*;
+
+    let msg = json!({"message": "frame", "strip": 1, "offset": 4,
+        "runs": [{"count": 2, "color": {"r": 255, "g": 0, "b": 16}}]});
+    let data = encode_message(MessageSet::Led, &msg).unwrap();
+    assert_eq!(data, vec![2, 1, 4, 0, 2, 255, 0, 16]);
+    assert_eq!(decode_message(MessageSet::Led, &data), Some(msg));
+
+    let imu = decode_message(MessageSet::Imu, &[3, 1, 0, 0, 0, 0xff, 0xff, 0, 0x40, 0, 0, 0, 0]).unwrap();
+    assert_eq!(imu["quat"], json!([-1, 0x4000, 0, 0]));
+    assert_eq!(decode_message(MessageSet::Imu, &[3, 1]), None);
+
+    assert!(encode_message(MessageSet::Power, &json!({"message": "status", "voltage_mv": 1})).is_err());
+    assert!(encode_message(MessageSet::Mode, &json!({"message": "request", "mode": 9})).is_err());
+}
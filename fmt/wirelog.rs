@@ -0,0 +1,171 @@
+use std::io;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+use serde_json::Value;
+use l0::comm::{Packet, SYNC_ACK, SYNC_REQ};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireEvent {
+    Packet(Packet),
+    Sync { ack: bool, seq: u8 },
+}
+
+// One line of a wire log. ts is relative to the start of the session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireRecord {
+    pub ts: Duration,
+    pub dir: Direction,
+    pub event: WireEvent,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.is_ascii() || !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+// A record is a flat JSON object so it is easy to filter with jq or load
+// into a dataframe:
+//
+//   {"ts":0.125,"dir":"tx","seq":3,"code":1,"len":2,"data":"0164"}
+//   {"ts":0.130,"dir":"rx","sync":"ack","seq":7}
+impl WireRecord {
+    pub fn packet(ts: Duration, dir: Direction, pkt: &Packet) -> Self {
+        WireRecord { ts, dir, event: WireEvent::Packet(pkt.clone()) }
+    }
+
+    // sync is SYNC_REQ or SYNC_ACK.
+    pub fn sync(ts: Duration, dir: Direction, sync: u8, seq: u8) -> Self {
+        WireRecord { ts, dir, event: WireEvent::Sync { ack: sync == SYNC_ACK, seq } }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let dir = match self.dir {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        };
+        let ts = self.ts.as_secs_f64();
+        match self.event {
+            WireEvent::Packet(ref pkt) => json!({
+                "ts": ts, "dir": dir, "seq": pkt.seq, "code": pkt.code, "len": pkt.data.len(), "data": hex(&pkt.data),
+            }),
+            WireEvent::Sync { ack, seq } => json!({
+                "ts": ts, "dir": dir, "sync": if ack { "ack" } else { "request" }, "seq": seq,
+            }),
+        }
+    }
+
+    pub fn from_json(v: &Value) -> io::Result<Self> {
+        let ts = v["ts"].as_f64().filter(|t| *t >= 0.0).ok_or_else(|| invalid("missing ts"))?;
+        let dir = match v["dir"].as_str() {
+            Some("rx") => Direction::Rx,
+            Some("tx") => Direction::Tx,
+            _ => return Err(invalid("dir must be rx or tx")),
+        };
+        let byte = |key: &str| v[key].as_u64().filter(|n| *n <= 0xff).map(|n| n as u8)
+            .ok_or_else(|| invalid(&format!("missing {}", key)));
+        let seq = byte("seq")?;
+        let event = match v["sync"].as_str() {
+            Some("request") => WireEvent::Sync { ack: false, seq },
+            Some("ack") => WireEvent::Sync { ack: true, seq },
+            Some(_) => return Err(invalid("sync must be request or ack")),
+            None => {
+                let mut pkt = Packet::new_with(seq, byte("code")?);
+                pkt.data = v["data"].as_str().and_then(unhex).ok_or_else(|| invalid("missing data"))?;
+                WireEvent::Packet(pkt)
+            },
+        };
+        Ok(WireRecord { ts: Duration::from_micros((ts * 1e6).round() as u64), dir, event })
+    }
+
+    // SYNC_REQ or SYNC_ACK for sync events, 0 for packets.
+    pub fn sync_byte(&self) -> u8 {
+        match self.event {
+            WireEvent::Sync { ack: true, .. } => SYNC_ACK,
+            WireEvent::Sync { ack: false, .. } => SYNC_REQ,
+            WireEvent::Packet(_) => 0,
+        }
+    }
+}
+
+// Writes records as JSON lines.
+pub struct WireLogWriter<W> {
+    out: W,
+}
+
+impl<W: Write> WireLogWriter<W> {
+    pub fn new(out: W) -> Self {
+        WireLogWriter { out }
+    }
+
+    pub fn write(&mut self, rec: &WireRecord) -> io::Result<()> {
+        writeln!(self.out, "{}", rec.to_json())
+    }
+
+    pub fn packet(&mut self, ts: Duration, dir: Direction, pkt: &Packet) -> io::Result<()> {
+        self.write(&WireRecord::packet(ts, dir, pkt))
+    }
+
+    pub fn sync(&mut self, ts: Duration, dir: Direction, sync: u8, seq: u8) -> io::Result<()> {
+        self.write(&WireRecord::sync(ts, dir, sync, seq))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+// Iterates over the records of a wire log, skipping blank lines.
+pub struct WireLogReader<R> {
+    input: R,
+    line: usize,
+}
+
+impl<R: BufRead> WireLogReader<R> {
+    pub fn new(input: R) -> Self {
+        WireLogReader { input, line: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for WireLogReader<R> {
+    type Item = io::Result<WireRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            match self.input.read_line(&mut buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(e) => return Some(Err(e)),
+            }
+            if !buf.trim().is_empty() {
+                break;
+            }
+        }
+        let line = self.line;
+        Some(serde_json::from_str(&buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            .and_then(|v| WireRecord::from_json(&v))
+            .map_err(|e| io::Error::new(e.kind(), format!("line {}: {}", line, e))))
+    }
+}
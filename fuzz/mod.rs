@@ -0,0 +1,160 @@
+// Arbitrary inputs for fuzzing the protocol stack, e.g. with cargo-fuzz:
+//
+//   fuzz_target!(|data: &[u8]| robo::fuzz::fuzz_roundtrip(data));
+//
+// Downstream crates can build on the Arbitrary impls to fuzz their own
+// handling of packets and damaged links.
+use arbitrary::{Arbitrary, Result, Unstructured};
+use l0::comm::*;
+
+impl<'a> Arbitrary<'a> for Packet {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut pkt = Packet::new_with(u.int_in_range(1..=0xef)?, u8::arbitrary(u)? & 0x8f);
+        let len = u.int_in_range(0..=PACKET_MAX_DATA_LEN)?;
+        for _ in 0..len {
+            pkt.data.push(u8::arbitrary(u)?);
+        }
+        Ok(pkt)
+    }
+}
+
+// Wire bytes built from pieces a parser has to tell apart: sync requests
+// and acks, encoded packets and loose bytes. Plain random bytes rarely get
+// past a handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteStream(pub Vec<u8>);
+
+impl<'a> Arbitrary<'a> for ByteStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut bytes = Vec::new();
+        for _ in 0..u.arbitrary_len::<u8>()? {
+            match u.int_in_range(0..=3u8)? {
+                0 => bytes.extend_from_slice(&[SYNC_REQ, u.int_in_range(1..=0xef)?]),
+                1 => bytes.extend_from_slice(&[SYNC_ACK, u.int_in_range(1..=0xef)?]),
+                2 => {
+                    Packet::arbitrary(u)?.encode(&mut bytes).expect("write to vec");
+                },
+                _ => bytes.push(u8::arbitrary(u)?),
+            }
+        }
+        Ok(ByteStream(bytes))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    Drop,
+    Flip(u8), // xor mask, never 0.
+    Insert(u8),
+    Duplicate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub offset: usize, // into the original stream.
+    pub kind: FaultKind,
+}
+
+// Damage done to a byte stream on its way over a link.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultSchedule {
+    pub faults: Vec<Fault>,
+}
+
+impl<'a> Arbitrary<'a> for FaultKind {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=3u8)? {
+            0 => FaultKind::Drop,
+            1 => FaultKind::Flip(u.int_in_range(1..=0xff)?),
+            2 => FaultKind::Insert(u8::arbitrary(u)?),
+            _ => FaultKind::Duplicate,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for FaultSchedule {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut faults = Vec::new();
+        for _ in 0..u.arbitrary_len::<(u16, u8)>()? {
+            faults.push(Fault { offset: u16::arbitrary(u)? as usize, kind: FaultKind::arbitrary(u)? });
+        }
+        faults.sort_by_key(|f| f.offset);
+        Ok(FaultSchedule { faults })
+    }
+}
+
+impl FaultSchedule {
+    pub fn is_empty(&self) -> bool {
+        self.faults.is_empty()
+    }
+
+    // Faults past the end of bytes are ignored.
+    pub fn apply(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(bytes.len());
+        for (i, b) in bytes.iter().enumerate() {
+            let mut keep = true;
+            let mut b = *b;
+            for f in self.faults.iter().filter(|f| f.offset == i) {
+                match f.kind {
+                    FaultKind::Drop => keep = false,
+                    FaultKind::Flip(mask) => b ^= mask,
+                    FaultKind::Insert(x) => out.push(x),
+                    FaultKind::Duplicate => out.push(b),
+                }
+            }
+            if keep {
+                out.push(b);
+            }
+        }
+        out
+    }
+}
+
+fn check_packet(pkt: &Packet) {
+    assert!(pkt.seq.is_valid(), "packet with seq 0x{:02x}", pkt.seq);
+    assert_eq!(pkt.code & 0x70, 0, "packet code 0x{:02x} keeps length bits", pkt.code);
+    assert!(pkt.data.len() <= PACKET_MAX_DATA_LEN, "packet of {} bytes", pkt.data.len());
+}
+
+// Fuzz entry point checking the parser's invariants; it panics on a
+// violation. From data it builds packets, encodes them after a handshake,
+// and runs the result through a Parser, first intact, then damaged by a
+// fault schedule, and finally with an arbitrary byte stream:
+//
+// - the intact stream decodes to exactly the packets sent, without the
+//   parser recovering from anything;
+// - a damaged stream or arbitrary bytes never panic and only yield
+//   packets that could have been encoded.
+pub fn fuzz_roundtrip(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let (sent, faults, stream) = match <(Vec<Packet>, FaultSchedule, ByteStream)>::arbitrary(&mut u) {
+        Ok(input) => input,
+        Err(_) => return,
+    };
+    let mut seq: PacketSeq = 1;
+    let mut wire = vec![SYNC_ACK, seq];
+    let sent: Vec<Packet> = sent.into_iter().map(|mut pkt| {
+        pkt.seq = seq;
+        seq = seq.next();
+        pkt.encode(&mut wire).expect("write to vec");
+        pkt
+    }).collect();
+
+    let mut parser = Parser::new();
+    let received: Vec<Packet> = parser.parse_iter(wire.iter().cloned()).flatten().collect();
+    assert_eq!(received, sent);
+    assert_eq!(*parser.recoveries(), Recoveries::default());
+
+    for bytes in &[faults.apply(&wire), stream.0] {
+        let mut parser = Parser::new();
+        for b in bytes {
+            if let Some(pkt) = parser.parse(*b).packet {
+                check_packet(&pkt);
+            }
+        }
+        parser.timeout();
+    }
+}
+
+#[cfg(test)]
+mod tests;
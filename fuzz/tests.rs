@@ -0,0 +1,41 @@
+#![cfg(test)]
+
+use super::*;
+
+#[test]
+fn fault_schedule_apply() {
+    let faults = FaultSchedule { faults: vec![
+        Fault { offset: 0, kind: FaultKind::Drop },
+        Fault { offset: 1, kind: FaultKind::Flip(0x0f) },
+        Fault { offset: 2, kind: FaultKind::Insert(0xaa) },
+        Fault { offset: 3, kind: FaultKind::Duplicate },
+        Fault { offset: 9, kind: FaultKind::Drop },
+    ] };
+    assert_eq!(faults.apply(&[1, 2, 3, 4]), vec![0x0d, 0xaa, 3, 4, 4]);
+    assert_eq!(FaultSchedule::default().apply(&[1, 2]), vec![1, 2]);
+}
+
+#[test]
+fn fuzz_roundtrip_smoke() {
+    // A cheap xorshift so the inputs vary without a rand dependency.
+    let mut state: u32 = 0x1234_5678;
+    for len in 0..200 {
+        let data: Vec<u8> = (0..len * 8).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect();
+        fuzz_roundtrip(&data);
+    }
+}
+
+#[test]
+fn arbitrary_packets_are_valid() {
+    let data: Vec<u8> = (0..=255).cycle().take(4096).collect();
+    let mut u = Unstructured::new(&data);
+    for _ in 0..20 {
+        let pkt = Packet::arbitrary(&mut u).unwrap();
+        assert!(pkt.seq.is_valid() && pkt.code & 0x70 == 0 && pkt.data.len() <= PACKET_MAX_DATA_LEN);
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+use super::Twist;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AckermannCommand {
+    pub steering: f64,    // front wheel angle in radians, positive turns left.
+    pub wheel_speed: f64, // drive wheel speed in rad/s.
+}
+
+// Bicycle model of a car-like robot steered by a servo on the front axle and
+// driven by a single motor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ackermann {
+    pub wheelbase: f64,      // meters between front and rear axles
+    pub wheel_radius: f64,   // meters
+    pub max_steering: f64,   // radians
+    pub max_steer_rate: f64, // radians per second
+    steering: f64,
+}
+
+impl Ackermann {
+    pub fn new(wheelbase: f64, wheel_radius: f64, max_steering: f64, max_steer_rate: f64) -> Self {
+        Ackermann {
+            wheelbase,
+            wheel_radius,
+            max_steering,
+            max_steer_rate,
+            steering: 0.0,
+        }
+    }
+
+    // The steering angle last commanded.
+    pub fn steering(&self) -> f64 {
+        self.steering
+    }
+
+    // Computes the command for speed (m/s) and curvature (1/m) after dt has
+    // elapsed since the previous command; the steering angle moves towards
+    // its target no faster than max_steer_rate.
+    pub fn command(&mut self, speed: f64, curvature: f64, dt: Duration) -> AckermannCommand {
+        let target = (self.wheelbase * curvature).atan()
+            .max(-self.max_steering)
+            .min(self.max_steering);
+        let step = self.max_steer_rate * dt.as_secs_f64();
+        self.steering += (target - self.steering).max(-step).min(step);
+        AckermannCommand {
+            steering: self.steering,
+            wheel_speed: speed / self.wheel_radius,
+        }
+    }
+
+    pub fn command_twist(&mut self, twist: &Twist, dt: Duration) -> AckermannCommand {
+        let curvature = if twist.vx.abs() > f64::EPSILON {
+            twist.omega / twist.vx
+        } else {
+            0.0
+        };
+        self.command(twist.vx, curvature, dt)
+    }
+
+    // Body twist resulting from a steering angle and drive wheel speed.
+    pub fn twist(&self, steering: f64, wheel_speed: f64) -> Twist {
+        let v = wheel_speed * self.wheel_radius;
+        Twist::new(v, v * steering.tan() / self.wheelbase)
+    }
+}
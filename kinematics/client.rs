@@ -0,0 +1,157 @@
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use l2::{Message, Sender};
+use l2::motor::MotorMessage;
+use super::{DriveKinematics, Twist, Watchdog, WatchdogTrip};
+
+// Sends twist commands to the drive motors as motor velocity messages. The
+// wheels map to consecutive motors starting at first_motor, in the order
+// produced by the kinematics.
+//
+// With a watchdog, the robot is stopped when no motion command was sent
+// within the window. The check runs from spawn_watchdog's thread, so a hung
+// command loop still stops it, or from the caller's own loop through
+// check_watchdog(), which must then be separate from the command source.
+pub struct DriveClient<K, S> {
+    kinematics: K,
+    sender: S,
+    code: u8,
+    first_motor: u8,
+    last: Option<Twist>,
+    watchdog: Option<Watchdog>,
+}
+
+impl<K: DriveKinematics, S: Sender> DriveClient<K, S> {
+    pub fn new(kinematics: K, sender: S, code: u8) -> Self {
+        DriveClient {
+            kinematics,
+            sender,
+            code,
+            first_motor: 0,
+            last: None,
+            watchdog: None,
+        }
+    }
+
+    pub fn with_first_motor(mut self, motor: u8) -> Self {
+        self.first_motor = motor;
+        self
+    }
+
+    pub fn with_watchdog(mut self, window: Duration) -> Self {
+        self.watchdog = Some(Watchdog::new(window));
+        self
+    }
+
+    pub fn drive(&mut self, twist: &Twist) -> io::Result<()> {
+        let speeds = self.kinematics.wheel_speeds(twist);
+        let msg = MotorMessage::set_velocities(self.first_motor, &speeds);
+        self.sender.send(msg.to_packet(self.code))?;
+        self.last = Some(*twist);
+        if let Some(ref mut w) = self.watchdog {
+            if *twist == Twist::zero() {
+                w.disarm();
+            } else {
+                w.refresh(Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.sender.send(MotorMessage::Stop.to_packet(self.code))?;
+        self.last = Some(Twist::zero());
+        if let Some(ref mut w) = self.watchdog {
+            w.disarm();
+        }
+        Ok(())
+    }
+
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    pub fn check_watchdog(&mut self) -> io::Result<Option<WatchdogTrip>> {
+        self.check_watchdog_at(Instant::now())
+    }
+
+    // Sends a stop and returns the trip if the watchdog expired at now.
+    pub fn check_watchdog_at(&mut self, now: Instant) -> io::Result<Option<WatchdogTrip>> {
+        let elapsed = match self.watchdog.as_mut().and_then(|w| w.check(now)) {
+            Some(elapsed) => elapsed,
+            None => return Ok(None),
+        };
+        let trip = WatchdogTrip {
+            last_command: self.last.unwrap_or_default(),
+            elapsed,
+        };
+        self.stop()?;
+        Ok(Some(trip))
+    }
+
+    // Checks the watchdog of client every period from a background thread,
+    // handing callback each trip, or the error sending its stop. The thread
+    // ends when the returned WatchdogThread or the client is dropped. The
+    // command loop must not hold the lock while it waits on anything else.
+    pub fn spawn_watchdog<F>(client: &Arc<Mutex<Self>>, period: Duration, mut callback: F) -> WatchdogThread
+        where K: Send + 'static, S: Send + 'static, F: FnMut(io::Result<WatchdogTrip>) + Send + 'static {
+        let client = Arc::downgrade(client);
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handle = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(period);
+                let client = match client.upgrade() {
+                    Some(c) => c,
+                    None => return,
+                };
+                // A command loop that panicked holding the lock is exactly
+                // when the robot still has to be stopped.
+                let result = client.lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .check_watchdog();
+                match result {
+                    Ok(None) => {},
+                    Ok(Some(trip)) => callback(Ok(trip)),
+                    Err(e) => callback(Err(e)),
+                }
+            }
+        });
+        WatchdogThread { stop, handle: Some(handle) }
+    }
+
+    // The last twist successfully sent.
+    pub fn last_command(&self) -> Option<&Twist> {
+        self.last.as_ref()
+    }
+
+    pub fn kinematics(&self) -> &K {
+        &self.kinematics
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    pub fn sender_mut(&mut self) -> &mut S {
+        &mut self.sender
+    }
+}
+
+// Stops a spawn_watchdog thread when dropped, waiting at most its period.
+pub struct WatchdogThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for WatchdogThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(h) = self.handle.take() {
+            let _ = h.join();
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use super::{DriveKinematics, Twist};
+
+// Two independently driven wheels on a common axle, track_width apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffDrive {
+    pub track_width: f64,  // meters between wheel contact points
+    pub wheel_radius: f64, // meters
+}
+
+impl DiffDrive {
+    pub fn new(track_width: f64, wheel_radius: f64) -> Self {
+        DiffDrive { track_width, wheel_radius }
+    }
+
+    // Left and right wheel speeds in rad/s.
+    pub fn wheel_speeds_lr(&self, linear: f64, angular: f64) -> (f64, f64) {
+        let half = angular * self.track_width / 2.0;
+        ((linear - half) / self.wheel_radius, (linear + half) / self.wheel_radius)
+    }
+
+    pub fn twist_lr(&self, left: f64, right: f64) -> Twist {
+        let (l, r) = (left * self.wheel_radius, right * self.wheel_radius);
+        Twist::new((l + r) / 2.0, (r - l) / self.track_width)
+    }
+}
+
+impl DriveKinematics for DiffDrive {
+    fn wheel_speeds(&self, twist: &Twist) -> Vec<f64> {
+        let (l, r) = self.wheel_speeds_lr(twist.vx, twist.omega);
+        vec![l, r]
+    }
+
+    fn twist(&self, wheel_speeds: &[f64]) -> Twist {
+        match *wheel_speeds {
+            [l, r] => self.twist_lr(l, r),
+            _ => Twist::zero(),
+        }
+    }
+}
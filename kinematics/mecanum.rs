@@ -0,0 +1,54 @@
+use super::{DriveKinematics, Twist};
+
+// Four mecanum (or 45 degree omni) wheels, ordered front-left, front-right,
+// rear-left, rear-right. half_wheelbase and half_track are the distances
+// from the center to the wheel axles and contact points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mecanum {
+    pub half_wheelbase: f64,
+    pub half_track: f64,
+    pub wheel_radius: f64,
+}
+
+impl Mecanum {
+    pub fn new(wheelbase: f64, track_width: f64, wheel_radius: f64) -> Self {
+        Mecanum {
+            half_wheelbase: wheelbase / 2.0,
+            half_track: track_width / 2.0,
+            wheel_radius,
+        }
+    }
+
+    pub fn wheel_speeds4(&self, twist: &Twist) -> [f64; 4] {
+        let k = (self.half_wheelbase + self.half_track) * twist.omega;
+        let r = self.wheel_radius;
+        [
+            (twist.vx - twist.vy - k) / r,
+            (twist.vx + twist.vy + k) / r,
+            (twist.vx + twist.vy - k) / r,
+            (twist.vx - twist.vy + k) / r,
+        ]
+    }
+
+    pub fn twist4(&self, w: &[f64; 4]) -> Twist {
+        let r = self.wheel_radius / 4.0;
+        Twist::holonomic(
+            r * (w[0] + w[1] + w[2] + w[3]),
+            r * (-w[0] + w[1] + w[2] - w[3]),
+            r * (-w[0] + w[1] - w[2] + w[3]) / (self.half_wheelbase + self.half_track),
+        )
+    }
+}
+
+impl DriveKinematics for Mecanum {
+    fn wheel_speeds(&self, twist: &Twist) -> Vec<f64> {
+        self.wheel_speeds4(twist).to_vec()
+    }
+
+    fn twist(&self, wheel_speeds: &[f64]) -> Twist {
+        match *wheel_speeds {
+            [fl, fr, rl, rr] => self.twist4(&[fl, fr, rl, rr]),
+            _ => Twist::zero(),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+mod client;
+mod watchdog;
+
+pub mod ackermann;
+pub mod diff_drive;
+pub mod mecanum;
+
+pub use self::client::*;
+pub use self::watchdog::*;
+
+// Body frame velocity command: vx forward and vy left in m/s, omega
+// counter-clockwise in rad/s.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Twist {
+    pub vx: f64,
+    pub vy: f64,
+    pub omega: f64,
+}
+
+impl Twist {
+    pub fn new(linear: f64, angular: f64) -> Self {
+        Twist { vx: linear, vy: 0.0, omega: angular }
+    }
+
+    pub fn holonomic(vx: f64, vy: f64, omega: f64) -> Self {
+        Twist { vx, vy, omega }
+    }
+
+    pub fn zero() -> Self {
+        Twist::default()
+    }
+}
+
+// Maps a body twist to wheel angular velocities in rad/s, and back for
+// odometry.
+pub trait DriveKinematics {
+    fn wheel_speeds(&self, twist: &Twist) -> Vec<f64>;
+    fn twist(&self, wheel_speeds: &[f64]) -> Twist;
+}
+
+#[cfg(test)]
+mod tests;
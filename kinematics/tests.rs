@@ -0,0 +1,188 @@
+#![cfg(test)]
+
+use std::time::Duration;
+use l0::comm::Packet;
+use l2::Message;
+use l2::motor::MotorMessage;
+use super::*;
+use super::ackermann::*;
+use super::diff_drive::*;
+use super::mecanum::*;
+
+fn assert_near(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+fn assert_twist(a: &Twist, b: &Twist) {
+    assert_near(a.vx, b.vx);
+    assert_near(a.vy, b.vy);
+    assert_near(a.omega, b.omega);
+}
+
+#[test]
+fn test_diff_drive_wheel_speeds() {
+    let dd = DiffDrive::new(0.5, 0.1);
+    assert_eq!(dd.wheel_speeds_lr(1.0, 0.0), (10.0, 10.0));
+    let (l, r) = dd.wheel_speeds_lr(0.0, 2.0);
+    assert_near(l, -5.0);
+    assert_near(r, 5.0);
+    assert_eq!(dd.twist(&[1.0]), Twist::zero());
+}
+
+#[test]
+fn test_diff_drive_inverse() {
+    let dd = DiffDrive::new(0.3, 0.05);
+    let t = Twist::new(0.4, -1.2);
+    assert_twist(&dd.twist(&dd.wheel_speeds(&t)), &t);
+}
+
+#[test]
+fn test_drive_client() {
+    let mut sent: Vec<Packet> = Vec::new();
+    {
+        let mut c = DriveClient::new(DiffDrive::new(0.5, 0.1), &mut sent, 4).with_first_motor(2);
+        assert!(c.last_command().is_none());
+        c.drive(&Twist::new(0.1, 0.0)).unwrap();
+        c.stop().unwrap();
+        assert_eq!(c.last_command(), Some(&Twist::zero()));
+    }
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].code, 4);
+    assert_eq!(MotorMessage::from_packet(&sent[0]),
+        Some(MotorMessage::SetVelocities { first: 2, velocities: vec![100, 100] }));
+    assert_eq!(MotorMessage::from_packet(&sent[1]), Some(MotorMessage::Stop));
+}
+
+#[test]
+fn test_mecanum_wheel_speeds() {
+    let m = Mecanum::new(0.4, 0.3, 0.05);
+    assert_eq!(m.wheel_speeds4(&Twist::new(1.0, 0.0)), [20.0, 20.0, 20.0, 20.0]);
+    assert_eq!(m.wheel_speeds4(&Twist::holonomic(0.0, 1.0, 0.0)), [-20.0, 20.0, 20.0, -20.0]);
+    let w = m.wheel_speeds4(&Twist::holonomic(0.0, 0.0, 1.0));
+    assert_near(w[0], -7.0);
+    assert_near(w[1], 7.0);
+    assert_near(w[2], -7.0);
+    assert_near(w[3], 7.0);
+}
+
+#[test]
+fn test_mecanum_inverse() {
+    let m = Mecanum::new(0.4, 0.3, 0.05);
+    let t = Twist::holonomic(0.3, -0.2, 0.7);
+    assert_twist(&m.twist(&m.wheel_speeds(&t)), &t);
+    assert_eq!(m.twist(&[1.0, 2.0]), Twist::zero());
+}
+
+#[test]
+fn test_mecanum_drive_client() {
+    let mut sent: Vec<Packet> = Vec::new();
+    DriveClient::new(Mecanum::new(0.4, 0.3, 0.05), &mut sent, 1)
+        .drive(&Twist::holonomic(0.0, 0.1, 0.0)).unwrap();
+    assert_eq!(MotorMessage::from_packet(&sent[0]),
+        Some(MotorMessage::SetVelocities { first: 0, velocities: vec![-200, 200, 200, -200] }));
+}
+
+#[test]
+fn test_ackermann_steering_limits() {
+    let mut a = Ackermann::new(0.25, 0.05, 0.5, 1.0);
+    let cmd = a.command(1.0, 1.0, Duration::from_millis(100));
+    assert_near(cmd.steering, 0.1);
+    assert_near(cmd.wheel_speed, 20.0);
+    for _ in 0..10 {
+        a.command(1.0, 100.0, Duration::from_millis(100));
+    }
+    assert_near(a.steering(), 0.5);
+    let cmd = a.command(1.0, 0.0, Duration::from_secs(10));
+    assert_near(cmd.steering, 0.0);
+}
+
+#[test]
+fn test_ackermann_inverse() {
+    let mut a = Ackermann::new(0.25, 0.05, 0.6, 100.0);
+    let t = Twist::new(0.5, 1.0);
+    let cmd = a.command_twist(&t, Duration::from_secs(1));
+    assert_twist(&a.twist(cmd.steering, cmd.wheel_speed), &t);
+    assert_near(a.command_twist(&Twist::new(0.0, 1.0), Duration::from_secs(1)).steering, 0.0);
+}
+
+#[test]
+fn test_drive_client_watchdog() {
+    use std::time::Instant;
+
+    let mut sent: Vec<Packet> = Vec::new();
+    let mut c = DriveClient::new(DiffDrive::new(0.5, 0.1), &mut sent, 1)
+        .with_watchdog(Duration::from_millis(200));
+    let start = Instant::now();
+    assert_eq!(c.check_watchdog_at(start + Duration::from_secs(1)).unwrap(), None);
+
+    c.drive(&Twist::new(0.5, 0.0)).unwrap();
+    let later = Instant::now() + Duration::from_millis(300);
+    let trip = c.check_watchdog_at(later).unwrap().unwrap();
+    assert_eq!(trip.last_command, Twist::new(0.5, 0.0));
+    assert!(trip.elapsed >= Duration::from_millis(300));
+    assert_eq!(c.last_command(), Some(&Twist::zero()));
+    assert_eq!(c.watchdog().unwrap().trips(), 1);
+    // tripping disarms until the next motion command.
+    assert_eq!(c.check_watchdog_at(later + Duration::from_secs(1)).unwrap(), None);
+
+    c.drive(&Twist::new(0.5, 0.0)).unwrap();
+    c.drive(&Twist::zero()).unwrap();
+    assert!(!c.watchdog().unwrap().is_armed());
+    assert_eq!(MotorMessage::from_packet(&c.sender()[1]), Some(MotorMessage::Stop));
+}
+
+#[test]
+fn test_drive_client_watchdog_thread() {
+    use std::sync::{mpsc, Arc, Mutex};
+
+    let c = DriveClient::new(DiffDrive::new(0.5, 0.1), Vec::<Packet>::new(), 1)
+        .with_watchdog(Duration::from_millis(50));
+    let c = Arc::new(Mutex::new(c));
+    let (tx, rx) = mpsc::channel();
+    let _thread = DriveClient::spawn_watchdog(&c, Duration::from_millis(10), move |trip| {
+        let _ = tx.send(trip.unwrap());
+    });
+    c.lock().unwrap().drive(&Twist::new(0.5, 0.0)).unwrap();
+    // no more commands: the thread stops the robot on its own.
+    let trip = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(trip.last_command, Twist::new(0.5, 0.0));
+    let c = c.lock().unwrap();
+    assert_eq!(MotorMessage::from_packet(c.sender().last().unwrap()), Some(MotorMessage::Stop));
+}
+
+#[test]
+fn test_drive_client_watchdog_thread_poisoned() {
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+
+    let c = DriveClient::new(DiffDrive::new(0.5, 0.1), Vec::<Packet>::new(), 1)
+        .with_watchdog(Duration::from_millis(50));
+    let c = Arc::new(Mutex::new(c));
+    let (tx, rx) = mpsc::channel();
+    let _thread = DriveClient::spawn_watchdog(&c, Duration::from_millis(10), move |trip| {
+        let _ = tx.send(trip.unwrap());
+    });
+    let c2 = c.clone();
+    let _ = thread::spawn(move || {
+        let mut c = c2.lock().unwrap();
+        c.drive(&Twist::new(0.5, 0.0)).unwrap();
+        panic!("command loop died");
+    }).join();
+    assert!(c.is_poisoned());
+    let trip = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(trip.last_command, Twist::new(0.5, 0.0));
+    let c = c.lock().unwrap_or_else(|e| e.into_inner());
+    assert_eq!(MotorMessage::from_packet(c.sender().last().unwrap()), Some(MotorMessage::Stop));
+}
+
+#[test]
+fn test_watchdog() {
+    use std::time::Instant;
+
+    let t0 = Instant::now();
+    let mut w = Watchdog::new(Duration::from_millis(100));
+    w.refresh(t0);
+    assert_eq!(w.check(t0 + Duration::from_millis(99)), None);
+    assert_eq!(w.check(t0 + Duration::from_millis(100)), Some(Duration::from_millis(100)));
+    assert!(!w.is_armed());
+}
@@ -0,0 +1,55 @@
+use std::time::{Duration, Instant};
+use super::Twist;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchdogTrip {
+    pub last_command: Twist,
+    pub elapsed: Duration, // since the last refresh.
+}
+
+// Trips when armed and not refreshed within window. Tripping disarms it
+// until the next refresh.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    window: Duration,
+    armed_at: Option<Instant>,
+    trips: u64,
+}
+
+impl Watchdog {
+    pub fn new(window: Duration) -> Self {
+        Watchdog { window, armed_at: None, trips: 0 }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn refresh(&mut self, now: Instant) {
+        self.armed_at = Some(now);
+    }
+
+    pub fn disarm(&mut self) {
+        self.armed_at = None;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed_at.is_some()
+    }
+
+    // Total number of trips so far.
+    pub fn trips(&self) -> u64 {
+        self.trips
+    }
+
+    // Returns the time since the last refresh if the watchdog trips now.
+    pub fn check(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.armed_at?);
+        if elapsed < self.window {
+            return None;
+        }
+        self.armed_at = None;
+        self.trips += 1;
+        Some(elapsed)
+    }
+}
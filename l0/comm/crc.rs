@@ -0,0 +1,58 @@
+// An incremental CRC, fed a frame a piece at a time and read once at the
+// end, which is how CRC peripherals work: implement it on one to take the
+// checksum off the CPU, where framing code is generic over it.
+pub trait Crc {
+    // Starts a new checksum.
+    fn reset(&mut self);
+    fn update(&mut self, bytes: &[u8]);
+    // The checksum of what was fed since the reset.
+    fn finish(&mut self) -> u16;
+
+    fn checksum(&mut self, bytes: &[u8]) -> u16 {
+        self.reset();
+        self.update(bytes);
+        self.finish()
+    }
+}
+
+// One byte of CRC-16/MCRF4XX, for SoftCrc16 and compile-time frames.
+pub const fn crc16_step(crc: u16, b: u8) -> u16 {
+    let mut t = b ^ (crc as u8);
+    t ^= t << 4;
+    (crc >> 8) ^ ((t as u16) << 8) ^ ((t as u16) << 3) ^ ((t as u16) >> 4)
+}
+
+// CRC-16/MCRF4XX (reflected 0x1021, initial 0xffff, as MAVLink uses) in
+// software.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoftCrc16 {
+    crc: u16,
+}
+
+impl Default for SoftCrc16 {
+    fn default() -> Self {
+        SoftCrc16::new()
+    }
+}
+
+impl SoftCrc16 {
+    pub const fn new() -> Self {
+        SoftCrc16 { crc: 0xffff }
+    }
+}
+
+impl Crc for SoftCrc16 {
+    fn reset(&mut self) {
+        self.crc = 0xffff;
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.crc = crc16_step(self.crc, b);
+        }
+    }
+
+    fn finish(&mut self) -> u16 {
+        self.crc
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use super::parser::*;
+
+// Parses the traffic of many peers on a shared medium, such as CAN IDs or
+// UDP peer addresses, with a Parser per source tag, so one receive loop
+// can serve them all without one peer's garbage or handshake disturbing
+// another's sync. A source's parser is made, with the config given, on its
+// first bytes.
+pub struct Demux<K> {
+    config: SyncConfig,
+    parsers: HashMap<K, Parser>,
+}
+
+impl<K: Eq + Hash> Default for Demux<K> {
+    fn default() -> Self {
+        Demux::new()
+    }
+}
+
+impl<K: Eq + Hash> Demux<K> {
+    pub fn new() -> Self {
+        Demux::with_config(SyncConfig::default())
+    }
+
+    pub fn with_config(config: SyncConfig) -> Self {
+        Demux { config, parsers: HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parsers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parsers.is_empty()
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &K> {
+        self.parsers.keys()
+    }
+
+    pub fn parser(&self, tag: &K) -> Option<&Parser> {
+        self.parsers.get(tag)
+    }
+
+    // Forgets a source, as when its peer goes away.
+    pub fn remove(&mut self, tag: &K) -> Option<Parser> {
+        self.parsers.remove(tag)
+    }
+
+    // Parses bytes from tag, handing f each status and, when it completed
+    // one, the packet, as Parser::parse_slice_budgeted does. A sync in a
+    // status goes back to tag. Returns the packets completed.
+    pub fn parse<F>(&mut self, tag: K, bytes: &[u8], f: F) -> usize
+        where F: FnMut(ParseStatus, Option<Frame<'_>>) {
+        let config = self.config;
+        let parser = self.parsers.entry(tag).or_insert_with(|| Parser::with_config(config));
+        parser.parse_slice_budgeted(bytes, usize::MAX, f).1
+    }
+
+    // Ends tag's frame in progress, for idle-gap framing, as at the end of
+    // a datagram.
+    pub fn idle(&mut self, tag: &K) -> Option<ParseStatus> {
+        self.parsers.get_mut(tag).map(|p| p.idle_status())
+    }
+}
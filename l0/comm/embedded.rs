@@ -0,0 +1,323 @@
+use core::fmt;
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::{ErrorType, Read, Write};
+use super::isr::IsrConsumer;
+use super::packet::*;
+use super::parser::*;
+use super::stats::LinkStats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkError<E> {
+    Serial(E),
+    NotReady, // the handshake isn't done, or the link is suspended.
+    TooLong,  // payload longer than the link takes.
+    Full,     // no room to queue the packet yet.
+}
+
+impl<E: fmt::Display> fmt::Display for LinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LinkError::Serial(ref e) => write!(f, "serial error: {}", e),
+            LinkError::NotReady => f.write_str("link not ready"),
+            LinkError::TooLong => f.write_str("payload too long"),
+            LinkError::Full => f.write_str("no room to queue"),
+        }
+    }
+}
+
+impl<E> From<E> for LinkError<E> {
+    fn from(e: E) -> Self {
+        LinkError::Serial(e)
+    }
+}
+
+// The device side of a link over an embedded-hal serial port, for firmware.
+// It never allocates: packets are parsed in place and borrowed from the
+// link until the next poll. Call tick from a periodic timer; after
+// timeout_ticks of them mid-frame, the link drops the frame and resyncs.
+pub struct EmbeddedLink<S> {
+    serial: S,
+    parser: Parser,
+    seq: PacketSeq,
+    ready: bool,
+    synced: bool,
+    suspended: bool,
+    timeout_ticks: u32,
+    timer: Option<u32>, // ticks left, while running.
+    stats: LinkStats,
+    epoch: Option<u8>,
+}
+
+impl<S, E> EmbeddedLink<S> where S: Read<u8> + Write<u8> + ErrorType<Error = E> {
+    // Starts the handshake by sending a sync request.
+    pub fn new(serial: S, timeout_ticks: u32) -> Result<Self, E> {
+        EmbeddedLink::start(serial, timeout_ticks, None, SyncConfig::default())
+    }
+
+    // With idle-gap framing, call idle from the UART's idle line interrupt,
+    // once the bytes before it are polled. The link is ready at once.
+    pub fn with_sync_config(serial: S, timeout_ticks: u32, config: SyncConfig) -> Result<Self, E> {
+        EmbeddedLink::start(serial, timeout_ticks, None, config)
+    }
+
+    // Sends epoch, a boot count kept in flash or backup RAM, with each
+    // handshake so the host can tell a reboot from a glitch. Counts wrap
+    // below 0xf0.
+    pub fn with_epoch(serial: S, timeout_ticks: u32, epoch: u8) -> Result<Self, E> {
+        EmbeddedLink::start(serial, timeout_ticks, Some(epoch % 0xf0), SyncConfig::default())
+    }
+
+    fn start(serial: S, timeout_ticks: u32, epoch: Option<u8>, config: SyncConfig) -> Result<Self, E> {
+        let mut link = EmbeddedLink::with_parser(serial, timeout_ticks, epoch, Parser::with_config(config));
+        let mut s = link.parser.reset_status();
+        if config.framing == Framing::IdleGap {
+            s = link.parser.idle_status();
+        }
+        link.apply(s)?;
+        Ok(link)
+    }
+
+    // Carries on from a snapshot kept in RAM through a suspend, without a
+    // handshake; None if it isn't a snapshot of this version.
+    pub fn restore(serial: S, timeout_ticks: u32, snapshot: &[u8]) -> Option<Self> {
+        let (parser, seq, epoch) = match *snapshot {
+            [ref parser @ .., seq, epoch] if snapshot.len() == LINK_SNAPSHOT_LEN => (parser, seq, epoch),
+            _ => return None,
+        };
+        let parser = Parser::restore(parser)?;
+        if !seq.is_valid() || (epoch >= 0xf0 && epoch != 0xff) {
+            return None;
+        }
+        let epoch = if epoch == 0xff { None } else { Some(epoch) };
+        let s = parser.status();
+        let mut link = EmbeddedLink::with_parser(serial, timeout_ticks, epoch, parser);
+        link.seq = seq;
+        link.ready = s.state.is_ready();
+        link.synced = link.ready;
+        link.suspended = s.state.is_suspended();
+        Some(link)
+    }
+
+    // What restore needs: the parser's snapshot, then the next seq to send
+    // and the epoch, 0xff for none.
+    pub fn snapshot(&self) -> [u8; LINK_SNAPSHOT_LEN] {
+        let mut snapshot = [0; LINK_SNAPSHOT_LEN];
+        snapshot[..PARSER_SNAPSHOT_LEN].copy_from_slice(&self.parser.snapshot());
+        snapshot[PARSER_SNAPSHOT_LEN] = self.seq;
+        snapshot[PARSER_SNAPSHOT_LEN + 1] = self.epoch.unwrap_or(0xff);
+        snapshot
+    }
+
+    fn with_parser(serial: S, timeout_ticks: u32, epoch: Option<u8>, parser: Parser) -> Self {
+        EmbeddedLink {
+            serial,
+            parser,
+            seq: 1,
+            ready: false,
+            synced: false,
+            suspended: false,
+            timeout_ticks,
+            timer: None,
+            stats: LinkStats::default(),
+            epoch,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    // True after a suspend handshake, until either side resumes the link.
+    // The UART can sleep meanwhile; bytes received are ignored until a
+    // sync request wakes the link.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    pub fn recoveries(&self) -> &Recoveries {
+        self.parser.recoveries()
+    }
+
+    pub fn phase(&self) -> LinkPhase {
+        self.parser.phase()
+    }
+
+    pub fn expected_seq(&self) -> Option<PacketSeq> {
+        self.parser.expected_seq()
+    }
+
+    pub fn serial(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        for b in bytes {
+            nb::block!(self.serial.write(*b))?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, s: ParseStatus) -> Result<(), E> {
+        // A ping from the host passes through a state that isn't ready, as
+        // for Endpoint; the link isn't lost unless its seq is bad.
+        let ready = s.state.is_ready() || (self.ready && s.state.is_receiving());
+        if ready && !self.ready {
+            if self.synced {
+                self.stats.resyncs += 1;
+            }
+            self.synced = true;
+        }
+        self.ready = ready;
+        self.suspended = s.state.is_suspended();
+        match s.timer_action() {
+            TimerAction::Restart => self.timer = Some(self.timeout_ticks),
+            TimerAction::Stop => self.timer = None,
+            TimerAction::NoChange => {},
+        }
+        if s.sync != 0 {
+            if let (SYNC_REQ, false, Some(epoch)) = (s.sync, self.ready, self.epoch) {
+                self.write(&[EPOCH, epoch])?;
+                self.stats.bytes_sent += 2;
+            }
+            let (sync, seq) = (self.parser.config().wire(s.sync), self.seq);
+            self.write(&[sync, seq])?;
+            self.stats.bytes_sent += 2;
+        }
+        Ok(())
+    }
+
+    // True when b completed a packet.
+    fn parse(&mut self, b: u8) -> Result<bool, E> {
+        self.stats.bytes_received += 1;
+        let s = self.parser.parse_frame(b);
+        self.apply(s)?;
+        if s.packet {
+            self.stats.packets_received += 1;
+        }
+        Ok(s.packet)
+    }
+
+    // Reads what the port has received, answering sync requests, and
+    // returns the first complete packet.
+    pub fn poll(&mut self) -> Result<Option<Frame<'_>>, E> {
+        loop {
+            let b = match self.serial.read() {
+                Ok(b) => b,
+                Err(nb::Error::WouldBlock) => return Ok(None),
+                Err(nb::Error::Other(e)) => return Err(e),
+            };
+            if self.parse(b)? {
+                return Ok(Some(self.parser.frame()));
+            }
+        }
+    }
+
+    // Like poll, for bytes an interrupt handler queued rather than the port.
+    // Sync responses still go out through the port.
+    pub fn poll_queue<const N: usize>(&mut self, rx: &mut IsrConsumer<'_, N>) -> Result<Option<Frame<'_>>, E> {
+        self.stats.overflows = rx.overflows() as u64;
+        while let Some(b) = rx.pop() {
+            if self.parse(b)? {
+                return Ok(Some(self.parser.frame()));
+            }
+        }
+        Ok(None)
+    }
+
+    // The line went idle, ending the frame in progress under idle-gap
+    // framing; a frame cut short is dropped.
+    pub fn idle(&mut self) -> Result<(), E> {
+        let s = self.parser.idle_status();
+        self.apply(s)
+    }
+
+    // Counts down the receive timer.
+    pub fn tick(&mut self) -> Result<(), E> {
+        match self.timer {
+            Some(0) | Some(1) => {
+                self.timer = None;
+                let s = self.parser.timeout_status();
+                self.apply(s)
+            },
+            Some(n) => {
+                self.timer = Some(n - 1);
+                Ok(())
+            },
+            None => Ok(()),
+        }
+    }
+
+    // Asks the host to suspend the link; once it acks, is_suspended is
+    // true and the device can sleep.
+    pub fn suspend(&mut self) -> Result<(), LinkError<E>> {
+        if !self.ready || self.suspended {
+            return Err(LinkError::NotReady);
+        }
+        let seq = self.seq;
+        self.write(&[SUSPEND_REQ, seq])?;
+        self.stats.bytes_sent += 2;
+        Ok(())
+    }
+
+    // Wakes the host after sleeping, or a suspended link the host asked
+    // for. Packets can be sent again right away.
+    pub fn resume(&mut self) -> Result<(), E> {
+        self.write(&[WAKE])?;
+        self.stats.bytes_sent += 1;
+        let s = self.parser.resume_status();
+        self.apply(s)
+    }
+
+    // Asks the host to hold all but urgent packets, as when the receive
+    // queue is nearly full, until flow_on or the next handshake.
+    pub fn flow_off(&mut self) -> Result<(), LinkError<E>> {
+        self.flow(FLOW_OFF)
+    }
+
+    pub fn flow_on(&mut self) -> Result<(), LinkError<E>> {
+        self.flow(FLOW_ON)
+    }
+
+    fn flow(&mut self, sync: u8) -> Result<(), LinkError<E>> {
+        if !self.ready || self.suspended {
+            return Err(LinkError::NotReady);
+        }
+        let seq = self.seq;
+        self.write(&[sync, seq])?;
+        self.stats.bytes_sent += 2;
+        Ok(())
+    }
+
+    // Assigns the next sequence number and writes the packet, blocking
+    // until the port has taken it.
+    pub fn send(&mut self, code: u8, data: &[u8]) -> Result<(), LinkError<E>> {
+        if !self.ready || self.suspended {
+            return Err(LinkError::NotReady);
+        }
+        let config = self.parser.config();
+        if data.len() > config.max_len as usize {
+            return Err(LinkError::TooLong);
+        }
+        let (head, n) = frame_head(self.seq, code, data.len());
+        let (tail, t) = config.frame_tail(&head[..n], data);
+        self.seq = self.seq.next();
+        self.write(&head[..n])?;
+        self.write(data)?;
+        self.write(&tail[..t])?;
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += (n + data.len() + t) as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), E> {
+        nb::block!(self.serial.flush())
+    }
+}
@@ -0,0 +1,751 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::packet::*;
+use super::parser::*;
+use super::pool::*;
+use super::rtt::*;
+use super::stats::LinkStats;
+
+// Structured events when built with tracing, nothing otherwise.
+#[cfg(feature = "tracing")]
+macro_rules! link_event {
+    ($level:ident, $($arg:tt)*) => { ::tracing::$level!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! link_event {
+    ($level:ident, $($arg:tt)*) => {};
+}
+
+// Changes in the state of a link, for Endpoint::on_event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    Synchronized, // the first handshake completed.
+    Resynced,     // synchronized again after losing sync.
+    PeerRebooted, // the peer sent a new epoch with its handshake.
+    LostSync,
+    PeerClosing,  // the peer sent FIN; flush, then close.
+    Closed,
+}
+
+impl fmt::Display for LinkEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            LinkEvent::Synchronized => "synchronized",
+            LinkEvent::Resynced => "resynced",
+            LinkEvent::PeerRebooted => "peer rebooted",
+            LinkEvent::LostSync => "lost sync",
+            LinkEvent::PeerClosing => "peer closing",
+            LinkEvent::Closed => "closed",
+        })
+    }
+}
+
+// A packet with when it arrived: rx is the host time its last bytes were
+// read, hw the transport's own timestamp of that read, where it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamped {
+    pub pkt: Packet,
+    pub rx: Instant,
+    pub hw: Option<Duration>,
+}
+
+impl Stamped {
+    // How long since the packet arrived, to extrapolate a sample to now.
+    pub fn age(&self) -> Duration {
+        self.rx.elapsed()
+    }
+}
+
+// Runs the l0 protocol on a byte stream: the sync handshake, sequence
+// numbering of outgoing packets and parsing of incoming ones. Either side
+// of a link can use it.
+pub struct Endpoint<T> {
+    device: String,
+    transport: T,
+    parser: Parser,
+    seq: PacketSeq,
+    ready: bool,
+    synced: bool,
+    suspended: bool,
+    stats: LinkStats,
+    prev: u8,  // the last byte received.
+    acks: u64, // sync acks received.
+    rtt: AdaptiveTimeout,
+    timer: Option<Instant>,     // the receive timer, while running.
+    sync_sent: Option<Instant>, // the last sync request, until acked.
+    backlog: Vec<u8>, // read but left unparsed by poll_budgeted.
+    epoch: Option<u8>,
+    peer_epoch: Option<u8>, // as of the last handshake.
+    setup: Vec<Packet>,
+    callbacks: Vec<Box<dyn FnMut(LinkEvent) + Send>>,
+    closing: bool,      // FIN sent.
+    peer_closing: bool, // FIN received.
+    closed: bool,
+    hw_clock: Option<Box<dyn FnMut() -> Option<Duration> + Send>>,
+    rx: (Instant, Option<Duration>), // the stamp of the last read.
+    urgent: Vec<u8>,
+    held: VecDeque<Packet>,     // by the peer's FLOW_OFF.
+    flow_off: Option<Instant>,  // since the peer's FLOW_OFF.
+}
+
+impl<T: Read + Write> Endpoint<T> {
+    // Starts the handshake by sending a sync request.
+    pub fn new(transport: T) -> io::Result<Self> {
+        Endpoint::named(transport, "")
+    }
+
+    // device identifies the peer in trace events.
+    pub fn named(transport: T, device: &str) -> io::Result<Self> {
+        Endpoint::start(transport, device, None, SyncConfig::default())
+    }
+
+    // With idle-gap framing the end of each short read is taken as the
+    // line going idle, so the transport's reads must return at one, as a
+    // serial port set to return on an inter-byte timeout does. The link
+    // is ready at once.
+    pub fn with_sync_config(transport: T, device: &str, config: SyncConfig) -> io::Result<Self> {
+        Endpoint::start(transport, device, None, config)
+    }
+
+    // For a device keeping a boot count: epoch goes with each handshake,
+    // so the peer tells this boot from the last. Counts wrap below 0xf0;
+    // restart moves to the next.
+    pub fn with_epoch(transport: T, device: &str, epoch: u8) -> io::Result<Self> {
+        Endpoint::start(transport, device, Some(epoch % 0xf0), SyncConfig::default())
+    }
+
+    fn start(transport: T, device: &str, epoch: Option<u8>, config: SyncConfig) -> io::Result<Self> {
+        let mut ep = Endpoint::with_parser(transport, device, epoch, Parser::with_config(config));
+        ep.begin()?;
+        ep.synced = ep.ready;
+        Ok(ep)
+    }
+
+    // Carries on a link from a snapshot, as after the host process
+    // restarts, without a handshake; InvalidData if it isn't a snapshot of
+    // this version.
+    pub fn restore(transport: T, device: &str, snapshot: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "bad link snapshot");
+        let (parser, seq, epoch) = match *snapshot {
+            [ref parser @ .., seq, epoch] if snapshot.len() == LINK_SNAPSHOT_LEN => (parser, seq, epoch),
+            _ => return Err(invalid()),
+        };
+        let parser = Parser::restore(parser).ok_or_else(invalid)?;
+        if !seq.is_valid() || (epoch >= 0xf0 && epoch != 0xff) {
+            return Err(invalid());
+        }
+        let epoch = if epoch == 0xff { None } else { Some(epoch) };
+        let s = parser.status();
+        let mut ep = Endpoint::with_parser(transport, device, epoch, parser);
+        ep.seq = seq;
+        ep.ready = s.state.is_ready();
+        ep.suspended = s.state.is_suspended();
+        ep.closed = s.state.is_closed();
+        ep.synced = s.state.is_ready() || ep.closed;
+        ep.peer_epoch = ep.parser.peer_epoch();
+        ep.peer_closing = s.state.is_closing();
+        if s.state.is_flow_off() {
+            ep.flow_off = Some(Instant::now());
+        }
+        Ok(ep)
+    }
+
+    // What restore needs: the parser's snapshot, then the next seq to send
+    // and the epoch, 0xff for none. Take it between polls.
+    pub fn snapshot(&self) -> [u8; LINK_SNAPSHOT_LEN] {
+        let mut snapshot = [0; LINK_SNAPSHOT_LEN];
+        snapshot[..PARSER_SNAPSHOT_LEN].copy_from_slice(&self.parser.snapshot());
+        snapshot[PARSER_SNAPSHOT_LEN] = self.seq;
+        snapshot[PARSER_SNAPSHOT_LEN + 1] = self.epoch.unwrap_or(0xff);
+        snapshot
+    }
+
+    fn with_parser(transport: T, device: &str, epoch: Option<u8>, parser: Parser) -> Self {
+        Endpoint {
+            device: device.to_string(),
+            transport,
+            parser,
+            seq: 1,
+            ready: false,
+            synced: false,
+            suspended: false,
+            stats: LinkStats::default(),
+            prev: 0,
+            acks: 0,
+            rtt: AdaptiveTimeout::new(TimeoutConfig::default()),
+            timer: None,
+            sync_sent: None,
+            backlog: Vec::new(),
+            epoch,
+            peer_epoch: None,
+            setup: Vec::new(),
+            callbacks: Vec::new(),
+            closing: false,
+            peer_closing: false,
+            closed: false,
+            hw_clock: None,
+            rx: (Instant::now(), None),
+            urgent: Vec::new(),
+            held: VecDeque::new(),
+            flow_off: None,
+        }
+    }
+
+    // Sends a sync request to start the handshake, or with idle-gap
+    // framing, is ready from the next byte.
+    fn begin(&mut self) -> io::Result<()> {
+        let mut s = self.parser.reset_status();
+        if self.parser.framing() == Framing::IdleGap {
+            s = self.parser.idle_status();
+        }
+        self.ready = s.state.is_ready();
+        self.apply_timer(s, Instant::now());
+        self.respond(s.sync)
+    }
+
+    // Starts the handshake over from seq 1, as a device does on reboot.
+    pub fn restart(&mut self) -> io::Result<()> {
+        self.seq = 1;
+        self.ready = false;
+        self.suspended = false;
+        self.closing = false;
+        self.peer_closing = false;
+        self.closed = false;
+        self.prev = 0;
+        self.epoch = self.epoch.map(|e| (e + 1) % 0xf0);
+        self.begin()
+    }
+
+    // True once the peer's sequence is known, so packets can be sent.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    // True while the peer sleeps, after a suspend handshake.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    // True after close, or the peer's, until a new handshake.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    // True while the peer holds packets off with FLOW_OFF.
+    pub fn is_flow_off(&self) -> bool {
+        self.flow_off.is_some()
+    }
+
+    // Packets waiting for the peer's FLOW_ON.
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    // Packets on these codes, such as e-stop, are sent even while the
+    // peer holds the rest off.
+    pub fn set_urgent(&mut self, codes: &[u8]) {
+        self.urgent = codes.to_vec();
+    }
+
+    // True once the peer sent FIN, until this side closes too.
+    pub fn is_peer_closing(&self) -> bool {
+        self.peer_closing
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    // Times the parser gave up on a frame or handshake, by cause.
+    pub fn recoveries(&self) -> &Recoveries {
+        self.parser.recoveries()
+    }
+
+    pub fn phase(&self) -> LinkPhase {
+        self.parser.phase()
+    }
+
+    pub fn expected_seq(&self) -> Option<PacketSeq> {
+        self.parser.expected_seq()
+    }
+
+    // Sync acks received, including those of the handshake.
+    pub fn acks(&self) -> u64 {
+        self.acks
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    // This side's boot count, when it keeps one.
+    pub fn epoch(&self) -> Option<u8> {
+        self.epoch
+    }
+
+    // The boot count the peer sent with its last handshake.
+    pub fn peer_epoch(&self) -> Option<u8> {
+        self.peer_epoch
+    }
+
+    // Packets sent again each time the peer reboots, such as the
+    // parameters and subscriptions it forgets.
+    pub fn set_setup(&mut self, pkts: Vec<Packet>) {
+        self.setup = pkts;
+    }
+
+    pub fn on_event<F>(&mut self, callback: F) where F: FnMut(LinkEvent) + Send + 'static {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    fn emit(&mut self, event: LinkEvent) {
+        for cb in self.callbacks.iter_mut() {
+            cb(event);
+        }
+    }
+
+    // The smoothed round trip of sync requests, the handshake's and pings'.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt.rtt()
+    }
+
+    // How long a packet may take to arrive, or the peer to answer the
+    // handshake, before check_timeout gives up on it.
+    pub fn timeout(&self) -> Duration {
+        self.rtt.timeout()
+    }
+
+    // Forgets the round trips measured so far.
+    pub fn set_timeout_config(&mut self, config: TimeoutConfig) {
+        self.rtt = AdaptiveTimeout::new(config);
+    }
+
+    fn apply_timer(&mut self, s: ParseStatus, now: Instant) {
+        match s.timer_action() {
+            TimerAction::Restart => self.timer = Some(now),
+            TimerAction::Stop => self.timer = None,
+            TimerAction::NoChange => {},
+        }
+    }
+
+    fn respond(&mut self, sync: u8) -> io::Result<()> {
+        if sync != 0 {
+            link_event!(debug, device = %self.device, seq = self.seq,
+                phase = match sync {
+                    SYNC_REQ => "request",
+                    SYNC_ACK => "ack",
+                    SUSPEND_REQ => "suspend request",
+                    SUSPEND_ACK => "suspend ack",
+                    FIN => "fin",
+                    FIN_ACK => "fin ack",
+                    FLOW_OFF => "flow off",
+                    _ => "flow on",
+                }, "sync sent");
+            if let (SYNC_REQ, false, Some(epoch)) = (sync, self.ready, self.epoch) {
+                self.transport.write_all(&[EPOCH, epoch])?;
+                self.stats.bytes_sent += 2;
+            }
+            self.transport.write_all(&[self.parser.config().wire(sync), self.seq])?;
+            self.stats.bytes_sent += 2;
+            if sync == SYNC_REQ {
+                self.sync_sent = Some(Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    // Reads what the transport has buffered, answering sync requests and
+    // returning complete packets.
+    pub fn poll(&mut self) -> io::Result<Vec<Packet>> {
+        let mut pkts = Vec::new();
+        self.receive(usize::MAX, usize::MAX, |f, _| pkts.push(f.to_packet()))?;
+        Ok(pkts)
+    }
+
+    // Like poll, with each packet's receive time.
+    pub fn poll_stamped(&mut self) -> io::Result<Vec<Stamped>> {
+        let mut pkts = Vec::new();
+        self.receive(usize::MAX, usize::MAX, |f, (rx, hw)| {
+            pkts.push(Stamped { pkt: f.to_packet(), rx, hw })
+        })?;
+        Ok(pkts)
+    }
+
+    // clock reads the transport's timestamp of the data just read, such as
+    // a UART's capture register or a socket's SO_TIMESTAMP, for
+    // poll_stamped; it's called after each read.
+    pub fn set_hw_clock<F>(&mut self, clock: F) where F: FnMut() -> Option<Duration> + Send + 'static {
+        self.hw_clock = Some(Box::new(clock));
+    }
+
+    // Like poll, stopping after max_packets packets or max_bytes bytes
+    // parsed, so a control loop can bound the time it spends here per tick.
+    // Bytes read past the budget wait for the next poll. The flag is true
+    // when the budget ran out, so there may be more to do.
+    pub fn poll_budgeted(&mut self, max_packets: usize, max_bytes: usize) -> io::Result<(Vec<Packet>, bool)> {
+        let mut pkts = Vec::new();
+        let more = self.receive(max_packets, max_bytes, |f, _| pkts.push(f.to_packet()))?;
+        Ok((pkts, more))
+    }
+
+    // Like poll, appending packets taken from pool to out, so that with both
+    // reused receiving allocates nothing. When the pool runs dry, a
+    // DropOldest pool gives up the packet at the front of out, and an Error
+    // pool drops the new packet and fails the poll once all is read.
+    pub fn poll_pooled(&mut self, pool: &PacketPool, out: &mut VecDeque<Recycled<Packet>>) -> io::Result<usize> {
+        let mut received = 0;
+        let mut dropped = 0;
+        self.receive(usize::MAX, usize::MAX, |f, _| {
+            let pkt = match pool.get() {
+                None if pool.policy() == PoolPolicy::DropOldest => out.pop_front(),
+                pkt => pkt,
+            };
+            match pkt {
+                Some(mut pkt) => {
+                    pkt.seq = f.seq;
+                    pkt.code = f.code;
+                    pkt.data.clear();
+                    pkt.data.extend_from_slice(f.data);
+                    out.push_back(pkt);
+                    received += 1;
+                },
+                None => dropped += 1,
+            }
+        })?;
+        if dropped > 0 && pool.policy() == PoolPolicy::Error {
+            return Err(io::Error::other(format!("packet pool exhausted, {} dropped", dropped)));
+        }
+        Ok(received)
+    }
+
+    // The receive path, handing f each packet as it completes, with the
+    // stamp of the read it completed in, within the budget; true if it ran
+    // out.
+    fn receive<F: FnMut(Frame, (Instant, Option<Duration>))>(&mut self, max_packets: usize, max_bytes: usize, mut f: F) -> io::Result<bool> {
+        let mut packets = 0;
+        let mut bytes = 0;
+        if !self.backlog.is_empty() {
+            let mut backlog = ::std::mem::take(&mut self.backlog);
+            let n = backlog.len().min(max_bytes);
+            let (taken, k) = self.feed(&backlog[..n], max_packets, &mut f)?;
+            backlog.drain(..taken);
+            self.backlog = backlog;
+            if !self.backlog.is_empty() {
+                return Ok(true);
+            }
+            self.idle();
+            packets += k;
+            bytes += taken;
+        }
+        let mut buf = [0u8; 512];
+        while packets < max_packets && bytes < max_bytes {
+            let want = buf.len().min(max_bytes - bytes);
+            let n = match self.transport.read(&mut buf[..want]) {
+                Ok(0) => return Ok(false),
+                Ok(n) => n,
+                // Serial ports report an empty read as a timeout.
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => return Ok(false),
+                Err(e) => return Err(e),
+            };
+            self.stats.bytes_received += n as u64;
+            self.rx = (Instant::now(), self.hw_clock.as_mut().and_then(|c| c()));
+            let (taken, k) = self.feed(&buf[..n], max_packets - packets, &mut f)?;
+            if taken < n {
+                self.backlog.extend_from_slice(&buf[taken..n]);
+                return Ok(true);
+            }
+            packets += k;
+            bytes += taken;
+            // A short read drained the transport; reading again would only
+            // wait out a serial port's timeout.
+            if n < want {
+                self.idle();
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Ends the frame in progress at what idle-gap framing takes for the
+    // line going idle.
+    fn idle(&mut self) {
+        if self.parser.framing() == Framing::IdleGap {
+            let s = self.parser.idle_status();
+            self.apply_timer(s, Instant::now());
+        }
+    }
+
+    // Parses bytes until max_packets packets are complete, returning how
+    // many bytes that took and the packets.
+    fn feed<F: FnMut(Frame, (Instant, Option<Duration>))>(&mut self, bytes: &[u8], max_packets: usize, f: &mut F) -> io::Result<(usize, usize)> {
+        let now = Instant::now();
+        let mut packets = 0;
+        let mut pos = 0;
+        while pos < bytes.len() && packets < max_packets {
+            let (taken, s) = self.parser.parse_bytes(&bytes[pos..]);
+            pos += taken;
+            let b = bytes[pos - 1];
+            if taken > 1 {
+                self.prev = bytes[pos - 2];
+            }
+            // A sync request from a synchronized peer, such as a ping or a
+            // rebooted peer's handshake, passes through a state that isn't
+            // ready; the link isn't lost unless its seq is bad.
+            let ready = s.state.is_ready() || (self.ready && s.state.is_receiving());
+            let closed = s.state.is_closed();
+            let mut event = None;
+            if ready && !self.ready {
+                self.closing = false;
+                self.peer_closing = false;
+                if self.synced {
+                    self.stats.resyncs += 1;
+                }
+                link_event!(info, device = %self.device, seq = self.seq,
+                    resync = self.synced, "link synchronized");
+                event = Some(if self.synced { LinkEvent::Resynced } else { LinkEvent::Synchronized });
+                self.synced = true;
+            } else if closed && !self.closed {
+                link_event!(info, device = %self.device, "link closed");
+                self.closing = false;
+                self.peer_closing = false;
+                event = Some(LinkEvent::Closed);
+            } else if !ready && self.ready {
+                link_event!(warn, device = %self.device, byte = b, "link lost sync");
+                event = Some(LinkEvent::LostSync);
+            }
+            if ready && s.state.is_closing() && !self.peer_closing {
+                link_event!(info, device = %self.device, "peer closing");
+                self.peer_closing = true;
+                event = Some(LinkEvent::PeerClosing);
+            }
+            self.ready = ready;
+            self.closed = closed;
+            self.suspended = s.state.is_suspended();
+            // prev is the byte as received, so the ack may be overridden.
+            if self.prev == self.parser.config().wire(SYNC_ACK) && ready && !s.state.is_receiving() && !s.packet {
+                self.acks += 1;
+                if let Some(sent) = self.sync_sent.take() {
+                    self.rtt.sample(now.saturating_duration_since(sent));
+                }
+            }
+            self.apply_timer(s, now);
+            self.prev = b;
+            self.respond(s.sync)?;
+            // A handshake completing carries the peer's epoch, if any.
+            if ready && (s.sync == SYNC_ACK || event.is_some()) && self.parser.peer_epoch() != self.peer_epoch {
+                let rebooted = self.peer_epoch.is_some();
+                self.peer_epoch = self.parser.peer_epoch();
+                if rebooted {
+                    link_event!(warn, device = %self.device, epoch = ?self.peer_epoch, "peer rebooted");
+                    event = Some(LinkEvent::PeerRebooted);
+                }
+            }
+            self.flow(ready && s.state.is_flow_off(), now)?;
+            if let Some(event) = event {
+                self.emit(event);
+                if event == LinkEvent::PeerRebooted {
+                    for pkt in self.setup.clone() {
+                        self.send(pkt)?;
+                    }
+                }
+                // Both sides closing at once ack each other.
+                if event == LinkEvent::PeerClosing && self.closing {
+                    self.ack_close()?;
+                }
+            }
+            if s.packet {
+                let frame = self.parser.frame();
+                link_event!(trace, device = %self.device, seq = frame.seq, code = frame.code,
+                    len = frame.data.len(), "packet received");
+                self.stats.packets_received += 1;
+                packets += 1;
+                f(frame, self.rx);
+            }
+        }
+        Ok((pos, packets))
+    }
+
+    // Drops a packet cut short, or starts an unanswered handshake over,
+    // once the receive timer has run for the timeout; true if it did. Call
+    // it periodically, more often than the timeout floor. Each expiry
+    // doubles the timeout until a round trip is measured again.
+    pub fn check_timeout(&mut self) -> io::Result<bool> {
+        self.check_timeout_at(Instant::now())
+    }
+
+    pub fn check_timeout_at(&mut self, now: Instant) -> io::Result<bool> {
+        match self.timer {
+            Some(t) if now.saturating_duration_since(t) >= self.rtt.timeout() => {},
+            _ => return Ok(false),
+        }
+        self.timer = None;
+        self.rtt.backoff();
+        let s = self.parser.timeout_status();
+        if self.ready && !s.state.is_ready() {
+            link_event!(warn, device = %self.device, timeout = ?self.rtt.timeout(), "link timed out");
+        }
+        self.ready = s.state.is_ready();
+        self.suspended = s.state.is_suspended();
+        self.apply_timer(s, now);
+        self.respond(s.sync)?;
+        Ok(true)
+    }
+
+    // Shuts the link down, waiting up to deadline for the peer to flush
+    // what it has pending and ack, and returning the packets it sent
+    // meanwhile. When the peer sent FIN first, acks it at once. Either way
+    // the link is closed when this returns, until a new handshake.
+    pub fn close(&mut self, deadline: Duration) -> io::Result<Vec<Packet>> {
+        let mut pkts = Vec::new();
+        if self.closed {
+            return Ok(pkts);
+        }
+        if self.peer_closing {
+            self.ack_close()?;
+            return Ok(pkts);
+        }
+        if self.ready {
+            let s = self.parser.close_status();
+            self.respond(s.sync)?;
+            self.closing = true;
+            let start = Instant::now();
+            while !self.closed && start.elapsed() < deadline {
+                pkts.extend(self.poll()?);
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        if !self.closed {
+            link_event!(warn, device = %self.device, "close not acked");
+            self.parser.closed_status();
+            self.shut();
+        }
+        Ok(pkts)
+    }
+
+    // Tracks the peer's flow control, sending what was held once it's on
+    // again and the link is ready.
+    fn flow(&mut self, off: bool, now: Instant) -> io::Result<()> {
+        match (off, self.flow_off) {
+            (true, None) => {
+                link_event!(debug, device = %self.device, "peer flow off");
+                self.flow_off = Some(now);
+                self.stats.stalls += 1;
+            },
+            (false, Some(since)) => {
+                link_event!(debug, device = %self.device, held = self.held.len(), "peer flow on");
+                self.flow_off = None;
+                self.stats.stall_time += now.saturating_duration_since(since);
+            },
+            _ => {},
+        }
+        if !off && self.ready && !self.suspended {
+            while let Some(pkt) = self.held.pop_front() {
+                self.send(pkt)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn ack_close(&mut self) -> io::Result<()> {
+        let s = self.parser.close_status();
+        self.respond(s.sync)?;
+        self.shut();
+        Ok(())
+    }
+
+    fn shut(&mut self) {
+        self.ready = false;
+        self.closed = true;
+        self.closing = false;
+        self.peer_closing = false;
+        self.timer = None;
+        self.emit(LinkEvent::Closed);
+    }
+
+    // Sends a sync request carrying the current seq. A synchronized peer
+    // acks it without disturbing the link, so it also serves as an echo.
+    pub fn send_sync(&mut self) -> io::Result<()> {
+        if !self.ready {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link not synchronized"));
+        }
+        self.respond(SYNC_REQ)
+    }
+
+    // Asks the peer to suspend the link, so either side can sleep once
+    // the peer acks it. Packets can't be sent until resume.
+    pub fn suspend(&mut self) -> io::Result<()> {
+        if !self.ready {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link not synchronized"));
+        }
+        self.respond(SUSPEND_REQ)
+    }
+
+    // Asks the peer to hold all but urgent packets until flow_on, as when
+    // the receiver is falling behind. A sync request, such as send_sync,
+    // turns flow back on too.
+    pub fn flow_off(&mut self) -> io::Result<()> {
+        if !self.ready {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link not synchronized"));
+        }
+        self.respond(FLOW_OFF)
+    }
+
+    pub fn flow_on(&mut self) -> io::Result<()> {
+        if !self.ready {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link not synchronized"));
+        }
+        self.respond(FLOW_ON)
+    }
+
+    // Wakes a suspended peer with WAKE and a sync request; the link is
+    // usable again as soon as this returns.
+    pub fn resume(&mut self) -> io::Result<()> {
+        let s = self.parser.resume_status();
+        self.suspended = false;
+        self.apply_timer(s, Instant::now());
+        self.transport.write_all(&[WAKE])?;
+        self.stats.bytes_sent += 1;
+        self.respond(s.sync)
+    }
+
+    // Assigns the next sequence number and writes the packet, or holds it
+    // while the peer has flow off and its code isn't urgent.
+    pub fn send(&mut self, mut pkt: Packet) -> io::Result<()> {
+        if self.closed {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link closed"));
+        }
+        if !self.ready {
+            link_event!(debug, device = %self.device, code = pkt.code, "send before sync");
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link not synchronized"));
+        }
+        if self.suspended {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "link suspended"));
+        }
+        // Refused before it takes a seq, which the peer would miss.
+        let config = self.parser.config();
+        if pkt.data.len() > config.max_len as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "packet data too long"));
+        }
+        if self.flow_off.is_some() && !self.urgent.contains(&pkt.code) {
+            self.held.push_back(pkt);
+            return Ok(());
+        }
+        pkt.seq = self.seq;
+        link_event!(trace, device = %self.device, seq = pkt.seq, code = pkt.code,
+            len = pkt.data.len(), "packet sent");
+        self.seq = self.seq.next();
+        let n = pkt.encode_with(&config, &mut self.transport)?;
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += n as u64;
+        Ok(())
+    }
+}
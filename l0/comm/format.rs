@@ -0,0 +1,71 @@
+// defmt formatting of the l0 types, so firmware can log protocol activity
+// over RTT.
+
+use defmt;
+use defmt::{Format, Formatter};
+#[cfg(feature = "embedded")]
+use super::embedded::LinkError;
+#[cfg(feature = "std")]
+use super::packet::Packet;
+use super::parser::*;
+use super::stats::LinkStats;
+
+impl Format for TimerAction {
+    fn format(&self, f: Formatter) {
+        match *self {
+            TimerAction::NoChange => defmt::write!(f, "NoChange"),
+            TimerAction::Restart => defmt::write!(f, "Restart"),
+            TimerAction::Stop => defmt::write!(f, "Stop"),
+        }
+    }
+}
+
+impl Format for ParseStatus {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "ParseStatus {{ sync: {=u8:#x}, state: {=u8:#x}, packet: {=bool} }}",
+            self.sync, self.state, self.packet)
+    }
+}
+
+impl<'a> Format for Frame<'a> {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "Frame {{ seq: {=u8}, code: {=u8:#x}, data: {=[u8]:02x} }}", self.seq, self.code, self.data)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Format for Packet {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "Packet {{ seq: {=u8}, code: {=u8:#x}, data: {=[u8]:02x} }}",
+            self.seq, self.code, self.data.as_slice())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Format for ParseResult {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "ParseResult {{ sync: {=u8:#x}, state: {=u8:#x}, packet: {} }}",
+            self.sync, self.state, self.packet)
+    }
+}
+
+impl Format for LinkStats {
+    fn format(&self, f: Formatter) {
+        defmt::write!(f, "LinkStats {{ packets_sent: {=u64}, packets_received: {=u64}, bytes_sent: {=u64}, \
+            bytes_received: {=u64}, resyncs: {=u64}, overflows: {=u64}, stalls: {=u64}, stall_time_us: {=u64} }}",
+            self.packets_sent, self.packets_received, self.bytes_sent, self.bytes_received, self.resyncs,
+            self.overflows, self.stalls, self.stall_time.as_micros().min(u64::MAX as u128) as u64)
+    }
+}
+
+#[cfg(feature = "embedded")]
+impl<E: Format> Format for LinkError<E> {
+    fn format(&self, f: Formatter) {
+        match *self {
+            LinkError::Serial(ref e) => defmt::write!(f, "Serial({})", e),
+            LinkError::NotReady => defmt::write!(f, "NotReady"),
+            LinkError::TooLong => defmt::write!(f, "TooLong"),
+            LinkError::Full => defmt::write!(f, "Full"),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// A lock-free byte queue from a UART interrupt handler to the main loop,
+// which drains it into the parser. It holds N - 1 bytes. Split it once,
+// giving the producer to the handler; bytes pushed while it is full are
+// dropped and counted. Only atomic loads and stores are used, so it works
+// on cores without compare-and-swap.
+pub struct IsrQueue<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    head: AtomicUsize, // next to pop, written by the consumer only.
+    tail: AtomicUsize, // next to push, written by the producer only.
+    overflows: AtomicUsize,
+}
+
+// The halves are the only way at the buffer, and split hands out one each.
+unsafe impl<const N: usize> Sync for IsrQueue<N> {}
+
+impl<const N: usize> Default for IsrQueue<N> {
+    fn default() -> Self {
+        IsrQueue::new()
+    }
+}
+
+impl<const N: usize> IsrQueue<N> {
+    const CAPACITY: usize = {
+        assert!(N > 1, "an IsrQueue needs room for at least one byte");
+        N - 1
+    };
+
+    pub const fn new() -> Self {
+        IsrQueue {
+            buf: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overflows: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        Self::CAPACITY
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        (tail + N - head) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Bytes dropped because the queue was full, wrapping on overflow.
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    pub fn split(&mut self) -> (IsrProducer<'_, N>, IsrConsumer<'_, N>) {
+        let _ = Self::CAPACITY;
+        (IsrProducer { queue: self }, IsrConsumer { queue: self })
+    }
+}
+
+pub struct IsrProducer<'a, const N: usize> {
+    queue: &'a IsrQueue<N>,
+}
+
+impl<'a, const N: usize> IsrProducer<'a, N> {
+    // False when the queue was full and the byte dropped.
+    pub fn push(&mut self, b: u8) -> bool {
+        let q = self.queue;
+        let tail = q.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+        if next == q.head.load(Ordering::Acquire) {
+            q.overflows.store(q.overflows.load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed);
+            return false;
+        }
+        // The consumer doesn't read at tail until the store below.
+        unsafe { (q.buf.get() as *mut u8).add(tail).write(b) };
+        q.tail.store(next, Ordering::Release);
+        true
+    }
+}
+
+pub struct IsrConsumer<'a, const N: usize> {
+    queue: &'a IsrQueue<N>,
+}
+
+impl<'a, const N: usize> IsrConsumer<'a, N> {
+    pub fn pop(&mut self) -> Option<u8> {
+        let q = self.queue;
+        let head = q.head.load(Ordering::Relaxed);
+        if head == q.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        // The producer doesn't write at head until the store below.
+        let b = unsafe { (q.buf.get() as *const u8).add(head).read() };
+        q.head.store((head + 1) % N, Ordering::Release);
+        Some(b)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn overflows(&self) -> usize {
+        self.queue.overflows()
+    }
+}
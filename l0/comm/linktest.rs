@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use super::endpoint::Endpoint;
+use super::packet::*;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+const WINDOW: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PingStats {
+    pub sent: u32,
+    pub received: u32,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+}
+
+impl PingStats {
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.sent as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    pub sent: u64,
+    pub echoed: u64,
+    pub bytes: u64, // payload bytes echoed back.
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    // Payload bytes per second that made the round trip.
+    pub fn goodput(&self) -> f64 {
+        if self.elapsed.as_secs_f64() <= 0.0 {
+            return 0.0;
+        }
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        1.0 - self.echoed as f64 / self.sent as f64
+    }
+}
+
+// Link diagnostics for validating cabling and radios in the field.
+impl<T: Read + Write> Endpoint<T> {
+    // Round trips of count sync requests, which any peer answers without
+    // firmware support. Packets arriving meanwhile are dropped.
+    pub fn ping(&mut self, count: u32, timeout: Duration) -> io::Result<PingStats> {
+        let mut stats = PingStats { sent: count, ..PingStats::default() };
+        let mut total = Duration::from_secs(0);
+        for _ in 0..count {
+            let acks = self.acks();
+            let start = Instant::now();
+            self.send_sync()?;
+            while self.acks() == acks && start.elapsed() < timeout {
+                thread::sleep(POLL_INTERVAL);
+                self.poll()?;
+            }
+            if self.acks() == acks {
+                continue;
+            }
+            let rtt = start.elapsed();
+            if stats.received == 0 || rtt < stats.min {
+                stats.min = rtt;
+            }
+            stats.max = stats.max.max(rtt);
+            stats.received += 1;
+            total += rtt;
+        }
+        if stats.received > 0 {
+            stats.avg = total / stats.received;
+        }
+        Ok(stats)
+    }
+
+    // Saturates the link for duration with full packets on code, which the
+    // peer must echo back with the same payload as an event (code | 0x80).
+    // At most a few packets are in flight; one not echoed within timeout
+    // counts as lost. Other packets arriving meanwhile are dropped.
+    pub fn throughput_test(&mut self, duration: Duration, code: u8, timeout: Duration) -> io::Result<Throughput> {
+        let mut result = Throughput::default();
+        let mut in_flight: VecDeque<(u32, Instant)> = VecDeque::new();
+        let start = Instant::now();
+        let mut counter = 0u32;
+        loop {
+            let sending = start.elapsed() < duration;
+            if !sending && in_flight.is_empty() {
+                break;
+            }
+            while sending && in_flight.len() < WINDOW {
+                let mut pkt = Packet::new_with(0, code);
+                pkt.data.extend_from_slice(&counter.to_le_bytes());
+                pkt.data.resize(PACKET_MAX_DATA_LEN, 0x55);
+                self.send(pkt)?;
+                in_flight.push_back((counter, Instant::now()));
+                result.sent += 1;
+                counter = counter.wrapping_add(1);
+            }
+            thread::sleep(POLL_INTERVAL);
+            for pkt in self.poll()? {
+                if pkt.code != code | 0x80 || pkt.data.len() < 4 {
+                    continue;
+                }
+                let n = u32::from_le_bytes([pkt.data[0], pkt.data[1], pkt.data[2], pkt.data[3]]);
+                if let Some(i) = in_flight.iter().position(|f| f.0 == n) {
+                    in_flight.remove(i);
+                    result.echoed += 1;
+                    result.bytes += pkt.data.len() as u64;
+                }
+            }
+            in_flight.retain(|f| f.1.elapsed() < timeout);
+        }
+        result.elapsed = start.elapsed();
+        Ok(result)
+    }
+}
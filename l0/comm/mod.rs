@@ -1,8 +1,54 @@
+mod crc;
+#[cfg(feature = "std")]
+mod demux;
+#[cfg(feature = "embedded")]
+mod embedded;
+#[cfg(feature = "std")]
+mod endpoint;
+#[cfg(feature = "defmt")]
+mod format;
+mod isr;
+#[cfg(feature = "std")]
+mod linktest;
+// The parser doesn't index either, so no input can make it panic.
+#[deny(clippy::indexing_slicing, clippy::unwrap_used, clippy::expect_used, clippy::panic)]
 mod parser;
 mod packet;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(feature = "std")]
+mod rtt;
+#[cfg(feature = "critical-section")]
+mod shared;
+#[cfg(feature = "embedded")]
+mod split;
+#[cfg(feature = "embedded")]
+mod static_link;
+mod stats;
 
+pub use self::crc::*;
+#[cfg(feature = "std")]
+pub use self::demux::*;
+#[cfg(feature = "embedded")]
+pub use self::embedded::*;
+#[cfg(feature = "std")]
+pub use self::endpoint::*;
+pub use self::isr::*;
+#[cfg(feature = "std")]
+pub use self::linktest::*;
 pub use self::parser::*;
 pub use self::packet::*;
+#[cfg(feature = "std")]
+pub use self::pool::*;
+#[cfg(feature = "std")]
+pub use self::rtt::*;
+#[cfg(feature = "critical-section")]
+pub use self::shared::*;
+#[cfg(feature = "embedded")]
+pub use self::split::*;
+#[cfg(feature = "embedded")]
+pub use self::static_link::*;
+pub use self::stats::*;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
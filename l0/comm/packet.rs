@@ -1,4 +1,10 @@
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+use super::crc::crc16_step;
+#[cfg(feature = "std")]
+use super::parser::SyncConfig;
 
 pub type PacketSeq = u8;
 
@@ -24,13 +30,146 @@ impl Sequencer for PacketSeq {
 }
 
 pub const PACKET_DATA_BUF_LEN: usize = 128;
+pub const PACKET_MAX_DATA_LEN: usize = 127;
 
+// The bytes preceding a payload of len bytes: seq, code with the length in
+// bits 4-6 and, when that can't hold it, a length byte. Returns them with
+// how many are used.
+pub const fn frame_head(seq: PacketSeq, code: u8, len: usize) -> ([u8; 3], usize) {
+    let mut head = [seq, code & 0x8f, len as u8];
+    if head[2] < 7 {
+        head[1] |= (head[2] << 4) & 0x70;
+        (head, 2)
+    } else {
+        head[1] |= 0x70;
+        (head, 3)
+    }
+}
+
+// How many bytes a frame with a payload of len bytes takes on the wire.
+pub const fn frame_len(len: usize) -> usize {
+    if len < 7 { 2 + len } else { 3 + len }
+}
+
+// The frame of a packet known at compile time, such as an e-stop or
+// heartbeat kept in flash and written as is. M must be frame_len(N) and N
+// at most PACKET_MAX_DATA_LEN, or the const or static holding it fails to
+// build; const_frame! works M out. The frame has no checksum, so it suits
+// only links with Checksum::None; encode_const_crc16 is for Crc16 links.
+// The peer takes the frame only with the seq it expects, so fixed frames
+// suit links with SeqCheck::Lenient.
+//
+//   static ESTOP: [u8; 3] = encode_const(1, 0x0f, [1]);
+pub const fn encode_const<const N: usize, const M: usize>(seq: PacketSeq, code: u8, data: [u8; N]) -> [u8; M] {
+    assert!(N <= PACKET_MAX_DATA_LEN && M == frame_len(N), "frame length must be frame_len(N)");
+    let (head, n) = frame_head(seq, code, N);
+    let mut frame = [0u8; M];
+    let mut i = 0;
+    while i < M {
+        frame[i] = if i < n { head[i] } else { data[i - n] };
+        i += 1;
+    }
+    frame
+}
+
+// encode_const with the Checksum::Crc16 trailer, low byte first: M must be
+// frame_len(N) + 2.
+pub const fn encode_const_crc16<const N: usize, const M: usize>(seq: PacketSeq, code: u8, data: [u8; N]) -> [u8; M] {
+    assert!(N <= PACKET_MAX_DATA_LEN && M == frame_len(N) + 2, "frame length must be frame_len(N) + 2");
+    let (head, n) = frame_head(seq, code, N);
+    let mut frame = [0u8; M];
+    let mut crc = 0xffff;
+    let mut i = 0;
+    while i < M - 2 {
+        frame[i] = if i < n { head[i] } else { data[i - n] };
+        crc = crc16_step(crc, frame[i]);
+        i += 1;
+    }
+    frame[M - 2] = crc as u8;
+    frame[M - 1] = (crc >> 8) as u8;
+    frame
+}
+
+// encode_const with the frame's length worked out from the data, or
+// encode_const_crc16 when the data is followed by crc16:
+//
+//   static HEARTBEAT: &[u8] = &const_frame!(1, 0x01, []);
+//   static HEARTBEAT_CRC: &[u8] = &const_frame!(1, 0x01, [], crc16);
+#[macro_export]
+macro_rules! const_frame {
+    ($seq:expr, $code:expr, [$($b:expr),* $(,)*], crc16) => {
+        $crate::l0::comm::encode_const_crc16::<
+            { <[u8]>::len(&[$($b as u8),*]) },
+            { $crate::l0::comm::frame_len(<[u8]>::len(&[$($b as u8),*])) + 2 },
+        >($seq, $code, [$($b),*])
+    };
+    ($seq:expr, $code:expr, [$($b:expr),* $(,)*]) => {
+        $crate::l0::comm::encode_const::<
+            { <[u8]>::len(&[$($b as u8),*]) },
+            { $crate::l0::comm::frame_len(<[u8]>::len(&[$($b as u8),*])) },
+        >($seq, $code, [$($b),*])
+    };
+}
+
+// The frame as its head, in the caller's head buffer, and the payload, for
+// scatter-gather DMA or a vectored write without copying the payload.
+pub fn frame_scatter<'a>(head: &'a mut [u8; 3], seq: PacketSeq, code: u8, data: &'a [u8]) -> [&'a [u8]; 2] {
+    let (h, n) = frame_head(seq, code, data.len());
+    *head = h;
+    [&head[..n], data]
+}
+
+// Writes the frame into a ring buffer, such as one a circular DMA channel
+// drains, starting at index at and wrapping at the end. The caller checks
+// there's room for frame_len bytes; returns the index after the frame.
+pub fn frame_to_ring(ring: &mut [u8], at: usize, seq: PacketSeq, code: u8, data: &[u8]) -> usize {
+    if ring.is_empty() {
+        return 0;
+    }
+    let (head, n) = frame_head(seq, code, data.len());
+    let mut i = at % ring.len();
+    for b in head[..n].iter().chain(data) {
+        ring[i] = *b;
+        i += 1;
+        if i == ring.len() {
+            i = 0;
+        }
+    }
+    i
+}
+
+#[cfg(feature = "std")]
+fn encode_frame<W: io::Write>(w: &mut W, config: &SyncConfig, seq: PacketSeq, code: u8, data: &[u8]) -> io::Result<usize> {
+    let len = data.len();
+    if len > config.max_len as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "packet data too long"));
+    }
+    let mut buf = [0u8; 5 + PACKET_MAX_DATA_LEN];
+    let (head, n) = frame_head(seq, code, len);
+    buf[..n].copy_from_slice(&head[..n]);
+    buf[n..n + len].copy_from_slice(data);
+    let (tail, t) = config.frame_tail(&head[..n], data);
+    buf[n + len..n + len + t].copy_from_slice(&tail[..t]);
+    w.write_all(&buf[..n + len + t])?;
+    Ok(n + len + t)
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Packet {
     pub seq: PacketSeq,
     pub code: u8,
     pub data: Vec<u8>,
 }
 
+#[cfg(feature = "std")]
+impl Default for Packet {
+    fn default() -> Self {
+        Packet::new()
+    }
+}
+
+#[cfg(feature = "std")]
 impl Packet {
     pub fn new() -> Self {
         Packet {
@@ -52,18 +191,76 @@ impl Packet {
         }
     }
 
+    // Writes the frame with a single write_all, so an unbuffered port or
+    // socket gets it whole.
     pub fn encode<W: io::Write>(&self, w: &mut W) -> io::Result<usize> {
-        let mut head: [u8; 3] = [self.seq, self.code & 0x8f, self.data.len() as u8];
-        let mut count = w.write(if head[2] < 7 {
-            head[1] |= (head[2] << 4) & 0x70;
-            &head[..2]
-        } else {
-            head[1] |= 0x70;
-            &head[..]
-        })?;
-        if self.data.len() > 0 {
-            count += w.write(self.data.as_slice())?;
+        encode_frame(w, &SyncConfig::default(), self.seq, self.code, &self.data)
+    }
+
+    // Like encode, for a link with config: refused past its max_len, and
+    // with the checksum after the frame when it has one.
+    pub fn encode_with<W: io::Write>(&self, config: &SyncConfig, w: &mut W) -> io::Result<usize> {
+        encode_frame(w, config, self.seq, self.code, &self.data)
+    }
+
+    // Like encode, but hands the payload to write_vectored beside the head
+    // instead of copying it, looping on short writes.
+    pub fn encode_vectored<W: io::Write>(&self, w: &mut W) -> io::Result<usize> {
+        if self.data.len() > PACKET_MAX_DATA_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "packet data too long"));
+        }
+        let mut head = [0u8; 3];
+        let [head, data] = self.scatter(&mut head);
+        let total = head.len() + data.len();
+        let mut done = 0;
+        while done < total {
+            let rest = [
+                io::IoSlice::new(head.get(done..).unwrap_or(&[])),
+                io::IoSlice::new(data.get(done.saturating_sub(head.len())..).unwrap_or(&[])),
+            ];
+            match w.write_vectored(&rest) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole packet")),
+                Ok(n) => done += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
         }
-        Ok(count)
+        Ok(total)
+    }
+
+    pub fn scatter<'a>(&'a self, head: &'a mut [u8; 3]) -> [&'a [u8]; 2] {
+        frame_scatter(head, self.seq, self.code, &self.data)
+    }
+}
+
+// A packet whose payload is shared rather than owned, so cloning it or
+// slicing its data doesn't copy, nor does converting from a Packet.
+#[cfg(feature = "bytes")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BytesPacket {
+    pub seq: PacketSeq,
+    pub code: u8,
+    pub data: Bytes,
+}
+
+#[cfg(feature = "bytes")]
+impl BytesPacket {
+    pub fn encode<W: io::Write>(&self, w: &mut W) -> io::Result<usize> {
+        encode_frame(w, &SyncConfig::default(), self.seq, self.code, &self.data)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<Packet> for BytesPacket {
+    fn from(p: Packet) -> Self {
+        BytesPacket { seq: p.seq, code: p.code, data: p.data.into() }
+    }
+}
+
+// Copies the data only when it is still shared.
+#[cfg(feature = "bytes")]
+impl From<BytesPacket> for Packet {
+    fn from(p: BytesPacket) -> Self {
+        Packet { seq: p.seq, code: p.code, data: p.data.into() }
     }
 }
@@ -1,12 +1,210 @@
+use core::fmt;
+use super::crc::*;
 use super::packet::*;
 
 pub const SYNC_REQ: u8 = 0xff;
 pub const SYNC_ACK: u8 = 0xfe;
+// A side about to sleep sends SUSPEND_REQ and its seq, which the peer acks
+// with SUSPEND_ACK before both stop sending. The side waking up sends WAKE,
+// which may be lost while the sleeper's UART starts, then a sync request;
+// the ack resumes the link where it stopped.
+pub const SUSPEND_REQ: u8 = 0xfc;
+pub const SUSPEND_ACK: u8 = 0xfb;
+pub const WAKE: u8 = 0xfd;
+// A side keeping a boot count sends EPOCH and the count, below 0xf0, ahead
+// of its handshake's sync request, so the peer can tell a reboot from a
+// resync after a glitch. Peers not knowing it just resync.
+pub const EPOCH: u8 = 0xfa;
+// A side shutting down sends FIN and its seq. The peer stays ready, so it
+// can flush what it has pending, then answers FIN_ACK and its seq; both
+// are closed from then until a new handshake.
+pub const FIN: u8 = 0xf9;
+pub const FIN_ACK: u8 = 0xf8;
+// A side running short of receive buffer sends FLOW_OFF and its seq; the
+// peer holds all but urgent packets until FLOW_ON and its seq, or a sync
+// request.
+pub const FLOW_OFF: u8 = 0xf7;
+pub const FLOW_ON: u8 = 0xf6;
 
 pub type SyncState = u8;
 
 pub const SYNC_STATE_READY: SyncState = 0x01;
 pub const SYNC_STATE_RECV:  SyncState = 0x02;
+pub const SYNC_STATE_SUSPENDED: SyncState = 0x04;
+pub const SYNC_STATE_CLOSING: SyncState = 0x08;
+pub const SYNC_STATE_CLOSED: SyncState = 0x10;
+pub const SYNC_STATE_FLOW_OFF: SyncState = 0x20;
+
+// How a parser finds where frames start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    // The sync handshake and seqs, which work on any byte stream.
+    Sync,
+    // Frames end where the transport reports the line going idle, as
+    // RS-485 drivers with UART idle detection do, and start ready without
+    // a handshake. Any valid seq is taken; a bad frame is skipped up to the
+    // next idle instead of resyncing. Sync requests are still acked.
+    IdleGap,
+}
+
+// Which seqs a synchronized parser takes on a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqCheck {
+    // Only the one following the last, so a lost frame means a resync.
+    Strict,
+    // Any valid seq, so a lost frame costs only itself, for links that
+    // recover losses above l0 or don't need to.
+    Lenient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    None,
+    // Each frame is followed by the SoftCrc16 of its bytes, low byte
+    // first; a frame failing it is dropped as a bad seq would be.
+    Crc16,
+}
+
+// The options both ends of a link must agree on, used by the parser and
+// by Packet::encode_with, so a link's sender and receiver share one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncConfig {
+    pub framing: Framing,
+    // The longest payload taken or sent, up to PACKET_MAX_DATA_LEN.
+    pub max_len: u8,
+    pub checksum: Checksum,
+    // The bytes sent for SYNC_REQ and SYNC_ACK, so a bus shared with
+    // another protocol can avoid its markers. Each is its own or one of
+    // the unused bytes 0xf0 to 0xf5.
+    pub sync_req: u8,
+    pub sync_ack: u8,
+    pub seq_check: SeqCheck,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            framing: Framing::Sync,
+            max_len: PACKET_MAX_DATA_LEN as u8,
+            checksum: Checksum::None,
+            sync_req: SYNC_REQ,
+            sync_ack: SYNC_ACK,
+            seq_check: SeqCheck::Strict,
+        }
+    }
+}
+
+impl SyncConfig {
+    pub fn is_valid(&self) -> bool {
+        let spare = |b: u8| (0xf0..=0xf5).contains(&b);
+        self.max_len as usize <= PACKET_MAX_DATA_LEN
+            && (self.sync_req == SYNC_REQ || spare(self.sync_req))
+            && (self.sync_ack == SYNC_ACK || spare(self.sync_ack))
+            && self.sync_req != self.sync_ack
+    }
+
+    // Swaps the sync bytes with their overrides, so it takes a sync from a
+    // ParseStatus to the byte to send, and a received byte back.
+    pub fn wire(&self, b: u8) -> u8 {
+        match b {
+            b if b == self.sync_req => SYNC_REQ,
+            SYNC_REQ => self.sync_req,
+            b if b == self.sync_ack => SYNC_ACK,
+            SYNC_ACK => self.sync_ack,
+            b => b,
+        }
+    }
+
+    // The checksum following a frame's head and data, with how many of
+    // its bytes are sent.
+    pub fn frame_tail(&self, head: &[u8], data: &[u8]) -> ([u8; 2], usize) {
+        match self.checksum {
+            Checksum::None => ([0; 2], 0),
+            Checksum::Crc16 => {
+                let mut crc = SoftCrc16::new();
+                crc.update(head);
+                crc.update(data);
+                (crc.finish().to_le_bytes(), 2)
+            },
+        }
+    }
+}
+
+// Sets up a parser's SyncConfig, starting from the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParserBuilder {
+    config: SyncConfig,
+}
+
+impl ParserBuilder {
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.config.framing = framing;
+        self
+    }
+
+    pub fn max_len(mut self, len: usize) -> Self {
+        self.config.max_len = len.min(PACKET_MAX_DATA_LEN) as u8;
+        self
+    }
+
+    pub fn checksum(mut self, checksum: Checksum) -> Self {
+        self.config.checksum = checksum;
+        self
+    }
+
+    pub fn sync_bytes(mut self, req: u8, ack: u8) -> Self {
+        self.config.sync_req = req;
+        self.config.sync_ack = ack;
+        self
+    }
+
+    pub fn seq_check(mut self, check: SeqCheck) -> Self {
+        self.config.seq_check = check;
+        self
+    }
+
+    // The config, for the encoder or a link; None if its sync bytes
+    // aren't allowed.
+    pub fn config(&self) -> Option<SyncConfig> {
+        if self.config.is_valid() { Some(self.config) } else { None }
+    }
+
+    pub fn build(self) -> Option<Parser> {
+        self.config().map(Parser::with_config)
+    }
+}
+
+// Snapshots start with their format's version; restore refuses others.
+pub const SNAPSHOT_VERSION: u8 = 2;
+pub const PARSER_SNAPSHOT_LEN: usize = 8;
+// A link's snapshot is its parser's, then its own seq and epoch.
+pub const LINK_SNAPSHOT_LEN: usize = PARSER_SNAPSHOT_LEN + 2;
+
+const SNAPSHOT_PROMISCUOUS: u8 = 0x01;
+const SNAPSHOT_IDLE_GAP: u8 = 0x02;
+const SNAPSHOT_CLOSING: u8 = 0x04;
+const SNAPSHOT_FLOW_OFF: u8 = 0x08;
+const SNAPSHOT_CRC16: u8 = 0x10;
+const SNAPSHOT_LENIENT: u8 = 0x20;
+
+// Times the parser gave up on what it was parsing, by cause, so that a
+// change making it less robust shows in the counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Recoveries {
+    pub bad_seq: u32,   // a seq, or an epoch count, out of place.
+    pub bad_len: u32,   // a length past the config's max_len.
+    pub timeouts: u32,  // the receive timer ran out mid-frame or handshake.
+    pub cut_short: u32, // a frame ended by an idle gap.
+    pub bad_crc: u32,   // a frame failing its checksum.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    BadSeq,
+    BadLen,
+    Timeout,
+    BadCrc,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TimerAction {
@@ -15,42 +213,201 @@ pub enum TimerAction {
     Stop,       // stop the timer.
 }
 
+impl fmt::Display for TimerAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            TimerAction::NoChange => "no change",
+            TimerAction::Restart => "restart",
+            TimerAction::Stop => "stop",
+        })
+    }
+}
+
+// Where a parser is, for showing why a link is stuck.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkPhase {
+    // Waiting for a handshake, or skipping a bad frame to the next idle.
+    Syncing,
+    // Between frames.
+    Ready,
+    // Partway through a frame or a sync.
+    Receiving,
+    Suspended,
+    Closed,
+}
+
+impl fmt::Display for LinkPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            LinkPhase::Syncing => "syncing",
+            LinkPhase::Ready => "ready",
+            LinkPhase::Receiving => "receiving",
+            LinkPhase::Suspended => "suspended",
+            LinkPhase::Closed => "closed",
+        })
+    }
+}
+
+// Shows a SyncState by its flags, as READY|RECV, or NONE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateFlags(pub SyncState);
+
+const STATE_NAMES: [(SyncState, &str); 6] = [
+    (SYNC_STATE_READY, "READY"),
+    (SYNC_STATE_RECV, "RECV"),
+    (SYNC_STATE_SUSPENDED, "SUSPENDED"),
+    (SYNC_STATE_CLOSING, "CLOSING"),
+    (SYNC_STATE_CLOSED, "CLOSED"),
+    (SYNC_STATE_FLOW_OFF, "FLOW_OFF"),
+];
+
+impl fmt::Display for StateFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("NONE");
+        }
+        let mut rest = self.0;
+        let mut sep = "";
+        for &(flag, name) in STATE_NAMES.iter() {
+            if rest & flag != 0 {
+                write!(f, "{}{}", sep, name)?;
+                rest &= !flag;
+                sep = "|";
+            }
+        }
+        if rest != 0 {
+            write!(f, "{}0x{:02x}", sep, rest)?;
+        }
+        Ok(())
+    }
+}
+
 pub trait SyncStateReader {
-    fn is_ready(self) -> bool;
-    fn is_receiving(self) -> bool;
+    fn is_ready(&self) -> bool;
+    fn is_receiving(&self) -> bool;
+    fn is_suspended(&self) -> bool;
+    // The peer sent FIN, and waits for FIN_ACK.
+    fn is_closing(&self) -> bool;
+    fn is_closed(&self) -> bool;
+    // The peer sent FLOW_OFF, and not FLOW_ON since.
+    fn is_flow_off(&self) -> bool;
 }
 
 impl SyncStateReader for SyncState {
-    fn is_ready(self) -> bool {
-        self & SYNC_STATE_READY != 0
+    fn is_ready(&self) -> bool {
+        *self & SYNC_STATE_READY != 0
+    }
+
+    fn is_receiving(&self) -> bool {
+        *self & SYNC_STATE_RECV != 0
+    }
+
+    fn is_suspended(&self) -> bool {
+        *self & SYNC_STATE_SUSPENDED != 0
+    }
+
+    fn is_closing(&self) -> bool {
+        *self & SYNC_STATE_CLOSING != 0
     }
 
-    fn is_receiving(self) -> bool {
-        self & SYNC_STATE_RECV != 0
+    fn is_closed(&self) -> bool {
+        *self & SYNC_STATE_CLOSED != 0
+    }
+
+    fn is_flow_off(&self) -> bool {
+        *self & SYNC_STATE_FLOW_OFF != 0
     }
 }
 
+#[cfg(feature = "std")]
 pub struct ParseResult {
     pub sync: u8,
     pub state: SyncState,
     pub packet: Option<Packet>,
 }
 
-impl ParseResult {
-    fn new(sync: u8, state: SyncState) -> ParseResult {
-        ParseResult {
-            sync,
-            state,
-            packet: None,
+// What parsing a byte did, without taking the packet: when packet is set,
+// Parser::frame holds it until the next byte. Parsing this way never
+// allocates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseStatus {
+    pub sync: u8,
+    pub state: SyncState,
+    pub packet: bool,
+}
+
+// As READY|RECV sync=0xfe packet, leaving out what's unset.
+impl fmt::Display for ParseStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", StateFlags(self.state))?;
+        if self.sync != 0 {
+            write!(f, " sync=0x{:02x}", self.sync)?;
         }
+        if self.packet {
+            f.write_str(" packet")?;
+        }
+        Ok(())
     }
+}
 
-    fn new_packet(pkt: Option<Packet>) -> ParseResult {
-        ParseResult {
-            sync: 0,
-            state: SYNC_STATE_READY,
-            packet: pkt,
-        }
+#[cfg(feature = "std")]
+impl From<ParseStatus> for ParseResult {
+    fn from(s: ParseStatus) -> Self {
+        ParseResult { sync: s.sync, state: s.state, packet: None }
+    }
+}
+
+// The packet last parsed, borrowed from the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub seq: PacketSeq,
+    pub code: u8,
+    pub data: &'a [u8],
+}
+
+#[cfg(feature = "std")]
+impl<'a> Frame<'a> {
+    pub fn to_packet(&self) -> Packet {
+        Packet { seq: self.seq, code: self.code, data: self.data.to_vec() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ParseResult {
+    pub fn timer_action(&self) -> TimerAction {
+        ParseStatus { sync: self.sync, state: self.state, packet: self.packet.is_some() }.timer_action()
+    }
+
+    // True once the handshake is done and packets are parsed.
+    pub fn is_synced(&self) -> bool {
+        self.state.is_ready()
+    }
+
+    // The sync byte to send, followed by the sender's seq. A promiscuous
+    // parser reports the sync it saw here instead.
+    pub fn needs_sync_reply(&self) -> Option<u8> {
+        if self.sync != 0 { Some(self.sync) } else { None }
+    }
+
+    pub fn into_packet(self) -> Option<Packet> {
+        self.packet
+    }
+}
+
+// Yields the packet, if any, so that results flatten into packets.
+#[cfg(feature = "std")]
+impl IntoIterator for ParseResult {
+    type Item = Packet;
+    type IntoIter = ::std::option::IntoIter<Packet>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packet.into_iter()
+    }
+}
+
+impl ParseStatus {
+    fn new(sync: u8, state: SyncState) -> ParseStatus {
+        ParseStatus { sync, state, packet: false }
     }
 
     pub fn timer_action(&self) -> TimerAction {
@@ -74,13 +431,46 @@ enum ParsingState {
     MsgCode,    // waiting for message code
     MsgLen,     // waiting for message length
     MsgData,    // waiting for message data
+    SuspendReqSeq, // waiting for seq after suspendREQ
+    SuspendAckSeq, // waiting for seq after suspendACK
+    Suspended,  // ignoring all but syncREQ
+    WakeSeq,    // recv syncREQ while suspended, wait seq
+    EpochSync,  // recv epoch before the handshake, wait count
+    EpochMsg,   // recv epoch while synced, wait count
+    FinSeq,     // recv FIN, wait seq
+    FinAckSeq,  // recv FIN_ACK, wait seq
+    FlowOffSeq, // recv FLOW_OFF, wait seq
+    FlowOnSeq,  // recv FLOW_ON, wait seq
+    MsgCrcLo,   // waiting for the checksum's low byte
+    MsgCrcHi,   // waiting for the checksum's high byte
+    Closed,     // waiting for a new handshake after FIN_ACK
+    Gap,        // skipping a bad frame until the line idles
 }
 
+// A Parser's memory is fixed: one payload buffer of PACKET_MAX_DATA_LEN
+// and a few counters, whatever the input.
 pub struct Parser {
     state: ParsingState,
     peer_seq: PacketSeq,
-    packet: Option<Packet>,
+    seq: PacketSeq,
+    code: u8,
+    data: [u8; PACKET_MAX_DATA_LEN],
+    received: usize,
     data_len: usize,
+    promiscuous: bool,
+    peer_epoch: Option<u8>,
+    peer_closing: bool, // FIN received, not yet acked.
+    peer_flow_off: bool,
+    config: SyncConfig,
+    crc: SoftCrc16, // of the frame so far.
+    crc_lo: u8,
+    recoveries: Recoveries,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser::new()
+    }
 }
 
 impl Parser {
@@ -88,112 +478,577 @@ impl Parser {
         Parser {
             state: ParsingState::SyncAck,
             peer_seq: 0,
-            packet: None,
+            seq: 0,
+            code: 0,
+            data: [0; PACKET_MAX_DATA_LEN],
+            received: 0,
             data_len: 0,
+            promiscuous: false,
+            peer_epoch: None,
+            peer_closing: false,
+            peer_flow_off: false,
+            config: SyncConfig::default(),
+            crc: SoftCrc16::new(),
+            crc_lo: 0,
+            recoveries: Recoveries::default(),
         }
     }
 
+    pub fn with_config(config: SyncConfig) -> Self {
+        let state = match config.framing {
+            Framing::Sync => ParsingState::SyncAck,
+            Framing::IdleGap => ParsingState::MsgSeq,
+        };
+        Parser { state, config, ..Parser::new() }
+    }
+
+    pub fn builder() -> ParserBuilder {
+        ParserBuilder::default()
+    }
+
+    pub fn config(&self) -> SyncConfig {
+        self.config
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.config.framing
+    }
+
+    pub fn recoveries(&self) -> &Recoveries {
+        &self.recoveries
+    }
+
+    fn recover(&mut self, cause: Recovery) -> ParseStatus {
+        let r = &mut self.recoveries;
+        let count = match cause {
+            Recovery::BadSeq => &mut r.bad_seq,
+            Recovery::BadLen => &mut r.bad_len,
+            Recovery::Timeout => &mut r.timeouts,
+            Recovery::BadCrc => &mut r.bad_crc,
+        };
+        *count = count.saturating_add(1);
+        self.reset_status()
+    }
+
+    // A parser for watching traffic it doesn't take part in: it accepts
+    // packets with any valid seq, without a handshake first. It never asks
+    // for a response; sync instead reports the sync byte whose seq was just
+    // read, so handshakes can be shown.
+    pub fn promiscuous() -> Self {
+        Parser {
+            state: ParsingState::MsgSeq,
+            promiscuous: true,
+            ..Parser::new()
+        }
+    }
+
+    #[cfg(feature = "std")]
     pub fn reset(&mut self) -> ParseResult {
+        self.reset_status().into()
+    }
+
+    pub fn reset_status(&mut self) -> ParseStatus {
+        if self.promiscuous {
+            self.state = ParsingState::MsgSeq;
+            return ParseStatus::new(0, 0);
+        }
+        self.peer_closing = false;
+        self.peer_flow_off = false;
+        if self.config.framing == Framing::IdleGap {
+            return self.transit_and_result(ParsingState::Gap);
+        }
         self.state = ParsingState::SyncAck;
-        ParseResult::new(SYNC_REQ, 0)
+        ParseStatus::new(SYNC_REQ, 0)
     }
 
+    // For idle-gap framing, the transport saw the line go idle: a frame
+    // cut short by it is dropped, and the next byte starts a new one.
+    pub fn idle_status(&mut self) -> ParseStatus {
+        if self.config.framing != Framing::IdleGap {
+            return self.result_from_state();
+        }
+        match self.state {
+            ParsingState::Suspended | ParsingState::Closed => self.result_from_state(),
+            ParsingState::WakeSeq => self.transit_and_result(ParsingState::Suspended),
+            ParsingState::MsgSeq | ParsingState::Gap => self.transit_and_result(ParsingState::MsgSeq),
+            _ => {
+                self.recoveries.cut_short = self.recoveries.cut_short.saturating_add(1);
+                self.transit_and_result(ParsingState::MsgSeq)
+            },
+        }
+    }
+
+    // Leaves the suspended state, for the side waking the link: send WAKE,
+    // then the sync request returned, whose ack arrives as usual.
+    pub fn resume_status(&mut self) -> ParseStatus {
+        if matches!(self.state, ParsingState::Suspended | ParsingState::WakeSeq) {
+            self.state = ParsingState::MsgSeq;
+        }
+        ParseStatus { sync: SYNC_REQ, ..self.result_from_state() }
+    }
+
+    // Shuts the link down: the sync returned is FIN, or FIN_ACK when the
+    // peer sent FIN first, which closes the link at once.
+    pub fn close_status(&mut self) -> ParseStatus {
+        if self.peer_closing {
+            self.closed_status();
+            return ParseStatus { sync: FIN_ACK, ..self.result_from_state() };
+        }
+        ParseStatus { sync: FIN, ..self.result_from_state() }
+    }
+
+    // Closes the link without waiting for the peer, as when its FIN_ACK
+    // doesn't come in time.
+    pub fn closed_status(&mut self) -> ParseStatus {
+        self.peer_closing = false;
+        self.transit_and_result(ParsingState::Closed)
+    }
+
+    // The boot count the peer last sent, kept across resyncs.
+    pub fn peer_epoch(&self) -> Option<u8> {
+        self.peer_epoch
+    }
+
+    pub fn phase(&self) -> LinkPhase {
+        match self.state {
+            ParsingState::SyncAck |
+            ParsingState::SyncReqSeq |
+            ParsingState::SyncAckSeq |
+            ParsingState::EpochSync |
+            ParsingState::Gap => LinkPhase::Syncing,
+            ParsingState::MsgSeq => LinkPhase::Ready,
+            ParsingState::Suspended |
+            ParsingState::WakeSeq => LinkPhase::Suspended,
+            ParsingState::Closed => LinkPhase::Closed,
+            _ => LinkPhase::Receiving,
+        }
+    }
+
+    // The seq the next frame must carry, once synchronized. A parser
+    // taking any valid seq expects this one if none were lost.
+    pub fn expected_seq(&self) -> Option<PacketSeq> {
+        match self.phase() {
+            LinkPhase::Ready | LinkPhase::Receiving if self.peer_seq.is_valid() => Some(self.peer_seq),
+            _ => None,
+        }
+    }
+
+    // What a parser needs to carry on where it was after a process restart
+    // or suspend-to-RAM, without a resync: whether it's synchronized,
+    // suspended or closed, the seq it expects, the peer's epoch and the
+    // SyncConfig. Take it between frames; the rest of a frame in progress
+    // would resync.
+    pub fn snapshot(&self) -> [u8; PARSER_SNAPSHOT_LEN] {
+        let state = self.result_from_state().state;
+        let code = if state.is_closed() {
+            3
+        } else if state.is_suspended() {
+            2
+        } else if state.is_ready() {
+            1
+        } else {
+            0
+        };
+        let mut flags = 0;
+        if self.promiscuous {
+            flags |= SNAPSHOT_PROMISCUOUS;
+        }
+        if self.config.framing == Framing::IdleGap {
+            flags |= SNAPSHOT_IDLE_GAP;
+        }
+        if self.peer_closing {
+            flags |= SNAPSHOT_CLOSING;
+        }
+        if self.peer_flow_off {
+            flags |= SNAPSHOT_FLOW_OFF;
+        }
+        if self.config.checksum == Checksum::Crc16 {
+            flags |= SNAPSHOT_CRC16;
+        }
+        if self.config.seq_check == SeqCheck::Lenient {
+            flags |= SNAPSHOT_LENIENT;
+        }
+        let c = &self.config;
+        [SNAPSHOT_VERSION, code, self.peer_seq, self.peer_epoch.unwrap_or(0xff), flags, c.max_len, c.sync_req, c.sync_ack]
+    }
+
+    // The state as of the last byte, without parsing one.
+    pub fn status(&self) -> ParseStatus {
+        self.result_from_state()
+    }
+
+    // A parser from a snapshot; None if it isn't one of this version.
+    pub fn restore(snapshot: &[u8]) -> Option<Parser> {
+        let (version, code, peer_seq, epoch, flags, max_len, sync_req, sync_ack) = match *snapshot {
+            [version, code, peer_seq, epoch, flags, max_len, sync_req, sync_ack] =>
+                (version, code, peer_seq, epoch, flags, max_len, sync_req, sync_ack),
+            _ => return None,
+        };
+        let state = match code {
+            0 => ParsingState::SyncAck,
+            1 => ParsingState::MsgSeq,
+            2 => ParsingState::Suspended,
+            3 => ParsingState::Closed,
+            _ => return None,
+        };
+        // Idle-gap and promiscuous parsers are ready before any seq.
+        let synced = code == 1 || code == 2;
+        let unsequenced = peer_seq == 0 && flags & (SNAPSHOT_PROMISCUOUS | SNAPSHOT_IDLE_GAP) != 0;
+        if version != SNAPSHOT_VERSION || (synced && !peer_seq.is_valid() && !unsequenced) || (epoch >= 0xf0 && epoch != 0xff) {
+            return None;
+        }
+        let config = SyncConfig {
+            framing: if flags & SNAPSHOT_IDLE_GAP != 0 { Framing::IdleGap } else { Framing::Sync },
+            max_len,
+            checksum: if flags & SNAPSHOT_CRC16 != 0 { Checksum::Crc16 } else { Checksum::None },
+            sync_req,
+            sync_ack,
+            seq_check: if flags & SNAPSHOT_LENIENT != 0 { SeqCheck::Lenient } else { SeqCheck::Strict },
+        };
+        if !config.is_valid() {
+            return None;
+        }
+        Some(Parser {
+            state,
+            peer_seq,
+            promiscuous: flags & SNAPSHOT_PROMISCUOUS != 0,
+            peer_epoch: if epoch == 0xff { None } else { Some(epoch) },
+            peer_closing: flags & SNAPSHOT_CLOSING != 0,
+            peer_flow_off: flags & SNAPSHOT_FLOW_OFF != 0,
+            ..Parser::with_config(config)
+        })
+    }
+
+    fn accepts_seq(&self, b: u8) -> bool {
+        let any = self.promiscuous || self.config.framing == Framing::IdleGap || self.config.seq_check == SeqCheck::Lenient;
+        b == self.peer_seq || (any && b.is_valid())
+    }
+
+    #[cfg(feature = "std")]
     pub fn parse(&mut self, b: u8) -> ParseResult {
+        let status = self.parse_frame(b);
+        ParseResult {
+            sync: status.sync,
+            state: status.state,
+            packet: if status.packet { Some(self.frame().to_packet()) } else { None },
+        }
+    }
+
+    // Parses bytes in turn, lazily, so a receive loop can use combinators:
+    // parse_iter(bytes).flatten() yields just the packets.
+    #[cfg(feature = "std")]
+    pub fn parse_iter<'a, I>(&'a mut self, bytes: I) -> impl Iterator<Item = ParseResult> + 'a
+        where I: IntoIterator<Item = u8>, I::IntoIter: 'a {
+        bytes.into_iter().map(move |b| self.parse(b))
+    }
+
+    // The packet completed by the last byte parsed, when its status said so.
+    pub fn frame(&self) -> Frame<'_> {
+        let data = self.data.get(..self.received).unwrap_or(&[]);
+        Frame { seq: self.seq, code: self.code, data }
+    }
+
+    // Parses bytes up to the first one whose status needs acting on: a sync
+    // to send, a packet, or a change of state. Returns how many were taken
+    // with that status, or all of them with the last status. Payloads are
+    // copied in bulk, which is several times faster than parse_frame on
+    // busy links.
+    pub fn parse_bytes(&mut self, bytes: &[u8]) -> (usize, ParseStatus) {
+        let mut status = self.result_from_state();
+        let mut i = 0;
+        while let Some(&b) = bytes.get(i) {
+            // All but the last byte of a payload leave the status as it is.
+            if self.state == ParsingState::MsgData {
+                let n = self.data_len.saturating_sub(self.received + 1).min(bytes.len() - i - 1);
+                if n > 0 {
+                    let end = self.received + n;
+                    if let (Some(dst), Some(src)) = (self.data.get_mut(self.received..end), bytes.get(i..i + n)) {
+                        dst.copy_from_slice(src);
+                        self.crc.update(src);
+                        self.received = end;
+                        i += n;
+                        continue;
+                    }
+                }
+            }
+            let prev = status.state;
+            status = self.parse_frame(b);
+            i += 1;
+            if status.sync != 0 || status.packet || status.state != prev {
+                break;
+            }
+        }
+        (i, status)
+    }
+
+    // Parses buf with parse_bytes until max_packets packets are complete,
+    // handing f each status and, when it completed one, the packet. Returns
+    // how many bytes were taken and packets completed; the rest of buf is
+    // for the next call, so a control loop can bound its time per tick.
+    pub fn parse_slice_budgeted<F>(&mut self, buf: &[u8], max_packets: usize, mut f: F) -> (usize, usize)
+        where F: FnMut(ParseStatus, Option<Frame<'_>>) {
+        let mut pos = 0;
+        let mut packets = 0;
+        while packets < max_packets {
+            let rest = match buf.get(pos..) {
+                Some(rest) if !rest.is_empty() => rest,
+                _ => break,
+            };
+            let (taken, s) = self.parse_bytes(rest);
+            pos += taken;
+            if s.packet {
+                packets += 1;
+                f(s, Some(self.frame()));
+            } else {
+                f(s, None);
+            }
+        }
+        (pos, packets)
+    }
+
+    pub fn parse_frame(&mut self, b: u8) -> ParseStatus {
+        let s = self.step(b);
+        self.check_invariants();
+        s
+    }
+
+    // What no input may break, checked in debug builds, where the fuzz
+    // targets run.
+    fn check_invariants(&self) {
+        debug_assert!(self.data_len <= PACKET_MAX_DATA_LEN);
+        debug_assert!(self.received <= self.data_len || self.state != ParsingState::MsgData);
+        debug_assert!(self.state != ParsingState::MsgData || self.received < self.data_len);
+        debug_assert!(self.peer_epoch.is_none_or(|e| e < 0xf0));
+    }
+
+    fn step(&mut self, b: u8) -> ParseStatus {
+        if matches!(self.state, ParsingState::MsgCode | ParsingState::MsgLen | ParsingState::MsgData) {
+            self.crc.update(&[b]);
+        }
+        // Control bytes as the parser knows them; seqs and data are never
+        // sync bytes, whatever the overrides.
+        let c = self.config.wire(b);
         match self.state {
-            ParsingState::SyncAck => match b {
+            ParsingState::SyncAck |
+            ParsingState::Closed => match c {
                     SYNC_REQ => self.transit_and_result(ParsingState::SyncReqSeq),
                     SYNC_ACK => self.transit_and_result(ParsingState::SyncAckSeq),
+                    EPOCH => self.transit_and_result(ParsingState::EpochSync),
                     _ => self.result_from_state()
                 },
             ParsingState::SyncReqSeq => if b.is_valid() {
                     self.peer_seq = b;
+                    self.peer_flow_off = false;
                     self.state = ParsingState::MsgSeq;
-                    ParseResult::new(SYNC_ACK, SYNC_STATE_READY)
+                    ParseStatus::new(if self.promiscuous { SYNC_REQ } else { SYNC_ACK }, SYNC_STATE_READY)
                 } else {
-                    self.reset()
+                    self.recover(Recovery::BadSeq)
                 },
             ParsingState::SyncAckSeq => if b.is_valid() {
                     self.peer_seq = b;
-                    self.transit_and_result(ParsingState::MsgSeq)
+                    self.state = ParsingState::MsgSeq;
+                    ParseStatus::new(if self.promiscuous { SYNC_ACK } else { 0 }, SYNC_STATE_READY)
                 } else {
-                    self.reset()
+                    self.recover(Recovery::BadSeq)
                 },
-            ParsingState::MsgSeq => match b {
+            ParsingState::MsgSeq => match c {
                     SYNC_REQ => self.transit_and_result(ParsingState::SyncReqSeq),
                     SYNC_ACK => self.transit_and_result(ParsingState::MsgAckSeq),
-                    b if b == self.peer_seq => {
-                            self.packet.replace(Packet::new_with_seq(b));
-                            self.peer_seq = self.peer_seq.next();
+                    SUSPEND_REQ => self.transit_and_result(ParsingState::SuspendReqSeq),
+                    SUSPEND_ACK => self.transit_and_result(ParsingState::SuspendAckSeq),
+                    EPOCH => self.transit_and_result(ParsingState::EpochMsg),
+                    FIN => self.transit_and_result(ParsingState::FinSeq),
+                    FIN_ACK => self.transit_and_result(ParsingState::FinAckSeq),
+                    FLOW_OFF => self.transit_and_result(ParsingState::FlowOffSeq),
+                    FLOW_ON => self.transit_and_result(ParsingState::FlowOnSeq),
+                    WAKE => self.result_from_state(),
+                    b if self.accepts_seq(b) => {
+                            self.seq = b;
+                            self.received = 0;
+                            self.crc.reset();
+                            self.crc.update(&[b]);
+                            self.peer_seq = b.next();
                             self.transit_and_result(ParsingState::MsgCode)
                         },
-                    _ => self.reset()
+                    _ => self.recover(Recovery::BadSeq)
+                },
+            ParsingState::MsgAckSeq => if self.accepts_seq(b) {
+                    self.peer_seq = b;
+                    self.state = ParsingState::MsgSeq;
+                    ParseStatus::new(if self.promiscuous { SYNC_ACK } else { 0 }, SYNC_STATE_READY)
+                } else {
+                    self.recover(Recovery::BadSeq)
+                },
+            ParsingState::SuspendReqSeq => if self.accepts_seq(b) {
+                    self.state = ParsingState::Suspended;
+                    ParseStatus::new(if self.promiscuous { SUSPEND_REQ } else { SUSPEND_ACK }, self.result_from_state().state)
+                } else {
+                    self.recover(Recovery::BadSeq)
                 },
-            ParsingState::MsgAckSeq => if b == self.peer_seq {
-                    self.transit_and_result(ParsingState::MsgSeq)
+            ParsingState::SuspendAckSeq => if self.accepts_seq(b) {
+                    self.state = ParsingState::Suspended;
+                    ParseStatus::new(if self.promiscuous { SUSPEND_ACK } else { 0 }, self.result_from_state().state)
                 } else {
-                    self.reset()
+                    self.recover(Recovery::BadSeq)
                 },
+            // The first bytes after waking may be garbled, so anything but
+            // a sync request with a valid seq leaves the link suspended.
+            ParsingState::Suspended => match c {
+                    SYNC_REQ => self.transit_and_result(ParsingState::WakeSeq),
+                    _ => self.result_from_state(),
+                },
+            ParsingState::WakeSeq => if b.is_valid() {
+                    self.peer_seq = b;
+                    self.state = ParsingState::MsgSeq;
+                    ParseStatus::new(if self.promiscuous { SYNC_REQ } else { SYNC_ACK }, SYNC_STATE_READY)
+                } else {
+                    self.transit_and_result(ParsingState::Suspended)
+                },
+            ParsingState::FinSeq => if self.accepts_seq(b) {
+                    self.peer_closing = true;
+                    self.state = ParsingState::MsgSeq;
+                    ParseStatus { sync: if self.promiscuous { FIN } else { 0 }, ..self.result_from_state() }
+                } else {
+                    self.recover(Recovery::BadSeq)
+                },
+            ParsingState::FinAckSeq => if self.accepts_seq(b) {
+                    self.closed_status();
+                    ParseStatus { sync: if self.promiscuous { FIN_ACK } else { 0 }, ..self.result_from_state() }
+                } else {
+                    self.recover(Recovery::BadSeq)
+                },
+            ParsingState::FlowOffSeq |
+            ParsingState::FlowOnSeq => if self.accepts_seq(b) {
+                    let off = self.state == ParsingState::FlowOffSeq;
+                    self.peer_flow_off = off;
+                    self.state = ParsingState::MsgSeq;
+                    let sync = match (self.promiscuous, off) {
+                        (false, _) => 0,
+                        (true, true) => FLOW_OFF,
+                        (true, false) => FLOW_ON,
+                    };
+                    ParseStatus { sync, ..self.result_from_state() }
+                } else {
+                    self.recover(Recovery::BadSeq)
+                },
+            ParsingState::EpochSync |
+            ParsingState::EpochMsg => if b < 0xf0 {
+                    self.peer_epoch = Some(b);
+                    let synced = self.state == ParsingState::EpochMsg;
+                    self.state = if synced { ParsingState::MsgSeq } else { ParsingState::SyncAck };
+                    ParseStatus { sync: if self.promiscuous { EPOCH } else { 0 }, ..self.result_from_state() }
+                } else {
+                    self.recover(Recovery::BadSeq)
+                },
+            ParsingState::Gap => self.result_from_state(),
             ParsingState::MsgCode => {
-                let pkt = self.packet.as_mut().unwrap();
-                pkt.code = b & 0x8f;
+                self.code = b & 0x8f;
                 let data_len = (b >> 4) & 7;
                 match data_len {
-                    0 => self.packet_ready(),
+                    0 => self.frame_end(),
                     7 => self.transit_and_result(ParsingState::MsgLen),
+                    n if n > self.config.max_len => self.recover(Recovery::BadLen),
                     _ => {
                         self.data_len = data_len as usize;
                         self.transit_and_result(ParsingState::MsgData)
                     }
                 }
             },
-            ParsingState::MsgLen => if b >= 0x80 {
-                    self.reset()
+            ParsingState::MsgLen => if b > self.config.max_len {
+                    self.recover(Recovery::BadLen)
                 } else if b == 0 {
-                    self.packet_ready()
+                    self.frame_end()
                 } else {
                     self.data_len = b as usize;
                     self.transit_and_result(ParsingState::MsgData)
                 },
+            // data_len is under 128, so there's always room for b.
             ParsingState::MsgData => {
-                let pkt = self.packet.as_mut().unwrap();
-                pkt.data.push(b);
-                if pkt.data.len() >= self.data_len {
-                    self.packet_ready()
+                if let Some(d) = self.data.get_mut(self.received) {
+                    *d = b;
+                    self.received += 1;
+                }
+                if self.received >= self.data_len {
+                    self.frame_end()
                 } else {
                     self.result_from_state()
                 }
-            }
+            },
+            ParsingState::MsgCrcLo => {
+                self.crc_lo = b;
+                self.transit_and_result(ParsingState::MsgCrcHi)
+            },
+            ParsingState::MsgCrcHi => if u16::from_le_bytes([self.crc_lo, b]) == self.crc.finish() {
+                    self.packet_ready()
+                } else {
+                    self.recover(Recovery::BadCrc)
+                },
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn timeout(&mut self) -> ParseResult {
-        if self.state != ParsingState::MsgSeq {
-            self.reset()
+        self.timeout_status().into()
+    }
+
+    pub fn timeout_status(&mut self) -> ParseStatus {
+        // The line was quiet for the timeout, which is an idle gap.
+        if self.config.framing == Framing::IdleGap {
+            return self.idle_status();
+        }
+        if self.state == ParsingState::WakeSeq {
+            self.transit_and_result(ParsingState::Suspended)
+        } else if !matches!(self.state, ParsingState::MsgSeq | ParsingState::Suspended | ParsingState::Closed) {
+            self.recover(Recovery::Timeout)
         } else {
             self.result_from_state()
         }
     }
 
-    fn transit_and_result(&mut self, state: ParsingState) -> ParseResult {
+    fn transit_and_result(&mut self, state: ParsingState) -> ParseStatus {
         self.state = state;
         self.result_from_state()
     }
 
-    fn result_from_state(&self) -> ParseResult {
-        ParseResult::new(0, match self.state {
+    fn result_from_state(&self) -> ParseStatus {
+        let closing = if self.peer_closing { SYNC_STATE_CLOSING } else { 0 };
+        let flow_off = if self.peer_flow_off { SYNC_STATE_FLOW_OFF } else { 0 };
+        ParseStatus::new(0, closing | flow_off | match self.state {
             ParsingState::SyncAck => 0,
             ParsingState::SyncReqSeq |
-            ParsingState::SyncAckSeq => SYNC_STATE_RECV,
-            ParsingState::MsgSeq => SYNC_STATE_READY,
+            ParsingState::SyncAckSeq |
+            ParsingState::EpochSync => SYNC_STATE_RECV,
+            ParsingState::MsgSeq |
+            ParsingState::Gap => SYNC_STATE_READY,
             ParsingState::MsgAckSeq |
             ParsingState::MsgCode |
             ParsingState::MsgLen |
-            ParsingState::MsgData => SYNC_STATE_READY | SYNC_STATE_RECV,
+            ParsingState::MsgData |
+            ParsingState::SuspendReqSeq |
+            ParsingState::SuspendAckSeq |
+            ParsingState::EpochMsg |
+            ParsingState::FinSeq |
+            ParsingState::FinAckSeq |
+            ParsingState::FlowOffSeq |
+            ParsingState::FlowOnSeq |
+            ParsingState::MsgCrcLo |
+            ParsingState::MsgCrcHi => SYNC_STATE_READY | SYNC_STATE_RECV,
+            ParsingState::Suspended => SYNC_STATE_READY | SYNC_STATE_SUSPENDED,
+            ParsingState::WakeSeq => SYNC_STATE_READY | SYNC_STATE_RECV | SYNC_STATE_SUSPENDED,
+            ParsingState::Closed => SYNC_STATE_CLOSED,
         })
     }
 
-    fn packet_ready(&mut self) -> ParseResult {
+    // The frame's data is in; its checksum follows, if the config has one.
+    fn frame_end(&mut self) -> ParseStatus {
+        match self.config.checksum {
+            Checksum::None => self.packet_ready(),
+            Checksum::Crc16 => self.transit_and_result(ParsingState::MsgCrcLo),
+        }
+    }
+
+    fn packet_ready(&mut self) -> ParseStatus {
         self.state = ParsingState::MsgSeq;
-        ParseResult::new_packet(self.packet.take())
+        ParseStatus { packet: true, ..self.result_from_state() }
     }
 }
@@ -0,0 +1,149 @@
+use std::mem::ManuallyDrop;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, Weak};
+use super::packet::*;
+
+// What a pool does when every item is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolPolicy {
+    Grow,       // allocate another, which the pool keeps once returned.
+    DropOldest, // reuse the oldest item its user queued; see Endpoint::poll_pooled.
+    Error,      // give nothing, counting the miss.
+}
+
+// Items a pool hands out: made once, then cleared on return for reuse.
+pub trait Recycle {
+    fn pooled() -> Self;
+    fn recycle(&mut self);
+}
+
+impl Recycle for Packet {
+    fn pooled() -> Self {
+        Packet::new()
+    }
+
+    // Keeps the data's capacity, so refilling it doesn't allocate.
+    fn recycle(&mut self) {
+        self.seq = 0;
+        self.code = 0;
+        self.data.clear();
+    }
+}
+
+struct Free<T> {
+    items: Vec<T>,
+    capacity: usize,
+    misses: u64,
+}
+
+// A pool of reusable items, preallocated so steady-state use allocates
+// nothing. Items come out as Recycled and go back when dropped, from any
+// thread; clones share the pool.
+pub struct Pool<T> {
+    free: Arc<Mutex<Free<T>>>,
+    policy: PoolPolicy,
+}
+
+pub type PacketPool = Pool<Packet>;
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Pool { free: self.free.clone(), policy: self.policy }
+    }
+}
+
+impl<T: Recycle> Pool<T> {
+    pub fn new(capacity: usize, policy: PoolPolicy) -> Self {
+        let items = (0..capacity).map(|_| T::pooled()).collect();
+        Pool {
+            free: Arc::new(Mutex::new(Free { items, capacity, misses: 0 })),
+            policy,
+        }
+    }
+
+    pub fn policy(&self) -> PoolPolicy {
+        self.policy
+    }
+
+    // Items the pool holds, in use or not; it grows under PoolPolicy::Grow.
+    pub fn capacity(&self) -> usize {
+        self.free.lock().unwrap().capacity
+    }
+
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().items.len()
+    }
+
+    // Times an item was wanted with none available, grown or not.
+    pub fn misses(&self) -> u64 {
+        self.free.lock().unwrap().misses
+    }
+
+    pub fn get(&self) -> Option<Recycled<T>> {
+        let mut free = self.free.lock().unwrap();
+        let item = match free.items.pop() {
+            Some(item) => item,
+            None => {
+                free.misses += 1;
+                if self.policy != PoolPolicy::Grow {
+                    return None;
+                }
+                free.capacity += 1;
+                T::pooled()
+            },
+        };
+        Some(Recycled { item: ManuallyDrop::new(item), pool: Arc::downgrade(&self.free) })
+    }
+}
+
+// An item on loan from a pool, returned to it on drop.
+pub struct Recycled<T: Recycle> {
+    item: ManuallyDrop<T>,
+    pool: Weak<Mutex<Free<T>>>,
+}
+
+impl<T: Recycle> Recycled<T> {
+    // Takes the item for good, leaving the pool one smaller.
+    pub fn detach(mut this: Self) -> T {
+        let pool = ::std::mem::replace(&mut this.pool, Weak::new());
+        // this is forgotten rather than dropped, so the item is taken once.
+        let item = unsafe { ManuallyDrop::take(&mut this.item) };
+        ::std::mem::forget(this);
+        if let Some(pool) = pool.upgrade() {
+            let mut free = pool.lock().unwrap();
+            free.capacity = free.capacity.saturating_sub(1);
+        }
+        item
+    }
+}
+
+impl<T: Recycle> Deref for Recycled<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<T: Recycle> DerefMut for Recycled<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.item
+    }
+}
+
+impl<T: Recycle + ::std::fmt::Debug> ::std::fmt::Debug for Recycled<T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        (*self.item).fmt(f)
+    }
+}
+
+impl<T: Recycle> Drop for Recycled<T> {
+    fn drop(&mut self) {
+        // Drop runs once, so the item is taken once.
+        let mut item = unsafe { ManuallyDrop::take(&mut self.item) };
+        if let Some(pool) = self.pool.upgrade() {
+            item.recycle();
+            pool.lock().unwrap().items.push(item);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+// How a timeout follows the measured round trip:
+// rtt_multiplier * smoothed rtt + var_multiplier * its deviation, within
+// floor and ceiling. initial stands until the first sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    pub rtt_multiplier: u32,
+    pub var_multiplier: u32,
+    pub floor: Duration,
+    pub ceiling: Duration,
+    pub initial: Duration,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        TimeoutConfig {
+            rtt_multiplier: 2,
+            var_multiplier: 4,
+            floor: Duration::from_millis(10),
+            ceiling: Duration::from_secs(10),
+            initial: Duration::from_secs(1),
+        }
+    }
+}
+
+// A timeout derived from round trip samples, smoothed as TCP does, so one
+// setting serves a 1 ms UART and a 300 ms satellite hop alike. Each timeout
+// that expires without a sample doubles it, up to the ceiling.
+#[derive(Debug, Clone)]
+pub struct AdaptiveTimeout {
+    config: TimeoutConfig,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    backoff: u32,
+}
+
+impl AdaptiveTimeout {
+    pub fn new(config: TimeoutConfig) -> Self {
+        AdaptiveTimeout { config, srtt: None, rttvar: Duration::from_secs(0), backoff: 0 }
+    }
+
+    pub fn config(&self) -> &TimeoutConfig {
+        &self.config
+    }
+
+    // The smoothed round trip, once there is a sample.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn sample(&mut self, rtt: Duration) {
+        self.backoff = 0;
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            },
+            Some(srtt) => {
+                let err = rtt.abs_diff(srtt);
+                self.rttvar = (self.rttvar * 3 + err) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            },
+        }
+    }
+
+    pub fn backoff(&mut self) {
+        self.backoff = (self.backoff + 1).min(16);
+    }
+
+    // Forgets the samples, as for a different peer.
+    pub fn reset(&mut self) {
+        self.srtt = None;
+        self.rttvar = Duration::from_secs(0);
+        self.backoff = 0;
+    }
+
+    pub fn timeout(&self) -> Duration {
+        let c = &self.config;
+        let base = match self.srtt {
+            Some(srtt) => srtt * c.rtt_multiplier + self.rttvar * c.var_multiplier,
+            None => c.initial,
+        };
+        let t = base.max(c.floor).min(c.ceiling);
+        t.checked_mul(1 << self.backoff).unwrap_or(c.ceiling).min(c.ceiling)
+    }
+}
@@ -0,0 +1,83 @@
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embedded_hal_nb::serial::{ErrorType, Read, Write};
+use super::embedded::*;
+use super::packet::*;
+use super::parser::*;
+use super::stats::LinkStats;
+
+// An EmbeddedLink behind a critical section, usually a static, for sending
+// from interrupt handlers while the main loop receives:
+//
+//   static LINK: SharedLink<Uart> = SharedLink::new();
+//   LINK.init(EmbeddedLink::new(uart, 10)?);
+//
+// Each call takes the section once. poll holds it while draining what the
+// port has buffered, up to one packet, and copying that packet out; send
+// holds it while the whole packet is written, blocking on the port. Keep
+// both short where interrupt latency matters: poll often, and send from
+// interrupts only packets the port can take without waiting long.
+pub struct SharedLink<S> {
+    link: Mutex<RefCell<Option<EmbeddedLink<S>>>>,
+}
+
+impl<S> Default for SharedLink<S> {
+    fn default() -> Self {
+        SharedLink::new()
+    }
+}
+
+impl<S> SharedLink<S> {
+    pub const fn new() -> Self {
+        SharedLink { link: Mutex::new(RefCell::new(None)) }
+    }
+
+    // Replaces the link, returning the one before.
+    pub fn init(&self, link: EmbeddedLink<S>) -> Option<EmbeddedLink<S>> {
+        critical_section::with(|cs| self.link.borrow_ref_mut(cs).replace(link))
+    }
+
+    pub fn take(&self) -> Option<EmbeddedLink<S>> {
+        critical_section::with(|cs| self.link.borrow_ref_mut(cs).take())
+    }
+
+    // Runs f on the link, when there is one, inside the section.
+    pub fn with<R, F: FnOnce(&mut EmbeddedLink<S>) -> R>(&self, f: F) -> Option<R> {
+        critical_section::with(|cs| self.link.borrow_ref_mut(cs).as_mut().map(f))
+    }
+}
+
+impl<S, E> SharedLink<S> where S: Read<u8> + Write<u8> + ErrorType<Error = E> {
+    pub fn is_ready(&self) -> bool {
+        self.with(|link| link.is_ready()).unwrap_or(false)
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.with(|link| *link.stats()).unwrap_or_default()
+    }
+
+    // Like EmbeddedLink::poll, with the payload copied to buf.
+    pub fn poll<'b>(&self, buf: &'b mut [u8; PACKET_MAX_DATA_LEN]) -> Result<Option<Frame<'b>>, LinkError<E>> {
+        let head = self.with(|link| match link.poll()? {
+            Some(f) => {
+                let n = f.data.len();
+                buf[..n].copy_from_slice(f.data);
+                Ok(Some((f.seq, f.code, n)))
+            },
+            None => Ok(None),
+        });
+        match head.ok_or(LinkError::NotReady)? {
+            Ok(Some((seq, code, n))) => Ok(Some(Frame { seq, code, data: &buf[..n] })),
+            Ok(None) => Ok(None),
+            Err(e) => Err(LinkError::Serial(e)),
+        }
+    }
+
+    pub fn tick(&self) -> Result<(), LinkError<E>> {
+        Ok(self.with(|link| link.tick()).ok_or(LinkError::NotReady)??)
+    }
+
+    pub fn send(&self, code: u8, data: &[u8]) -> Result<(), LinkError<E>> {
+        self.with(|link| link.send(code, data)).ok_or(LinkError::NotReady)?
+    }
+}
@@ -0,0 +1,203 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::{ErrorType, Read, Write};
+use super::embedded::LinkError;
+use super::packet::*;
+use super::parser::*;
+use super::stats::LinkStats;
+
+// What the halves of a split link share: whether the link is up, and the
+// sync responses the receive half wants sent. Each field has a single
+// writer and only atomic loads and stores are used, so it needs no lock,
+// nor compare-and-swap. Usually a static:
+//
+//   static LINK: LinkShared = LinkShared::new();
+//   let (rx, tx) = LINK.split(uart_rx, uart_tx, 10);
+pub struct LinkShared {
+    ready: AtomicBool, // written by the receive half.
+    sync: AtomicU8,    // the latest sync byte to send.
+    syncs: AtomicU8,   // bumped with each sync to send, wrapping.
+}
+
+impl Default for LinkShared {
+    fn default() -> Self {
+        LinkShared::new()
+    }
+}
+
+impl LinkShared {
+    pub const fn new() -> Self {
+        LinkShared { ready: AtomicBool::new(false), sync: AtomicU8::new(0), syncs: AtomicU8::new(0) }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    // An EmbeddedLink in halves over a port's receive and transmit sides,
+    // for owning them from different contexts: the receive half from the
+    // UART interrupt, say, and the transmit half from the main loop. The
+    // transmit half sends the sync responses, so poll it regularly.
+    pub fn split<R, W>(&self, rx: R, tx: W, timeout_ticks: u32) -> (LinkRx<'_, R>, LinkTx<'_, W>) {
+        self.ready.store(false, Ordering::Release);
+        let mut rx = LinkRx {
+            serial: rx,
+            shared: self,
+            parser: Parser::new(),
+            synced: false,
+            timeout_ticks,
+            timer: None,
+            stats: LinkStats::default(),
+        };
+        let s = rx.parser.reset_status();
+        rx.apply(s);
+        let tx = LinkTx { serial: tx, shared: self, seq: 1, answered: 0, stats: LinkStats::default() };
+        (rx, tx)
+    }
+}
+
+pub struct LinkRx<'a, R> {
+    serial: R,
+    shared: &'a LinkShared,
+    parser: Parser,
+    synced: bool,
+    timeout_ticks: u32,
+    timer: Option<u32>,
+    stats: LinkStats, // the receive side's counters.
+}
+
+impl<'a, R> LinkRx<'a, R> {
+    fn apply(&mut self, s: ParseStatus) {
+        // A ping from the host passes through a state that isn't ready.
+        let ready = s.state.is_ready() || (self.shared.is_ready() && s.state.is_receiving());
+        if ready && !self.shared.is_ready() {
+            if self.synced {
+                self.stats.resyncs += 1;
+            }
+            self.synced = true;
+        }
+        self.shared.ready.store(ready, Ordering::Release);
+        match s.timer_action() {
+            TimerAction::Restart => self.timer = Some(self.timeout_ticks),
+            TimerAction::Stop => self.timer = None,
+            TimerAction::NoChange => {},
+        }
+        if s.sync != 0 {
+            let shared = self.shared;
+            shared.sync.store(s.sync, Ordering::Relaxed);
+            shared.syncs.store(shared.syncs.load(Ordering::Relaxed).wrapping_add(1), Ordering::Release);
+        }
+    }
+}
+
+impl<'a, R, E> LinkRx<'a, R> where R: Read<u8> + ErrorType<Error = E> {
+    pub fn is_ready(&self) -> bool {
+        self.shared.is_ready()
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    pub fn serial(&mut self) -> &mut R {
+        &mut self.serial
+    }
+
+    // Reads what the port has received and returns the first complete
+    // packet. Sync requests are left for the transmit half to answer.
+    pub fn poll(&mut self) -> Result<Option<Frame<'_>>, E> {
+        loop {
+            let b = match self.serial.read() {
+                Ok(b) => b,
+                Err(nb::Error::WouldBlock) => return Ok(None),
+                Err(nb::Error::Other(e)) => return Err(e),
+            };
+            self.stats.bytes_received += 1;
+            let s = self.parser.parse_frame(b);
+            self.apply(s);
+            if s.packet {
+                self.stats.packets_received += 1;
+                return Ok(Some(self.parser.frame()));
+            }
+        }
+    }
+
+    // Counts down the receive timer.
+    pub fn tick(&mut self) {
+        match self.timer {
+            Some(0) | Some(1) => {
+                self.timer = None;
+                let s = self.parser.timeout_status();
+                self.apply(s);
+            },
+            Some(n) => self.timer = Some(n - 1),
+            None => {},
+        }
+    }
+}
+
+pub struct LinkTx<'a, W> {
+    serial: W,
+    shared: &'a LinkShared,
+    seq: PacketSeq,
+    answered: u8, // the shared syncs count last sent.
+    stats: LinkStats, // the transmit side's counters.
+}
+
+impl<'a, W, E> LinkTx<'a, W> where W: Write<u8> + ErrorType<Error = E> {
+    pub fn is_ready(&self) -> bool {
+        self.shared.is_ready()
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    pub fn serial(&mut self) -> &mut W {
+        &mut self.serial
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), E> {
+        for b in bytes {
+            nb::block!(self.serial.write(*b))?;
+        }
+        Ok(())
+    }
+
+    // Sends the sync response the receive half asked for, if any. Several
+    // asked for since the last poll go out as one, the latest.
+    pub fn poll(&mut self) -> Result<(), E> {
+        let syncs = self.shared.syncs.load(Ordering::Acquire);
+        if syncs != self.answered {
+            self.answered = syncs;
+            let sync = self.shared.sync.load(Ordering::Relaxed);
+            let seq = self.seq;
+            self.write(&[sync, seq])?;
+            self.stats.bytes_sent += 2;
+        }
+        Ok(())
+    }
+
+    // Assigns the next sequence number and writes the packet, blocking
+    // until the port has taken it.
+    pub fn send(&mut self, code: u8, data: &[u8]) -> Result<(), LinkError<E>> {
+        self.poll()?;
+        if !self.is_ready() {
+            return Err(LinkError::NotReady);
+        }
+        if data.len() > PACKET_MAX_DATA_LEN {
+            return Err(LinkError::TooLong);
+        }
+        let (head, n) = frame_head(self.seq, code, data.len());
+        self.seq = self.seq.next();
+        self.write(&head[..n])?;
+        self.write(data)?;
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += (n + data.len()) as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), E> {
+        nb::block!(self.serial.flush())
+    }
+}
@@ -0,0 +1,216 @@
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::{ErrorType, Read, Write};
+use super::embedded::LinkError;
+use super::packet::*;
+use super::parser::*;
+use super::stats::LinkStats;
+
+// A byte ring, filled and drained whole records at a time.
+struct Ring<const N: usize> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    const fn new() -> Self {
+        Ring { buf: [0; N], head: 0, len: 0 }
+    }
+
+    fn free(&self) -> usize {
+        N - self.len
+    }
+
+    // The caller checks there's room.
+    fn push(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.buf[(self.head + self.len) % N] = *b;
+            self.len += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        if self.len == 0 { None } else { Some(self.buf[self.head]) }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(b)
+    }
+}
+
+// A link that never blocks or allocates, with every buffer sized at
+// compile time: received packets queue in RX bytes until taken with recv,
+// and outgoing bytes in TX until the port takes them. Each queued packet
+// costs three bytes more than its payload, which bounds the payload to
+// MAX_PAYLOAD. Call poll from the main loop and tick from a periodic
+// timer, as with EmbeddedLink.
+pub struct StaticLink<S, const RX: usize, const TX: usize> {
+    serial: S,
+    parser: Parser,
+    seq: PacketSeq,
+    ready: bool,
+    synced: bool,
+    timeout_ticks: u32,
+    timer: Option<u32>,
+    stats: LinkStats,
+    rx: Ring<RX>, // records of seq, code, length and payload.
+    tx: Ring<TX>,
+}
+
+impl<S, E, const RX: usize, const TX: usize> StaticLink<S, RX, TX>
+    where S: Read<u8> + Write<u8> + ErrorType<Error = E> {
+    pub const MAX_PAYLOAD: usize = {
+        assert!(RX > 3 && TX > 3, "StaticLink queues need room for a header");
+        let rx = RX - 3;
+        let tx = TX - 3;
+        let max = if rx < tx { rx } else { tx };
+        if max < PACKET_MAX_DATA_LEN { max } else { PACKET_MAX_DATA_LEN }
+    };
+
+    // The sync request starting the handshake goes out on the first poll.
+    pub fn new(serial: S, timeout_ticks: u32) -> Self {
+        let _ = Self::MAX_PAYLOAD;
+        let mut link = StaticLink {
+            serial,
+            parser: Parser::new(),
+            seq: 1,
+            ready: false,
+            synced: false,
+            timeout_ticks,
+            timer: None,
+            stats: LinkStats::default(),
+            rx: Ring::new(),
+            tx: Ring::new(),
+        };
+        let s = link.parser.reset_status();
+        link.apply(s);
+        link
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    // Bytes waiting for the port.
+    pub fn pending(&self) -> usize {
+        TX - self.tx.free()
+    }
+
+    pub fn serial(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    pub fn release(self) -> S {
+        self.serial
+    }
+
+    fn apply(&mut self, s: ParseStatus) {
+        // A ping from the host passes through a state that isn't ready.
+        let ready = s.state.is_ready() || (self.ready && s.state.is_receiving());
+        if ready && !self.ready {
+            if self.synced {
+                self.stats.resyncs += 1;
+            }
+            self.synced = true;
+        }
+        self.ready = ready;
+        match s.timer_action() {
+            TimerAction::Restart => self.timer = Some(self.timeout_ticks),
+            TimerAction::Stop => self.timer = None,
+            TimerAction::NoChange => {},
+        }
+        // Without room the peer times out and asks again.
+        if s.sync != 0 && self.tx.free() >= 2 {
+            self.tx.push(&[s.sync, self.seq]);
+            self.stats.bytes_sent += 2;
+        }
+    }
+
+    fn parse(&mut self, b: u8) {
+        self.stats.bytes_received += 1;
+        let s = self.parser.parse_frame(b);
+        self.apply(s);
+        if !s.packet {
+            return;
+        }
+        let f = self.parser.frame();
+        if f.data.len() <= Self::MAX_PAYLOAD && self.rx.free() >= f.data.len() + 3 {
+            self.rx.push(&[f.seq, f.code, f.data.len() as u8]);
+            self.rx.push(f.data);
+            self.stats.packets_received += 1;
+        } else {
+            self.stats.overflows += frame_len(f.data.len()) as u64;
+        }
+    }
+
+    // Parses what the port has received and writes queued bytes until it
+    // would block.
+    pub fn poll(&mut self) -> Result<(), E> {
+        loop {
+            match self.serial.read() {
+                Ok(b) => self.parse(b),
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+        while let Some(b) = self.tx.peek() {
+            match self.serial.write(b) {
+                Ok(()) => { self.tx.pop(); },
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // The oldest packet received, its payload copied to buf.
+    pub fn recv<'b>(&mut self, buf: &'b mut [u8; PACKET_MAX_DATA_LEN]) -> Option<Frame<'b>> {
+        let seq = self.rx.pop()?;
+        let code = self.rx.pop().unwrap_or(0);
+        let len = self.rx.pop().unwrap_or(0) as usize;
+        for b in buf[..len].iter_mut() {
+            *b = self.rx.pop().unwrap_or(0);
+        }
+        Some(Frame { seq, code, data: &buf[..len] })
+    }
+
+    // Counts down the receive timer.
+    pub fn tick(&mut self) {
+        match self.timer {
+            Some(0) | Some(1) => {
+                self.timer = None;
+                let s = self.parser.timeout_status();
+                self.apply(s);
+            },
+            Some(n) => self.timer = Some(n - 1),
+            None => {},
+        }
+    }
+
+    // Assigns the next sequence number and queues the packet.
+    pub fn send(&mut self, code: u8, data: &[u8]) -> Result<(), LinkError<E>> {
+        if !self.ready {
+            return Err(LinkError::NotReady);
+        }
+        if data.len() > Self::MAX_PAYLOAD {
+            return Err(LinkError::TooLong);
+        }
+        let (head, n) = frame_head(self.seq, code, data.len());
+        if self.tx.free() < n + data.len() {
+            return Err(LinkError::Full);
+        }
+        self.seq = self.seq.next();
+        self.tx.push(&head[..n]);
+        self.tx.push(data);
+        self.stats.packets_sent += 1;
+        self.stats.bytes_sent += (n + data.len()) as u64;
+        Ok(())
+    }
+}
@@ -0,0 +1,14 @@
+use core::time::Duration;
+
+// Traffic counters of a link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub resyncs: u64,   // handshakes after the first, from either side.
+    pub overflows: u64, // bytes lost to a full receive queue.
+    pub stalls: u64,    // times the peer turned flow off.
+    pub stall_time: Duration, // held by the peer, as of its last FLOW_ON.
+}
@@ -1,13 +1,12 @@
 #![cfg(test)]
 
+use std::collections::VecDeque;
 use std::fmt;
+use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant};
 use super::*;
-
-impl PartialEq for Packet {
-    fn eq(&self, other: &Self) -> bool {
-        self.seq == other.seq && self.code == other.code && self.data == other.data
-    }
-}
+use const_frame;
 
 impl PartialEq for ParseResult {
     fn eq(&self, other: &Self) -> bool {
@@ -16,20 +15,11 @@ impl PartialEq for ParseResult {
                 Some(pkt1) => pkt == pkt1,
                 _ => false
             },
-            None => match &other.packet {
-                None => true,
-                _ => false
-            }
+            None => other.packet.is_none()
         }
     }
 }
 
-impl fmt::Debug for Packet {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Packet {{ seq: {}, code: {}, data: {:?} }}", self.seq, self.code, self.data)
-    }
-}
-
 impl fmt::Debug for ParseResult {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ParseResult {{ sync: {}, state: {}, packet: {} }}", 
@@ -49,6 +39,14 @@ macro_rules! test_packet_encode {
             let mut w: Vec<u8> = Vec::new();
             assert_eq!(pkt.encode(&mut w).unwrap(), expected.len());
             assert_eq!(w.as_slice(), expected.as_slice());
+            assert_eq!(frame_len(pkt.data.len()), expected.len());
+            let mut head = [0; 3];
+            assert_eq!(pkt.scatter(&mut head).concat(), expected);
+            let mut ring = [0; 11];
+            let end = frame_to_ring(&mut ring, 7, pkt.seq, pkt.code, &pkt.data);
+            assert_eq!(end, (7 + expected.len()) % 11);
+            let wrapped: Vec<u8> = ring.iter().cycle().skip(7).take(expected.len()).cloned().collect();
+            assert_eq!(wrapped, expected);
         }
     }
 }
@@ -60,6 +58,75 @@ test_packet_encode!(test_packet_encode_event_no_data, Packet::new_with(1, 0x82),
 test_packet_encode!(test_packet_encode_event_small_data, Packet{seq: 1, code: 0x82, data: vec![1]}, 1, 0x92, 1);
 test_packet_encode!(test_packet_encode_event_large_data, Packet{seq: 1, code: 0x82, data: vec![1, 2, 3, 4, 5, 6, 7]}, 1, 0xf2, 7, 1, 2, 3, 4, 5, 6, 7);
 
+// A writer taking at most 3 bytes a call, as a busy unbuffered port may.
+struct Trickle(Vec<u8>, usize);
+
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(3);
+        self.0.extend_from_slice(&buf[..n]);
+        self.1 += 1;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_packet_encode_short_writes() {
+    let mut w = Trickle(Vec::new(), 0);
+    let pkt = Packet { seq: 1, code: 2, data: vec![1, 2, 3, 4, 5, 6, 7] };
+    assert_eq!(pkt.encode(&mut w).unwrap(), 10);
+    assert_eq!(w.0, vec![1, 0x72, 7, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(w.1, 4);
+    let pkt = Packet { seq: 1, code: 2, data: vec![0; PACKET_MAX_DATA_LEN + 1] };
+    assert_eq!(pkt.encode(&mut w).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes_packet() {
+    let pkt = Packet { seq: 1, code: 2, data: vec![1, 2, 3, 4, 5, 6, 7] };
+    let copy = pkt.clone();
+    let ptr = copy.data.as_ptr();
+    let shared = BytesPacket::from(copy);
+    assert_eq!(shared.data.as_ptr(), ptr);
+    assert_eq!(shared.data.slice(2..4).as_ptr(), shared.data[2..].as_ptr());
+    let mut w = Vec::new();
+    assert_eq!(shared.encode(&mut w).unwrap(), 10);
+    assert_eq!(w, vec![1, 0x72, 7, 1, 2, 3, 4, 5, 6, 7]);
+    let back = Packet::from(shared);
+    assert_eq!(back.data.as_ptr(), ptr);
+    assert_eq!(back, pkt);
+}
+
+#[test]
+fn test_packet_encode_vectored() {
+    let mut w: Vec<u8> = Vec::new();
+    let pkt = Packet { seq: 1, code: 2, data: vec![1, 2, 3, 4, 5, 6, 7] };
+    assert_eq!(pkt.encode_vectored(&mut w).unwrap(), 10);
+    assert_eq!(Packet::new_with(2, 0x81).encode_vectored(&mut w).unwrap(), 2);
+    assert_eq!(w, vec![1, 0x72, 7, 1, 2, 3, 4, 5, 6, 7, 2, 0x81]);
+
+    // the default write_vectored takes one slice a call, 3 bytes of it here.
+    let mut w = Trickle(Vec::new(), 0);
+    assert_eq!(pkt.encode_vectored(&mut w).unwrap(), 10);
+    assert_eq!(w.0, vec![1, 0x72, 7, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(w.1, 4);
+}
+
+#[test]
+fn test_soft_crc16() {
+    let mut crc = SoftCrc16::new();
+    assert_eq!(crc.checksum(b"123456789"), 0x6f91);
+    crc.reset();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0x6f91);
+}
+
 #[test]
 fn test_packet_seq() {
     for n in 0xf0..0x100 {
@@ -89,6 +156,209 @@ fn test_parser_reset() {
     assert!(pr.packet.is_none());
 }
 
+// Random input, heavy on sync and length bytes, never panics the parser.
+#[test]
+fn test_parser_any_input() {
+    let mut lcg: u32 = 1;
+    for p in [Parser::new(), Parser::promiscuous()].iter_mut() {
+        for _ in 0..100000 {
+            lcg = lcg.wrapping_mul(1103515245).wrapping_add(12345);
+            let b = match (lcg >> 16) % 4 {
+                0 => SYNC_ACK,
+                1 => 0x7f,
+                _ => (lcg >> 24) as u8,
+            };
+            if p.parse_frame(b).packet {
+                assert!(p.frame().data.len() <= PACKET_MAX_DATA_LEN);
+            }
+        }
+    }
+    // the payload buffer is all the memory a parser has.
+    assert!(::std::mem::size_of::<Parser>() <= PACKET_MAX_DATA_LEN + 80);
+}
+
+#[test]
+fn test_parser_recoveries() {
+    let mut p = Parser::new();
+    for b in &[SYNC_ACK, 1, 2, SYNC_ACK, 1, 1, 0x7f, 0xff, SYNC_ACK, 2, 2, 0x71] {
+        p.parse_frame(*b);
+    }
+    p.timeout_status();
+    assert_eq!(*p.recoveries(), Recoveries { bad_seq: 1, bad_len: 1, timeouts: 1, cut_short: 0, bad_crc: 0 });
+
+    let mut p = Parser::with_config(SyncConfig { framing: Framing::IdleGap, ..SyncConfig::default() });
+    p.parse_frame(1);
+    p.idle_status();
+    p.idle_status();
+    assert_eq!(p.recoveries().cut_short, 1);
+}
+
+// parse_bytes stops wherever parse_frame's status needs acting on, with the
+// same status and packet, and nowhere else.
+#[test]
+fn test_parse_bytes() {
+    let mut lcg: u32 = 7;
+    let mut rand = |n: u32| {
+        lcg = lcg.wrapping_mul(1103515245).wrapping_add(12345);
+        (lcg >> 8) % n
+    };
+    let mut input = vec![SYNC_ACK, 1];
+    let mut seq = 1u8;
+    for _ in 0..2000 {
+        match rand(20) {
+            0 => input.push(rand(256) as u8),
+            1 => input.extend_from_slice(&[SYNC_REQ, seq]),
+            _ => {
+                let len = rand(PACKET_MAX_DATA_LEN as u32 + 1) as usize;
+                let pkt = Packet { seq, code: rand(256) as u8, data: (0..len).map(|_| rand(256) as u8).collect() };
+                pkt.encode(&mut input).unwrap();
+                seq = seq.next();
+            },
+        }
+    }
+    let mut a = Parser::new();
+    let mut b = Parser::new();
+    let mut prev = ParseStatus { sync: 0, state: 0, packet: false };
+    let mut pos = 0;
+    let mut packets = 0;
+    while pos < input.len() {
+        let chunk = &input[pos..(pos + 1 + rand(300) as usize).min(input.len())];
+        let (n, status) = b.parse_bytes(chunk);
+        assert!(n > 0 && n <= chunk.len());
+        for (i, byte) in chunk[..n].iter().enumerate() {
+            let s = a.parse_frame(*byte);
+            let quiet = s.sync == 0 && !s.packet && s.state == prev.state;
+            assert_eq!(quiet, i + 1 < n || (n == chunk.len() && quiet), "at {}", pos + i);
+            prev = s;
+        }
+        assert_eq!(status, prev);
+        if status.packet {
+            assert_eq!(a.frame(), b.frame());
+            packets += 1;
+        }
+        pos += n;
+    }
+    assert!(packets > 1000);
+}
+
+#[test]
+fn test_parser_epoch() {
+    let mut p = Parser::new();
+    for b in &[EPOCH, 0, SYNC_ACK, 1] {
+        p.parse_frame(*b);
+    }
+    assert_eq!(p.peer_epoch(), Some(0));
+    assert!(p.parse_frame(EPOCH).state.is_ready());
+    assert!(p.parse_frame(5).state.is_ready());
+    assert_eq!(p.peer_epoch(), Some(5));
+    // a sync byte where the count should resync.
+    p.parse_frame(EPOCH);
+    assert_eq!(p.parse_frame(SYNC_REQ), ParseStatus { sync: SYNC_REQ, state: 0, packet: false });
+    p.reset_status();
+    assert_eq!(p.peer_epoch(), Some(5));
+}
+
+#[test]
+fn test_parser_close() {
+    let mut p = Parser::new();
+    for b in &[SYNC_ACK, 1, FIN] {
+        p.parse_frame(*b);
+    }
+    let s = p.parse_frame(1);
+    assert!(s.state.is_ready() && s.state.is_closing());
+    assert_eq!(p.close_status().sync, FIN_ACK);
+    assert!(p.timeout_status().state.is_closed());
+    assert!(!p.parse_frame(2).state.is_ready());
+    // a new handshake reopens the link.
+    p.parse_frame(SYNC_REQ);
+    assert_eq!(p.parse_frame(5), ParseStatus { sync: SYNC_ACK, state: SYNC_STATE_READY, packet: false });
+
+    assert_eq!(p.close_status().sync, FIN);
+    p.parse_frame(FIN_ACK);
+    assert_eq!(p.parse_frame(5).state, SYNC_STATE_CLOSED);
+}
+
+#[test]
+fn test_parser_idle_gap() {
+    let mut p = Parser::with_config(SyncConfig { framing: Framing::IdleGap, ..SyncConfig::default() });
+    assert_eq!(p.idle_status().state, SYNC_STATE_READY);
+    // any seq starts a frame, without a handshake.
+    for b in &[9, 0x11] {
+        assert!(!p.parse_frame(*b).packet);
+    }
+    assert!(p.parse_frame(1).packet);
+    assert_eq!(p.frame(), Frame { seq: 9, code: 1, data: &[1] });
+
+    // a frame cut short ends at the idle.
+    p.parse_frame(3);
+    p.parse_frame(0x32);
+    assert!(!p.idle_status().state.is_receiving());
+    assert!(p.parse_frame(4).state.is_receiving());
+    assert!(p.parse_frame(0x02).packet);
+
+    // garbage skips to the next idle, without a sync request.
+    assert_eq!(p.parse_frame(0xf3), ParseStatus { sync: 0, state: SYNC_STATE_READY, packet: false });
+    assert!(!p.parse_frame(5).state.is_receiving());
+    assert!(!p.parse_frame(0x02).packet);
+    p.idle_status();
+    p.parse_frame(5);
+    assert!(p.parse_frame(0x02).packet);
+    assert_eq!(p.timeout_status().state, SYNC_STATE_READY);
+}
+
+#[test]
+fn test_demux() {
+    let mut d = Demux::new();
+    let mut got = Vec::new();
+    let mut on = |tag: u16, bytes: &[u8], d: &mut Demux<u16>| {
+        let mut syncs = Vec::new();
+        d.parse(tag, bytes, |s, f| {
+            if s.sync != 0 {
+                syncs.push(s.sync);
+            }
+            got.extend(f.map(|f| (tag, f.seq, f.code)));
+        });
+        syncs
+    };
+    assert_eq!(on(0x10, &[SYNC_REQ, 4], &mut d), vec![SYNC_ACK]);
+    assert_eq!(on(0x20, &[SYNC_REQ, 9], &mut d), vec![SYNC_ACK]);
+    // garbage from one peer leaves the other in sync.
+    assert_eq!(on(0x20, &[0xf3], &mut d), vec![SYNC_REQ]);
+    on(0x10, &[4, 0x01], &mut d);
+    on(0x20, &[10, 0x02], &mut d);
+    on(0x10, &[5], &mut d);
+    on(0x10, &[0x03], &mut d);
+    assert_eq!(got, vec![(0x10, 4, 1), (0x10, 5, 3)]);
+    assert_eq!(d.len(), 2);
+    assert!(d.parser(&0x10).is_some());
+    d.remove(&0x20);
+    assert_eq!(d.tags().collect::<Vec<_>>(), vec![&0x10]);
+
+    // datagrams end at an idle.
+    let mut d = Demux::with_config(SyncConfig { framing: Framing::IdleGap, ..SyncConfig::default() });
+    let mut n = d.parse("a", &[1, 0x21, 0], |_, _| ());
+    d.idle(&"a");
+    n += d.parse("a", &[2, 0x01], |_, _| ());
+    assert_eq!(n, 1);
+}
+
+#[test]
+fn test_parse_slice_budgeted() {
+    let mut input = vec![SYNC_ACK, 1];
+    for seq in 1..4 {
+        Packet { seq, code: seq, data: vec![seq; 3] }.encode(&mut input).unwrap();
+    }
+    let mut p = Parser::new();
+    let mut codes = Vec::new();
+    let (n, packets) = p.parse_slice_budgeted(&input, 2, |_, f| codes.extend(f.map(|f| f.code)));
+    assert_eq!((n, packets), (2 + 2 * frame_len(3), 2));
+    assert_eq!(codes, vec![1, 2]);
+    let (m, packets) = p.parse_slice_budgeted(&input[n..], 2, |_, f| codes.extend(f.map(|f| f.code)));
+    assert_eq!((n + m, packets), (input.len(), 1));
+    assert_eq!(codes, vec![1, 2, 3]);
+    assert_eq!(p.parse_slice_budgeted(&input[..4], 0, |_, _| panic!()), (0, 0));
+}
+
 #[test]
 fn test_sync_state() {
     assert!(!0u8.is_ready());
@@ -153,6 +423,14 @@ impl ParserTestSeq {
         self.expect(ParseResult{sync: 0, state: SYNC_STATE_READY|SYNC_STATE_RECV, packet: None})
     }
 
+    fn expect_suspended(self) -> Self {
+        self.expect(ParseResult{sync: 0, state: SYNC_STATE_READY|SYNC_STATE_SUSPENDED, packet: None})
+    }
+
+    fn expect_waking(self) -> Self {
+        self.expect(ParseResult{sync: 0, state: SYNC_STATE_READY|SYNC_STATE_RECV|SYNC_STATE_SUSPENDED, packet: None})
+    }
+
     fn expect_last(mut self, pr: ParseResult) -> Self {
         self.last = pr;
         self
@@ -166,6 +444,14 @@ impl ParserTestSeq {
         self.expect_last(ParseResult{sync: SYNC_REQ, state: 0, packet: None})
     }
 
+    fn suspended(self, sync: u8) -> Self {
+        self.expect_last(ParseResult{sync, state: SYNC_STATE_READY|SYNC_STATE_SUSPENDED, packet: None})
+    }
+
+    fn waking(self) -> Self {
+        self.expect_last(ParseResult{sync: 0, state: SYNC_STATE_READY|SYNC_STATE_RECV|SYNC_STATE_SUSPENDED, packet: None})
+    }
+
     fn synced(self) -> Self {
         self.expect_last(ParseResult{sync: 0, state: SYNC_STATE_READY, packet: None})
     }
@@ -274,6 +560,31 @@ test_parser!(test_parser_ack_invalid_seq_after_sync,
 	parse!(2, 2).expect_receiving().packet(2, 2, &[])
 );
 
+test_parser!(test_parser_suspend_and_wake,
+    parse!(SYNC_ACK, 1).expect_syncing().synced(),
+    parse!(1, 2).expect_receiving().packet(1, 2, &[]),
+    parse!(WAKE).synced(),
+    parse!(SUSPEND_REQ, 2).expect_receiving().suspended(SUSPEND_ACK),
+    parse!(0, 2, WAKE, SYNC_ACK, 2).expect_suspended().suspended(0),
+    timeout!().suspended(0),
+    parse!(SYNC_REQ, 2).expect_waking().synced_with_ack(),
+    parse!(2, 2).expect_receiving().packet(2, 2, &[])
+);
+
+test_parser!(test_parser_wake_garbled,
+    parse!(SYNC_ACK, 1).expect_syncing().synced(),
+    parse!(SUSPEND_ACK, 1).expect_receiving().suspended(0),
+    parse!(SYNC_REQ, 0xf5).expect_waking().suspended(0),
+    parse!(SYNC_REQ).expect_suspended().waking(),
+    timeout!().suspended(0),
+    parse!(SYNC_REQ, 1).expect_waking().synced_with_ack()
+);
+
+test_parser!(test_parser_suspend_invalid_seq,
+    parse!(SYNC_ACK, 1).expect_syncing().synced(),
+    parse!(SUSPEND_REQ, 3).expect_receiving().resync()
+);
+
 test_parser!(test_parser_invalid_seq,
     parse!(SYNC_ACK, 1).expect_syncing().synced(),
     parse!(1, 2).expect_receiving().packet(1, 2, &[]),
@@ -288,3 +599,1015 @@ test_parser!(test_parser_invalid_data_len,
 	parse!(1, 2, 3, 4),
     parse!(SYNC_ACK, 1).expect_syncing().synced()
 );
+
+#[test]
+fn test_endpoint_handshake() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::named(b, "base").unwrap();
+    assert_eq!(dev.device(), "base");
+    assert!(host.send(Packet::new_with(0, 1)).is_err());
+    assert!(dev.poll().unwrap().is_empty());
+    assert!(host.poll().unwrap().is_empty());
+    assert!(host.is_ready() && dev.is_ready());
+
+    host.send(Packet { seq: 0, code: 0x01, data: vec![1, 2, 3] }).unwrap();
+    host.send(Packet::new_with(0, 0x02)).unwrap();
+    dev.send(Packet::new_with(0, 0x81)).unwrap();
+    let pkts = dev.poll().unwrap();
+    assert_eq!(pkts, vec![Packet { seq: 1, code: 0x01, data: vec![1, 2, 3] }, Packet::new_with(2, 0x02)]);
+    assert_eq!(host.poll().unwrap(), vec![Packet::new_with(1, 0x81)]);
+    assert_eq!(*host.stats(), LinkStats {
+        packets_sent: 2,
+        packets_received: 1,
+        bytes_sent: 11,
+        bytes_received: 6,
+        resyncs: 0,
+        overflows: 0,
+        ..LinkStats::default()
+    });
+
+    // garbage resets the device parser, which resyncs.
+    host.transport().write_all(&[0x55]).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(dev.is_ready());
+    assert_eq!(dev.stats().resyncs, 1);
+
+    // a restarted device numbers its packets from 1 again.
+    dev.restart().unwrap();
+    assert!(!dev.is_ready());
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(dev.is_ready());
+    dev.send(Packet::new_with(0, 0x81)).unwrap();
+    assert_eq!(host.poll().unwrap(), vec![Packet::new_with(1, 0x81)]);
+}
+
+#[test]
+fn test_endpoint_suspend() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    host.send(Packet::new_with(0, 0x01)).unwrap();
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(1, 0x01)]);
+
+    dev.suspend().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(host.is_suspended() && dev.is_suspended());
+    assert!(host.is_ready() && dev.is_ready());
+    assert!(host.send(Packet::new_with(0, 0x01)).is_err());
+
+    // the first bytes after waking may be garbled.
+    host.transport().write_all(&[0x31]).unwrap();
+    host.resume().unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    assert!(!host.is_suspended() && !dev.is_suspended());
+    host.send(Packet::new_with(0, 0x02)).unwrap();
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(2, 0x02)]);
+    assert_eq!((host.stats().resyncs, dev.stats().resyncs), (0, 0));
+}
+
+#[test]
+fn test_endpoint_poll_budgeted() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    for code in 1..=5 {
+        host.send(Packet { seq: 0, code, data: vec![code; 4] }).unwrap();
+    }
+    let (pkts, more) = dev.poll_budgeted(2, usize::MAX).unwrap();
+    assert_eq!(pkts.iter().map(|p| p.code).collect::<Vec<_>>(), vec![1, 2]);
+    assert!(more);
+    let (pkts, more) = dev.poll_budgeted(10, frame_len(4) + 2).unwrap();
+    assert_eq!(pkts.iter().map(|p| p.code).collect::<Vec<_>>(), vec![3]);
+    assert!(more);
+    let (pkts, more) = dev.poll_budgeted(10, 100).unwrap();
+    assert_eq!(pkts.iter().map(|p| p.code).collect::<Vec<_>>(), vec![4, 5]);
+    assert!(!more);
+    assert_eq!(dev.stats().packets_received, 5);
+}
+
+#[test]
+fn test_endpoint_poll_stamped() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    let mut ticks = 0;
+    dev.set_hw_clock(move || {
+        ticks += 1;
+        Some(Duration::from_micros(ticks * 100))
+    });
+    host.send(Packet { seq: 0, code: 1, data: vec![1; 4] }).unwrap();
+    host.send(Packet { seq: 0, code: 2, data: vec![2; 4] }).unwrap();
+    let before = Instant::now();
+    let pkts = dev.poll_stamped().unwrap();
+    assert_eq!(pkts.iter().map(|p| p.pkt.code).collect::<Vec<_>>(), vec![1, 2]);
+    // both came in one read.
+    assert_eq!(pkts[0].hw, Some(Duration::from_micros(100)));
+    assert_eq!(pkts[1].hw, pkts[0].hw);
+    assert!(pkts[0].rx >= before && pkts[0].rx <= Instant::now());
+    assert!(pkts[0].age() < Duration::from_secs(1));
+
+    host.send(Packet { seq: 0, code: 3, data: vec![] }).unwrap();
+    let pkts = dev.poll_stamped().unwrap();
+    assert_eq!(pkts[0].hw, Some(Duration::from_micros(200)));
+}
+
+#[test]
+fn test_endpoint_send_too_long() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    host.send(Packet { seq: 0, code: 1, data: vec![] }).unwrap();
+    let seq = dev.poll().unwrap()[0].seq;
+    let err = host.send(Packet { seq: 0, code: 1, data: vec![0; PACKET_MAX_DATA_LEN + 1] }).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    host.send(Packet { seq: 0, code: 2, data: vec![] }).unwrap();
+    assert_eq!(dev.poll().unwrap(), vec![Packet { seq: seq.next(), code: 2, data: vec![] }]);
+    assert_eq!(dev.stats().resyncs, 0);
+}
+
+#[test]
+fn test_endpoint_crc16() {
+    let config = Parser::builder().checksum(Checksum::Crc16).sync_bytes(0xf0, 0xf1).config().unwrap();
+    let mut wire = vec![0xf1, 3];
+    Packet { seq: 3, code: 1, data: vec![1, 2, 3, 4] }.encode_with(&config, &mut wire).unwrap();
+    let mut p = Parser::with_config(config);
+    let mut pkts = 0;
+    p.parse_slice_budgeted(&wire, usize::MAX, |_, f| pkts += f.is_some() as usize);
+    assert_eq!((pkts, p.recoveries().bad_crc), (1, 0));
+
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::with_sync_config(a, "host", config).unwrap();
+    let mut dev = Endpoint::with_sync_config(b, "dev", config).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(host.is_ready() && dev.is_ready());
+    host.send(Packet { seq: 0, code: 1, data: vec![1; 4] }).unwrap();
+    host.send(Packet { seq: 0, code: 2, data: vec![2; 40] }).unwrap();
+    let pkts = dev.poll().unwrap();
+    assert_eq!(pkts.iter().map(|p| p.data.len()).collect::<Vec<_>>(), vec![4, 40]);
+    assert_eq!(dev.recoveries().bad_crc, 0);
+}
+
+#[test]
+fn test_endpoint_acks_sync_override() {
+    let config = Parser::builder().sync_bytes(0xf0, 0xf1).config().unwrap();
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::with_sync_config(a, "host", config).unwrap();
+    let mut dev = Endpoint::with_sync_config(b, "dev", config).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(host.is_ready() && dev.is_ready());
+    assert!(dev.acks() > 0);
+
+    let acks = host.acks();
+    host.send_sync().unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    assert_eq!(host.acks(), acks + 1);
+    assert_eq!(host.stats().resyncs, 0);
+}
+
+#[test]
+fn test_endpoint_peer_reboot() {
+    use std::sync::{Arc, Mutex};
+
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::with_epoch(b, "", 7).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    host.on_event(move |e| log.lock().unwrap().push(e));
+    host.set_setup(vec![Packet { seq: 0, code: 0x05, data: vec![1, 2] }]);
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert_eq!(host.peer_epoch(), Some(7));
+    assert!(dev.poll().unwrap().is_empty());
+
+    // a peer's ping and a glitch both leave the epoch as it was.
+    dev.send_sync().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    dev.transport().write_all(&[0x55]).unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    assert!(host.is_ready());
+    assert_eq!(*events.lock().unwrap(), vec![LinkEvent::Synchronized, LinkEvent::LostSync, LinkEvent::Resynced]);
+    events.lock().unwrap().clear();
+
+    dev.restart().unwrap();
+    assert_eq!(dev.epoch(), Some(8));
+    assert!(host.poll().unwrap().is_empty());
+    assert_eq!(dev.poll().unwrap(), vec![Packet { seq: 1, code: 0x05, data: vec![1, 2] }]);
+    assert!(dev.is_ready());
+    assert_eq!(host.peer_epoch(), Some(8));
+    assert_eq!(*events.lock().unwrap(), vec![LinkEvent::PeerRebooted]);
+    dev.send(Packet::new_with(0, 0x81)).unwrap();
+    assert_eq!(host.poll().unwrap(), vec![Packet::new_with(1, 0x81)]);
+}
+
+#[test]
+fn test_endpoint_close() {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let log = events.clone();
+    dev.on_event(move |e| log.lock().unwrap().push(e));
+    dev.poll().unwrap();
+    host.poll().unwrap();
+
+    // the device flushes what it has, then closes too.
+    let peer = thread::spawn(move || {
+        while !dev.is_peer_closing() {
+            dev.poll().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        dev.send(Packet::new_with(0, 0x81)).unwrap();
+        assert!(dev.close(Duration::from_secs(1)).unwrap().is_empty());
+        dev
+    });
+    let start = Instant::now();
+    assert_eq!(host.close(Duration::from_secs(5)).unwrap(), vec![Packet::new_with(1, 0x81)]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let mut dev = peer.join().unwrap();
+    assert!(host.is_closed() && dev.is_closed());
+    assert!(!host.is_ready() && !dev.is_ready());
+    assert!(host.send(Packet::new_with(0, 0x01)).is_err());
+    assert_eq!(*events.lock().unwrap(), vec![LinkEvent::Synchronized, LinkEvent::PeerClosing, LinkEvent::Closed]);
+
+    // a peer that never acks is given up on at the deadline.
+    dev.restart().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(host.is_ready() && dev.is_ready() && !dev.is_closed());
+    dev.close(Duration::from_millis(20)).unwrap();
+    assert!(dev.is_closed());
+    host.poll().unwrap();
+    assert!(host.is_peer_closing() && host.is_ready());
+}
+
+#[test]
+fn test_endpoint_flow_control() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    host.set_urgent(&[0x0f]);
+
+    dev.flow_off().unwrap();
+    host.poll().unwrap();
+    assert!(host.is_flow_off());
+    host.send(Packet::new_with(0, 0x01)).unwrap();
+    host.send(Packet::new_with(0, 0x0f)).unwrap();
+    host.send(Packet::new_with(0, 0x02)).unwrap();
+    assert_eq!(host.held(), 2);
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(1, 0x0f)]);
+
+    dev.flow_on().unwrap();
+    host.poll().unwrap();
+    assert!(!host.is_flow_off());
+    assert_eq!(host.held(), 0);
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(2, 0x01), Packet::new_with(3, 0x02)]);
+    assert!(dev.is_ready());
+    assert_eq!(host.stats().stalls, 1);
+
+    // a resync turns flow back on.
+    dev.flow_off().unwrap();
+    host.poll().unwrap();
+    host.send(Packet::new_with(0, 0x03)).unwrap();
+    dev.restart().unwrap();
+    host.poll().unwrap();
+    assert!(!host.is_flow_off() && host.held() == 0);
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(4, 0x03)]);
+}
+
+#[test]
+fn test_parser_builder() {
+    assert!(Parser::builder().sync_bytes(FLOW_OFF, SYNC_ACK).build().is_none());
+    let builder = Parser::builder().max_len(4).checksum(Checksum::Crc16).sync_bytes(0xf0, 0xf1);
+    let config = builder.config().unwrap();
+    let mut p = builder.build().unwrap();
+    assert_eq!(p.parse(SYNC_REQ).state, 0);
+    p.parse(0xf0);
+    let r = p.parse(3);
+    assert_eq!(r.needs_sync_reply().map(|s| config.wire(s)), Some(0xf1));
+
+    let mut wire = Vec::new();
+    Packet { seq: 3, code: 1, data: vec![1, 2] }.encode_with(&config, &mut wire).unwrap();
+    assert_eq!(wire.len(), 6);
+    assert!(Packet { seq: 4, code: 1, data: vec![0; 5] }.encode_with(&config, &mut wire).is_err());
+    let pkts: Vec<Packet> = p.parse_iter(wire.iter().cloned()).flatten().collect();
+    assert_eq!(pkts, vec![Packet { seq: 3, code: 1, data: vec![1, 2] }]);
+    wire.clear();
+    Packet { seq: 4, code: 1, data: vec![1, 2] }.encode_with(&config, &mut wire).unwrap();
+    wire[3] ^= 1;
+    assert_eq!(p.parse_iter(wire.iter().cloned()).flatten().count(), 0);
+    assert_eq!(p.recoveries().bad_crc, 1);
+
+    let mut p = Parser::builder().seq_check(SeqCheck::Lenient).build().unwrap();
+    let mut wire = vec![SYNC_REQ, 3];
+    Packet { seq: 9, code: 1, data: vec![] }.encode(&mut wire).unwrap();
+    assert_eq!(p.parse_iter(wire.iter().cloned()).flatten().count(), 1);
+}
+
+#[test]
+fn test_encode_const() {
+    static ESTOP: [u8; 3] = encode_const(1, 0x0f, [1]);
+    static HEARTBEAT: &[u8] = &const_frame!(2, 0x01, []);
+    const LONG: [u8; 11] = const_frame!(3, 0x82, [1, 2, 3, 4, 5, 6, 7, 8]);
+    for (frame, pkt) in [
+        (&ESTOP[..], Packet { seq: 1, code: 0x0f, data: vec![1] }),
+        (HEARTBEAT, Packet { seq: 2, code: 0x01, data: vec![] }),
+        (&LONG[..], Packet { seq: 3, code: 0x82, data: vec![1, 2, 3, 4, 5, 6, 7, 8] }),
+    ] {
+        let mut wire = Vec::new();
+        pkt.encode(&mut wire).unwrap();
+        assert_eq!(frame, &wire[..]);
+    }
+
+    // with a checksum, as a Crc16 link encodes and takes it.
+    static ESTOP_CRC: [u8; 5] = encode_const_crc16(1, 0x0f, [1]);
+    const LONG_CRC: [u8; 13] = const_frame!(1, 0x82, [1, 2, 3, 4, 5, 6, 7, 8], crc16);
+    let config = Parser::builder().checksum(Checksum::Crc16).seq_check(SeqCheck::Lenient).config().unwrap();
+    let mut p = Parser::with_config(config);
+    for (frame, pkt) in [
+        (&ESTOP_CRC[..], Packet { seq: 1, code: 0x0f, data: vec![1] }),
+        (&LONG_CRC[..], Packet { seq: 1, code: 0x82, data: vec![1, 2, 3, 4, 5, 6, 7, 8] }),
+    ] {
+        let mut wire = Vec::new();
+        pkt.encode_with(&config, &mut wire).unwrap();
+        assert_eq!(frame, &wire[..]);
+        let mut input = vec![SYNC_ACK, 1];
+        input.extend_from_slice(frame);
+        assert_eq!(p.parse_iter(input).flatten().collect::<Vec<_>>(), vec![pkt]);
+    }
+}
+
+#[test]
+fn test_parser_phase() {
+    let mut p = Parser::new();
+    assert_eq!((p.phase(), p.expected_seq()), (LinkPhase::Syncing, None));
+    for b in &[SYNC_ACK, 3] {
+        p.parse_frame(*b);
+    }
+    assert_eq!((p.phase(), p.expected_seq()), (LinkPhase::Ready, Some(3)));
+    p.parse_frame(3);
+    assert_eq!((p.phase(), p.expected_seq()), (LinkPhase::Receiving, Some(4)));
+    p.parse_frame(0x11);
+    p.parse_frame(0);
+    assert_eq!(p.phase(), LinkPhase::Ready);
+    p.parse_frame(SUSPEND_REQ);
+    p.parse_frame(4);
+    assert_eq!((p.phase(), p.expected_seq()), (LinkPhase::Suspended, None));
+    assert_eq!(p.closed_status().state, SYNC_STATE_CLOSED);
+    assert_eq!(p.phase().to_string(), "closed");
+}
+
+#[test]
+fn test_parse_result_accessors() {
+    let mut input = vec![SYNC_REQ, 3];
+    Packet { seq: 3, code: 1, data: vec![1, 2] }.encode(&mut input).unwrap();
+    Packet { seq: 4, code: 2, data: vec![] }.encode(&mut input).unwrap();
+    let mut p = Parser::new();
+    let results: Vec<ParseResult> = p.parse_iter(input.iter().cloned()).collect();
+    assert_eq!(results.iter().filter_map(|r| r.needs_sync_reply()).collect::<Vec<_>>(), vec![SYNC_ACK]);
+    assert!(!results[0].is_synced() && results[1].is_synced());
+    let pkts: Vec<Packet> = results.into_iter().flatten().collect();
+    assert_eq!(pkts.iter().map(|p| p.code).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(p.parse(0x55).into_packet(), None);
+}
+
+#[test]
+fn test_state_display() {
+    assert_eq!(StateFlags(SYNC_STATE_READY | SYNC_STATE_RECV).to_string(), "READY|RECV");
+    assert_eq!(StateFlags(0).to_string(), "NONE");
+    assert_eq!(StateFlags(SYNC_STATE_CLOSED | 0x80).to_string(), "CLOSED|0x80");
+    assert_eq!(TimerAction::Restart.to_string(), "restart");
+    let s = ParseStatus { sync: SYNC_ACK, state: SYNC_STATE_READY, packet: true };
+    assert_eq!(s.to_string(), "READY sync=0xfe packet");
+    assert_eq!(Parser::new().status().to_string(), "NONE");
+    assert_eq!(LinkEvent::PeerRebooted.to_string(), "peer rebooted");
+}
+
+#[test]
+fn test_parser_snapshot() {
+    let mut p = Parser::new();
+    assert!(Parser::restore(&p.snapshot()).is_some());
+    for b in &[EPOCH, 3, SYNC_ACK, 7, FLOW_OFF, 7] {
+        p.parse_frame(*b);
+    }
+    let snap = p.snapshot();
+    assert_eq!(snap.len(), PARSER_SNAPSHOT_LEN);
+    let mut q = Parser::restore(&snap).unwrap();
+    assert_eq!(q.peer_epoch(), Some(3));
+    assert_eq!(q.status().state, SYNC_STATE_READY | SYNC_STATE_FLOW_OFF);
+    assert!(q.parse_frame(7).state.is_receiving());
+    assert!(q.parse_frame(0x01).packet);
+
+    // other versions and bad fields are refused.
+    let mut bad = snap;
+    bad[0] = SNAPSHOT_VERSION + 1;
+    assert!(Parser::restore(&bad).is_none());
+    let mut bad = snap;
+    bad[2] = 0;
+    assert!(Parser::restore(&bad).is_none());
+    assert!(Parser::restore(&snap[..4]).is_none());
+    let mut bad = snap;
+    bad[6] = FLOW_OFF;
+    assert!(Parser::restore(&bad).is_none());
+
+    // the config comes back with the state.
+    let config = Parser::builder().max_len(8).checksum(Checksum::Crc16).sync_bytes(0xf0, 0xf1)
+        .seq_check(SeqCheck::Lenient).config().unwrap();
+    let mut p = Parser::with_config(config);
+    for b in &[0xf1, 3] {
+        p.parse_frame(*b);
+    }
+    assert_eq!(Parser::restore(&p.snapshot()).unwrap().config(), config);
+
+    // parsers ready without a handshake restore before any frame.
+    let idle = Parser::with_config(SyncConfig { framing: Framing::IdleGap, ..SyncConfig::default() });
+    for p in [idle, Parser::promiscuous()] {
+        let mut q = Parser::restore(&p.snapshot()).unwrap();
+        assert_eq!(q.snapshot(), p.snapshot());
+        assert!(q.status().state.is_ready());
+        q.parse_frame(5);
+        assert!(q.parse_frame(0x01).packet);
+    }
+}
+
+#[test]
+fn test_endpoint_restore() {
+    let (mut a, b) = ::sim::Loopback::pair();
+    let mut dev = Endpoint::new(b).unwrap();
+    let snap = {
+        let mut host = Endpoint::with_epoch(&mut a, "", 5).unwrap();
+        dev.poll().unwrap();
+        host.poll().unwrap();
+        host.send(Packet::new_with(0, 0x01)).unwrap();
+        dev.poll().unwrap();
+        host.snapshot()
+    };
+    assert!(Endpoint::restore(&mut a, "", &snap[1..]).is_err());
+    let mut host = Endpoint::restore(&mut a, "", &snap).unwrap();
+    assert!(host.is_ready());
+    assert_eq!(host.epoch(), Some(5));
+    host.send(Packet::new_with(0, 0x02)).unwrap();
+    dev.send(Packet::new_with(0, 0x81)).unwrap();
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(2, 0x02)]);
+    assert_eq!(host.poll().unwrap(), vec![Packet::new_with(1, 0x81)]);
+    assert_eq!(dev.stats().resyncs + host.stats().resyncs, 0);
+}
+
+#[test]
+fn test_adaptive_timeout() {
+    let ms = Duration::from_millis;
+    let mut t = AdaptiveTimeout::new(TimeoutConfig::default());
+    assert_eq!((t.rtt(), t.timeout()), (None, ms(1000)));
+    t.sample(ms(1));
+    assert_eq!(t.timeout(), ms(10)); // floor
+    for _ in 0..50 {
+        t.sample(ms(300));
+    }
+    let sat = t.timeout();
+    assert!(sat >= ms(600) && sat < ms(700), "{:?}", sat);
+    t.backoff();
+    assert_eq!(t.timeout(), sat * 2);
+    for _ in 0..20 {
+        t.backoff();
+    }
+    assert_eq!(t.timeout(), ms(10000)); // ceiling
+    t.sample(ms(300));
+    assert!(t.timeout() < ms(1000));
+}
+
+#[test]
+fn test_endpoint_timeout() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.set_timeout_config(TimeoutConfig { initial: Duration::from_millis(50), ..TimeoutConfig::default() });
+    let now = Instant::now();
+    assert!(!dev.check_timeout_at(now).unwrap());
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    assert!(host.rtt().is_some() && dev.rtt().is_some());
+    assert!(host.timeout() < Duration::from_secs(1));
+
+    // a packet cut short is dropped once the timeout passes.
+    host.transport().write_all(&[0x01, 0x05, 0x02, 0xaa]).unwrap();
+    dev.poll().unwrap();
+    let timeout = dev.timeout();
+    assert!(!dev.check_timeout_at(Instant::now()).unwrap());
+    assert!(dev.check_timeout_at(Instant::now() + timeout).unwrap());
+    assert!(!dev.is_ready());
+    assert_eq!(dev.timeout(), (timeout * 2).min(Duration::from_secs(10)));
+    host.poll().unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    assert!(host.is_ready() && dev.is_ready());
+    host.send(Packet::new_with(0, 0x03)).unwrap();
+    assert_eq!(dev.poll().unwrap(), vec![Packet::new_with(1, 0x03)]);
+}
+
+#[test]
+fn test_packet_pool() {
+    let pool = PacketPool::new(2, PoolPolicy::Error);
+    let mut a = pool.get().unwrap();
+    a.data.extend_from_slice(&[1, 2]);
+    let ptr = a.data.as_ptr();
+    let b = pool.get().unwrap();
+    assert!(pool.get().is_none());
+    assert_eq!((pool.available(), pool.misses()), (0, 1));
+    drop(a);
+    let a = pool.get().unwrap();
+    assert!(a.data.is_empty() && a.data.capacity() >= PACKET_DATA_BUF_LEN);
+    assert_eq!(a.data.as_ptr(), ptr);
+    let owned: Packet = Recycled::detach(b);
+    assert_eq!(owned, Packet::new());
+    assert_eq!(pool.capacity(), 1);
+    drop(a);
+    assert_eq!(pool.available(), 1);
+
+    let pool = PacketPool::new(0, PoolPolicy::Grow);
+    let held: Vec<_> = (0..3).map(|_| pool.get().unwrap()).collect();
+    drop(held);
+    assert_eq!((pool.capacity(), pool.available(), pool.misses()), (3, 3, 3));
+}
+
+#[test]
+fn test_endpoint_poll_pooled() {
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut dev = Endpoint::new(b).unwrap();
+    dev.poll().unwrap();
+    host.poll().unwrap();
+    let send = |dev: &mut Endpoint<_>, n: u8| for i in 0..n {
+        dev.send(Packet { seq: 0, code: 0x81, data: vec![i] }).unwrap();
+    };
+
+    let pool = PacketPool::new(2, PoolPolicy::DropOldest);
+    let mut out = VecDeque::new();
+    send(&mut dev, 3);
+    assert_eq!(host.poll_pooled(&pool, &mut out).unwrap(), 3);
+    assert_eq!(out.iter().map(|p| p.data[0]).collect::<Vec<_>>(), vec![1, 2]);
+    out.clear();
+    assert_eq!(pool.available(), 2);
+
+    let pool = PacketPool::new(2, PoolPolicy::Error);
+    send(&mut dev, 3);
+    assert!(host.poll_pooled(&pool, &mut out).is_err());
+    assert_eq!(out.iter().map(|p| p.data[0]).collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(host.stats().packets_received, 6);
+}
+
+#[test]
+fn test_parser_promiscuous() {
+    let mut p = Parser::promiscuous();
+    let mut pkts = Vec::new();
+    let mut syncs = Vec::new();
+    // joins mid-stream, sees a reboot on the way.
+    for b in &[0xf3, 0xf4, 7, 0x11, 0xaa, EPOCH, 4, SYNC_REQ, 3, SYNC_ACK, 9, 3, 0x82] {
+        let r = p.parse(*b);
+        if r.sync != 0 {
+            syncs.push(r.sync);
+        }
+        pkts.extend(r.packet);
+    }
+    assert_eq!(syncs, vec![EPOCH, SYNC_REQ, SYNC_ACK]);
+    assert_eq!(p.peer_epoch(), Some(4));
+    assert_eq!(pkts, vec![
+        Packet { seq: 7, code: 1, data: vec![0xaa] },
+        Packet::new_with(3, 0x82),
+    ]);
+    assert!(p.parse(0xf5).packet.is_none());
+    assert!(p.parse(1).packet.is_none());
+    assert_eq!(p.parse(0).packet, Some(Packet::new_with(1, 0)));
+}
+
+#[test]
+fn test_endpoint_ping_and_throughput() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let (a, b) = ::sim::Loopback::pair();
+    let mut host = Endpoint::new(a).unwrap();
+    let stop = Arc::new(AtomicBool::new(false));
+    let done = stop.clone();
+    // echoes requests on 0x0e back as events.
+    let peer = thread::spawn(move || {
+        let mut dev = Endpoint::new(b).unwrap();
+        while !done.load(Ordering::Relaxed) {
+            for pkt in dev.poll().unwrap() {
+                if pkt.code == 0x0e {
+                    dev.send(Packet { seq: 0, code: 0x8e, data: pkt.data }).unwrap();
+                }
+            }
+            thread::sleep(Duration::from_micros(200));
+        }
+    });
+    while !host.is_ready() {
+        host.poll().unwrap();
+    }
+
+    let stats = host.ping(5, Duration::from_secs(1)).unwrap();
+    assert_eq!((stats.sent, stats.received), (5, 5));
+    assert!(stats.min <= stats.avg && stats.avg <= stats.max);
+    assert_eq!(host.stats().resyncs, 0);
+
+    let t = host.throughput_test(Duration::from_millis(50), 0x0e, Duration::from_secs(1)).unwrap();
+    assert!(t.sent > 0);
+    assert_eq!(t.echoed, t.sent);
+    assert_eq!(t.bytes, t.sent * PACKET_MAX_DATA_LEN as u64);
+    assert_eq!(t.loss(), 0.0);
+    assert!(t.goodput() > 0.0);
+
+    // nothing answers once the peer stops.
+    stop.store(true, Ordering::Relaxed);
+    peer.join().unwrap();
+    let stats = host.ping(2, Duration::from_millis(10)).unwrap();
+    assert_eq!((stats.received, stats.loss()), (0, 1.0));
+}
+
+// A serial port of embedded-hal-nb, taking up to limit bytes when set.
+#[cfg(feature = "embedded")]
+#[derive(Default)]
+struct Uart {
+    rx: ::std::collections::VecDeque<u8>,
+    tx: Vec<u8>,
+    limit: Option<usize>,
+}
+
+#[cfg(feature = "embedded")]
+mod uart {
+    use std::convert::Infallible;
+    use embedded_hal_nb::{nb, serial};
+    use super::Uart;
+
+    impl serial::ErrorType for Uart {
+        type Error = Infallible;
+    }
+
+    impl serial::Read<u8> for Uart {
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl serial::Write<u8> for Uart {
+        fn write(&mut self, b: u8) -> nb::Result<(), Infallible> {
+            if self.limit.is_some_and(|n| self.tx.len() >= n) {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.tx.push(b);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_embedded_link() {
+    let mut link = EmbeddedLink::new(Uart::default(), 3).unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![SYNC_REQ, 1]);
+    assert_eq!(link.send(0x81, &[]), Err(LinkError::NotReady));
+
+    link.serial().rx.extend(&[SYNC_ACK, 1, 1, 0x11, 7]);
+    assert_eq!(link.poll().unwrap(), Some(Frame { seq: 1, code: 0x01, data: &[7] }));
+    assert!(link.is_ready());
+    assert_eq!(link.poll().unwrap(), None);
+    link.send(0x81, &[1, 2]).unwrap();
+    assert_eq!(link.send(0x81, &[0; PACKET_MAX_DATA_LEN + 1]), Err(LinkError::TooLong));
+    assert_eq!(link.serial().tx.split_off(0), vec![1, 0xa1, 1, 2]);
+
+    // a frame cut short times out after three ticks and resyncs.
+    link.serial().rx.extend(&[2, 0x72]);
+    assert_eq!(link.poll().unwrap(), None);
+    link.tick().unwrap();
+    link.tick().unwrap();
+    assert!(link.serial().tx.is_empty());
+    link.tick().unwrap();
+    assert!(!link.is_ready());
+    assert_eq!(link.serial().tx.split_off(0), vec![SYNC_REQ, 2]);
+
+    // bytes queued by an interrupt handler, one lost to a full queue.
+    let mut q: IsrQueue<5> = IsrQueue::new();
+    let (mut isr, mut rx) = q.split();
+    assert!(isr.push(SYNC_ACK) && isr.push(2) && isr.push(2) && isr.push(0));
+    assert!(!isr.push(3));
+    assert_eq!(link.poll_queue(&mut rx).unwrap(), Some(Frame { seq: 2, code: 0, data: &[] }));
+    assert!(link.is_ready());
+    assert_eq!(*link.stats(), LinkStats {
+        packets_sent: 1,
+        packets_received: 2,
+        bytes_sent: 8,
+        bytes_received: 11,
+        resyncs: 1,
+        overflows: 1,
+        ..LinkStats::default()
+    });
+}
+
+#[test]
+fn test_isr_queue() {
+    use std::thread;
+
+    let mut q: IsrQueue<3> = IsrQueue::default();
+    assert_eq!(q.capacity(), 2);
+    {
+        let (mut isr, mut rx) = q.split();
+        assert_eq!(rx.pop(), None);
+        for round in 0..3u8 {
+            assert!(isr.push(round) && isr.push(round + 1));
+            assert!(!isr.push(0xff));
+            assert_eq!(rx.len(), 2);
+            assert_eq!((rx.pop(), rx.pop(), rx.pop()), (Some(round), Some(round + 1), None));
+        }
+        assert_eq!(rx.overflows(), 3);
+    }
+    assert!(q.is_empty());
+
+    // bytes arrive in order across threads, however the two interleave.
+    let mut q: IsrQueue<16> = IsrQueue::new();
+    let (mut isr, mut rx) = q.split();
+    thread::scope(|s| {
+        s.spawn(move || {
+            for b in 0..=255u8 {
+                while !isr.push(b) {
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut next = 0u16;
+        while next < 256 {
+            if let Some(b) = rx.pop() {
+                assert_eq!(b as u16, next);
+                next += 1;
+            }
+        }
+    });
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_static_link() {
+    type Link = StaticLink<Uart, 16, 12>;
+    assert_eq!(Link::MAX_PAYLOAD, 9);
+    let mut link = Link::new(Uart { limit: Some(2), ..Uart::default() }, 3);
+    assert!(link.serial().tx.is_empty());
+    link.poll().unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![SYNC_REQ, 1]);
+    link.serial().limit = None;
+
+    // two packets queue up; the third doesn't fit and is counted.
+    link.serial().rx.extend(&[SYNC_ACK, 1, 1, 0x31, 1, 2, 3, 2, 0x72, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 3, 0x01]);
+    link.poll().unwrap();
+    assert!(link.is_ready());
+    let mut buf = [0; PACKET_MAX_DATA_LEN];
+    assert_eq!(link.recv(&mut buf), Some(Frame { seq: 1, code: 0x01, data: &[1, 2, 3] }));
+    assert_eq!(link.recv(&mut buf), Some(Frame { seq: 3, code: 0x01, data: &[] }));
+    assert_eq!(link.recv(&mut buf), None);
+    assert_eq!(link.stats().overflows, 12);
+
+    link.send(0x81, &[0; 9]).unwrap();
+    assert_eq!(link.send(0x81, &[0; 10]), Err(LinkError::TooLong));
+    assert_eq!(link.send(0x81, &[]), Err(LinkError::Full));
+    assert_eq!(link.pending(), 12);
+    link.serial().limit = Some(4);
+    link.poll().unwrap();
+    assert_eq!(link.pending(), 8);
+    link.send(0x82, &[5]).unwrap();
+    link.serial().limit = None;
+    link.poll().unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![1, 0xf1, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0x92, 5]);
+    assert_eq!(link.stats().packets_sent, 2);
+
+    // a frame cut short times out and the sync request waits for poll.
+    link.serial().rx.extend(&[4, 0x72]);
+    link.poll().unwrap();
+    for _ in 0..3 {
+        link.tick();
+    }
+    assert!(!link.is_ready());
+    link.poll().unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![SYNC_REQ, 3]);
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_embedded_link_idle_gap() {
+    let config = SyncConfig { framing: Framing::IdleGap, ..SyncConfig::default() };
+    let mut link = EmbeddedLink::with_sync_config(Uart::default(), 3, config).unwrap();
+    assert!(link.is_ready());
+    assert!(link.serial().tx.is_empty());
+    link.send(0x81, &[]).unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![1, 0x81]);
+
+    link.serial().rx.extend(&[6, 0x21]);
+    assert_eq!(link.poll().unwrap(), None);
+    link.idle().unwrap();
+    link.serial().rx.extend(&[7, 0x11, 5]);
+    assert_eq!(link.poll().unwrap(), Some(Frame { seq: 7, code: 0x01, data: &[5] }));
+    assert_eq!(link.stats().packets_received, 1);
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_embedded_link_ping() {
+    let mut link = EmbeddedLink::new(Uart::default(), 3).unwrap();
+    link.serial().rx.extend(&[SYNC_ACK, 4]);
+    assert_eq!(link.poll().unwrap(), None);
+    link.serial().tx.clear();
+    link.serial().rx.push_back(SYNC_REQ);
+    assert_eq!(link.poll().unwrap(), None);
+    assert!(link.is_ready());
+    link.send(0x81, &[]).unwrap();
+    link.serial().rx.push_back(4);
+    assert_eq!(link.poll().unwrap(), None);
+    assert_eq!(link.serial().tx.split_off(0), vec![1, 0x81, SYNC_ACK, 2]);
+    assert_eq!(link.stats().resyncs, 0);
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_embedded_link_restore() {
+    let mut link = EmbeddedLink::with_epoch(Uart::default(), 3, 2).unwrap();
+    link.serial().rx.extend(&[SYNC_ACK, 4]);
+    assert_eq!(link.poll().unwrap(), None);
+    link.send(0x81, &[]).unwrap();
+    let snap = link.snapshot();
+    let mut uart = link.release();
+    uart.tx.clear();
+
+    let mut link = EmbeddedLink::restore(uart, 3, &snap).unwrap();
+    assert!(link.is_ready());
+    link.send(0x81, &[]).unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![2, 0x81]);
+    link.serial().rx.extend(&[4, 0x01]);
+    assert_eq!(link.poll().unwrap(), Some(Frame { seq: 4, code: 0x01, data: &[] }));
+    assert!(EmbeddedLink::restore(Uart::default(), 3, &snap[..3]).is_none());
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_embedded_link_suspend() {
+    let mut link = EmbeddedLink::new(Uart::default(), 3).unwrap();
+    assert_eq!(link.suspend(), Err(LinkError::NotReady));
+    link.serial().rx.extend(&[SYNC_ACK, 1]);
+    link.poll().unwrap();
+    link.suspend().unwrap();
+    link.serial().rx.extend(&[SUSPEND_ACK, 1]);
+    link.poll().unwrap();
+    assert!(link.is_suspended());
+    assert_eq!(link.send(0x81, &[]), Err(LinkError::NotReady));
+    link.resume().unwrap();
+    assert!(!link.is_suspended());
+    link.send(0x81, &[]).unwrap();
+    assert_eq!(link.serial().tx.split_off(0), vec![SYNC_REQ, 1, SUSPEND_REQ, 1, WAKE, SYNC_REQ, 1, 1, 0x81]);
+
+    // suspended by the host, woken by it after garbage.
+    link.serial().rx.extend(&[SYNC_ACK, 1, SUSPEND_REQ, 1]);
+    link.poll().unwrap();
+    assert!(link.is_suspended());
+    link.serial().rx.extend(&[0x00, WAKE, SYNC_REQ, 1]);
+    link.poll().unwrap();
+    assert!(!link.is_suspended() && link.is_ready());
+    assert_eq!(link.serial().tx.split_off(0), vec![SUSPEND_ACK, 2, SYNC_ACK, 2]);
+    assert_eq!(link.stats().resyncs, 0);
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_static_link_ping() {
+    let mut link = StaticLink::<Uart, 16, 16>::new(Uart::default(), 3);
+    link.serial().rx.extend(&[SYNC_ACK, 4]);
+    link.poll().unwrap();
+    assert!(link.is_ready());
+    link.serial().rx.push_back(SYNC_REQ);
+    link.poll().unwrap();
+    assert!(link.is_ready());
+    link.send(0x81, &[]).unwrap();
+    link.serial().rx.push_back(4);
+    link.poll().unwrap();
+    assert!(link.is_ready());
+    assert_eq!(link.stats().resyncs, 0);
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_split_link_ping() {
+    let shared = LinkShared::new();
+    let (mut rx, mut tx) = shared.split(Uart::default(), Uart::default(), 3);
+    rx.serial().rx.extend(&[SYNC_ACK, 4]);
+    rx.poll().unwrap();
+    assert!(tx.is_ready());
+    rx.serial().rx.push_back(SYNC_REQ);
+    rx.poll().unwrap();
+    tx.send(0x81, &[]).unwrap();
+    rx.serial().rx.push_back(4);
+    rx.poll().unwrap();
+    assert!(tx.is_ready());
+    assert_eq!(rx.stats().resyncs, 0);
+}
+
+#[cfg(feature = "embedded")]
+#[test]
+fn test_split_link() {
+    let shared = LinkShared::new();
+    let (mut rx, mut tx) = shared.split(Uart::default(), Uart::default(), 3);
+    assert!(!tx.is_ready());
+    assert_eq!(tx.send(0x81, &[]), Err(LinkError::NotReady));
+    assert_eq!(tx.serial().tx.split_off(0), vec![SYNC_REQ, 1]);
+
+    // the receive half reads and the transmit half answers.
+    rx.serial().rx.extend(&[SYNC_REQ, 1, SYNC_ACK, 1, 1, 0x11, 7]);
+    assert_eq!(rx.poll().unwrap(), Some(Frame { seq: 1, code: 0x01, data: &[7] }));
+    assert!(rx.is_ready() && tx.is_ready() && shared.is_ready());
+    tx.send(0x81, &[1, 2]).unwrap();
+    assert_eq!(tx.serial().tx.split_off(0), vec![SYNC_ACK, 1, 1, 0xa1, 1, 2]);
+    tx.poll().unwrap();
+    assert!(tx.serial().tx.is_empty());
+
+    // a timeout on the receive side resyncs the transmit side.
+    rx.serial().rx.extend(&[2, 0x72]);
+    assert_eq!(rx.poll().unwrap(), None);
+    for _ in 0..3 {
+        rx.tick();
+    }
+    assert!(!tx.is_ready());
+    tx.poll().unwrap();
+    assert_eq!(tx.serial().tx.split_off(0), vec![SYNC_REQ, 2]);
+    assert_eq!((rx.stats().packets_received, rx.stats().bytes_received), (1, 9));
+    assert_eq!((tx.stats().packets_sent, tx.stats().bytes_sent), (1, 10));
+
+    // the halves work from different threads.
+    rx.serial().rx.extend(&[SYNC_ACK, 2]);
+    ::std::thread::scope(|s| {
+        s.spawn(move || while !rx.is_ready() {
+            rx.poll().unwrap();
+        });
+        while tx.send(0x80, &[]).is_err() {}
+        assert_eq!(tx.serial().tx.split_off(0), vec![2, 0x80]);
+    });
+}
+
+#[cfg(feature = "critical-section")]
+#[test]
+fn test_shared_link() {
+    static LINK: SharedLink<Uart> = SharedLink::new();
+    let mut buf = [0; PACKET_MAX_DATA_LEN];
+    assert_eq!(LINK.poll(&mut buf), Err(LinkError::NotReady));
+    assert_eq!(LINK.send(0x81, &[]), Err(LinkError::NotReady));
+    assert!(LINK.init(EmbeddedLink::new(Uart::default(), 3).unwrap()).is_none());
+
+    LINK.with(|link| link.serial().rx.extend(&[SYNC_ACK, 1, 1, 0x21, 7, 8]));
+    assert_eq!(LINK.poll(&mut buf).unwrap(), Some(Frame { seq: 1, code: 0x01, data: &[7, 8] }));
+    assert!(LINK.is_ready());
+
+    // an interrupt handler sends while the main loop polls.
+    ::std::thread::scope(|s| {
+        s.spawn(|| for _ in 0..10 {
+            LINK.send(0x81, &[1]).unwrap();
+        });
+        for _ in 0..10 {
+            LINK.tick().unwrap();
+            assert_eq!(LINK.poll(&mut buf).unwrap(), None);
+        }
+    });
+    assert_eq!(LINK.stats().packets_sent, 10);
+    let tx = LINK.take().unwrap().release().tx;
+    assert_eq!(tx.len(), 2 + 10 * 3);
+    assert!(!LINK.is_ready());
+}
@@ -0,0 +1,164 @@
+use std::io;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, rest, Layout, U16, U8};
+
+pub const MAX_CHANNELS: usize = 16;
+
+const OP_READ: u8 = 0x01;
+const OP_SAMPLE: u8 = 0x02;
+const OP_START: u8 = 0x03;
+const OP_STOP: u8 = 0x04;
+const OP_SAMPLES: u8 = 0x05;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("read", OP_READ, &[field("channel", U8)]),
+    Layout::new("sample", OP_SAMPLE, &[field("channel", U8), field("raw", U16)]),
+    Layout::new("start_sampling", OP_START, &[field("mask", U16), field("rate_hz", U16)]),
+    Layout::new("stop_sampling", OP_STOP, &[]),
+    Layout::new("samples", OP_SAMPLES, &[field("mask", U16), rest("raw", U16)]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdcMessage {
+    Read { channel: u8 },                      // request a single conversion.
+    Sample { channel: u8, raw: u16 },          // reply to Read.
+    StartSampling { mask: u16, rate_hz: u16 }, // stream channels in mask.
+    StopSampling,
+    Samples { mask: u16, raw: Vec<u16> },      // one raw value per bit in mask, lowest first.
+}
+
+impl Message for AdcMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            AdcMessage::Read { channel } => {
+                data.push(OP_READ);
+                data.push(channel);
+            },
+            AdcMessage::Sample { channel, raw } => {
+                data.push(OP_SAMPLE);
+                data.push(channel);
+                put_u16(data, raw);
+            },
+            AdcMessage::StartSampling { mask, rate_hz } => {
+                data.push(OP_START);
+                put_u16(data, mask);
+                put_u16(data, rate_hz);
+            },
+            AdcMessage::StopSampling => data.push(OP_STOP),
+            AdcMessage::Samples { mask, ref raw } => {
+                data.push(OP_SAMPLES);
+                put_u16(data, mask);
+                for v in raw {
+                    put_u16(data, *v);
+                }
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_READ => AdcMessage::Read { channel: r.u8()? },
+            OP_SAMPLE => AdcMessage::Sample { channel: r.u8()?, raw: r.u16()? },
+            OP_START => AdcMessage::StartSampling { mask: r.u16()?, rate_hz: r.u16()? },
+            OP_STOP => AdcMessage::StopSampling,
+            OP_SAMPLES => {
+                let mask = r.u16()?;
+                let mut raw = Vec::with_capacity(mask.count_ones() as usize);
+                for _ in 0..mask.count_ones() {
+                    raw.push(r.u16()?);
+                }
+                AdcMessage::Samples { mask, raw }
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Linear conversion from raw counts: volts = raw * scale + offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration { scale: 1.0, offset: 0.0 }
+    }
+}
+
+impl Calibration {
+    // An ideal converter of bits resolution, 1 to 31, full scale at vref.
+    pub fn from_reference(vref: f32, bits: u8) -> io::Result<Self> {
+        if !(1..=31).contains(&bits) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "ADC resolution must be 1 to 31 bits"));
+        }
+        Ok(Calibration {
+            scale: vref / ((1u32 << bits) - 1) as f32,
+            offset: 0.0,
+        })
+    }
+
+    pub fn volts(&self, raw: u16) -> f32 {
+        raw as f32 * self.scale + self.offset
+    }
+
+    // Parameter value encoding: scale and offset as little-endian f32.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(8);
+        put_u32(&mut data, self.scale.to_bits());
+        put_u32(&mut data, self.offset.to_bits());
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let cal = Calibration {
+            scale: f32::from_bits(r.u32()?),
+            offset: f32::from_bits(r.u32()?),
+        };
+        r.finish(cal)
+    }
+}
+
+pub struct Scaler {
+    channels: [Calibration; MAX_CHANNELS],
+}
+
+impl Default for Scaler {
+    fn default() -> Self {
+        Scaler::new()
+    }
+}
+
+impl Scaler {
+    pub fn new() -> Self {
+        Scaler { channels: [Calibration::default(); MAX_CHANNELS] }
+    }
+
+    pub fn set(&mut self, channel: u8, cal: Calibration) {
+        if let Some(c) = self.channels.get_mut(channel as usize) {
+            *c = cal;
+        }
+    }
+
+    pub fn get(&self, channel: u8) -> Option<&Calibration> {
+        self.channels.get(channel as usize)
+    }
+
+    pub fn volts(&self, channel: u8, raw: u16) -> Option<f32> {
+        self.get(channel).map(|c| c.volts(raw))
+    }
+
+    // Converts a Samples message into (channel, volts) pairs.
+    pub fn scale_samples(&self, mask: u16, raw: &[u16]) -> Vec<(u8, f32)> {
+        (0..MAX_CHANNELS as u8)
+            .filter(|ch| mask & (1 << ch) != 0)
+            .zip(raw.iter())
+            .map(|(ch, v)| (ch, self.channels[ch as usize].volts(*v)))
+            .collect()
+    }
+}
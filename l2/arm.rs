@@ -0,0 +1,201 @@
+use std::io;
+use std::time::Duration;
+use super::{Message, Sender};
+use super::codec::*;
+use super::layout::{field, rest, FieldType, Layout, I16, U16, U8};
+
+pub const POSITION_LSB: f64 = 0.001; // rad, +/-32 rad.
+pub const VELOCITY_LSB: f64 = 0.001; // rad/s, +/-32 rad/s.
+
+const OP_SET_POSITIONS: u8 = 0x01;
+const OP_SET_VELOCITIES: u8 = 0x02;
+const OP_STOP: u8 = 0x03;
+const OP_STATE: u8 = 0x04;
+
+const JOINT_STATE: FieldType = FieldType::Struct("joint_state", &[field("position", I16), field("velocity", I16)]);
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("set_positions", OP_SET_POSITIONS, &[field("first", U8), field("duration_ms", U16), rest("positions", I16)]),
+    Layout::new("set_velocities", OP_SET_VELOCITIES, &[field("first", U8), rest("velocities", I16)]),
+    Layout::new("stop", OP_STOP, &[]),
+    Layout::new("state", OP_STATE, &[field("first", U8), rest("joints", JOINT_STATE)]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArmMessage {
+    // Moves consecutive joints starting at `first` so they all arrive at
+    // their positions together after duration_ms.
+    SetPositions { first: u8, duration_ms: u16, positions: Vec<i16> },
+    SetVelocities { first: u8, velocities: Vec<i16> },
+    Stop, // hold all joints where they are.
+    // Telemetry of consecutive joints starting at `first`.
+    State { first: u8, positions: Vec<i16>, velocities: Vec<i16> },
+}
+
+fn to_fixed(v: f64, lsb: f64) -> i16 {
+    (v / lsb).round().max(i16::MIN as f64).min(i16::MAX as f64) as i16
+}
+
+impl Message for ArmMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            ArmMessage::SetPositions { first, duration_ms, ref positions } => {
+                data.push(OP_SET_POSITIONS);
+                data.push(first);
+                put_u16(data, duration_ms);
+                for p in positions {
+                    put_i16(data, *p);
+                }
+            },
+            ArmMessage::SetVelocities { first, ref velocities } => {
+                data.push(OP_SET_VELOCITIES);
+                data.push(first);
+                for v in velocities {
+                    put_i16(data, *v);
+                }
+            },
+            ArmMessage::Stop => data.push(OP_STOP),
+            ArmMessage::State { first, ref positions, ref velocities } => {
+                data.push(OP_STATE);
+                data.push(first);
+                for (p, v) in positions.iter().zip(velocities) {
+                    put_i16(data, *p);
+                    put_i16(data, *v);
+                }
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_SET_POSITIONS => {
+                let first = r.u8()?;
+                let duration_ms = r.u16()?;
+                let mut positions = Vec::new();
+                while !r.is_empty() {
+                    positions.push(r.i16()?);
+                }
+                ArmMessage::SetPositions { first, duration_ms, positions }
+            },
+            OP_SET_VELOCITIES => {
+                let first = r.u8()?;
+                let mut velocities = Vec::new();
+                while !r.is_empty() {
+                    velocities.push(r.i16()?);
+                }
+                ArmMessage::SetVelocities { first, velocities }
+            },
+            OP_STOP => ArmMessage::Stop,
+            OP_STATE => {
+                let first = r.u8()?;
+                let mut positions = Vec::new();
+                let mut velocities = Vec::new();
+                while !r.is_empty() {
+                    positions.push(r.i16()?);
+                    velocities.push(r.i16()?);
+                }
+                ArmMessage::State { first, positions, velocities }
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct JointState {
+    pub position: f64,
+    pub velocity: f64,
+}
+
+// Commands a serial arm and tracks its joint state from telemetry.
+pub struct ArmClient<S> {
+    sender: S,
+    code: u8,
+    joints: Vec<JointState>,
+}
+
+impl<S: Sender> ArmClient<S> {
+    pub fn new(sender: S, code: u8, joints: usize) -> Self {
+        ArmClient {
+            sender,
+            code,
+            joints: vec![JointState::default(); joints],
+        }
+    }
+
+    pub fn joints(&self) -> &[JointState] {
+        &self.joints
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    // Moves all joints to positions (rad), arriving together after duration.
+    pub fn move_to(&mut self, positions: &[f64], duration: Duration) -> io::Result<()> {
+        self.check_len(positions.len())?;
+        let msg = ArmMessage::SetPositions {
+            first: 0,
+            duration_ms: duration.as_millis().min(u16::MAX as u128) as u16,
+            positions: positions.iter().map(|p| to_fixed(*p, POSITION_LSB)).collect(),
+        };
+        self.sender.send(msg.to_packet(self.code))
+    }
+
+    // Like move_to, with the duration chosen so no joint exceeds max_velocity
+    // given the last reported positions. max_velocity must be positive, and
+    // the move one that can be timed.
+    pub fn move_to_at(&mut self, positions: &[f64], max_velocity: f64) -> io::Result<Duration> {
+        self.check_len(positions.len())?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "invalid velocity or travel");
+        if !(max_velocity.is_finite() && max_velocity > 0.0) {
+            return Err(invalid());
+        }
+        let travel = positions.iter().zip(&self.joints)
+            .map(|(p, j)| (p - j.position).abs())
+            .fold(0.0, f64::max);
+        let duration = Duration::try_from_secs_f64(travel / max_velocity).map_err(|_| invalid())?;
+        self.move_to(positions, duration)?;
+        Ok(duration)
+    }
+
+    pub fn set_velocities(&mut self, velocities: &[f64]) -> io::Result<()> {
+        self.check_len(velocities.len())?;
+        let msg = ArmMessage::SetVelocities {
+            first: 0,
+            velocities: velocities.iter().map(|v| to_fixed(*v, VELOCITY_LSB)).collect(),
+        };
+        self.sender.send(msg.to_packet(self.code))
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.sender.send(ArmMessage::Stop.to_packet(self.code))
+    }
+
+    // Updates joint state from telemetry, joints beyond the arm are ignored.
+    pub fn handle(&mut self, msg: &ArmMessage) {
+        if let ArmMessage::State { first, ref positions, ref velocities } = *msg {
+            let states = positions.iter().zip(velocities);
+            for (j, (p, v)) in self.joints.iter_mut().skip(first as usize).zip(states) {
+                j.position = *p as f64 * POSITION_LSB;
+                j.velocity = *v as f64 * VELOCITY_LSB;
+            }
+        }
+    }
+
+    // True once every joint is within tolerance of positions.
+    pub fn reached(&self, positions: &[f64], tolerance: f64) -> bool {
+        positions.len() == self.joints.len() &&
+            positions.iter().zip(&self.joints).all(|(p, j)| (p - j.position).abs() <= tolerance)
+    }
+
+    fn check_len(&self, n: usize) -> io::Result<()> {
+        if n == self.joints.len() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "joint count mismatch"))
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use l0::comm::PACKET_MAX_DATA_LEN;
+use super::Message;
+use super::codec::*;
+use super::fragment::is_newer;
+use super::layout::{field, rest, Layout, U16, U8};
+
+const OP_CONFIG: u8 = 0x01;
+const OP_CHUNK: u8 = 0x02;
+const OP_STOP: u8 = 0x03;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("config", OP_CONFIG, &[field("format", U8), field("rate_hz", U16), field("channels", U8)]),
+    Layout::new("chunk", OP_CHUNK, &[field("seq", U16), rest("data", U8)]),
+    Layout::new("stop", OP_STOP, &[]),
+];
+
+// op and seq precede the samples of a chunk.
+const CHUNK_HEADER_LEN: usize = 3;
+pub const MAX_CHUNK_BYTES: usize = PACKET_MAX_DATA_LEN - CHUNK_HEADER_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Pcm8,  // signed 8-bit.
+    Pcm16, // signed 16-bit little-endian.
+}
+
+impl SampleFormat {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(SampleFormat::Pcm8),
+            1 => Some(SampleFormat::Pcm16),
+            _ => None,
+        }
+    }
+
+    pub fn sample_bytes(self) -> usize {
+        match self {
+            SampleFormat::Pcm8 => 1,
+            SampleFormat::Pcm16 => 2,
+        }
+    }
+
+    pub fn encode(self, samples: &[i16], data: &mut Vec<u8>) {
+        for s in samples {
+            match self {
+                SampleFormat::Pcm8 => data.push((*s >> 8) as u8),
+                SampleFormat::Pcm16 => put_i16(data, *s),
+            }
+        }
+    }
+
+    pub fn decode(self, data: &[u8]) -> Vec<i16> {
+        match self {
+            SampleFormat::Pcm8 => data.iter().map(|b| (*b as i8 as i16) << 8).collect(),
+            SampleFormat::Pcm16 => data.chunks(2)
+                .filter(|c| c.len() == 2)
+                .map(|c| (c[0] as u16 | (c[1] as u16) << 8) as i16)
+                .collect(),
+        }
+    }
+}
+
+// The same message set is used in both directions: the device streams the
+// microphone and the host streams to the speaker, each on its own code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioMessage {
+    // Announces the format of the chunks that follow.
+    Config { format: SampleFormat, rate_hz: u16, channels: u8 },
+    // Interleaved samples; seq increments per chunk and wraps.
+    Chunk { seq: u16, data: Vec<u8> },
+    Stop,
+}
+
+impl Message for AudioMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            AudioMessage::Config { format, rate_hz, channels } => {
+                data.push(OP_CONFIG);
+                data.push(format as u8);
+                put_u16(data, rate_hz);
+                data.push(channels);
+            },
+            AudioMessage::Chunk { seq, data: ref samples } => {
+                data.push(OP_CHUNK);
+                put_u16(data, seq);
+                data.extend_from_slice(samples);
+            },
+            AudioMessage::Stop => data.push(OP_STOP),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_CONFIG => AudioMessage::Config {
+                format: SampleFormat::from_u8(r.u8()?)?,
+                rate_hz: r.u16()?,
+                channels: r.u8()?,
+            },
+            OP_CHUNK => AudioMessage::Chunk { seq: r.u16()?, data: r.bytes(r.remaining())?.to_vec() },
+            OP_STOP => AudioMessage::Stop,
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Packs samples into chunk messages, numbering them consecutively.
+pub struct ChunkEncoder {
+    format: SampleFormat,
+    frame_len: usize, // samples across all channels at one instant.
+    seq: u16,
+}
+
+impl ChunkEncoder {
+    pub fn new(format: SampleFormat, channels: u8) -> Self {
+        ChunkEncoder { format, frame_len: channels.max(1) as usize, seq: 0 }
+    }
+
+    // Chunks never split a multi-channel frame.
+    pub fn encode(&mut self, samples: &[i16]) -> Vec<AudioMessage> {
+        let frame_bytes = self.frame_len * self.format.sample_bytes();
+        let per_chunk = MAX_CHUNK_BYTES / frame_bytes * self.frame_len;
+        samples.chunks(per_chunk)
+            .map(|c| {
+                let mut data = Vec::with_capacity(c.len() * self.format.sample_bytes());
+                self.format.encode(c, &mut data);
+                let seq = self.seq;
+                self.seq = self.seq.wrapping_add(1);
+                AudioMessage::Chunk { seq, data }
+            })
+            .collect()
+    }
+}
+
+// Reorders chunks on the host and smooths out arrival jitter. Playback
+// starts once depth chunks are buffered; a chunk missing when its turn comes
+// is concealed with silence, and one arriving after that is dropped. Running
+// dry restarts buffering.
+pub struct JitterBuffer {
+    format: SampleFormat,
+    depth: usize,
+    chunks: BTreeMap<u16, Vec<i16>>, // keyed by seq offset from next.
+    next: Option<u16>,
+    playing: bool,
+    chunk_len: usize,
+    lost: u64,
+    late: u64,
+}
+
+impl JitterBuffer {
+    pub fn new(format: SampleFormat, depth: usize) -> Self {
+        JitterBuffer {
+            format,
+            depth: depth.max(1),
+            chunks: BTreeMap::new(),
+            next: None,
+            playing: false,
+            chunk_len: 0,
+            lost: 0,
+            late: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    // Chunks replaced by silence.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    // Chunks dropped for arriving after their turn.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    pub fn reset(&mut self) {
+        self.chunks.clear();
+        self.next = None;
+        self.playing = false;
+    }
+
+    // Feeds a received message, Config switches the sample format.
+    pub fn handle(&mut self, msg: &AudioMessage) {
+        match *msg {
+            AudioMessage::Config { format, .. } => {
+                self.reset();
+                self.format = format;
+            },
+            AudioMessage::Chunk { seq, ref data } => self.push(seq, data),
+            AudioMessage::Stop => self.reset(),
+        }
+    }
+
+    pub fn push(&mut self, seq: u16, data: &[u8]) {
+        let next = *self.next.get_or_insert(seq);
+        if is_newer(next, seq) {
+            if self.playing {
+                self.late += 1;
+                return;
+            }
+            // an earlier chunk while still buffering moves the start back.
+            self.rebase(seq);
+        }
+        let next = self.next.unwrap_or(seq);
+        self.chunks.insert(seq.wrapping_sub(next), self.format.decode(data));
+    }
+
+    // The next chunk of samples to play, None while buffering.
+    pub fn pop(&mut self) -> Option<Vec<i16>> {
+        if !self.playing {
+            if self.chunks.len() < self.depth {
+                return None;
+            }
+            self.playing = true;
+        }
+        let next = self.next?;
+        let samples = match self.chunks.remove(&0) {
+            Some(samples) => {
+                self.chunk_len = samples.len();
+                samples
+            },
+            None if self.chunks.is_empty() => {
+                self.playing = false;
+                return None;
+            },
+            None => {
+                self.lost += 1;
+                vec![0; self.chunk_len]
+            },
+        };
+        self.rebase(next.wrapping_add(1));
+        Some(samples)
+    }
+
+    fn rebase(&mut self, next: u16) {
+        if let Some(old) = self.next {
+            let shift = next.wrapping_sub(old);
+            self.chunks = ::std::mem::take(&mut self.chunks).into_iter()
+                .map(|(k, v)| (k.wrapping_sub(shift), v))
+                .collect();
+        }
+        self.next = Some(next);
+    }
+}
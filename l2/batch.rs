@@ -0,0 +1,124 @@
+use std::io;
+use std::time::{Duration, Instant};
+use l0::comm::{Packet, PACKET_MAX_DATA_LEN};
+use super::codec::Reader;
+use super::Sender;
+
+// code and length precede each packet's data in a batch.
+pub const BATCH_RECORD_HEADER_LEN: usize = 2;
+
+// The most data a packet can have and still go in a batch.
+pub const BATCH_MAX_RECORD_LEN: usize = PACKET_MAX_DATA_LEN - BATCH_RECORD_HEADER_LEN;
+
+// A batch carries several packets in one payload, each as its code, a
+// length byte and its data, so they cost one packet's framing.
+pub fn put_record(data: &mut Vec<u8>, pkt: &Packet) {
+    data.push(pkt.code);
+    data.push(pkt.data.len() as u8);
+    data.extend_from_slice(&pkt.data);
+}
+
+// The packets of a batch payload, with seq 0; None if it is cut short.
+pub fn unbatch(data: &[u8]) -> Option<Vec<Packet>> {
+    let mut r = Reader::new(data);
+    let mut pkts = Vec::new();
+    while !r.is_empty() {
+        let code = r.u8()?;
+        let len = r.u8()? as usize;
+        let mut pkt = Packet::new_with(0, code);
+        pkt.data.extend_from_slice(r.bytes(len)?);
+        pkts.push(pkt);
+    }
+    Some(pkts)
+}
+
+// Merges telemetry sent within window of the first packet into batches on
+// code, trading that much latency for fewer packets on constrained radios.
+// A batch goes out when the window ends, at the next push or poll after
+// it, or when the next packet won't fit. A lone packet goes out as itself,
+// as does one too long to batch, after what's pending.
+pub struct Coalescer<S> {
+    inner: S,
+    code: u8,
+    window: Duration,
+    batch: Vec<u8>,
+    first: Option<Packet>, // sent as is if nothing joins it.
+    started: Option<Instant>,
+    urgent: Vec<u8>,
+}
+
+impl<S: Sender> Coalescer<S> {
+    pub fn new(inner: S, code: u8, window: Duration) -> Self {
+        Coalescer { inner, code, window, batch: Vec::new(), first: None, started: None, urgent: Vec::new() }
+    }
+
+    // Packets on these codes go out at once, ahead of the pending batch.
+    pub fn set_urgent(&mut self, codes: &[u8]) {
+        self.urgent = codes.to_vec();
+    }
+
+    pub fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.started.is_none()
+    }
+
+    pub fn push_at(&mut self, pkt: Packet, now: Instant) -> io::Result<()> {
+        if self.urgent.contains(&pkt.code) {
+            return self.inner.send(pkt);
+        }
+        self.poll_at(now)?;
+        if pkt.data.len() > BATCH_MAX_RECORD_LEN {
+            self.flush()?;
+            return self.inner.send(pkt);
+        }
+        if self.batch.len() + BATCH_RECORD_HEADER_LEN + pkt.data.len() > PACKET_MAX_DATA_LEN {
+            self.flush()?;
+        }
+        put_record(&mut self.batch, &pkt);
+        if self.started.is_none() {
+            self.started = Some(now);
+            self.first = Some(pkt);
+        } else {
+            self.first = None;
+        }
+        if self.window == Duration::from_secs(0) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // Sends the pending batch if its window has ended; true if it did.
+    pub fn poll_at(&mut self, now: Instant) -> io::Result<bool> {
+        match self.started {
+            Some(t) if now.duration_since(t) >= self.window => self.flush(),
+            _ => Ok(false),
+        }
+    }
+
+    pub fn poll(&mut self) -> io::Result<bool> {
+        self.poll_at(Instant::now())
+    }
+
+    // Sends what's pending now; true if there was anything.
+    pub fn flush(&mut self) -> io::Result<bool> {
+        if self.started.take().is_none() {
+            return Ok(false);
+        }
+        let data = ::std::mem::take(&mut self.batch);
+        let pkt = match self.first.take() {
+            Some(pkt) => pkt,
+            None => Packet { seq: 0, code: self.code, data },
+        };
+        self.inner.send(pkt)?;
+        Ok(true)
+    }
+}
+
+impl<S: Sender> Sender for Coalescer<S> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.push_at(pkt, Instant::now())
+    }
+}
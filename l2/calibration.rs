@@ -0,0 +1,281 @@
+use super::Message;
+use super::codec::*;
+use super::imu::*;
+use super::layout::{array, field, Layout, I16, U16, U8};
+
+const OP_STREAM_RAW: u8 = 0x01;
+const OP_STOP_RAW: u8 = 0x02;
+const OP_WRITE: u8 = 0x03;
+const OP_WRITTEN: u8 = 0x04;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("stream_raw", OP_STREAM_RAW, &[field("rate_hz", U16)]),
+    Layout::new("stop_raw", OP_STOP_RAW, &[]),
+    Layout::new("write", OP_WRITE, &[field("sensor", U8), array("offset", I16, 3), field("scale_q14", U16)]),
+    Layout::new("written", OP_WRITTEN, &[field("sensor", U8), field("ok", U8)]),
+];
+
+pub const SCALE_LSB: f64 = 1.0 / 16384.0; // Q2.14
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sensor {
+    Gyro,
+    Accel,
+    Mag,
+}
+
+impl Sensor {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Sensor::Gyro),
+            1 => Some(Sensor::Accel),
+            2 => Some(Sensor::Mag),
+            _ => None,
+        }
+    }
+
+    pub fn lsb(self) -> f64 {
+        match self {
+            Sensor::Gyro => GYRO_LSB,
+            Sensor::Accel => ACCEL_LSB,
+            Sensor::Mag => MAG_LSB,
+        }
+    }
+}
+
+// While raw streaming is on, the device sends ImuMessage Motion and Mag
+// samples without applying its calibration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationMessage {
+    StreamRaw { rate_hz: u16 },
+    StopRaw,
+    // Stores corrected = (raw - offset) * scale, offset in the sensor's
+    // wire units.
+    Write { sensor: Sensor, offset: [i16; 3], scale_q14: u16 },
+    Written { sensor: Sensor, ok: bool },
+}
+
+impl Message for CalibrationMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            CalibrationMessage::StreamRaw { rate_hz } => {
+                data.push(OP_STREAM_RAW);
+                put_u16(data, rate_hz);
+            },
+            CalibrationMessage::StopRaw => data.push(OP_STOP_RAW),
+            CalibrationMessage::Write { sensor, offset, scale_q14 } => {
+                data.push(OP_WRITE);
+                data.push(sensor as u8);
+                for v in &offset {
+                    put_i16(data, *v);
+                }
+                put_u16(data, scale_q14);
+            },
+            CalibrationMessage::Written { sensor, ok } => {
+                data.push(OP_WRITTEN);
+                data.push(sensor as u8);
+                data.push(ok as u8);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_STREAM_RAW => CalibrationMessage::StreamRaw { rate_hz: r.u16()? },
+            OP_STOP_RAW => CalibrationMessage::StopRaw,
+            OP_WRITE => CalibrationMessage::Write {
+                sensor: Sensor::from_u8(r.u8()?)?,
+                offset: [r.i16()?, r.i16()?, r.i16()?],
+                scale_q14: r.u16()?,
+            },
+            OP_WRITTEN => CalibrationMessage::Written { sensor: Sensor::from_u8(r.u8()?)?, ok: r.u8()? != 0 },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Least-squares sphere through points, returning center and radius. Solves
+// |p|^2 = 2 c.p + (r^2 - |c|^2), which is linear in c and the constant.
+pub fn fit_sphere(points: &[Vec3]) -> Option<(Vec3, f64)> {
+    if points.len() < 4 {
+        return None;
+    }
+    let mut a = [[0.0; 5]; 4]; // normal equations, augmented.
+    for p in points {
+        let row = [2.0 * p.x, 2.0 * p.y, 2.0 * p.z, 1.0];
+        let b = p.x * p.x + p.y * p.y + p.z * p.z;
+        for i in 0..4 {
+            for j in 0..4 {
+                a[i][j] += row[i] * row[j];
+            }
+            a[i][4] += row[i] * b;
+        }
+    }
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-9 * a[pivot].iter().map(|v| v.abs()).fold(1.0, f64::max) {
+            return None; // points on a plane or a line.
+        }
+        a.swap(col, pivot);
+        let p = a[col];
+        for (i, row) in a.iter_mut().enumerate() {
+            if i != col {
+                let f = row[col] / p[col];
+                for (v, pv) in row.iter_mut().zip(&p).skip(col) {
+                    *v -= f * pv;
+                }
+            }
+        }
+    }
+    let c = Vec3::new(a[0][4] / a[0][0], a[1][4] / a[1][1], a[2][4] / a[2][2]);
+    let k = a[3][4] / a[3][3];
+    let r2 = k + c.x * c.x + c.y * c.y + c.z * c.z;
+    if r2 <= 0.0 {
+        return None;
+    }
+    Some((c, r2.sqrt()))
+}
+
+// Offset and scale of one sensor, in physical units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorCalibration {
+    pub offset: Vec3,
+    pub scale: f64,
+}
+
+impl SensorCalibration {
+    pub fn apply(&self, v: &Vec3) -> Vec3 {
+        Vec3::new((v.x - self.offset.x) * self.scale, (v.y - self.offset.y) * self.scale, (v.z - self.offset.z) * self.scale)
+    }
+
+    pub fn to_message(&self, sensor: Sensor) -> CalibrationMessage {
+        CalibrationMessage::Write {
+            sensor,
+            offset: self.offset.to_fixed(sensor.lsb()),
+            scale_q14: (self.scale / SCALE_LSB).round().max(0.0).min(u16::MAX as f64) as u16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationStep {
+    Gyro,  // keep the IMU still.
+    Accel, // hold it still in several orientations, ideally all six faces.
+    Mag,   // rotate it slowly through all orientations.
+    Done,
+    Failed(Sensor),
+}
+
+// Guides through gyro, accelerometer and magnetometer calibration: send
+// start(), feed the raw samples to push(), show instructions() as the step
+// advances, and send write() once Done.
+pub struct Calibrator {
+    samples: usize,
+    step: CalibrationStep,
+    points: Vec<Vec3>,
+    gyro: Option<SensorCalibration>,
+    accel: Option<SensorCalibration>,
+    mag: Option<SensorCalibration>,
+}
+
+impl Calibrator {
+    // samples is the number collected per step.
+    pub fn new(samples: usize) -> Self {
+        Calibrator {
+            samples: samples.max(4),
+            step: CalibrationStep::Gyro,
+            points: Vec::new(),
+            gyro: None,
+            accel: None,
+            mag: None,
+        }
+    }
+
+    pub fn start(&mut self, rate_hz: u16) -> CalibrationMessage {
+        self.step = CalibrationStep::Gyro;
+        self.points.clear();
+        CalibrationMessage::StreamRaw { rate_hz }
+    }
+
+    pub fn step(&self) -> CalibrationStep {
+        self.step
+    }
+
+    pub fn instructions(&self) -> &'static str {
+        match self.step {
+            CalibrationStep::Gyro => "Keep the robot completely still.",
+            CalibrationStep::Accel => "Hold the robot still on each of its sides in turn.",
+            CalibrationStep::Mag => "Slowly rotate the robot through every orientation.",
+            CalibrationStep::Done => "Calibration complete.",
+            CalibrationStep::Failed(_) => "Calibration failed, samples did not cover enough orientations.",
+        }
+    }
+
+    // Fraction of the current step's samples collected.
+    pub fn progress(&self) -> f64 {
+        self.points.len() as f64 / self.samples as f64
+    }
+
+    pub fn push(&mut self, msg: &ImuMessage) {
+        let v = match (self.step, *msg) {
+            (CalibrationStep::Gyro, ImuMessage::Motion { gyro, .. }) => Vec3::from_fixed(gyro, GYRO_LSB),
+            (CalibrationStep::Accel, ImuMessage::Motion { accel, gyro, .. }) => {
+                // only samples taken at rest measure gravity alone.
+                if Vec3::from_fixed(gyro, GYRO_LSB).norm() > 0.05 {
+                    return;
+                }
+                Vec3::from_fixed(accel, ACCEL_LSB)
+            },
+            (CalibrationStep::Mag, ImuMessage::Mag { mag, .. }) => Vec3::from_fixed(mag, MAG_LSB),
+            _ => return,
+        };
+        self.points.push(v);
+        if self.points.len() < self.samples {
+            return;
+        }
+        let points = ::std::mem::take(&mut self.points);
+        self.step = match self.step {
+            CalibrationStep::Gyro => {
+                let n = points.len() as f64;
+                let sum = points.iter().fold(Vec3::default(), |s, p| Vec3::new(s.x + p.x, s.y + p.y, s.z + p.z));
+                self.gyro = Some(SensorCalibration { offset: Vec3::new(sum.x / n, sum.y / n, sum.z / n), scale: 1.0 });
+                CalibrationStep::Accel
+            },
+            CalibrationStep::Accel => match fit_sphere(&points) {
+                Some((offset, r)) => {
+                    self.accel = Some(SensorCalibration { offset, scale: 9.80665 / r });
+                    CalibrationStep::Mag
+                },
+                None => CalibrationStep::Failed(Sensor::Accel),
+            },
+            CalibrationStep::Mag => match fit_sphere(&points) {
+                // the field strength varies by location, only the offset matters.
+                Some((offset, _)) => {
+                    self.mag = Some(SensorCalibration { offset, scale: 1.0 });
+                    CalibrationStep::Done
+                },
+                None => CalibrationStep::Failed(Sensor::Mag),
+            },
+            step => step,
+        };
+    }
+
+    pub fn calibration(&self, sensor: Sensor) -> Option<&SensorCalibration> {
+        match sensor {
+            Sensor::Gyro => self.gyro.as_ref(),
+            Sensor::Accel => self.accel.as_ref(),
+            Sensor::Mag => self.mag.as_ref(),
+        }
+    }
+
+    // Messages storing the results on the device and ending raw streaming.
+    pub fn write(&self) -> Vec<CalibrationMessage> {
+        let mut msgs: Vec<_> = [Sensor::Gyro, Sensor::Accel, Sensor::Mag].iter()
+            .filter_map(|s| self.calibration(*s).map(|c| c.to_message(*s)))
+            .collect();
+        msgs.push(CalibrationMessage::StopRaw);
+        msgs
+    }
+}
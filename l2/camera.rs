@@ -0,0 +1,161 @@
+use std::time::Duration;
+use l0::comm::PACKET_MAX_DATA_LEN;
+use super::Message;
+use super::codec::*;
+use super::fragment::*;
+use super::layout::{field, Layout, U16, U8};
+use super::fragment::FRAGMENT;
+
+const OP_START: u8 = 0x01;
+const OP_STOP: u8 = 0x02;
+const OP_CHUNK: u8 = 0x03;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("start", OP_START, &[field("format", U8), field("width", U16), field("height", U16), field("fps", U8)]),
+    Layout::new("stop", OP_STOP, &[]),
+    Layout::new("chunk", OP_CHUNK, FRAGMENT),
+];
+
+pub const CHUNK_DATA_LEN: usize = PACKET_MAX_DATA_LEN - 1 - FRAGMENT_HEADER_LEN;
+
+// format, width, height and timestamp precede the image data of a frame.
+const FRAME_HEADER_LEN: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Grey8,
+    Jpeg,
+}
+
+impl ImageFormat {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(ImageFormat::Grey8),
+            1 => Some(ImageFormat::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CameraMessage {
+    Start { format: ImageFormat, width: u16, height: u16, fps: u8 },
+    Stop,
+    Chunk(Fragment), // a piece of an encoded frame, the fragment id is the frame seq.
+}
+
+impl Message for CameraMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            CameraMessage::Start { format, width, height, fps } => {
+                data.push(OP_START);
+                data.push(format as u8);
+                put_u16(data, width);
+                put_u16(data, height);
+                data.push(fps);
+            },
+            CameraMessage::Stop => data.push(OP_STOP),
+            CameraMessage::Chunk(ref frag) => {
+                data.push(OP_CHUNK);
+                frag.encode(data);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_START => CameraMessage::Start {
+                format: ImageFormat::from_u8(r.u8()?)?,
+                width: r.u16()?,
+                height: r.u16()?,
+                fps: r.u8()?,
+            },
+            OP_STOP => CameraMessage::Stop,
+            OP_CHUNK => CameraMessage::Chunk(Fragment::decode(&mut r)?),
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub seq: u16,
+    pub format: ImageFormat,
+    pub width: u16,
+    pub height: u16,
+    pub device_ts: Duration, // capture time on the device.
+    pub ts: Duration,        // host time the last chunk arrived.
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    // Encodes the frame as the chunk messages a device streams.
+    pub fn to_chunks(&self) -> Vec<CameraMessage> {
+        let mut payload = Vec::with_capacity(FRAME_HEADER_LEN + self.data.len());
+        payload.push(self.format as u8);
+        put_u16(&mut payload, self.width);
+        put_u16(&mut payload, self.height);
+        put_u32(&mut payload, self.device_ts.as_millis() as u32);
+        payload.extend_from_slice(&self.data);
+        split(self.seq, &payload, CHUNK_DATA_LEN).into_iter()
+            .map(CameraMessage::Chunk)
+            .collect()
+    }
+
+    fn from_payload(seq: u16, payload: &[u8], ts: Duration) -> Option<Self> {
+        let mut r = Reader::new(payload);
+        Some(Frame {
+            seq,
+            format: ImageFormat::from_u8(r.u8()?)?,
+            width: r.u16()?,
+            height: r.u16()?,
+            device_ts: Duration::from_millis(r.u32()? as u64),
+            ts,
+            data: r.bytes(r.remaining())?.to_vec(),
+        })
+    }
+}
+
+// Assembles frames from chunk messages. A frame still incomplete when a
+// newer frame completes is late and dropped, so a slow link shows the most
+// recent picture rather than falling behind.
+pub struct FrameReceiver {
+    reassembler: Reassembler,
+}
+
+impl Default for FrameReceiver {
+    fn default() -> Self {
+        FrameReceiver::new()
+    }
+}
+
+impl FrameReceiver {
+    pub fn new() -> Self {
+        FrameReceiver::with_pending(2)
+    }
+
+    // Assemble up to max_pending frames concurrently.
+    pub fn with_pending(max_pending: usize) -> Self {
+        FrameReceiver { reassembler: Reassembler::new(max_pending) }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.reassembler.dropped()
+    }
+
+    pub fn push(&mut self, msg: &CameraMessage, ts: Duration) -> Option<Frame> {
+        match *msg {
+            CameraMessage::Chunk(ref frag) => {
+                let (seq, payload) = self.reassembler.push(frag.clone())?;
+                Frame::from_payload(seq, &payload, ts)
+            },
+            CameraMessage::Stop => {
+                self.reassembler.reset();
+                None
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// Little-endian helpers shared by the message sets.
+
+pub fn put_u16(data: &mut Vec<u8>, v: u16) {
+    data.push(v as u8);
+    data.push((v >> 8) as u8);
+}
+
+pub fn put_i16(data: &mut Vec<u8>, v: i16) {
+    put_u16(data, v as u16);
+}
+
+pub fn put_u32(data: &mut Vec<u8>, v: u32) {
+    put_u16(data, v as u16);
+    put_u16(data, (v >> 16) as u16);
+}
+
+pub fn put_i32(data: &mut Vec<u8>, v: i32) {
+    put_u32(data, v as u32);
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        let b = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        let lo = self.u8()? as u16;
+        let hi = self.u8()? as u16;
+        Some(lo | (hi << 8))
+    }
+
+    pub fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|v| v as i16)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        let lo = self.u16()? as u32;
+        let hi = self.u16()? as u32;
+        Some(lo | (hi << 16))
+    }
+
+    pub fn i32(&mut self) -> Option<i32> {
+        self.u32().map(|v| v as i32)
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.remaining() < n {
+            return None;
+        }
+        let b = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Some(b)
+    }
+
+    // Returns the value only if the whole payload has been consumed.
+    pub fn finish<T>(&self, v: T) -> Option<T> {
+        if self.is_empty() {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, Layout, I32, U32, U8};
+
+const OP_COUNT: u8 = 0x01;
+const OP_VELOCITY: u8 = 0x02;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("count", OP_COUNT, &[field("channel", U8), field("count", U32)]),
+    Layout::new("velocity", OP_VELOCITY, &[field("channel", U8), field("ticks_per_sec", I32)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderMessage {
+    Count { channel: u8, count: u32 },            // raw hardware counter, may wrap.
+    Velocity { channel: u8, ticks_per_sec: i32 }, // device-side velocity estimate.
+}
+
+impl Message for EncoderMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            EncoderMessage::Count { channel, count } => {
+                data.push(OP_COUNT);
+                data.push(channel);
+                put_u32(data, count);
+            },
+            EncoderMessage::Velocity { channel, ticks_per_sec } => {
+                data.push(OP_VELOCITY);
+                data.push(channel);
+                put_i32(data, ticks_per_sec);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_COUNT => EncoderMessage::Count { channel: r.u8()?, count: r.u32()? },
+            OP_VELOCITY => EncoderMessage::Velocity { channel: r.u8()?, ticks_per_sec: r.i32()? },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Accumulates raw counter readings of a `bits` wide hardware counter into an
+// unbounded position, and low-pass filters the velocity with factor `alpha`
+// (1.0 disables filtering).
+pub struct EncoderTracker {
+    bits: u8,
+    alpha: f64,
+    last: Option<(u32, Duration)>,
+    position: i64,
+    velocity: f64,
+}
+
+impl EncoderTracker {
+    pub fn new(bits: u8, alpha: f64) -> Self {
+        EncoderTracker {
+            bits: if bits == 0 || bits > 32 { 32 } else { bits },
+            alpha,
+            last: None,
+            position: 0,
+            velocity: 0.0,
+        }
+    }
+
+    // Feeds a counter reading taken at `ts`, returns the ticks moved since
+    // the previous reading.
+    pub fn update(&mut self, count: u32, ts: Duration) -> i64 {
+        let (last_count, last_ts) = match self.last.replace((count, ts)) {
+            Some(last) => last,
+            None => return 0,
+        };
+        let range = 1i64 << self.bits;
+        let mut delta = (count as i64 - last_count as i64).rem_euclid(range);
+        if delta >= range / 2 {
+            delta -= range;
+        }
+        self.position += delta;
+        if ts > last_ts {
+            let dt = (ts - last_ts).as_secs_f64();
+            self.velocity += self.alpha * (delta as f64 / dt - self.velocity);
+        }
+        delta
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    // Filtered velocity in ticks per second.
+    pub fn velocity(&self) -> f64 {
+        self.velocity
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.position = 0;
+        self.velocity = 0.0;
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, Layout, I32, U8};
+
+const OP_READING: u8 = 0x01;
+const OP_SET_ALARM: u8 = 0x02;
+const OP_ALARM: u8 = 0x03;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("reading", OP_READING, &[field("sensor", U8), field("quantity", U8), field("value", I32)]),
+    Layout::new("set_alarm", OP_SET_ALARM, &[field("sensor", U8), field("quantity", U8), field("low", I32), field("high", I32)]),
+    Layout::new("alarm", OP_ALARM, &[field("sensor", U8), field("quantity", U8), field("level", U8), field("value", I32)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quantity {
+    Temperature, // 0.01 degC
+    Humidity,    // 0.01 %RH
+    Pressure,    // Pa
+}
+
+impl Quantity {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Quantity::Temperature),
+            1 => Some(Quantity::Humidity),
+            2 => Some(Quantity::Pressure),
+            _ => None,
+        }
+    }
+
+    // Size of one fixed-point unit in degC, %RH or Pa.
+    pub fn lsb(self) -> f64 {
+        match self {
+            Quantity::Temperature | Quantity::Humidity => 0.01,
+            Quantity::Pressure => 1.0,
+        }
+    }
+
+    pub fn to_fixed(self, v: f64) -> i32 {
+        (v / self.lsb()).round() as i32
+    }
+
+    pub fn from_fixed(self, v: i32) -> f64 {
+        v as f64 * self.lsb()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmLevel {
+    Clear,
+    Low,
+    High,
+}
+
+impl AlarmLevel {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(AlarmLevel::Clear),
+            1 => Some(AlarmLevel::Low),
+            2 => Some(AlarmLevel::High),
+            _ => None,
+        }
+    }
+}
+
+// Values are fixed-point in the units of the quantity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvMessage {
+    Reading { sensor: u8, quantity: Quantity, value: i32 },
+    // Device-side alarm band, Alarm is sent when the value leaves or
+    // re-enters it.
+    SetAlarm { sensor: u8, quantity: Quantity, low: i32, high: i32 },
+    Alarm { sensor: u8, quantity: Quantity, level: AlarmLevel, value: i32 },
+}
+
+impl Message for EnvMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            EnvMessage::Reading { sensor, quantity, value } => {
+                data.push(OP_READING);
+                data.push(sensor);
+                data.push(quantity as u8);
+                put_i32(data, value);
+            },
+            EnvMessage::SetAlarm { sensor, quantity, low, high } => {
+                data.push(OP_SET_ALARM);
+                data.push(sensor);
+                data.push(quantity as u8);
+                put_i32(data, low);
+                put_i32(data, high);
+            },
+            EnvMessage::Alarm { sensor, quantity, level, value } => {
+                data.push(OP_ALARM);
+                data.push(sensor);
+                data.push(quantity as u8);
+                data.push(level as u8);
+                put_i32(data, value);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_READING => EnvMessage::Reading {
+                sensor: r.u8()?,
+                quantity: Quantity::from_u8(r.u8()?)?,
+                value: r.i32()?,
+            },
+            OP_SET_ALARM => EnvMessage::SetAlarm {
+                sensor: r.u8()?,
+                quantity: Quantity::from_u8(r.u8()?)?,
+                low: r.i32()?,
+                high: r.i32()?,
+            },
+            OP_ALARM => EnvMessage::Alarm {
+                sensor: r.u8()?,
+                quantity: Quantity::from_u8(r.u8()?)?,
+                level: AlarmLevel::from_u8(r.u8()?)?,
+                value: r.i32()?,
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvSample {
+    pub value: f64, // degC, %RH or Pa.
+    pub alarm: AlarmLevel,
+    pub ts: Duration,
+}
+
+type AlarmCallback = Box<dyn FnMut(u8, Quantity, AlarmLevel, f64) + Send>;
+
+// Keeps the latest value and alarm level of every sensor quantity, e.g. to
+// derate motor drivers or stop charging when something runs hot.
+#[derive(Default)]
+pub struct EnvMonitor {
+    samples: HashMap<(u8, Quantity), EnvSample>,
+    callbacks: Vec<AlarmCallback>,
+}
+
+impl EnvMonitor {
+    pub fn new() -> Self {
+        EnvMonitor::default()
+    }
+
+    // Called with sensor, quantity, level and value whenever a level changes.
+    pub fn on_alarm<F>(&mut self, callback: F)
+        where F: FnMut(u8, Quantity, AlarmLevel, f64) + Send + 'static {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn update(&mut self, msg: &EnvMessage, ts: Duration) {
+        let (sensor, quantity, value, level) = match *msg {
+            EnvMessage::Reading { sensor, quantity, value } => (sensor, quantity, value, None),
+            EnvMessage::Alarm { sensor, quantity, level, value } => (sensor, quantity, value, Some(level)),
+            EnvMessage::SetAlarm { .. } => return,
+        };
+        let value = quantity.from_fixed(value);
+        let s = self.samples.entry((sensor, quantity))
+            .or_insert(EnvSample { value, alarm: AlarmLevel::Clear, ts });
+        s.value = value;
+        s.ts = ts;
+        if let Some(level) = level {
+            if level != s.alarm {
+                s.alarm = level;
+                for cb in self.callbacks.iter_mut() {
+                    cb(sensor, quantity, level, value);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, sensor: u8, quantity: Quantity) -> Option<&EnvSample> {
+        self.samples.get(&(sensor, quantity))
+    }
+
+    // Sensor quantities currently outside their alarm band.
+    pub fn alarms(&self) -> Vec<(u8, Quantity, AlarmLevel)> {
+        let mut alarms: Vec<_> = self.samples.iter()
+            .filter(|&(_, s)| s.alarm != AlarmLevel::Clear)
+            .map(|(&(sensor, q), s)| (sensor, q, s.alarm))
+            .collect();
+        alarms.sort_by_key(|a| (a.0, a.1 as u8));
+        alarms
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+use super::codec::*;
+use super::layout::{field, rest, Field, U16, U8};
+
+// id, index and count precede the fragment data.
+pub const FRAGMENT_HEADER_LEN: usize = 6;
+
+// The fields of a fragment, for the layouts of messages carrying one.
+pub const FRAGMENT: &[Field] = &[field("id", U16), field("index", U16), field("count", U16), rest("data", U8)];
+
+// One piece of a payload too large for a single packet. Fragments of the
+// same payload share the id, which increments per payload and wraps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub id: u16,
+    pub index: u16,
+    pub count: u16,
+    pub data: Vec<u8>,
+}
+
+impl Fragment {
+    pub fn encode(&self, data: &mut Vec<u8>) {
+        put_u16(data, self.id);
+        put_u16(data, self.index);
+        put_u16(data, self.count);
+        data.extend_from_slice(&self.data);
+    }
+
+    // Consumes the rest of the reader.
+    pub fn decode(r: &mut Reader) -> Option<Self> {
+        let id = r.u16()?;
+        let index = r.u16()?;
+        let count = r.u16()?;
+        if index >= count {
+            return None;
+        }
+        let data = r.bytes(r.remaining())?.to_vec();
+        Some(Fragment { id, index, count, data })
+    }
+}
+
+// A fragment whose data is a slice of the packet it came in, not a copy.
+#[cfg(feature = "bytes")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BytesFragment {
+    pub id: u16,
+    pub index: u16,
+    pub count: u16,
+    pub data: Bytes,
+}
+
+#[cfg(feature = "bytes")]
+impl BytesFragment {
+    // payload is a whole packet's data.
+    pub fn decode(payload: &Bytes) -> Option<Self> {
+        let mut r = Reader::new(payload);
+        let id = r.u16()?;
+        let index = r.u16()?;
+        let count = r.u16()?;
+        if index >= count {
+            return None;
+        }
+        Some(BytesFragment { id, index, count, data: payload.slice(FRAGMENT_HEADER_LEN..) })
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl From<Fragment> for BytesFragment {
+    fn from(f: Fragment) -> Self {
+        BytesFragment { id: f.id, index: f.index, count: f.count, data: f.data.into() }
+    }
+}
+
+// Splits payload into fragments of at most chunk_len data bytes.
+pub fn split(id: u16, payload: &[u8], chunk_len: usize) -> Vec<Fragment> {
+    let chunks: Vec<&[u8]> = if payload.is_empty() {
+        vec![payload]
+    } else {
+        payload.chunks(chunk_len).collect()
+    };
+    let count = chunks.len() as u16;
+    chunks.into_iter().enumerate()
+        .map(|(i, c)| Fragment { id, index: i as u16, count, data: c.to_vec() })
+        .collect()
+}
+
+// True if id a was assigned after b, allowing for wraparound.
+pub fn is_newer(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) > 0
+}
+
+// Fragment data as the reassembler holds it; shared with bytes, so
+// payloads of one fragment pass through push_bytes without a copy.
+#[cfg(feature = "bytes")]
+type Chunk = Bytes;
+#[cfg(not(feature = "bytes"))]
+type Chunk = Vec<u8>;
+
+struct Pending {
+    id: u16,
+    chunks: Vec<Option<Chunk>>,
+    received: usize,
+}
+
+// Reassembles payloads from fragments arriving in any order. At most
+// max_pending payloads are assembled at once, the oldest is dropped to make
+// room. Fragments of payloads older than the last completed one are late
+// and dropped too, so payloads complete in id order.
+pub struct Reassembler {
+    max_pending: usize,
+    pending: VecDeque<Pending>,
+    last: Option<u16>,
+    dropped: u64,
+}
+
+impl Reassembler {
+    pub fn new(max_pending: usize) -> Self {
+        Reassembler {
+            max_pending: max_pending.max(1),
+            pending: VecDeque::new(),
+            last: None,
+            dropped: 0,
+        }
+    }
+
+    // Number of payloads dropped incomplete, or late.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.last = None;
+    }
+
+    // Returns the payload id and data once the last fragment arrives.
+    #[allow(clippy::useless_conversion)] // Chunk is Vec<u8> without bytes.
+    pub fn push(&mut self, frag: Fragment) -> Option<(u16, Vec<u8>)> {
+        let (id, chunks) = self.insert(frag.id, frag.index, frag.count, frag.data.into())?;
+        Some((id, chunks.concat()))
+    }
+
+    // Like push, copying the data only to join fragments.
+    #[cfg(feature = "bytes")]
+    pub fn push_bytes(&mut self, frag: BytesFragment) -> Option<(u16, Bytes)> {
+        let (id, mut chunks) = self.insert(frag.id, frag.index, frag.count, frag.data)?;
+        if chunks.len() == 1 {
+            return chunks.pop().map(|c| (id, c));
+        }
+        Some((id, chunks.concat().into()))
+    }
+
+    // The chunks of the payload the fragment completed, if it did.
+    fn insert(&mut self, id: u16, index: u16, count: u16, data: Chunk) -> Option<(u16, Vec<Chunk>)> {
+        if let Some(last) = self.last {
+            if !is_newer(id, last) {
+                return None;
+            }
+        }
+        let pos = match self.pending.iter().position(|p| p.id == id) {
+            Some(pos) => pos,
+            None => {
+                if self.pending.len() >= self.max_pending {
+                    self.pending.pop_front();
+                    self.dropped += 1;
+                }
+                self.pending.push_back(Pending {
+                    id,
+                    chunks: vec![None; count as usize],
+                    received: 0,
+                });
+                self.pending.len() - 1
+            },
+        };
+        {
+            let p = &mut self.pending[pos];
+            match p.chunks.get_mut(index as usize) {
+                Some(slot) if slot.is_none() => {
+                    *slot = Some(data);
+                    p.received += 1;
+                },
+                _ => return None,
+            }
+            if p.received < p.chunks.len() {
+                return None;
+            }
+        }
+        // everything older than the completed payload is now late.
+        let done = self.pending.remove(pos)?;
+        let before = self.pending.len();
+        self.pending.retain(|p| is_newer(p.id, done.id));
+        self.dropped += (before - self.pending.len()) as u64;
+        self.last = Some(done.id);
+        Some((done.id, done.chunks.into_iter().flatten().collect()))
+    }
+}
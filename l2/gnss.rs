@@ -0,0 +1,172 @@
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::imu::Vec3;
+use super::layout::{field, Layout, I16, I32, U16, U8};
+
+pub const DEGREE_LSB: f64 = 1e-7;
+pub const EARTH_RADIUS: f64 = 6_371_000.0; // m, mean.
+
+const OP_POSITION: u8 = 0x01;
+const OP_VELOCITY: u8 = 0x02;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("position", OP_POSITION, &[field("lat_e7", I32), field("lon_e7", I32), field("alt_cm", I32),
+        field("quality", U8), field("sats", U8), field("hdop_centi", U16)]),
+    Layout::new("velocity", OP_VELOCITY, &[field("north_cms", I16), field("east_cms", I16), field("up_cms", I16)]),
+];
+
+// Fix quality as in the NMEA GGA sentence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixQuality {
+    NoFix = 0,
+    Gps = 1,
+    Dgps = 2,
+    RtkFixed = 4,
+    RtkFloat = 5,
+}
+
+impl FixQuality {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(FixQuality::NoFix),
+            1 => Some(FixQuality::Gps),
+            2 => Some(FixQuality::Dgps),
+            4 => Some(FixQuality::RtkFixed),
+            5 => Some(FixQuality::RtkFloat),
+            _ => None,
+        }
+    }
+
+    // Typical horizontal error in meters at an HDOP of 1.
+    pub fn base_error(self) -> f64 {
+        match self {
+            FixQuality::NoFix => f64::INFINITY,
+            FixQuality::Gps => 2.5,
+            FixQuality::Dgps => 1.0,
+            FixQuality::RtkFloat => 0.3,
+            FixQuality::RtkFixed => 0.02,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GnssMessage {
+    // Degrees in 1e-7, altitude above mean sea level, HDOP in 0.01.
+    Position { lat_e7: i32, lon_e7: i32, alt_cm: i32, quality: FixQuality, sats: u8, hdop_centi: u16 },
+    Velocity { north_cms: i16, east_cms: i16, up_cms: i16 },
+}
+
+impl Message for GnssMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            GnssMessage::Position { lat_e7, lon_e7, alt_cm, quality, sats, hdop_centi } => {
+                data.push(OP_POSITION);
+                put_i32(data, lat_e7);
+                put_i32(data, lon_e7);
+                put_i32(data, alt_cm);
+                data.push(quality as u8);
+                data.push(sats);
+                put_u16(data, hdop_centi);
+            },
+            GnssMessage::Velocity { north_cms, east_cms, up_cms } => {
+                data.push(OP_VELOCITY);
+                put_i16(data, north_cms);
+                put_i16(data, east_cms);
+                put_i16(data, up_cms);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_POSITION => GnssMessage::Position {
+                lat_e7: r.i32()?,
+                lon_e7: r.i32()?,
+                alt_cm: r.i32()?,
+                quality: FixQuality::from_u8(r.u8()?)?,
+                sats: r.u8()?,
+                hdop_centi: r.u16()?,
+            },
+            OP_VELOCITY => GnssMessage::Velocity {
+                north_cms: r.i16()?,
+                east_cms: r.i16()?,
+                up_cms: r.i16()?,
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GnssFix {
+    pub lat: f64, // degrees
+    pub lon: f64, // degrees
+    pub alt: f64, // m
+    pub quality: FixQuality,
+    pub sats: u8,
+    pub hdop: f64,
+    pub ts: Duration,
+}
+
+impl GnssFix {
+    // None for velocity messages and positions without a fix.
+    pub fn from_message(msg: &GnssMessage, ts: Duration) -> Option<Self> {
+        match *msg {
+            GnssMessage::Position { quality: FixQuality::NoFix, .. } => None,
+            GnssMessage::Position { lat_e7, lon_e7, alt_cm, quality, sats, hdop_centi } => Some(GnssFix {
+                lat: lat_e7 as f64 * DEGREE_LSB,
+                lon: lon_e7 as f64 * DEGREE_LSB,
+                alt: alt_cm as f64 / 100.0,
+                quality,
+                sats,
+                hdop: hdop_centi as f64 / 100.0,
+                ts,
+            }),
+            _ => None,
+        }
+    }
+
+    // Estimated standard deviation of the horizontal position in meters.
+    pub fn horizontal_error(&self) -> f64 {
+        self.quality.base_error() * self.hdop.max(1.0)
+    }
+}
+
+// Velocity in east-north-up order, m/s.
+pub fn enu_velocity(msg: &GnssMessage) -> Option<Vec3> {
+    match *msg {
+        GnssMessage::Velocity { north_cms, east_cms, up_cms } =>
+            Some(Vec3::new(east_cms as f64 / 100.0, north_cms as f64 / 100.0, up_cms as f64 / 100.0)),
+        _ => None,
+    }
+}
+
+// Flat-earth projection around an origin, x east and y north in meters.
+// Accurate to centimeters within a few kilometers of the origin, which is
+// plenty for a robot's working area.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalFrame {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl LocalFrame {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        LocalFrame { lat, lon }
+    }
+
+    pub fn to_local(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let x = (lon - self.lon).to_radians() * EARTH_RADIUS * self.lat.to_radians().cos();
+        let y = (lat - self.lat).to_radians() * EARTH_RADIUS;
+        (x, y)
+    }
+
+    pub fn to_geodetic(&self, x: f64, y: f64) -> (f64, f64) {
+        let lat = self.lat + (y / EARTH_RADIUS).to_degrees();
+        let lon = self.lon + (x / (EARTH_RADIUS * self.lat.to_radians().cos())).to_degrees();
+        (lat, lon)
+    }
+}
@@ -0,0 +1,246 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+use super::{Message, Sender};
+use super::codec::*;
+use super::layout::{field, Layout, U16, U8};
+
+pub const POSITION_FULL: u16 = 1000; // position in permille of the full opening.
+pub const FORCE_LSB: f64 = 0.01;     // N.
+
+const OP_OPEN: u8 = 0x01;
+const OP_CLOSE: u8 = 0x02;
+const OP_SET_POSITION: u8 = 0x03;
+const OP_SET_FORCE: u8 = 0x04;
+const OP_STATE: u8 = 0x05;
+const OP_GRASP_DETECTED: u8 = 0x06;
+const OP_CLOSED: u8 = 0x07;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("open", OP_OPEN, &[]),
+    Layout::new("close", OP_CLOSE, &[field("force_cn", U16)]),
+    Layout::new("set_position", OP_SET_POSITION, &[field("position", U16)]),
+    Layout::new("set_force", OP_SET_FORCE, &[field("force_cn", U16)]),
+    Layout::new("state", OP_STATE, &[field("position", U16), field("force_cn", U16), field("moving", U8)]),
+    Layout::new("grasp_detected", OP_GRASP_DETECTED, &[field("position", U16), field("force_cn", U16)]),
+    Layout::new("closed", OP_CLOSED, &[]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GripperMessage {
+    Open,
+    // Closes until the grip force reaches force_cn, then holds; the device
+    // answers with GraspDetected or, if nothing was in the way, Closed.
+    Close { force_cn: u16 },
+    SetPosition { position: u16 },
+    SetForce { force_cn: u16 }, // force limit for SetPosition moves.
+    State { position: u16, force_cn: u16, moving: bool },
+    GraspDetected { position: u16, force_cn: u16 },
+    Closed,
+}
+
+impl Message for GripperMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            GripperMessage::Open => data.push(OP_OPEN),
+            GripperMessage::Close { force_cn } => {
+                data.push(OP_CLOSE);
+                put_u16(data, force_cn);
+            },
+            GripperMessage::SetPosition { position } => {
+                data.push(OP_SET_POSITION);
+                put_u16(data, position);
+            },
+            GripperMessage::SetForce { force_cn } => {
+                data.push(OP_SET_FORCE);
+                put_u16(data, force_cn);
+            },
+            GripperMessage::State { position, force_cn, moving } => {
+                data.push(OP_STATE);
+                put_u16(data, position);
+                put_u16(data, force_cn);
+                data.push(moving as u8);
+            },
+            GripperMessage::GraspDetected { position, force_cn } => {
+                data.push(OP_GRASP_DETECTED);
+                put_u16(data, position);
+                put_u16(data, force_cn);
+            },
+            GripperMessage::Closed => data.push(OP_CLOSED),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_OPEN => GripperMessage::Open,
+            OP_CLOSE => GripperMessage::Close { force_cn: r.u16()? },
+            OP_SET_POSITION => GripperMessage::SetPosition { position: r.u16()? },
+            OP_SET_FORCE => GripperMessage::SetForce { force_cn: r.u16()? },
+            OP_STATE => GripperMessage::State { position: r.u16()?, force_cn: r.u16()?, moving: r.u8()? != 0 },
+            OP_GRASP_DETECTED => GripperMessage::GraspDetected { position: r.u16()?, force_cn: r.u16()? },
+            OP_CLOSED => GripperMessage::Closed,
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+fn force_to_fixed(force: f64) -> u16 {
+    (force / FORCE_LSB).round().max(0.0).min(u16::MAX as f64) as u16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GripperState {
+    pub position: f64, // 0 closed to 1 fully open.
+    pub force: f64,    // N.
+    pub moving: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grasp {
+    pub position: f64,
+    pub force: f64,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: GripperState,
+    // Outcome of the last close: Some(None) when it closed on nothing.
+    outcome: Option<Option<Grasp>>,
+    waker: Option<Waker>,
+}
+
+// Telemetry side of a gripper, fed from the receive loop. Clones share the
+// state with the GripperClient that created it.
+#[derive(Clone)]
+pub struct GripperMonitor {
+    shared: Arc<(Mutex<Shared>, Condvar)>,
+}
+
+impl GripperMonitor {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn state(&self) -> GripperState {
+        self.lock().state
+    }
+
+    pub fn handle(&self, msg: &GripperMessage) {
+        let mut s = self.lock();
+        let outcome = match *msg {
+            GripperMessage::State { position, force_cn, moving } => {
+                s.state = GripperState {
+                    position: position as f64 / POSITION_FULL as f64,
+                    force: force_cn as f64 * FORCE_LSB,
+                    moving,
+                };
+                return;
+            },
+            GripperMessage::GraspDetected { position, force_cn } => Some(Grasp {
+                position: position as f64 / POSITION_FULL as f64,
+                force: force_cn as f64 * FORCE_LSB,
+            }),
+            GripperMessage::Closed => None,
+            _ => return,
+        };
+        s.outcome = Some(outcome);
+        if let Some(w) = s.waker.take() {
+            w.wake();
+        }
+        self.shared.1.notify_all();
+    }
+}
+
+pub struct GripperClient<S> {
+    sender: S,
+    code: u8,
+    monitor: GripperMonitor,
+}
+
+impl<S: Sender> GripperClient<S> {
+    pub fn new(sender: S, code: u8) -> Self {
+        GripperClient {
+            sender,
+            code,
+            monitor: GripperMonitor { shared: Arc::new((Mutex::new(Shared::default()), Condvar::new())) },
+        }
+    }
+
+    pub fn monitor(&self) -> GripperMonitor {
+        self.monitor.clone()
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    pub fn open(&mut self) -> io::Result<()> {
+        self.send(GripperMessage::Open)
+    }
+
+    // position from 0 (closed) to 1 (fully open).
+    pub fn set_position(&mut self, position: f64) -> io::Result<()> {
+        let position = (position.clamp(0.0, 1.0) * POSITION_FULL as f64).round() as u16;
+        self.send(GripperMessage::SetPosition { position })
+    }
+
+    pub fn set_force(&mut self, force: f64) -> io::Result<()> {
+        self.send(GripperMessage::SetForce { force_cn: force_to_fixed(force) })
+    }
+
+    // Closes until force (N) is reached. The returned future resolves once
+    // the device reports the outcome, None if it closed on nothing.
+    pub fn close_until_force(&mut self, force: f64) -> io::Result<GraspFuture> {
+        self.monitor.lock().outcome = None;
+        self.send(GripperMessage::Close { force_cn: force_to_fixed(force) })?;
+        Ok(GraspFuture { monitor: self.monitor.clone() })
+    }
+
+    // Blocking variant of close_until_force, for callers that feed the
+    // monitor from another thread. Errors with TimedOut if the device does
+    // not report within timeout.
+    pub fn close_until_force_blocking(&mut self, force: f64, timeout: Duration) -> io::Result<Option<Grasp>> {
+        self.close_until_force(force)?;
+        let deadline = Instant::now() + timeout;
+        let (ref lock, ref cvar) = *self.monitor.shared;
+        let mut s = lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(outcome) = s.outcome {
+                return Ok(outcome);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no grasp report"));
+            }
+            s = cvar.wait_timeout(s, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+
+    fn send(&mut self, msg: GripperMessage) -> io::Result<()> {
+        self.sender.send(msg.to_packet(self.code))
+    }
+}
+
+pub struct GraspFuture {
+    monitor: GripperMonitor,
+}
+
+impl Future for GraspFuture {
+    type Output = Option<Grasp>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Grasp>> {
+        let mut s = self.monitor.lock();
+        match s.outcome {
+            Some(outcome) => Poll::Ready(outcome),
+            None => {
+                s.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::layout::{array, field, Layout, I16, U32};
+
+// Fixed-point scales of the wire encodings, all values are i16.
+pub const ACCEL_LSB: f64 = 0.001 * 9.80665; // 1 mg in m/s^2, +/-32 g.
+pub const GYRO_LSB: f64 = 0.001;            // rad/s, +/-32.7 rad/s.
+pub const MAG_LSB: f64 = 0.1;               // uT, +/-3276 uT.
+pub const QUAT_LSB: f64 = 1.0 / 16384.0;    // Q2.14 unit quaternion.
+
+const OP_MOTION: u8 = 0x01;
+const OP_MAG: u8 = 0x02;
+const OP_ORIENTATION: u8 = 0x03;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("motion", OP_MOTION, &[field("ts_ms", U32), array("accel", I16, 3), array("gyro", I16, 3)]),
+    Layout::new("mag", OP_MAG, &[field("ts_ms", U32), array("mag", I16, 3)]),
+    Layout::new("orientation", OP_ORIENTATION, &[field("ts_ms", U32), array("quat", I16, 4)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImuMessage {
+    Motion { ts_ms: u32, accel: [i16; 3], gyro: [i16; 3] },
+    Mag { ts_ms: u32, mag: [i16; 3] },
+    Orientation { ts_ms: u32, quat: [i16; 4] }, // w, x, y, z
+}
+
+fn put_vec(data: &mut Vec<u8>, v: &[i16]) {
+    for n in v {
+        put_i16(data, *n);
+    }
+}
+
+fn read_vec3(r: &mut Reader) -> Option<[i16; 3]> {
+    Some([r.i16()?, r.i16()?, r.i16()?])
+}
+
+impl Message for ImuMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            ImuMessage::Motion { ts_ms, accel, gyro } => {
+                data.push(OP_MOTION);
+                put_u32(data, ts_ms);
+                put_vec(data, &accel);
+                put_vec(data, &gyro);
+            },
+            ImuMessage::Mag { ts_ms, mag } => {
+                data.push(OP_MAG);
+                put_u32(data, ts_ms);
+                put_vec(data, &mag);
+            },
+            ImuMessage::Orientation { ts_ms, quat } => {
+                data.push(OP_ORIENTATION);
+                put_u32(data, ts_ms);
+                put_vec(data, &quat);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_MOTION => ImuMessage::Motion {
+                ts_ms: r.u32()?,
+                accel: read_vec3(&mut r)?,
+                gyro: read_vec3(&mut r)?,
+            },
+            OP_MAG => ImuMessage::Mag { ts_ms: r.u32()?, mag: read_vec3(&mut r)? },
+            OP_ORIENTATION => ImuMessage::Orientation {
+                ts_ms: r.u32()?,
+                quat: [r.i16()?, r.i16()?, r.i16()?, r.i16()?],
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Vec3 { x, y, z }
+    }
+
+    pub fn from_fixed(v: [i16; 3], lsb: f64) -> Self {
+        Vec3::new(v[0] as f64 * lsb, v[1] as f64 * lsb, v[2] as f64 * lsb)
+    }
+
+    pub fn to_fixed(&self, lsb: f64) -> [i16; 3] {
+        [to_fixed(self.x, lsb), to_fixed(self.y, lsb), to_fixed(self.z, lsb)]
+    }
+
+    pub fn norm(&self) -> f64 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+}
+
+fn to_fixed(v: f64, lsb: f64) -> i16 {
+    (v / lsb).round().max(i16::MIN as f64).min(i16::MAX as f64) as i16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    // Rotation by yaw radians about z.
+    pub fn from_yaw(yaw: f64) -> Self {
+        let (s, c) = (yaw / 2.0).sin_cos();
+        Quaternion { w: c, x: 0.0, y: 0.0, z: s }
+    }
+
+    pub fn from_fixed(q: [i16; 4]) -> Self {
+        Quaternion {
+            w: q[0] as f64 * QUAT_LSB,
+            x: q[1] as f64 * QUAT_LSB,
+            y: q[2] as f64 * QUAT_LSB,
+            z: q[3] as f64 * QUAT_LSB,
+        }
+    }
+
+    pub fn to_fixed(&self) -> [i16; 4] {
+        [
+            to_fixed(self.w, QUAT_LSB),
+            to_fixed(self.x, QUAT_LSB),
+            to_fixed(self.y, QUAT_LSB),
+            to_fixed(self.z, QUAT_LSB),
+        ]
+    }
+
+    // Rotation about x in radians.
+    pub fn roll(&self) -> f64 {
+        (2.0 * (self.w * self.x + self.y * self.z))
+            .atan2(1.0 - 2.0 * (self.x * self.x + self.y * self.y))
+    }
+
+    pub fn pitch(&self) -> f64 {
+        (2.0 * (self.w * self.y - self.z * self.x)).clamp(-1.0, 1.0).asin()
+    }
+
+    pub fn yaw(&self) -> f64 {
+        (2.0 * (self.w * self.z + self.x * self.y))
+            .atan2(1.0 - 2.0 * (self.y * self.y + self.z * self.z))
+    }
+}
+
+// Accelerations in m/s^2, angular rates in rad/s, ts is device time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuSample {
+    pub accel: Vec3,
+    pub gyro: Vec3,
+    pub ts: Duration,
+}
+
+impl ImuSample {
+    pub fn from_message(msg: &ImuMessage) -> Option<Self> {
+        match *msg {
+            ImuMessage::Motion { ts_ms, accel, gyro } => Some(ImuSample {
+                accel: Vec3::from_fixed(accel, ACCEL_LSB),
+                gyro: Vec3::from_fixed(gyro, GYRO_LSB),
+                ts: Duration::from_millis(ts_ms as u64),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_message(&self) -> ImuMessage {
+        ImuMessage::Motion {
+            ts_ms: self.ts.as_millis() as u32,
+            accel: self.accel.to_fixed(ACCEL_LSB),
+            gyro: self.gyro.to_fixed(GYRO_LSB),
+        }
+    }
+}
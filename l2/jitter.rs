@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Plays samples out on a steady clock, each delay after its device
+// timestamp as mapped to host time by the first sample, so a burst from a
+// lossy radio comes out at the rate it was sampled. Samples arriving out of
+// order are put back in order; one arriving after a later one has played is
+// dropped as late. Past max_len samples the oldest is dropped.
+pub struct JitterBuffer<T> {
+    delay: Duration,
+    max_len: usize,
+    queue: VecDeque<(Duration, T)>, // by device time.
+    base: Option<(Duration, Instant)>,
+    played: Option<Duration>,
+    late: u64,
+}
+
+impl<T> JitterBuffer<T> {
+    pub fn new(delay: Duration, max_len: usize) -> Self {
+        JitterBuffer {
+            delay,
+            max_len: max_len.max(1),
+            queue: VecDeque::new(),
+            base: None,
+            played: None,
+            late: 0,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    // Samples dropped for arriving too late.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    // Forgets the samples and the clock, as when the device restarts its
+    // timestamps.
+    pub fn reset(&mut self) {
+        self.queue.clear();
+        self.base = None;
+        self.played = None;
+    }
+
+    // ts is the sample's device timestamp, now when it arrived.
+    pub fn push_at(&mut self, ts: Duration, sample: T, now: Instant) {
+        if self.played.is_some_and(|p| ts <= p) {
+            self.late += 1;
+            return;
+        }
+        self.base.get_or_insert((ts, now));
+        let at = self.queue.iter().rposition(|s| s.0 <= ts).map_or(0, |i| i + 1);
+        self.queue.insert(at, (ts, sample));
+        if self.queue.len() > self.max_len {
+            if let Some((ts, _)) = self.queue.pop_front() {
+                self.played = Some(ts);
+            }
+        }
+    }
+
+    pub fn push(&mut self, ts: Duration, sample: T) {
+        self.push_at(ts, sample, Instant::now())
+    }
+
+    // When the sample at ts plays, once the clock is set.
+    pub fn playout(&self, ts: Duration) -> Option<Instant> {
+        self.base.map(|(t0, at)| match ts.checked_sub(t0) {
+            Some(d) => at + d + self.delay,
+            None => at + self.delay - (t0 - ts).min(self.delay),
+        })
+    }
+
+    // The next sample due by now, with its timestamp.
+    pub fn pop_at(&mut self, now: Instant) -> Option<(Duration, T)> {
+        let ts = self.queue.front()?.0;
+        if self.playout(ts).is_none_or(|t| t > now) {
+            return None;
+        }
+        self.played = Some(ts);
+        self.queue.pop_front()
+    }
+
+    pub fn pop(&mut self) -> Option<(Duration, T)> {
+        self.pop_at(Instant::now())
+    }
+}
@@ -0,0 +1,87 @@
+// Payload layouts of the messages, for generating code in other languages.
+// Every message starts with its op byte, which is not listed; the fields
+// follow packed and little-endian.
+
+pub use self::FieldType::{I16, I32, I8, U16, U32, U8};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    U8, // also bools and enums.
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    Struct(&'static str, &'static [Field]),
+}
+
+impl FieldType {
+    pub fn size(&self) -> usize {
+        match *self {
+            FieldType::U8 | FieldType::I8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 => 4,
+            FieldType::Struct(_, fields) => fields.iter().map(|f| f.size()).sum(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Count {
+    One,
+    Fixed(usize),
+    Rest, // as many as the payload holds, only for the last field.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub ty: FieldType,
+    pub count: Count,
+}
+
+impl Field {
+    // The size of a Rest field with no elements is 0.
+    pub fn size(&self) -> usize {
+        match self.count {
+            Count::One => self.ty.size(),
+            Count::Fixed(n) => n * self.ty.size(),
+            Count::Rest => 0,
+        }
+    }
+}
+
+pub const fn field(name: &'static str, ty: FieldType) -> Field {
+    Field { name, ty, count: Count::One }
+}
+
+pub const fn array(name: &'static str, ty: FieldType, n: usize) -> Field {
+    Field { name, ty, count: Count::Fixed(n) }
+}
+
+pub const fn rest(name: &'static str, ty: FieldType) -> Field {
+    Field { name, ty, count: Count::Rest }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub name: &'static str,
+    pub op: u8,
+    pub fields: &'static [Field],
+}
+
+impl Layout {
+    pub const fn new(name: &'static str, op: u8, fields: &'static [Field]) -> Self {
+        Layout { name, op, fields }
+    }
+
+    // Payload length including the op byte, without any Rest elements.
+    pub fn min_len(&self) -> usize {
+        1 + self.fields.iter().map(|f| f.size()).sum::<usize>()
+    }
+
+    // The Rest field, if the length varies.
+    pub fn rest(&self) -> Option<&Field> {
+        self.fields.last().filter(|f| f.count == Count::Rest)
+    }
+}
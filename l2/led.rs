@@ -0,0 +1,204 @@
+use l0::comm::PACKET_MAX_DATA_LEN;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, rest, FieldType, Layout, U16, U8};
+
+const OP_SET_COLOR: u8 = 0x01;
+const OP_FRAME: u8 = 0x02;
+const OP_ANIMATE: u8 = 0x03;
+
+const RGB: FieldType = FieldType::Struct("rgb", &[field("r", U8), field("g", U8), field("b", U8)]);
+const RUN: FieldType = FieldType::Struct("run", &[field("count", U8), field("color", RGB)]);
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("set_color", OP_SET_COLOR, &[field("strip", U8), field("color", RGB)]),
+    Layout::new("frame", OP_FRAME, &[field("strip", U8), field("offset", U16), rest("runs", RUN)]),
+    Layout::new("animate", OP_ANIMATE, &[field("strip", U8), field("animation", U8), field("color", RGB), field("period_ms", U16)]),
+];
+
+// op, strip and offset precede the runs of a frame.
+const FRAME_HEADER_LEN: usize = 4;
+const RUN_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    pub fn off() -> Self {
+        Rgb::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Animation {
+    Off,
+    Blink,
+    Breathe,
+    Chase,
+}
+
+impl Animation {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Animation::Off),
+            1 => Some(Animation::Blink),
+            2 => Some(Animation::Breathe),
+            3 => Some(Animation::Chase),
+            _ => None,
+        }
+    }
+}
+
+// A run of `count` consecutive pixels with the same color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    pub count: u8,
+    pub color: Rgb,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedMessage {
+    SetColor { strip: u8, color: Rgb },               // fill the whole strip.
+    Frame { strip: u8, offset: u16, runs: Vec<Run> }, // pixels starting at offset.
+    Animate { strip: u8, animation: Animation, color: Rgb, period_ms: u16 },
+}
+
+fn put_rgb(data: &mut Vec<u8>, c: Rgb) {
+    data.push(c.r);
+    data.push(c.g);
+    data.push(c.b);
+}
+
+fn read_rgb(r: &mut Reader) -> Option<Rgb> {
+    Some(Rgb::new(r.u8()?, r.u8()?, r.u8()?))
+}
+
+impl Message for LedMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            LedMessage::SetColor { strip, color } => {
+                data.push(OP_SET_COLOR);
+                data.push(strip);
+                put_rgb(data, color);
+            },
+            LedMessage::Frame { strip, offset, ref runs } => {
+                data.push(OP_FRAME);
+                data.push(strip);
+                put_u16(data, offset);
+                for run in runs {
+                    data.push(run.count);
+                    put_rgb(data, run.color);
+                }
+            },
+            LedMessage::Animate { strip, animation, color, period_ms } => {
+                data.push(OP_ANIMATE);
+                data.push(strip);
+                data.push(animation as u8);
+                put_rgb(data, color);
+                put_u16(data, period_ms);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_SET_COLOR => LedMessage::SetColor { strip: r.u8()?, color: read_rgb(&mut r)? },
+            OP_FRAME => {
+                let strip = r.u8()?;
+                let offset = r.u16()?;
+                let mut runs = Vec::new();
+                while !r.is_empty() {
+                    runs.push(Run { count: r.u8()?, color: read_rgb(&mut r)? });
+                }
+                LedMessage::Frame { strip, offset, runs }
+            },
+            OP_ANIMATE => LedMessage::Animate {
+                strip: r.u8()?,
+                animation: Animation::from_u8(r.u8()?)?,
+                color: read_rgb(&mut r)?,
+                period_ms: r.u16()?,
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Builds a full strip frame on the host and compresses it into as few
+// Frame messages as fit in a packet each.
+pub struct FrameBuilder {
+    strip: u8,
+    pixels: Vec<Rgb>,
+}
+
+impl FrameBuilder {
+    pub fn new(strip: u8, len: usize) -> Self {
+        FrameBuilder {
+            strip,
+            pixels: vec![Rgb::off(); len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    pub fn set(&mut self, index: usize, color: Rgb) -> &mut Self {
+        if let Some(p) = self.pixels.get_mut(index) {
+            *p = color;
+        }
+        self
+    }
+
+    pub fn fill(&mut self, color: Rgb) -> &mut Self {
+        for p in self.pixels.iter_mut() {
+            *p = color;
+        }
+        self
+    }
+
+    pub fn pixels(&self) -> &[Rgb] {
+        &self.pixels
+    }
+
+    pub fn build(&self) -> Vec<LedMessage> {
+        let max_runs = (PACKET_MAX_DATA_LEN - FRAME_HEADER_LEN) / RUN_LEN;
+        let mut msgs = Vec::new();
+        let mut runs: Vec<Run> = Vec::new();
+        let mut offset = 0;
+        for (pos, color) in self.pixels.iter().enumerate() {
+            let extend = match runs.last_mut() {
+                Some(run) if run.color == *color && run.count < 0xff => {
+                    run.count += 1;
+                    true
+                },
+                _ => false,
+            };
+            if !extend {
+                if runs.len() >= max_runs {
+                    msgs.push(LedMessage::Frame { strip: self.strip, offset: offset as u16, runs });
+                    runs = Vec::new();
+                    offset = pos;
+                }
+                runs.push(Run { count: 1, color: *color });
+            }
+        }
+        if !runs.is_empty() {
+            msgs.push(LedMessage::Frame { strip: self.strip, offset: offset as u16, runs });
+        }
+        msgs
+    }
+}
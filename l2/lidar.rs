@@ -0,0 +1,209 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+use l0::comm::PACKET_MAX_DATA_LEN;
+use super::Message;
+use super::codec::*;
+use super::fragment::*;
+use super::layout::{field, Layout, U16};
+use super::fragment::FRAGMENT;
+
+pub const ANGLE_LSB: f64 = PI / 18000.0; // rad, 0.01 degree.
+
+const OP_START: u8 = 0x01;
+const OP_STOP: u8 = 0x02;
+const OP_SEGMENT: u8 = 0x03;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("start", OP_START, &[field("rpm", U16)]),
+    Layout::new("stop", OP_STOP, &[]),
+    Layout::new("segment", OP_SEGMENT, FRAGMENT),
+];
+
+// start, increment and range count precede the ranges of a segment.
+const SEGMENT_HEADER_LEN: usize = 5;
+pub const MAX_SEGMENT_RANGES: usize = (PACKET_MAX_DATA_LEN - 1 - FRAGMENT_HEADER_LEN - SEGMENT_HEADER_LEN) / 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LidarMessage {
+    Start { rpm: u16 },
+    Stop,
+    // A segment of a scan; the fragment id is the scan number and the data
+    // a Segment.
+    Segment(Fragment),
+}
+
+impl Message for LidarMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            LidarMessage::Start { rpm } => {
+                data.push(OP_START);
+                put_u16(data, rpm);
+            },
+            LidarMessage::Stop => data.push(OP_STOP),
+            LidarMessage::Segment(ref frag) => {
+                data.push(OP_SEGMENT);
+                frag.encode(data);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_START => LidarMessage::Start { rpm: r.u16()? },
+            OP_STOP => LidarMessage::Stop,
+            OP_SEGMENT => LidarMessage::Segment(Fragment::decode(&mut r)?),
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Consecutive ranges in mm starting at start_cdeg, 0 when there was no
+// return. Angles are in 0.01 degree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub start_cdeg: u16,
+    pub increment_cdeg: u16,
+    pub ranges_mm: Vec<u16>,
+}
+
+impl Segment {
+    pub fn encode(&self, data: &mut Vec<u8>) {
+        put_u16(data, self.start_cdeg);
+        put_u16(data, self.increment_cdeg);
+        data.push(self.ranges_mm.len() as u8);
+        for r in &self.ranges_mm {
+            put_u16(data, *r);
+        }
+    }
+
+    pub fn decode(r: &mut Reader) -> Option<Self> {
+        let start_cdeg = r.u16()?;
+        let increment_cdeg = r.u16()?;
+        let n = r.u8()?;
+        let ranges_mm = (0..n).map(|_| r.u16()).collect::<Option<_>>()?;
+        Some(Segment { start_cdeg, increment_cdeg, ranges_mm })
+    }
+}
+
+// Splits the segments of scan number seq into messages.
+pub fn scan_messages(seq: u16, segments: &[Segment]) -> Vec<LidarMessage> {
+    segments.iter().enumerate()
+        .map(|(i, s)| {
+            let mut data = Vec::new();
+            s.encode(&mut data);
+            LidarMessage::Segment(Fragment { id: seq, index: i as u16, count: segments.len() as u16, data })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanStats {
+    pub valid: usize,
+    pub invalid: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+// A full revolution. Ranges are in meters, NaN where there was no return.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaserScan {
+    pub seq: u16,
+    pub angle_min: f64,
+    pub angle_increment: f64,
+    pub ranges: Vec<f64>,
+    pub ts: Duration,             // host time the scan completed.
+    pub period: Option<Duration>, // since the previous scan.
+}
+
+impl LaserScan {
+    fn from_segments(seq: u16, segments: &[Segment], ts: Duration) -> Option<Self> {
+        let first = segments.first()?;
+        let inc = first.increment_cdeg.max(1) as u32;
+        let mut ranges = Vec::new();
+        for s in segments {
+            // segments continue from the first start, wrapping past 360.
+            let offset = (s.start_cdeg as u32 + 36000 - first.start_cdeg as u32) % 36000;
+            let index = (offset / inc) as usize;
+            if ranges.len() < index + s.ranges_mm.len() {
+                ranges.resize(index + s.ranges_mm.len(), f64::NAN);
+            }
+            for (i, r) in s.ranges_mm.iter().enumerate() {
+                ranges[index + i] = if *r == 0 { f64::NAN } else { *r as f64 / 1000.0 };
+            }
+        }
+        Some(LaserScan {
+            seq,
+            angle_min: first.start_cdeg as f64 * ANGLE_LSB,
+            angle_increment: inc as f64 * ANGLE_LSB,
+            ranges,
+            ts,
+            period: None,
+        })
+    }
+
+    pub fn angle(&self, i: usize) -> f64 {
+        self.angle_min + i as f64 * self.angle_increment
+    }
+
+    pub fn stats(&self) -> ScanStats {
+        let valid: Vec<f64> = self.ranges.iter().cloned().filter(|r| !r.is_nan()).collect();
+        let (min, max, sum) = valid.iter()
+            .fold((f64::INFINITY, 0.0f64, 0.0), |(lo, hi, sum), r| (lo.min(*r), hi.max(*r), sum + r));
+        ScanStats {
+            valid: valid.len(),
+            invalid: self.ranges.len() - valid.len(),
+            min,
+            max,
+            mean: if valid.is_empty() { f64::NAN } else { sum / valid.len() as f64 },
+        }
+    }
+}
+
+// Assembles scans from segment messages. An incomplete scan is dropped
+// once a newer one completes.
+pub struct ScanReceiver {
+    reassembler: Reassembler,
+    last: Option<Duration>,
+}
+
+impl Default for ScanReceiver {
+    fn default() -> Self {
+        ScanReceiver::new()
+    }
+}
+
+impl ScanReceiver {
+    pub fn new() -> Self {
+        ScanReceiver { reassembler: Reassembler::new(2), last: None }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.reassembler.dropped()
+    }
+
+    pub fn push(&mut self, msg: &LidarMessage, ts: Duration) -> Option<LaserScan> {
+        match *msg {
+            LidarMessage::Segment(ref frag) => {
+                let (seq, payload) = self.reassembler.push(frag.clone())?;
+                let mut r = Reader::new(&payload);
+                let mut segments = Vec::new();
+                while !r.is_empty() {
+                    segments.push(Segment::decode(&mut r)?);
+                }
+                let mut scan = LaserScan::from_segments(seq, &segments, ts)?;
+                scan.period = self.last.map(|last| ts.saturating_sub(last));
+                self.last = Some(ts);
+                Some(scan)
+            },
+            LidarMessage::Stop => {
+                self.reassembler.reset();
+                self.last = None;
+                None
+            },
+            _ => None,
+        }
+    }
+}
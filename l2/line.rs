@@ -0,0 +1,147 @@
+use super::Message;
+use super::codec::*;
+use super::layout::{field, rest, Layout, U8};
+
+const OP_READING: u8 = 0x01;
+
+pub const LAYOUTS: &[Layout] = &[
+    // samples are packed bits at a time, least significant first.
+    Layout::new("reading", OP_READING, &[field("bits", U8), field("count", U8), rest("packed", U8)]),
+];
+
+// Reflectance of each sensor in the array, ordered left to right, each
+// sample `bits` wide (4 to 8) and packed LSB first. Bits outside that are
+// clamped into it, and only the first 255 samples are sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMessage {
+    pub bits: u8,
+    pub samples: Vec<u8>,
+}
+
+impl LineMessage {
+    pub fn max_sample(&self) -> u8 {
+        (0xffu16 >> (8 - self.wire_bits())) as u8
+    }
+
+    fn wire_bits(&self) -> u8 {
+        self.bits.clamp(4, 8)
+    }
+}
+
+impl Message for LineMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        let bits = self.wire_bits();
+        let count = self.samples.len().min(u8::MAX as usize);
+        data.push(OP_READING);
+        data.push(bits);
+        data.push(count as u8);
+        let mut acc = 0u16;
+        let mut n = 0;
+        for s in &self.samples[..count] {
+            acc |= ((*s & self.max_sample()) as u16) << n;
+            n += bits;
+            if n >= 8 {
+                data.push(acc as u8);
+                acc >>= 8;
+                n -= 8;
+            }
+        }
+        if n > 0 {
+            data.push(acc as u8);
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        if r.u8()? != OP_READING {
+            return None;
+        }
+        let bits = r.u8()?;
+        if !(4..=8).contains(&bits) {
+            return None;
+        }
+        let count = r.u8()? as usize;
+        let packed = r.bytes((count * bits as usize).div_ceil(8))?;
+        let mask = (0xffu16 >> (8 - bits)) as u8;
+        let mut samples = Vec::with_capacity(count);
+        let mut acc = 0u16;
+        let mut n = 0;
+        let mut bytes = packed.iter();
+        while samples.len() < count {
+            if n < bits {
+                acc |= (*bytes.next()? as u16) << n;
+                n += 8;
+            }
+            samples.push(acc as u8 & mask);
+            acc >>= bits;
+            n -= bits;
+        }
+        r.finish(LineMessage { bits, samples })
+    }
+}
+
+// Estimates where the line is under the array as the weighted centroid of
+// the sensor readings: -1 under the leftmost sensor, 1 under the rightmost.
+pub struct LineEstimator {
+    threshold: f64,
+    light_line: bool,
+    last: Option<f64>,
+}
+
+impl Default for LineEstimator {
+    fn default() -> Self {
+        LineEstimator::new()
+    }
+}
+
+impl LineEstimator {
+    pub fn new() -> Self {
+        LineEstimator {
+            threshold: 0.2,
+            light_line: false,
+            last: None,
+        }
+    }
+
+    // Fraction of full scale below which a sensor is not over the line.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    // For a line more reflective than the floor.
+    pub fn with_light_line(mut self) -> Self {
+        self.light_line = true;
+        self
+    }
+
+    // The last position seen, which tells which side the line was lost on.
+    pub fn last(&self) -> Option<f64> {
+        self.last
+    }
+
+    // None when no sensor sees the line.
+    pub fn update(&mut self, msg: &LineMessage) -> Option<f64> {
+        let max = msg.max_sample() as f64;
+        let n = msg.samples.len();
+        let (mut sum, mut weighted) = (0.0, 0.0);
+        for (i, s) in msg.samples.iter().enumerate() {
+            let mut v = *s as f64 / max;
+            if !self.light_line {
+                v = 1.0 - v; // dark lines reflect less.
+            }
+            if v < self.threshold {
+                continue;
+            }
+            let x = if n > 1 { i as f64 * 2.0 / (n - 1) as f64 - 1.0 } else { 0.0 };
+            sum += v;
+            weighted += v * x;
+        }
+        if sum == 0.0 {
+            return None;
+        }
+        let pos = weighted / sum;
+        self.last = Some(pos);
+        Some(pos)
+    }
+}
@@ -0,0 +1,78 @@
+use std::io;
+use l0::comm::{Endpoint, Packet};
+
+pub mod codec;
+
+pub mod adc;
+pub mod calibration;
+pub mod arm;
+pub mod audio;
+pub mod batch;
+pub mod camera;
+pub mod encoder;
+pub mod env;
+pub mod fragment;
+pub mod gnss;
+pub mod gripper;
+pub mod imu;
+pub mod jitter;
+pub mod layout;
+pub mod led;
+pub mod lidar;
+pub mod line;
+pub mod mode;
+pub mod motor;
+pub mod power;
+pub mod queue;
+pub mod range;
+pub mod reliable;
+pub mod schema;
+pub mod sound;
+pub mod stepper;
+pub mod touch;
+
+// An L2 message occupies the payload of a single packet. The packet code is
+// assigned by the device profile, and the first payload byte selects the
+// message within its set.
+pub trait Message: Sized {
+    fn encode(&self, data: &mut Vec<u8>);
+    fn decode(data: &[u8]) -> Option<Self>;
+
+    fn to_packet(&self, code: u8) -> Packet {
+        let mut pkt = Packet::new_with(0, code);
+        self.encode(&mut pkt.data);
+        pkt
+    }
+
+    fn from_packet(pkt: &Packet) -> Option<Self> {
+        Self::decode(&pkt.data)
+    }
+}
+
+// Delivers packets to the device. Assigning sequence numbers is up to the
+// implementation.
+pub trait Sender {
+    fn send(&mut self, pkt: Packet) -> io::Result<()>;
+}
+
+impl Sender for Vec<Packet> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.push(pkt);
+        Ok(())
+    }
+}
+
+impl<T: io::Read + io::Write> Sender for Endpoint<T> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        Endpoint::send(self, pkt)
+    }
+}
+
+impl<S: Sender + ?Sized> Sender for &mut S {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        (**self).send(pkt)
+    }
+}
+
+#[cfg(test)]
+mod tests;
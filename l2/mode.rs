@@ -0,0 +1,171 @@
+use super::Message;
+use super::codec::*;
+use super::layout::{field, Layout, U8};
+
+const OP_REQUEST: u8 = 0x01;
+const OP_CHANGED: u8 = 0x02;
+const OP_REJECTED: u8 = 0x03;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("request", OP_REQUEST, &[field("mode", U8)]),
+    Layout::new("changed", OP_CHANGED, &[field("from", U8), field("to", U8), field("reason", U8)]),
+    Layout::new("rejected", OP_REJECTED, &[field("mode", U8), field("current", U8)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Disabled,
+    Teleop,
+    Autonomous,
+    Estop,
+    Fault,
+}
+
+impl Mode {
+    fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Mode::Disabled),
+            1 => Some(Mode::Teleop),
+            2 => Some(Mode::Autonomous),
+            3 => Some(Mode::Estop),
+            4 => Some(Mode::Fault),
+            _ => None,
+        }
+    }
+
+    // Transitions the standard state machine allows. Any mode can drop to
+    // Estop or Fault, and those only lead back to Disabled; driving modes
+    // are entered from Disabled or each other.
+    pub fn can_transition(self, to: Mode) -> bool {
+        match (self, to) {
+            (from, to) if from == to => false,
+            (_, Mode::Estop) | (_, Mode::Fault) => true,
+            (Mode::Estop, to) | (Mode::Fault, to) => to == Mode::Disabled,
+            _ => true,
+        }
+    }
+
+    pub fn is_driving(self) -> bool {
+        self == Mode::Teleop || self == Mode::Autonomous
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeMessage {
+    // Host request, answered by Changed or Rejected.
+    Request { mode: Mode },
+    // Device announcement of a transition, including ones it initiated.
+    Changed { from: Mode, to: Mode, reason: u8 },
+    Rejected { mode: Mode, current: Mode },
+}
+
+impl Message for ModeMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            ModeMessage::Request { mode } => {
+                data.push(OP_REQUEST);
+                data.push(mode as u8);
+            },
+            ModeMessage::Changed { from, to, reason } => {
+                data.push(OP_CHANGED);
+                data.push(from as u8);
+                data.push(to as u8);
+                data.push(reason);
+            },
+            ModeMessage::Rejected { mode, current } => {
+                data.push(OP_REJECTED);
+                data.push(mode as u8);
+                data.push(current as u8);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_REQUEST => ModeMessage::Request { mode: Mode::from_u8(r.u8()?)? },
+            OP_CHANGED => ModeMessage::Changed {
+                from: Mode::from_u8(r.u8()?)?,
+                to: Mode::from_u8(r.u8()?)?,
+                reason: r.u8()?,
+            },
+            OP_REJECTED => ModeMessage::Rejected {
+                mode: Mode::from_u8(r.u8()?)?,
+                current: Mode::from_u8(r.u8()?)?,
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+type TransitionCallback = Box<dyn FnMut(Mode, Mode, u8) + Send>;
+
+// Mirrors the device mode from its announcements and notifies callbacks
+// registered for specific transitions.
+pub struct ModeObserver {
+    mode: Mode,
+    callbacks: Vec<(Option<Mode>, Option<Mode>, TransitionCallback)>,
+    rejected: Option<Mode>,
+}
+
+impl Default for ModeObserver {
+    fn default() -> Self {
+        ModeObserver::new()
+    }
+}
+
+impl ModeObserver {
+    pub fn new() -> Self {
+        ModeObserver {
+            mode: Mode::Disabled,
+            callbacks: Vec::new(),
+            rejected: None,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // The last requested mode the device refused, cleared by a change.
+    pub fn rejected(&self) -> Option<Mode> {
+        self.rejected
+    }
+
+    // Called with from, to and reason on transitions matching from and to,
+    // None matches any mode.
+    pub fn on_transition<F>(&mut self, from: Option<Mode>, to: Option<Mode>, callback: F)
+        where F: FnMut(Mode, Mode, u8) + Send + 'static {
+        self.callbacks.push((from, to, Box::new(callback)));
+    }
+
+    // A request message for mode, or None if the state machine won't allow
+    // the transition from the current mode.
+    pub fn request(&self, mode: Mode) -> Option<ModeMessage> {
+        if self.mode.can_transition(mode) {
+            Some(ModeMessage::Request { mode })
+        } else {
+            None
+        }
+    }
+
+    pub fn handle(&mut self, msg: &ModeMessage) {
+        match *msg {
+            ModeMessage::Changed { from, to, reason } => {
+                self.mode = to;
+                self.rejected = None;
+                for &mut (f, t, ref mut cb) in self.callbacks.iter_mut() {
+                    if f.is_none_or(|f| f == from) && t.is_none_or(|t| t == to) {
+                        cb(from, to, reason);
+                    }
+                }
+            },
+            ModeMessage::Rejected { mode, current } => {
+                self.mode = current;
+                self.rejected = Some(mode);
+            },
+            ModeMessage::Request { .. } => (),
+        }
+    }
+}
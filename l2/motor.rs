@@ -0,0 +1,63 @@
+use super::Message;
+use super::codec::*;
+use super::layout::{field, rest, Layout, I16, U8};
+
+pub const VELOCITY_LSB: f64 = 0.01; // rad/s, +/-327 rad/s.
+
+const OP_SET_VELOCITIES: u8 = 0x01;
+const OP_STOP: u8 = 0x02;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("set_velocities", OP_SET_VELOCITIES, &[field("first", U8), rest("velocities", I16)]),
+    Layout::new("stop", OP_STOP, &[]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MotorMessage {
+    // Velocities of consecutive motors starting at `first`, applied together.
+    SetVelocities { first: u8, velocities: Vec<i16> },
+    Stop, // stop all motors.
+}
+
+impl MotorMessage {
+    pub fn set_velocities(first: u8, velocities: &[f64]) -> Self {
+        MotorMessage::SetVelocities {
+            first,
+            velocities: velocities.iter()
+                .map(|v| (v / VELOCITY_LSB).round().max(i16::MIN as f64).min(i16::MAX as f64) as i16)
+                .collect(),
+        }
+    }
+}
+
+impl Message for MotorMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            MotorMessage::SetVelocities { first, ref velocities } => {
+                data.push(OP_SET_VELOCITIES);
+                data.push(first);
+                for v in velocities {
+                    put_i16(data, *v);
+                }
+            },
+            MotorMessage::Stop => data.push(OP_STOP),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_SET_VELOCITIES => {
+                let first = r.u8()?;
+                let mut velocities = Vec::new();
+                while !r.is_empty() {
+                    velocities.push(r.i16()?);
+                }
+                MotorMessage::SetVelocities { first, velocities }
+            },
+            OP_STOP => MotorMessage::Stop,
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
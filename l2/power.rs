@@ -0,0 +1,163 @@
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, Layout, I16, U16, U8};
+
+const OP_STATUS: u8 = 0x01;
+const OP_THRESHOLD: u8 = 0x02;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("status", OP_STATUS, &[field("voltage_mv", U16), field("current_ma", I16), field("soc_pct", U8)]),
+    Layout::new("threshold_crossed", OP_THRESHOLD, &[field("threshold_mv", U16), field("voltage_mv", U16), field("rising", U8)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerMessage {
+    // Positive current is discharge, negative is charging.
+    Status { voltage_mv: u16, current_ma: i16, soc_pct: u8 },
+    // Device-side threshold (in mV) crossed, rising or falling.
+    ThresholdCrossed { threshold_mv: u16, voltage_mv: u16, rising: bool },
+}
+
+impl Message for PowerMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            PowerMessage::Status { voltage_mv, current_ma, soc_pct } => {
+                data.push(OP_STATUS);
+                put_u16(data, voltage_mv);
+                put_i16(data, current_ma);
+                data.push(soc_pct);
+            },
+            PowerMessage::ThresholdCrossed { threshold_mv, voltage_mv, rising } => {
+                data.push(OP_THRESHOLD);
+                put_u16(data, threshold_mv);
+                put_u16(data, voltage_mv);
+                data.push(rising as u8);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_STATUS => PowerMessage::Status {
+                voltage_mv: r.u16()?,
+                current_ma: r.i16()?,
+                soc_pct: r.u8()?,
+            },
+            OP_THRESHOLD => PowerMessage::ThresholdCrossed {
+                threshold_mv: r.u16()?,
+                voltage_mv: r.u16()?,
+                rising: r.u8()? != 0,
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BatteryState {
+    pub voltage: f64, // volts
+    pub current: f64, // amps, positive is discharge
+    pub soc: f64,     // state of charge in percent
+    pub ts: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crossing {
+    Falling,
+    Rising,
+}
+
+type ThresholdCallback = Box<dyn FnMut(&BatteryState, Crossing) + Send>;
+
+struct Threshold {
+    soc: f64,
+    callback: ThresholdCallback,
+}
+
+// Tracks battery status telemetry, estimating the remaining runtime from a
+// low-pass filtered discharge current.
+pub struct BatteryMonitor {
+    capacity_ah: f64,
+    alpha: f64,
+    state: Option<BatteryState>,
+    avg_current: f64,
+    thresholds: Vec<Threshold>,
+}
+
+// Average current, in amps, below which the battery counts as idle: the
+// resolution of PowerMessage::Status.
+const IDLE_CURRENT: f64 = 0.001;
+
+impl BatteryMonitor {
+    pub fn new(capacity_ah: f64) -> Self {
+        BatteryMonitor {
+            capacity_ah,
+            alpha: 0.1,
+            state: None,
+            avg_current: 0.0,
+            thresholds: Vec::new(),
+        }
+    }
+
+    // Filter factor for the current average, 1.0 disables filtering.
+    pub fn with_filter(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    // Invokes `callback` whenever the state of charge crosses `soc` percent.
+    pub fn on_threshold<F>(&mut self, soc: f64, callback: F)
+        where F: FnMut(&BatteryState, Crossing) + Send + 'static {
+        self.thresholds.push(Threshold { soc, callback: Box::new(callback) });
+    }
+
+    pub fn update(&mut self, msg: &PowerMessage, ts: Duration) {
+        if let PowerMessage::Status { voltage_mv, current_ma, soc_pct } = *msg {
+            self.update_state(BatteryState {
+                voltage: voltage_mv as f64 / 1000.0,
+                current: current_ma as f64 / 1000.0,
+                soc: soc_pct as f64,
+                ts,
+            });
+        }
+    }
+
+    pub fn update_state(&mut self, state: BatteryState) {
+        match self.state {
+            Some(prev) => {
+                self.avg_current += self.alpha * (state.current - self.avg_current);
+                for t in self.thresholds.iter_mut() {
+                    if prev.soc >= t.soc && state.soc < t.soc {
+                        (t.callback)(&state, Crossing::Falling);
+                    } else if prev.soc < t.soc && state.soc >= t.soc {
+                        (t.callback)(&state, Crossing::Rising);
+                    }
+                }
+            },
+            None => self.avg_current = state.current,
+        }
+        self.state = Some(state);
+    }
+
+    pub fn state(&self) -> Option<&BatteryState> {
+        self.state.as_ref()
+    }
+
+    pub fn average_current(&self) -> f64 {
+        self.avg_current
+    }
+
+    // None while charging or idle, including while the average decays
+    // toward zero after the load stops.
+    pub fn remaining_runtime(&self) -> Option<Duration> {
+        let state = self.state?;
+        if self.avg_current < IDLE_CURRENT {
+            return None;
+        }
+        let hours = self.capacity_ah * state.soc / 100.0 / self.avg_current;
+        Duration::try_from_secs_f64(hours * 3600.0).ok()
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::mpsc;
+use l0::comm::Packet;
+use super::Sender;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    Urgent, // safety traffic, always first.
+}
+
+const PRIORITIES: usize = 4;
+
+// What a code's packets are owed under congestion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QosClass {
+    Reliable,   // commands: kept, in order, and retried.
+    BestEffort, // dropped, oldest first, when the queue is full.
+    LatestOnly, // telemetry: a new packet replaces the one queued.
+}
+
+// Classes by code, for TxQueue and the reliable layer. Codes not in
+// classes are default. Past capacity queued packets, a TxQueue drops
+// what isn't Reliable to make room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QosConfig {
+    pub classes: HashMap<u8, QosClass>,
+    pub default: QosClass,
+    pub capacity: usize,
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        QosConfig { classes: HashMap::new(), default: QosClass::Reliable, capacity: usize::MAX }
+    }
+}
+
+impl QosConfig {
+    pub fn class(&self, code: u8) -> QosClass {
+        self.classes.get(&code).cloned().unwrap_or(self.default)
+    }
+}
+
+// Outgoing packets ordered by priority, FIFO within a priority. Sending
+// through the Sender impl queues at Normal priority.
+#[derive(Default)]
+pub struct TxQueue {
+    queues: [VecDeque<Packet>; PRIORITIES],
+    qos: QosConfig,
+    dropped: u64,
+}
+
+impl TxQueue {
+    pub fn new() -> Self {
+        TxQueue::default()
+    }
+
+    pub fn with_qos(qos: QosConfig) -> Self {
+        TxQueue { qos, ..TxQueue::default() }
+    }
+
+    pub fn qos(&self) -> &QosConfig {
+        &self.qos
+    }
+
+    // Packets dropped or replaced for their class.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn push(&mut self, pkt: Packet, prio: Priority) {
+        if self.qos.class(pkt.code) == QosClass::LatestOnly {
+            let before = self.len();
+            self.retain(|p| p.code != pkt.code);
+            self.dropped += (before - self.len()) as u64;
+        }
+        self.queues[prio as usize].push_back(pkt);
+        if self.len() > self.qos.capacity {
+            self.shed();
+        }
+    }
+
+    // Drops the oldest packet that isn't Reliable, from the lowest
+    // priority up.
+    fn shed(&mut self) {
+        let qos = &self.qos;
+        for q in self.queues.iter_mut() {
+            if let Some(i) = q.iter().position(|p| qos.class(p.code) != QosClass::Reliable) {
+                q.remove(i);
+                self.dropped += 1;
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Packet> {
+        self.queues.iter_mut().rev().filter_map(|q| q.pop_front()).next()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(|q| q.is_empty())
+    }
+
+    pub fn len_of(&self, prio: Priority) -> usize {
+        self.queues[prio as usize].len()
+    }
+
+    // Keeps only the queued packets for which f returns true.
+    pub fn retain<F: FnMut(&Packet) -> bool>(&mut self, mut f: F) {
+        for q in self.queues.iter_mut() {
+            q.retain(&mut f);
+        }
+    }
+
+    // Sends queued packets in priority order until empty or an error.
+    pub fn flush_to<S: Sender>(&mut self, sender: &mut S) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(pkt) = self.pop() {
+            sender.send(pkt)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Sender for TxQueue {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.push(pkt, Priority::Normal);
+        Ok(())
+    }
+}
+
+// Creates an outgoing queue that any number of threads, such as teleop,
+// the mission engine and the safety layer, push to through clones of the
+// TxSender, while the thread owning the link drains the TxReceiver. Each
+// priority is a std channel, whose senders don't lock, so producers never
+// wait on the serial writer or each other. Order is kept per priority
+// and per producer.
+pub fn tx_channel() -> (TxSender, TxReceiver) {
+    let (low, low_rx) = mpsc::channel();
+    let (normal, normal_rx) = mpsc::channel();
+    let (high, high_rx) = mpsc::channel();
+    let (urgent, urgent_rx) = mpsc::channel();
+    (TxSender { queues: [low, normal, high, urgent] },
+     TxReceiver { queues: [low_rx, normal_rx, high_rx, urgent_rx] })
+}
+
+#[derive(Clone)]
+pub struct TxSender {
+    queues: [mpsc::Sender<Packet>; PRIORITIES],
+}
+
+impl TxSender {
+    // Fails once the receiver is gone.
+    pub fn push(&self, pkt: Packet, prio: Priority) -> io::Result<()> {
+        self.queues[prio as usize].send(pkt)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "tx queue closed"))
+    }
+}
+
+impl Sender for TxSender {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.push(pkt, Priority::Normal)
+    }
+}
+
+pub struct TxReceiver {
+    queues: [mpsc::Receiver<Packet>; PRIORITIES],
+}
+
+impl TxReceiver {
+    // The oldest packet of the highest priority queued, without waiting.
+    pub fn pop(&self) -> Option<Packet> {
+        self.queues.iter().rev().filter_map(|q| q.try_recv().ok()).next()
+    }
+
+    // Sends queued packets in priority order until empty or an error.
+    pub fn flush_to<S: Sender>(&self, sender: &mut S) -> io::Result<usize> {
+        let mut count = 0;
+        while let Some(pkt) = self.pop() {
+            sender.send(pkt)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, Layout, U16, U8};
+
+pub const RANGE_VALID: u8 = 0x01;
+pub const RANGE_OUT_OF_RANGE: u8 = 0x02; // no echo within the maximum distance.
+pub const RANGE_TOO_CLOSE: u8 = 0x04;    // target inside the blind zone.
+pub const RANGE_SATURATED: u8 = 0x08;    // ambient light or crosstalk.
+
+const OP_TRIGGER: u8 = 0x01;
+const OP_START: u8 = 0x02;
+const OP_STOP: u8 = 0x03;
+const OP_READING: u8 = 0x04;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("trigger", OP_TRIGGER, &[field("sensor", U8)]),
+    Layout::new("start_stream", OP_START, &[field("sensor", U8), field("rate_hz", U16)]),
+    Layout::new("stop_stream", OP_STOP, &[field("sensor", U8)]),
+    Layout::new("reading", OP_READING, &[field("sensor", U8), field("distance_mm", U16), field("fov_decideg", U16), field("flags", U8)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeMessage {
+    Trigger { sensor: u8 },                      // single-shot measurement.
+    StartStream { sensor: u8, rate_hz: u16 },
+    StopStream { sensor: u8 },
+    Reading { sensor: u8, distance_mm: u16, fov_decideg: u16, flags: u8 },
+}
+
+impl Message for RangeMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            RangeMessage::Trigger { sensor } => {
+                data.push(OP_TRIGGER);
+                data.push(sensor);
+            },
+            RangeMessage::StartStream { sensor, rate_hz } => {
+                data.push(OP_START);
+                data.push(sensor);
+                put_u16(data, rate_hz);
+            },
+            RangeMessage::StopStream { sensor } => {
+                data.push(OP_STOP);
+                data.push(sensor);
+            },
+            RangeMessage::Reading { sensor, distance_mm, fov_decideg, flags } => {
+                data.push(OP_READING);
+                data.push(sensor);
+                put_u16(data, distance_mm);
+                put_u16(data, fov_decideg);
+                data.push(flags);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_TRIGGER => RangeMessage::Trigger { sensor: r.u8()? },
+            OP_START => RangeMessage::StartStream { sensor: r.u8()?, rate_hz: r.u16()? },
+            OP_STOP => RangeMessage::StopStream { sensor: r.u8()? },
+            OP_READING => RangeMessage::Reading {
+                sensor: r.u8()?,
+                distance_mm: r.u16()?,
+                fov_decideg: r.u16()?,
+                flags: r.u8()?,
+            },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// A reading in SI units, ts is the host receive time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeReading {
+    pub distance: f64, // meters
+    pub fov: f64,      // radians
+    pub flags: u8,
+    pub ts: Duration,
+}
+
+impl RangeReading {
+    pub fn is_valid(&self) -> bool {
+        self.flags & RANGE_VALID != 0
+    }
+}
+
+pub struct RangeAggregator {
+    latest: HashMap<u8, RangeReading>,
+}
+
+impl Default for RangeAggregator {
+    fn default() -> Self {
+        RangeAggregator::new()
+    }
+}
+
+impl RangeAggregator {
+    pub fn new() -> Self {
+        RangeAggregator { latest: HashMap::new() }
+    }
+
+    // Records a Reading message, other messages are ignored.
+    pub fn update(&mut self, msg: &RangeMessage, ts: Duration) -> Option<&RangeReading> {
+        match *msg {
+            RangeMessage::Reading { sensor, distance_mm, fov_decideg, flags } => {
+                let reading = RangeReading {
+                    distance: distance_mm as f64 / 1000.0,
+                    fov: (fov_decideg as f64 / 10.0).to_radians(),
+                    flags,
+                    ts,
+                };
+                self.latest.insert(sensor, reading);
+                self.latest.get(&sensor)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, sensor: u8) -> Option<&RangeReading> {
+        self.latest.get(&sensor)
+    }
+
+    pub fn latest(&self) -> &HashMap<u8, RangeReading> {
+        &self.latest
+    }
+
+    // The closest valid reading across all sensors.
+    pub fn nearest(&self) -> Option<(u8, &RangeReading)> {
+        self.latest.iter()
+            .filter(|&(_, r)| r.is_valid())
+            .min_by(|a, b| a.1.distance.partial_cmp(&b.1.distance).unwrap_or(::std::cmp::Ordering::Equal))
+            .map(|(id, r)| (*id, r))
+    }
+
+    // Drops readings received before `since`.
+    pub fn expire(&mut self, since: Duration) {
+        self.latest.retain(|_, r| r.ts >= since);
+    }
+}
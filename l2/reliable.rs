@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::io;
+use l0::comm::{Packet, PACKET_MAX_DATA_LEN};
+use super::codec::*;
+use super::queue::{QosClass, QosConfig};
+use super::Sender;
+
+// The stream seq precedes the data of each reliable packet.
+pub const RELIABLE_HEADER_LEN: usize = 2;
+
+// The most seqs one NAK carries.
+pub const NAK_MAX_SEQS: usize = PACKET_MAX_DATA_LEN / 2;
+
+// Sends packets with a stream seq ahead of their data, keeping the last
+// window of them to send again when the receiver NAKs one it missed. The
+// l0 seq only tells a gap by losing sync, the stream seq names what was
+// lost, so recovering from a corrupted frame takes one round trip.
+pub struct Retransmitter<S> {
+    inner: S,
+    nak_code: u8,
+    window: usize,
+    next: u16,
+    sent: VecDeque<(u16, Packet)>,
+    resent: u64,
+    urgent: Vec<u8>,
+    qos: QosConfig,
+}
+
+impl<S: Sender> Retransmitter<S> {
+    pub fn new(inner: S, nak_code: u8, window: usize) -> Self {
+        Retransmitter {
+            inner,
+            nak_code,
+            window,
+            next: 0,
+            sent: VecDeque::new(),
+            resent: 0,
+            urgent: Vec::new(),
+            qos: QosConfig::default(),
+        }
+    }
+
+    // Only Reliable codes go in the stream; the others go out as they are,
+    // never retried, as urgent ones do.
+    pub fn set_qos(&mut self, qos: QosConfig) {
+        self.qos = qos;
+    }
+
+    // Packets on these codes, such as e-stop and faults, go out as they
+    // are, outside the stream, so the receiver never holds them for order.
+    pub fn set_urgent(&mut self, codes: &[u8]) {
+        self.urgent = codes.to_vec();
+    }
+
+    pub fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    // Packets sent again on request.
+    pub fn resent(&self) -> u64 {
+        self.resent
+    }
+
+    // Sends again what a NAK from the peer asks for, if still in the
+    // window; false if pkt isn't a NAK.
+    pub fn receive(&mut self, pkt: &Packet) -> io::Result<bool> {
+        if pkt.code != self.nak_code {
+            return Ok(false);
+        }
+        let mut r = Reader::new(&pkt.data);
+        while let Some(seq) = r.u16() {
+            if let Some((_, p)) = self.sent.iter().find(|s| s.0 == seq) {
+                self.inner.send(p.clone())?;
+                self.resent += 1;
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<S: Sender> Sender for Retransmitter<S> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        if self.urgent.contains(&pkt.code) || self.qos.class(pkt.code) != QosClass::Reliable {
+            return self.inner.send(pkt);
+        }
+        let seq = self.next;
+        self.next = seq.wrapping_add(1);
+        let mut data = Vec::with_capacity(RELIABLE_HEADER_LEN + pkt.data.len());
+        put_u16(&mut data, seq);
+        data.extend_from_slice(&pkt.data);
+        let pkt = Packet { seq: 0, code: pkt.code, data };
+        if self.window > 0 {
+            if self.sent.len() >= self.window {
+                self.sent.pop_front();
+            }
+            self.sent.push_back((seq, pkt.clone()));
+        }
+        self.inner.send(pkt)
+    }
+}
+
+// Delivers the packets of a Retransmitter in stream order, without their
+// seq, NAKing the missing ones as soon as a later one shows the gap. At
+// most max_pending packets wait on a gap; the oldest gaps past that are
+// given up as lost. A lost packet at the end of a burst shows no gap
+// until the next one arrives.
+pub struct Resequencer {
+    nak_code: u8,
+    max_pending: usize,
+    expected: Option<u16>,
+    pending: VecDeque<Option<Packet>>, // from expected on.
+    naks: u64,
+    lost: u64,
+    urgent: Vec<u8>,
+    on_urgent: Option<Box<dyn FnMut(Packet) + Send>>,
+    qos: QosConfig,
+}
+
+impl Resequencer {
+    pub fn new(nak_code: u8, max_pending: usize) -> Self {
+        Resequencer {
+            nak_code,
+            max_pending: max_pending.max(1),
+            expected: None,
+            pending: VecDeque::new(),
+            naks: 0,
+            lost: 0,
+            urgent: Vec::new(),
+            on_urgent: None,
+            qos: QosConfig::default(),
+        }
+    }
+
+    // Packets on codes that aren't Reliable are delivered as they arrive,
+    // matching the sender's Retransmitter::set_qos.
+    pub fn set_qos(&mut self, qos: QosConfig) {
+        self.qos = qos;
+    }
+
+    // Hands packets on codes to callback as they arrive, ahead of any
+    // held back by a gap; the sender's Retransmitter must mark the same
+    // codes urgent.
+    pub fn on_urgent<F>(&mut self, codes: &[u8], callback: F) where F: FnMut(Packet) + Send + 'static {
+        self.urgent = codes.to_vec();
+        self.on_urgent = Some(Box::new(callback));
+    }
+
+    // NAK packets sent.
+    pub fn naks(&self) -> u64 {
+        self.naks
+    }
+
+    // Packets given up on.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    // Packets held back by a gap.
+    pub fn pending(&self) -> usize {
+        self.pending.iter().filter(|p| p.is_some()).count()
+    }
+
+    // Forgets the stream, as when the sender restarts it.
+    pub fn reset(&mut self) {
+        self.expected = None;
+        self.pending.clear();
+    }
+
+    // The packets pkt makes deliverable, in order, sending a NAK on nak
+    // for any gap it reveals. Duplicates and packets too short to carry a
+    // seq give nothing.
+    pub fn receive<S: Sender>(&mut self, mut pkt: Packet, nak: &mut S) -> io::Result<Vec<Packet>> {
+        if self.urgent.contains(&pkt.code) {
+            if let Some(ref mut cb) = self.on_urgent {
+                cb(pkt);
+            }
+            return Ok(Vec::new());
+        }
+        if self.qos.class(pkt.code) != QosClass::Reliable {
+            return Ok(vec![pkt]);
+        }
+        let seq = match Reader::new(&pkt.data).u16() {
+            Some(seq) => seq,
+            None => return Ok(Vec::new()),
+        };
+        pkt.data.drain(..RELIABLE_HEADER_LEN);
+        let mut out = Vec::new();
+        let mut expected = *self.expected.get_or_insert(seq);
+        let mut ahead = seq.wrapping_sub(expected) as usize;
+        if ahead >= 0x8000 {
+            return Ok(out);
+        }
+        if ahead >= self.max_pending {
+            let skip = ahead + 1 - self.max_pending;
+            self.lost += skip.saturating_sub(self.pending.len()) as u64;
+            for _ in 0..skip.min(self.pending.len()) {
+                match self.pending.pop_front() {
+                    Some(Some(p)) => out.push(p),
+                    _ => self.lost += 1,
+                }
+            }
+            expected = expected.wrapping_add(skip as u16);
+            ahead -= skip;
+            self.expected = Some(expected);
+        }
+        let mut missing = Vec::new();
+        while self.pending.len() <= ahead {
+            if self.pending.len() < ahead && missing.len() / 2 < NAK_MAX_SEQS {
+                put_u16(&mut missing, expected.wrapping_add(self.pending.len() as u16));
+            }
+            self.pending.push_back(None);
+        }
+        match self.pending.get_mut(ahead) {
+            Some(slot) if slot.is_none() => *slot = Some(pkt),
+            _ => return Ok(out),
+        }
+        while let Some(Some(_)) = self.pending.front() {
+            if let Some(Some(p)) = self.pending.pop_front() {
+                out.push(p);
+            }
+            expected = expected.wrapping_add(1);
+        }
+        self.expected = Some(expected);
+        if !missing.is_empty() {
+            nak.send(Packet { seq: 0, code: self.nak_code, data: missing })?;
+            self.naks += 1;
+        }
+        Ok(out)
+    }
+}
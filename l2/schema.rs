@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use l0::comm::Packet;
+use super::Message;
+use super::layout::Layout;
+use super::{adc, arm, audio, calibration, camera, encoder, env, gnss, gripper, imu, led, lidar, line, mode,
+    motor, power, range, sound, stepper, touch};
+
+macro_rules! message_sets {
+    ($($set:ident => $name:expr, $ty:ty, $layouts:expr;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum MessageSet {
+            $($set),*
+        }
+
+        impl MessageSet {
+            pub const ALL: &'static [MessageSet] = &[$(MessageSet::$set),*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(MessageSet::$set => $name),*
+                }
+            }
+
+            // The decoded message in Debug form, which names its fields.
+            pub fn describe(self, data: &[u8]) -> Option<String> {
+                match self {
+                    $(MessageSet::$set => <$ty>::decode(data).map(|m| format!("{:?}", m))),*
+                }
+            }
+
+            pub fn layouts(self) -> &'static [Layout] {
+                match self {
+                    $(MessageSet::$set => $layouts),*
+                }
+            }
+        }
+    };
+}
+
+message_sets! {
+    Adc => "adc", adc::AdcMessage, adc::LAYOUTS;
+    Arm => "arm", arm::ArmMessage, arm::LAYOUTS;
+    Audio => "audio", audio::AudioMessage, audio::LAYOUTS;
+    Calibration => "calibration", calibration::CalibrationMessage, calibration::LAYOUTS;
+    Camera => "camera", camera::CameraMessage, camera::LAYOUTS;
+    Encoder => "encoder", encoder::EncoderMessage, encoder::LAYOUTS;
+    Env => "env", env::EnvMessage, env::LAYOUTS;
+    Gnss => "gnss", gnss::GnssMessage, gnss::LAYOUTS;
+    Gripper => "gripper", gripper::GripperMessage, gripper::LAYOUTS;
+    Imu => "imu", imu::ImuMessage, imu::LAYOUTS;
+    Led => "led", led::LedMessage, led::LAYOUTS;
+    Lidar => "lidar", lidar::LidarMessage, lidar::LAYOUTS;
+    Line => "line", line::LineMessage, line::LAYOUTS;
+    Mode => "mode", mode::ModeMessage, mode::LAYOUTS;
+    Motor => "motor", motor::MotorMessage, motor::LAYOUTS;
+    Power => "power", power::PowerMessage, power::LAYOUTS;
+    Range => "range", range::RangeMessage, range::LAYOUTS;
+    Sound => "sound", sound::SoundMessage, sound::LAYOUTS;
+    Stepper => "stepper", stepper::StepperMessage, stepper::LAYOUTS;
+    Touch => "touch", touch::TouchMessage, touch::LAYOUTS;
+}
+
+impl FromStr for MessageSet {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MessageSet::ALL.iter().cloned().find(|set| set.name() == s)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown message set {:?}", s)))
+    }
+}
+
+fn parse_code(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// A device profile: which message set each packet code carries. The text
+// form lists assignments like "0x01=motor,0x81=encoder", separated by
+// commas or newlines; '#' starts a comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    codes: BTreeMap<u8, MessageSet>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Schema::default()
+    }
+
+    pub fn with(mut self, code: u8, set: MessageSet) -> Self {
+        self.codes.insert(code, set);
+        self
+    }
+
+    pub fn insert(&mut self, code: u8, set: MessageSet) {
+        self.codes.insert(code, set);
+    }
+
+    pub fn get(&self, code: u8) -> Option<MessageSet> {
+        self.codes.get(&code).cloned()
+    }
+
+    pub fn code_of(&self, set: MessageSet) -> Option<u8> {
+        self.codes.iter().find(|&(_, s)| *s == set).map(|(c, _)| *c)
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = (u8, MessageSet)> + '_ {
+        self.codes.iter().map(|(c, s)| (*c, *s))
+    }
+
+    // None if the code has no message set assigned or the payload doesn't
+    // decode as one of its messages.
+    pub fn describe(&self, pkt: &Packet) -> Option<String> {
+        self.get(pkt.code)?.describe(&pkt.data)
+    }
+
+    // Checks a payload about to be sent: codes without a message set pass.
+    pub fn validate(&self, pkt: &Packet) -> io::Result<()> {
+        match self.get(pkt.code) {
+            Some(set) if set.describe(&pkt.data).is_none() => Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("payload is not a valid {} message", set.name()))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (code, set)) in self.codes().enumerate() {
+            write!(f, "{}0x{:02x}={}", if i > 0 { "," } else { "" }, code, set.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Schema {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut schema = Schema::new();
+        let items = s.lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split(','))
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty());
+        for item in items {
+            let mut parts = item.splitn(2, '=');
+            let code = parts.next().and_then(parse_code)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("bad code in {:?}", item)))?;
+            let set = parts.next().unwrap_or("").parse()?;
+            schema.insert(code, set);
+        }
+        Ok(schema)
+    }
+}
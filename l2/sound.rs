@@ -0,0 +1,162 @@
+use l0::comm::PACKET_MAX_DATA_LEN;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, rest, FieldType, Layout, U16, U8};
+
+const OP_TONE: u8 = 0x01;
+const OP_MELODY: u8 = 0x02;
+const OP_STOP: u8 = 0x03;
+
+const NOTE: FieldType = FieldType::Struct("note", &[field("pitch", U8), field("sixteenths", U8)]);
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("tone", OP_TONE, &[field("freq_hz", U16), field("duration_ms", U16)]),
+    Layout::new("melody", OP_MELODY, &[field("tempo_bpm", U16), rest("notes", NOTE)]),
+    Layout::new("stop", OP_STOP, &[]),
+];
+
+// op and tempo precede the notes of a melody.
+const MELODY_HEADER_LEN: usize = 3;
+pub const MAX_MELODY_NOTES: usize = (PACKET_MAX_DATA_LEN - MELODY_HEADER_LEN) / 2;
+
+pub const REST: u8 = 0;
+
+// A MIDI note number (REST for silence) held for a number of sixteenths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    pub pitch: u8,
+    pub sixteenths: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SoundMessage {
+    Tone { freq_hz: u16, duration_ms: u16 },
+    Melody { tempo_bpm: u16, notes: Vec<Note> },
+    Stop,
+}
+
+impl Message for SoundMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            SoundMessage::Tone { freq_hz, duration_ms } => {
+                data.push(OP_TONE);
+                put_u16(data, freq_hz);
+                put_u16(data, duration_ms);
+            },
+            SoundMessage::Melody { tempo_bpm, ref notes } => {
+                data.push(OP_MELODY);
+                put_u16(data, tempo_bpm);
+                for n in notes {
+                    data.push(n.pitch);
+                    data.push(n.sixteenths);
+                }
+            },
+            SoundMessage::Stop => data.push(OP_STOP),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_TONE => SoundMessage::Tone { freq_hz: r.u16()?, duration_ms: r.u16()? },
+            OP_MELODY => {
+                let tempo_bpm = r.u16()?;
+                let mut notes = Vec::new();
+                while !r.is_empty() {
+                    notes.push(Note { pitch: r.u8()?, sixteenths: r.u8()? });
+                }
+                SoundMessage::Melody { tempo_bpm, notes }
+            },
+            OP_STOP => SoundMessage::Stop,
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Parses scientific pitch notation ("C4", "F#3", "Bb5") or "R" for a rest
+// into a MIDI note number.
+pub fn parse_note(name: &str) -> Option<u8> {
+    if name == "R" || name == "r" {
+        return Some(REST);
+    }
+    let mut chars = name.chars();
+    let base: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (shift, octave) = if let Some(s) = rest.strip_prefix('#') {
+        (1, s)
+    } else if let Some(s) = rest.strip_prefix('b') {
+        (-1, s)
+    } else {
+        (0, rest)
+    };
+    let octave: i32 = octave.parse().ok()?;
+    let midi = (octave + 1) * 12 + base + shift;
+    if midi > 0 && midi < 128 {
+        Some(midi as u8)
+    } else {
+        None
+    }
+}
+
+pub fn note_freq(pitch: u8) -> f64 {
+    440.0 * 2f64.powf((pitch as f64 - 69.0) / 12.0)
+}
+
+pub struct MelodyBuilder {
+    tempo_bpm: u16,
+    notes: Vec<Note>,
+    valid: bool,
+}
+
+impl MelodyBuilder {
+    pub fn new(tempo_bpm: u16) -> Self {
+        MelodyBuilder {
+            tempo_bpm,
+            notes: Vec::new(),
+            valid: true,
+        }
+    }
+
+    // Parses a space separated list of "name/sixteenths", e.g. "C4/4 R/2 G4/8".
+    pub fn parse(tempo_bpm: u16, s: &str) -> Option<SoundMessage> {
+        let mut b = MelodyBuilder::new(tempo_bpm);
+        for tok in s.split_whitespace() {
+            let mut parts = tok.splitn(2, '/');
+            let name = parts.next()?;
+            let len = parts.next().map_or(Some(4), |l| l.parse().ok())?;
+            b.note(name, len);
+        }
+        b.build()
+    }
+
+    pub fn note(&mut self, name: &str, sixteenths: u8) -> &mut Self {
+        match parse_note(name) {
+            Some(pitch) => self.notes.push(Note { pitch, sixteenths }),
+            None => self.valid = false,
+        }
+        self
+    }
+
+    pub fn rest(&mut self, sixteenths: u8) -> &mut Self {
+        self.notes.push(Note { pitch: REST, sixteenths });
+        self
+    }
+
+    // None if any note name was invalid or the melody doesn't fit a packet.
+    pub fn build(&self) -> Option<SoundMessage> {
+        if !self.valid || self.notes.len() > MAX_MELODY_NOTES {
+            return None;
+        }
+        Some(SoundMessage::Melody { tempo_bpm: self.tempo_bpm, notes: self.notes.clone() })
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use super::{Message, Sender};
+use super::codec::*;
+use super::layout::{field, Layout, I32, U16, U8};
+
+const OP_MOVE_TO: u8 = 0x01;
+const OP_SET_PROFILE: u8 = 0x02;
+const OP_SET_MICROSTEPS: u8 = 0x03;
+const OP_HOME: u8 = 0x04;
+const OP_STOP: u8 = 0x05;
+const OP_MOVE_COMPLETE: u8 = 0x06;
+const OP_HOME_COMPLETE: u8 = 0x07;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("move_to", OP_MOVE_TO, &[field("motor", U8), field("position", I32)]),
+    Layout::new("set_profile", OP_SET_PROFILE, &[field("motor", U8), field("max_speed", U16), field("accel", U16)]),
+    Layout::new("set_microsteps", OP_SET_MICROSTEPS, &[field("motor", U8), field("microsteps", U16)]),
+    Layout::new("home", OP_HOME, &[field("motor", U8), field("forward", U8), field("speed", U16)]),
+    Layout::new("stop", OP_STOP, &[field("motor", U8)]),
+    Layout::new("move_complete", OP_MOVE_COMPLETE, &[field("motor", U8), field("position", I32)]),
+    Layout::new("home_complete", OP_HOME_COMPLETE, &[field("motor", U8)]),
+];
+
+// Positions are in microsteps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepperMessage {
+    MoveTo { motor: u8, position: i32 },
+    // Trapezoidal profile for subsequent moves, in microsteps/s and /s^2.
+    SetProfile { motor: u8, max_speed: u16, accel: u16 },
+    SetMicrosteps { motor: u8, microsteps: u16 },
+    // Seeks the limit switch in the given direction, which becomes position 0.
+    Home { motor: u8, forward: bool, speed: u16 },
+    Stop { motor: u8 },
+    MoveComplete { motor: u8, position: i32 },
+    HomeComplete { motor: u8 },
+}
+
+impl Message for StepperMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            StepperMessage::MoveTo { motor, position } => {
+                data.push(OP_MOVE_TO);
+                data.push(motor);
+                put_i32(data, position);
+            },
+            StepperMessage::SetProfile { motor, max_speed, accel } => {
+                data.push(OP_SET_PROFILE);
+                data.push(motor);
+                put_u16(data, max_speed);
+                put_u16(data, accel);
+            },
+            StepperMessage::SetMicrosteps { motor, microsteps } => {
+                data.push(OP_SET_MICROSTEPS);
+                data.push(motor);
+                put_u16(data, microsteps);
+            },
+            StepperMessage::Home { motor, forward, speed } => {
+                data.push(OP_HOME);
+                data.push(motor);
+                data.push(forward as u8);
+                put_u16(data, speed);
+            },
+            StepperMessage::Stop { motor } => {
+                data.push(OP_STOP);
+                data.push(motor);
+            },
+            StepperMessage::MoveComplete { motor, position } => {
+                data.push(OP_MOVE_COMPLETE);
+                data.push(motor);
+                put_i32(data, position);
+            },
+            StepperMessage::HomeComplete { motor } => {
+                data.push(OP_HOME_COMPLETE);
+                data.push(motor);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_MOVE_TO => StepperMessage::MoveTo { motor: r.u8()?, position: r.i32()? },
+            OP_SET_PROFILE => StepperMessage::SetProfile { motor: r.u8()?, max_speed: r.u16()?, accel: r.u16()? },
+            OP_SET_MICROSTEPS => StepperMessage::SetMicrosteps { motor: r.u8()?, microsteps: r.u16()? },
+            OP_HOME => StepperMessage::Home { motor: r.u8()?, forward: r.u8()? != 0, speed: r.u16()? },
+            OP_STOP => StepperMessage::Stop { motor: r.u8()? },
+            OP_MOVE_COMPLETE => StepperMessage::MoveComplete { motor: r.u8()?, position: r.i32()? },
+            OP_HOME_COMPLETE => StepperMessage::HomeComplete { motor: r.u8()? },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Default)]
+struct Axis {
+    queue: VecDeque<StepperMessage>,
+    busy: bool,
+    position: Option<i32>, // unknown until homed or a move completes.
+}
+
+// Queues moves per motor and sends the next one when the device reports
+// the previous complete. Profile and microstep changes are queued too so
+// they apply between the moves they were issued between.
+pub struct StepperClient<S> {
+    sender: S,
+    code: u8,
+    axes: HashMap<u8, Axis>,
+}
+
+impl<S: Sender> StepperClient<S> {
+    pub fn new(sender: S, code: u8) -> Self {
+        StepperClient {
+            sender,
+            code,
+            axes: HashMap::new(),
+        }
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    pub fn move_to(&mut self, motor: u8, position: i32) -> io::Result<()> {
+        self.enqueue(motor, StepperMessage::MoveTo { motor, position })
+    }
+
+    pub fn set_profile(&mut self, motor: u8, max_speed: u16, accel: u16) -> io::Result<()> {
+        self.enqueue(motor, StepperMessage::SetProfile { motor, max_speed, accel })
+    }
+
+    pub fn set_microsteps(&mut self, motor: u8, microsteps: u16) -> io::Result<()> {
+        self.enqueue(motor, StepperMessage::SetMicrosteps { motor, microsteps })
+    }
+
+    pub fn home(&mut self, motor: u8, forward: bool, speed: u16) -> io::Result<()> {
+        self.enqueue(motor, StepperMessage::Home { motor, forward, speed })
+    }
+
+    // Stops immediately and discards queued moves.
+    pub fn stop(&mut self, motor: u8) -> io::Result<()> {
+        if let Some(axis) = self.axes.get_mut(&motor) {
+            axis.queue.clear();
+            axis.busy = false;
+        }
+        self.sender.send(StepperMessage::Stop { motor }.to_packet(self.code))
+    }
+
+    pub fn position(&self, motor: u8) -> Option<i32> {
+        self.axes.get(&motor).and_then(|a| a.position)
+    }
+
+    pub fn is_idle(&self, motor: u8) -> bool {
+        self.axes.get(&motor).is_none_or(|a| !a.busy && a.queue.is_empty())
+    }
+
+    pub fn queued(&self, motor: u8) -> usize {
+        self.axes.get(&motor).map_or(0, |a| a.queue.len())
+    }
+
+    pub fn handle(&mut self, msg: &StepperMessage) -> io::Result<()> {
+        let (motor, position) = match *msg {
+            StepperMessage::MoveComplete { motor, position } => (motor, position),
+            StepperMessage::HomeComplete { motor } => (motor, 0),
+            _ => return Ok(()),
+        };
+        let axis = self.axes.entry(motor).or_default();
+        axis.position = Some(position);
+        axis.busy = false;
+        self.pump(motor)
+    }
+
+    fn enqueue(&mut self, motor: u8, msg: StepperMessage) -> io::Result<()> {
+        self.axes.entry(motor).or_default().queue.push_back(msg);
+        self.pump(motor)
+    }
+
+    // Sends queued messages up to and including the next motion.
+    fn pump(&mut self, motor: u8) -> io::Result<()> {
+        let axis = match self.axes.get_mut(&motor) {
+            Some(axis) => axis,
+            None => return Ok(()),
+        };
+        while !axis.busy {
+            let msg = match axis.queue.pop_front() {
+                Some(msg) => msg,
+                None => break,
+            };
+            if let StepperMessage::MoveTo { .. } | StepperMessage::Home { .. } = msg {
+                axis.busy = true;
+            }
+            self.sender.send(msg.to_packet(self.code))?;
+        }
+        Ok(())
+    }
+}
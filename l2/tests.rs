@@ -0,0 +1,1068 @@
+#![cfg(test)]
+
+use std::time::Duration;
+use l0::comm::Packet;
+use super::*;
+use super::adc::*;
+use super::encoder::*;
+use super::imu::*;
+use super::led::*;
+use super::motor::*;
+use super::power::*;
+use super::range::*;
+use super::schema::MessageSet;
+use super::sound::*;
+
+fn roundtrip<M: Message + PartialEq + ::std::fmt::Debug>(msg: M) {
+    let pkt = msg.to_packet(3);
+    assert_eq!(pkt.code, 3);
+    assert_eq!(M::from_packet(&pkt), Some(msg));
+}
+
+#[test]
+fn test_adc_roundtrip() {
+    roundtrip(AdcMessage::Read { channel: 2 });
+    roundtrip(AdcMessage::Sample { channel: 2, raw: 0x3ff });
+    roundtrip(AdcMessage::StartSampling { mask: 0x0005, rate_hz: 100 });
+    roundtrip(AdcMessage::StopSampling);
+    roundtrip(AdcMessage::Samples { mask: 0x0005, raw: vec![1, 0x0fff] });
+}
+
+#[test]
+fn test_adc_decode_invalid() {
+    assert_eq!(AdcMessage::decode(&[]), None);
+    assert_eq!(AdcMessage::decode(&[0x7f]), None);
+    assert_eq!(AdcMessage::decode(&[0x02, 1, 0]), None);
+    assert_eq!(AdcMessage::decode(&[0x04, 0]), None);
+    assert_eq!(AdcMessage::decode(&[0x05, 0x03, 0x00, 1, 0]), None);
+}
+
+#[test]
+fn test_adc_scaler() {
+    let mut s = Scaler::new();
+    s.set(0, Calibration::from_reference(3.3, 12).unwrap());
+    assert!(Calibration::from_reference(3.3, 0).is_err());
+    assert!(Calibration::from_reference(3.3, 32).is_err());
+    s.set(2, Calibration { scale: 0.01, offset: -1.0 });
+    assert_eq!(s.volts(0, 4095), Some(3.3));
+    assert_eq!(s.volts(16, 0), None);
+    let v = s.scale_samples(0x0005, &[0, 200]);
+    assert_eq!(v, vec![(0, 0.0), (2, 1.0)]);
+}
+
+#[test]
+fn test_adc_calibration_bytes() {
+    let cal = Calibration { scale: 0.5, offset: -0.25 };
+    assert_eq!(Calibration::from_bytes(&cal.to_bytes()), Some(cal));
+    assert_eq!(Calibration::from_bytes(&[0; 7]), None);
+}
+
+#[test]
+fn test_encoder_roundtrip() {
+    roundtrip(EncoderMessage::Count { channel: 1, count: 0xfffffff0 });
+    roundtrip(EncoderMessage::Velocity { channel: 1, ticks_per_sec: -1200 });
+}
+
+#[test]
+fn test_encoder_tracker_wraparound() {
+    let mut t = EncoderTracker::new(16, 1.0);
+    assert_eq!(t.update(0xfff0, Duration::from_millis(0)), 0);
+    assert_eq!(t.update(0x0010, Duration::from_millis(100)), 0x20);
+    assert_eq!(t.velocity(), 320.0);
+    assert_eq!(t.update(0xfff0, Duration::from_millis(200)), -0x20);
+    assert_eq!(t.position(), 0);
+    assert_eq!(t.velocity(), -320.0);
+}
+
+#[test]
+fn test_encoder_tracker_filter() {
+    let mut t = EncoderTracker::new(32, 0.5);
+    t.update(0, Duration::from_secs(0));
+    t.update(100, Duration::from_secs(1));
+    assert_eq!(t.velocity(), 50.0);
+    t.update(200, Duration::from_secs(2));
+    assert_eq!(t.velocity(), 75.0);
+    t.reset();
+    assert_eq!(t.position(), 0);
+    assert_eq!(t.velocity(), 0.0);
+}
+
+#[test]
+fn test_imu_roundtrip() {
+    roundtrip(ImuMessage::Motion { ts_ms: 1000, accel: [0, -1, 1000], gyro: [-32768, 0, 32767] });
+    roundtrip(ImuMessage::Mag { ts_ms: 1, mag: [1, 2, 3] });
+    roundtrip(ImuMessage::Orientation { ts_ms: 2, quat: [16384, 0, 0, 0] });
+    let mut data = Vec::new();
+    ImuMessage::Motion { ts_ms: 0, accel: [0; 3], gyro: [0; 3] }.encode(&mut data);
+    assert_eq!(data.len(), 17);
+}
+
+#[test]
+fn test_imu_sample_conversion() {
+    let msg = ImuMessage::Motion { ts_ms: 1500, accel: [0, 0, 1000], gyro: [0, 0, 500] };
+    let s = ImuSample::from_message(&msg).unwrap();
+    assert!((s.accel.z - 9.80665).abs() < 1e-9);
+    assert!((s.gyro.z - 0.5).abs() < 1e-9);
+    assert_eq!(s.ts, Duration::from_millis(1500));
+    assert_eq!(s.to_message(), msg);
+    assert_eq!(ImuSample::from_message(&ImuMessage::Mag { ts_ms: 0, mag: [0; 3] }), None);
+    assert_eq!(Vec3::new(1e6, -1e6, 0.0).to_fixed(GYRO_LSB), [32767, -32768, 0]);
+}
+
+#[test]
+fn test_imu_quaternion_yaw() {
+    let h = (0.5f64).sqrt();
+    let q = Quaternion { w: h, x: 0.0, y: 0.0, z: h };
+    assert!((q.yaw() - ::std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+    let q = Quaternion::from_fixed(q.to_fixed());
+    assert!((q.yaw() - ::std::f64::consts::FRAC_PI_2).abs() < 1e-3);
+}
+
+#[test]
+fn test_range_roundtrip() {
+    roundtrip(RangeMessage::Trigger { sensor: 1 });
+    roundtrip(RangeMessage::StartStream { sensor: 1, rate_hz: 20 });
+    roundtrip(RangeMessage::StopStream { sensor: 1 });
+    roundtrip(RangeMessage::Reading { sensor: 1, distance_mm: 1234, fov_decideg: 250, flags: RANGE_VALID });
+}
+
+#[test]
+fn test_range_aggregator() {
+    let mut agg = RangeAggregator::new();
+    assert!(agg.update(&RangeMessage::Trigger { sensor: 1 }, Duration::from_secs(0)).is_none());
+    agg.update(&RangeMessage::Reading { sensor: 1, distance_mm: 500, fov_decideg: 150, flags: RANGE_VALID }, Duration::from_secs(1));
+    agg.update(&RangeMessage::Reading { sensor: 2, distance_mm: 100, fov_decideg: 150, flags: RANGE_TOO_CLOSE }, Duration::from_secs(1));
+    agg.update(&RangeMessage::Reading { sensor: 1, distance_mm: 300, fov_decideg: 150, flags: RANGE_VALID }, Duration::from_secs(2));
+    assert_eq!(agg.latest().len(), 2);
+    let r = agg.get(1).unwrap();
+    assert_eq!(r.distance, 0.3);
+    assert!((r.fov - 15f64.to_radians()).abs() < 1e-9);
+    assert_eq!(agg.nearest().map(|(id, _)| id), Some(1));
+    agg.expire(Duration::from_secs(2));
+    assert!(agg.get(2).is_none());
+}
+
+#[test]
+fn test_power_roundtrip() {
+    roundtrip(PowerMessage::Status { voltage_mv: 11100, current_ma: -500, soc_pct: 80 });
+    roundtrip(PowerMessage::ThresholdCrossed { threshold_mv: 10500, voltage_mv: 10490, rising: false });
+}
+
+#[test]
+fn test_battery_monitor() {
+    use std::sync::{Arc, Mutex};
+
+    let crossings = Arc::new(Mutex::new(Vec::new()));
+    let mut m = BatteryMonitor::new(2.0).with_filter(1.0);
+    let c = crossings.clone();
+    m.on_threshold(20.0, move |s, dir| c.lock().unwrap().push((s.soc, dir)));
+    assert_eq!(m.remaining_runtime(), None);
+
+    m.update(&PowerMessage::Status { voltage_mv: 11000, current_ma: 1000, soc_pct: 50 }, Duration::from_secs(0));
+    assert_eq!(m.remaining_runtime(), Some(Duration::from_secs(3600)));
+    m.update(&PowerMessage::Status { voltage_mv: 10000, current_ma: 1000, soc_pct: 19 }, Duration::from_secs(1));
+    m.update(&PowerMessage::Status { voltage_mv: 12000, current_ma: -1000, soc_pct: 25 }, Duration::from_secs(2));
+    assert_eq!(m.remaining_runtime(), None);
+    assert_eq!(m.state().unwrap().voltage, 12.0);
+    assert_eq!(*crossings.lock().unwrap(), vec![(19.0, Crossing::Falling), (25.0, Crossing::Rising)]);
+
+    // an average decaying after the load stops reads as idle.
+    let mut m = BatteryMonitor::new(2.0);
+    m.update(&PowerMessage::Status { voltage_mv: 11000, current_ma: 1000, soc_pct: 50 }, Duration::from_secs(0));
+    for i in 0..400 {
+        m.update(&PowerMessage::Status { voltage_mv: 11000, current_ma: 0, soc_pct: 50 }, Duration::from_secs(i));
+    }
+    assert!(m.average_current() > 0.0);
+    assert_eq!(m.remaining_runtime(), None);
+}
+
+#[test]
+fn test_led_roundtrip() {
+    roundtrip(LedMessage::SetColor { strip: 0, color: Rgb::new(1, 2, 3) });
+    roundtrip(LedMessage::Frame { strip: 0, offset: 8, runs: vec![Run { count: 3, color: Rgb::new(4, 5, 6) }] });
+    roundtrip(LedMessage::Animate { strip: 1, animation: Animation::Chase, color: Rgb::new(0, 0, 255), period_ms: 500 });
+    assert_eq!(LedMessage::decode(&[0x03, 0, 9, 0, 0, 0, 0, 0]), None);
+    assert_eq!(LedMessage::decode(&[0x02, 0, 0, 0, 1, 2]), None);
+}
+
+#[test]
+fn test_led_frame_builder() {
+    let mut fb = FrameBuilder::new(2, 10);
+    fb.fill(Rgb::new(1, 1, 1)).set(3, Rgb::new(9, 9, 9));
+    let msgs = fb.build();
+    assert_eq!(msgs, vec![LedMessage::Frame { strip: 2, offset: 0, runs: vec![
+        Run { count: 3, color: Rgb::new(1, 1, 1) },
+        Run { count: 1, color: Rgb::new(9, 9, 9) },
+        Run { count: 6, color: Rgb::new(1, 1, 1) },
+    ] }]);
+}
+
+#[test]
+fn test_led_frame_builder_split() {
+    let mut fb = FrameBuilder::new(0, 300);
+    for i in 0..fb.len() {
+        fb.set(i, Rgb::new(i as u8, 0, 0));
+    }
+    let msgs = fb.build();
+    let mut next = 0;
+    for msg in &msgs {
+        assert!(msg.to_packet(0).data.len() <= ::l0::comm::PACKET_MAX_DATA_LEN);
+        match *msg {
+            LedMessage::Frame { offset, ref runs, .. } => {
+                assert_eq!(offset as usize, next);
+                next += runs.iter().map(|r| r.count as usize).sum::<usize>();
+            },
+            _ => panic!("unexpected message"),
+        }
+    }
+    assert_eq!(next, 300);
+}
+
+#[test]
+fn test_sound_roundtrip() {
+    roundtrip(SoundMessage::Tone { freq_hz: 440, duration_ms: 250 });
+    roundtrip(SoundMessage::Melody { tempo_bpm: 120, notes: vec![Note { pitch: 60, sixteenths: 4 }] });
+    roundtrip(SoundMessage::Stop);
+    assert_eq!(SoundMessage::decode(&[0x02, 120, 0, 60]), None);
+}
+
+#[test]
+fn test_sound_parse_note() {
+    assert_eq!(parse_note("A4"), Some(69));
+    assert_eq!(parse_note("C4"), Some(60));
+    assert_eq!(parse_note("c#4"), Some(61));
+    assert_eq!(parse_note("Bb3"), Some(58));
+    assert_eq!(parse_note("R"), Some(REST));
+    assert_eq!(parse_note("H4"), None);
+    assert_eq!(parse_note("C"), None);
+    assert_eq!(parse_note("G9"), Some(127));
+    assert_eq!(parse_note("A9"), None);
+    assert!((note_freq(69) - 440.0).abs() < 1e-9);
+    assert!((note_freq(81) - 880.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_sound_melody_builder() {
+    let m = MelodyBuilder::parse(100, "C4/4 R/2 G4").unwrap();
+    assert_eq!(m, SoundMessage::Melody { tempo_bpm: 100, notes: vec![
+        Note { pitch: 60, sixteenths: 4 },
+        Note { pitch: REST, sixteenths: 2 },
+        Note { pitch: 67, sixteenths: 4 },
+    ] });
+    assert_eq!(MelodyBuilder::parse(100, "C4/4 X4/4"), None);
+    let mut b = MelodyBuilder::new(100);
+    for _ in 0..MAX_MELODY_NOTES {
+        b.rest(1);
+    }
+    assert!(b.build().unwrap().to_packet(0).data.len() <= ::l0::comm::PACKET_MAX_DATA_LEN);
+    assert_eq!(b.rest(1).build(), None);
+}
+
+#[test]
+fn test_motor_roundtrip() {
+    roundtrip(MotorMessage::SetVelocities { first: 0, velocities: vec![-100, 100] });
+    roundtrip(MotorMessage::Stop);
+    assert_eq!(MotorMessage::set_velocities(1, &[1.0, -1e6]),
+        MotorMessage::SetVelocities { first: 1, velocities: vec![100, i16::MIN] });
+}
+
+#[test]
+fn test_coalescer() {
+    use std::time::{Duration, Instant};
+    use super::batch::*;
+
+    let t = Instant::now();
+    let ms = |n: u64| t + Duration::from_millis(n);
+    let pkt = |code: u8, len: usize| Packet { seq: 0, code, data: vec![code; len] };
+    let mut c = Coalescer::new(Vec::new(), 0x8f, Duration::from_millis(20));
+
+    // a lone sample goes out as itself once its window ends.
+    c.push_at(pkt(0x81, 2), ms(0)).unwrap();
+    assert!(!c.poll_at(ms(19)).unwrap());
+    assert!(c.poll_at(ms(20)).unwrap());
+    assert_eq!(c.inner().split_off(0), vec![pkt(0x81, 2)]);
+
+    // samples within the window share a packet, until one doesn't fit.
+    c.push_at(pkt(0x81, 2), ms(30)).unwrap();
+    c.push_at(pkt(0x82, 60), ms(35)).unwrap();
+    c.push_at(pkt(0x83, 60), ms(40)).unwrap();
+    let sent = c.inner().split_off(0);
+    assert_eq!(sent.len(), 1);
+    assert_eq!((sent[0].code, sent[0].data.len()), (0x8f, 66));
+    assert_eq!(unbatch(&sent[0].data).unwrap(), vec![pkt(0x81, 2), pkt(0x82, 60)]);
+
+    // one too long to batch follows what's pending.
+    c.push_at(pkt(0x84, 1), ms(45)).unwrap();
+    c.push_at(pkt(0x85, BATCH_MAX_RECORD_LEN + 1), ms(46)).unwrap();
+    let sent = c.inner().split_off(0);
+    assert_eq!(sent.iter().map(|p| p.code).collect::<Vec<_>>(), vec![0x8f, 0x85]);
+    assert_eq!(unbatch(&sent[0].data).unwrap(), vec![pkt(0x83, 60), pkt(0x84, 1)]);
+    assert!(c.is_empty() && !c.flush().unwrap());
+    assert_eq!(unbatch(&[0x81, 3, 1]), None);
+}
+
+#[test]
+fn test_nak_retransmit() {
+    use super::reliable::*;
+
+    let pkt = |code: u8| Packet { seq: 0, code, data: vec![code; 3] };
+    let codes = |pkts: &[Packet]| pkts.iter().map(|p| p.code).collect::<Vec<_>>();
+    let mut tx = Retransmitter::new(Vec::new(), 0x0e, 8);
+    let mut rx = Resequencer::new(0x0e, 4);
+    let mut naks = Vec::new();
+    for code in 1..=5 {
+        tx.send(pkt(code)).unwrap();
+    }
+    let mut wire = tx.inner().split_off(0);
+    assert_eq!(wire[0].data, vec![0, 0, 1, 1, 1]);
+
+    // the second is corrupted; the third shows the gap.
+    wire.remove(1);
+    let mut got = Vec::new();
+    for p in wire {
+        got.extend(rx.receive(p, &mut naks).unwrap());
+    }
+    assert_eq!(got, vec![pkt(1)]);
+    assert_eq!((rx.pending(), rx.naks()), (3, 1));
+    assert_eq!(naks, vec![Packet { seq: 0, code: 0x0e, data: vec![1, 0] }]);
+
+    assert!(!tx.receive(&pkt(0x01)).unwrap());
+    assert!(tx.receive(&naks[0]).unwrap());
+    assert_eq!(tx.resent(), 1);
+    let resent = tx.inner().pop().unwrap();
+    assert_eq!(codes(&rx.receive(resent.clone(), &mut naks).unwrap()), vec![2, 3, 4, 5]);
+    assert!(rx.receive(resent, &mut naks).unwrap().is_empty());
+
+    // a gap longer than the receiver waits is given up.
+    for code in 6..=12 {
+        tx.send(pkt(code)).unwrap();
+    }
+    let mut wire = tx.inner().split_off(0);
+    wire.remove(0);
+    let mut got = Vec::new();
+    for p in wire {
+        got.extend(rx.receive(p, &mut naks).unwrap());
+    }
+    assert_eq!(codes(&got), vec![7, 8, 9, 10, 11, 12]);
+    assert_eq!(rx.lost(), 1);
+}
+
+#[test]
+fn test_urgent_bypass() {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use super::batch::Coalescer;
+    use super::reliable::*;
+
+    let pkt = |code: u8| Packet { seq: 0, code, data: vec![code; 3] };
+    let mut tx = Retransmitter::new(Vec::new(), 0x0e, 8);
+    tx.set_urgent(&[0x0f]);
+    let mut rx = Resequencer::new(0x0e, 8);
+    let urgent = Arc::new(Mutex::new(Vec::new()));
+    let got = urgent.clone();
+    rx.on_urgent(&[0x0f], move |p| got.lock().unwrap().push(p));
+
+    for code in &[1, 2, 0x0f, 3] {
+        tx.send(pkt(*code)).unwrap();
+    }
+    let mut wire = tx.inner().split_off(0);
+    assert_eq!(wire[2], pkt(0x0f));
+    // the e-stop arrives while the others wait on a lost one.
+    wire.remove(1);
+    let mut naks = Vec::new();
+    assert_eq!(rx.receive(wire.remove(0), &mut naks).unwrap().len(), 1);
+    for p in wire {
+        assert!(rx.receive(p, &mut naks).unwrap().is_empty());
+    }
+    assert_eq!(*urgent.lock().unwrap(), vec![pkt(0x0f)]);
+    assert_eq!(rx.pending(), 1);
+
+    let mut c = Coalescer::new(Vec::new(), 0x8f, Duration::from_millis(20));
+    c.set_urgent(&[0x0f]);
+    let t = Instant::now();
+    c.push_at(pkt(0x81), t).unwrap();
+    c.push_at(pkt(0x82), t).unwrap();
+    c.push_at(pkt(0x0f), t).unwrap();
+    assert_eq!(c.inner().split_off(0), vec![pkt(0x0f)]);
+    assert!(!c.is_empty());
+}
+
+#[test]
+fn test_tx_queue_priority() {
+    use super::queue::*;
+
+    let mut q = TxQueue::new();
+    q.push(Packet::new_with(0, 1), Priority::Low);
+    q.send(Packet::new_with(0, 2)).unwrap();
+    q.push(Packet::new_with(0, 3), Priority::Urgent);
+    q.push(Packet::new_with(0, 4), Priority::Normal);
+    assert_eq!(q.len(), 4);
+    assert_eq!(q.len_of(Priority::Normal), 2);
+    q.retain(|p| p.code != 4);
+    let mut out: Vec<Packet> = Vec::new();
+    assert_eq!(q.flush_to(&mut out).unwrap(), 3);
+    assert!(q.is_empty());
+    assert_eq!(out.iter().map(|p| p.code).collect::<Vec<_>>(), vec![3, 2, 1]);
+}
+
+#[test]
+fn test_tx_queue_qos() {
+    use super::queue::*;
+    use super::reliable::*;
+
+    let mut qos = QosConfig { capacity: 4, ..QosConfig::default() };
+    qos.classes.insert(1, QosClass::LatestOnly);
+    qos.classes.insert(2, QosClass::BestEffort);
+    let mut q = TxQueue::with_qos(qos.clone());
+    for i in 0..3 {
+        q.push(Packet { seq: 0, code: 1, data: vec![i] }, Priority::Normal);
+    }
+    assert_eq!(q.len(), 1);
+    q.push(Packet::new_with(0, 2), Priority::Low);
+    q.push(Packet::new_with(0, 3), Priority::Normal);
+    q.push(Packet::new_with(0, 3), Priority::Normal);
+    // full: the best-effort one goes first, then the telemetry.
+    q.push(Packet::new_with(0, 3), Priority::High);
+    q.push(Packet::new_with(0, 3), Priority::High);
+    q.push(Packet::new_with(0, 3), Priority::High);
+    assert_eq!(q.dropped(), 4);
+    let mut out: Vec<Packet> = Vec::new();
+    q.flush_to(&mut out).unwrap();
+    assert_eq!(out.iter().map(|p| p.code).collect::<Vec<_>>(), vec![3; 5]);
+
+    let mut tx = Retransmitter::new(Vec::new(), 0x0e, 8);
+    tx.set_qos(qos.clone());
+    let mut rx = Resequencer::new(0x0e, 8);
+    rx.set_qos(qos);
+    tx.send(Packet { seq: 0, code: 1, data: vec![7] }).unwrap();
+    tx.send(Packet { seq: 0, code: 3, data: vec![8] }).unwrap();
+    let wire = tx.inner().split_off(0);
+    assert_eq!(wire[0].data, vec![7]);
+    assert_eq!(wire[1].data.len(), RELIABLE_HEADER_LEN + 1);
+    let mut naks = Vec::new();
+    assert_eq!(rx.receive(wire[0].clone(), &mut naks).unwrap()[0].data, vec![7]);
+    assert_eq!(rx.receive(wire[1].clone(), &mut naks).unwrap()[0].data, vec![8]);
+}
+
+#[test]
+fn test_tx_channel() {
+    use std::thread;
+    use super::queue::*;
+
+    let (tx, rx) = tx_channel();
+    let producers: Vec<_> = (0..4u8).map(|t| {
+        let mut tx = tx.clone();
+        thread::spawn(move || for i in 0..50u8 {
+            tx.push(Packet { seq: 0, code: t, data: vec![i] }, Priority::Low).unwrap();
+            if i % 10 == 0 {
+                tx.send(Packet { seq: 0, code: t, data: vec![i] }).unwrap();
+            }
+        })
+    }).collect();
+    for p in producers {
+        p.join().unwrap();
+    }
+    tx.push(Packet::new_with(0, 9), Priority::Urgent).unwrap();
+
+    let mut out: Vec<Packet> = Vec::new();
+    assert_eq!(rx.flush_to(&mut out).unwrap(), 221);
+    assert_eq!(out[0].code, 9);
+    assert!(out[1..21].iter().all(|p| p.data[0] % 10 == 0));
+    // each producer's packets keep their order within a priority.
+    for t in 0..4u8 {
+        let low: Vec<u8> = out[21..].iter().filter(|p| p.code == t).map(|p| p.data[0]).collect();
+        assert_eq!(low, (0..50).collect::<Vec<_>>());
+    }
+    drop(rx);
+    assert!(tx.push(Packet::new_with(0, 1), Priority::High).is_err());
+}
+
+#[test]
+fn test_fragment_reassembly() {
+    use super::fragment::*;
+
+    let payload: Vec<u8> = (0..250).map(|i| i as u8).collect();
+    let frags = split(7, &payload, 100);
+    assert_eq!(frags.len(), 3);
+    let mut r = Reassembler::new(2);
+    assert_eq!(r.push(frags[2].clone()), None);
+    assert_eq!(r.push(frags[0].clone()), None);
+    assert_eq!(r.push(frags[0].clone()), None);
+    assert_eq!(r.push(frags[1].clone()), Some((7, payload)));
+    // late fragments of completed payloads are ignored.
+    assert_eq!(r.push(frags[0].clone()), None);
+    assert_eq!(r.pending(), 0);
+    assert_eq!(split(1, &[], 10).len(), 1);
+    assert!(is_newer(0, 0xffff));
+    assert!(!is_newer(0xffff, 0));
+}
+
+#[test]
+fn test_fragment_drop_policy() {
+    use super::fragment::*;
+
+    let mut r = Reassembler::new(2);
+    r.push(split(1, &[1, 2], 1)[0].clone());
+    r.push(split(3, &[1, 2], 1)[0].clone());
+    // a third pending payload evicts the oldest.
+    r.push(split(4, &[1, 2], 1)[0].clone());
+    assert_eq!(r.dropped(), 1);
+    // completing 4 makes 3 late.
+    assert_eq!(r.push(split(4, &[1, 2], 1)[1].clone()), Some((4, vec![1, 2])));
+    assert_eq!(r.dropped(), 2);
+    assert_eq!(r.push(split(3, &[1, 2], 1)[1].clone()), None);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_fragment_bytes() {
+    use bytes::Bytes;
+    use l0::comm::BytesPacket;
+    use super::fragment::*;
+
+    let mut r = Reassembler::new(2);
+    let packets: Vec<BytesPacket> = split(9, &[1, 2, 3, 4, 5], 3).iter().map(|f| {
+        let mut data = Vec::new();
+        f.encode(&mut data);
+        Packet { seq: 1, code: 0x85, data }.into()
+    }).collect();
+    let frags: Vec<BytesFragment> = packets.iter().map(|p| BytesFragment::decode(&p.data).unwrap()).collect();
+    assert_eq!(frags[1].data, Bytes::from_static(&[4, 5]));
+    assert_eq!(frags[1].data.as_ptr(), packets[1].data[FRAGMENT_HEADER_LEN..].as_ptr());
+    assert_eq!(r.push_bytes(frags[1].clone()), None);
+    assert_eq!(r.push_bytes(frags[0].clone()), Some((9, Bytes::from_static(&[1, 2, 3, 4, 5]))));
+
+    // a payload of one fragment is the packet's own data.
+    let mut data = Vec::new();
+    split(10, &[6, 7], 3)[0].encode(&mut data);
+    let data = Bytes::from(data);
+    let (id, payload) = r.push_bytes(BytesFragment::decode(&data).unwrap()).unwrap();
+    assert_eq!((id, &payload[..]), (10, &[6, 7][..]));
+    assert_eq!(payload.as_ptr(), data[FRAGMENT_HEADER_LEN..].as_ptr());
+    assert_eq!(r.push(split(11, &[8], 3)[0].clone()), Some((11, vec![8])));
+    assert_eq!(BytesFragment::decode(&Bytes::from_static(&[0, 0, 1, 0, 1, 0])), None);
+}
+
+#[test]
+fn test_camera_frames() {
+    use super::camera::*;
+
+    roundtrip(CameraMessage::Start { format: ImageFormat::Jpeg, width: 320, height: 240, fps: 10 });
+    roundtrip(CameraMessage::Stop);
+
+    let frame = |seq: u16| Frame {
+        seq,
+        format: ImageFormat::Grey8,
+        width: 20,
+        height: 15,
+        device_ts: Duration::from_millis(1000 + seq as u64),
+        ts: Duration::from_secs(0),
+        data: vec![seq as u8; 300],
+    };
+    let mut rx = FrameReceiver::new();
+    let chunks = frame(1).to_chunks();
+    assert_eq!(chunks.len(), 3);
+    for c in &chunks {
+        let pkt = c.to_packet(9);
+        assert!(pkt.data.len() <= ::l0::comm::PACKET_MAX_DATA_LEN);
+        assert_eq!(CameraMessage::from_packet(&pkt).as_ref(), Some(c));
+    }
+    assert_eq!(rx.push(&chunks[0], Duration::from_secs(1)), None);
+    // frame 2 overtakes frame 1, which is then dropped as late.
+    let mut got = None;
+    for c in frame(2).to_chunks() {
+        got = rx.push(&c, Duration::from_secs(2));
+    }
+    let mut expected = frame(2);
+    expected.ts = Duration::from_secs(2);
+    assert_eq!(got, Some(expected));
+    assert_eq!(rx.push(&chunks[1], Duration::from_secs(3)), None);
+    assert_eq!(rx.push(&chunks[2], Duration::from_secs(3)), None);
+    assert_eq!(rx.dropped(), 1);
+}
+
+#[test]
+fn test_audio_chunks() {
+    use super::audio::*;
+
+    roundtrip(AudioMessage::Config { format: SampleFormat::Pcm16, rate_hz: 16000, channels: 2 });
+    roundtrip(AudioMessage::Chunk { seq: 0xfffe, data: vec![1, 2, 3, 4] });
+    roundtrip(AudioMessage::Stop);
+
+    let samples: Vec<i16> = (0..200).map(|i| i * 100 - 10000).collect();
+    let mut enc = ChunkEncoder::new(SampleFormat::Pcm16, 2);
+    let chunks = enc.encode(&samples);
+    assert_eq!(chunks.len(), 4);
+    let mut decoded = Vec::new();
+    for (i, c) in chunks.iter().enumerate() {
+        let pkt = c.to_packet(5);
+        assert!(pkt.data.len() <= ::l0::comm::PACKET_MAX_DATA_LEN);
+        match AudioMessage::from_packet(&pkt) {
+            Some(AudioMessage::Chunk { seq, data }) => {
+                assert_eq!(seq, i as u16);
+                assert_eq!(data.len() % 4, 0);
+                decoded.extend(SampleFormat::Pcm16.decode(&data));
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    assert_eq!(decoded, samples);
+    assert_eq!(SampleFormat::Pcm8.decode(&[0x80, 0x7f]), vec![-32768, 0x7f00]);
+}
+
+#[test]
+fn test_audio_jitter_buffer() {
+    use super::audio::*;
+
+    let chunk = |v: i16| {
+        let mut data = Vec::new();
+        SampleFormat::Pcm16.encode(&[v, v], &mut data);
+        data
+    };
+    let mut jb = JitterBuffer::new(SampleFormat::Pcm16, 3);
+    jb.push(0xffff, &chunk(1));
+    jb.push(1, &chunk(3));
+    assert_eq!(jb.pop(), None);
+    // reordered within the buffer depth.
+    jb.push(0xfffe, &chunk(0));
+    assert_eq!(jb.pop(), Some(vec![0, 0]));
+    assert_eq!(jb.pop(), Some(vec![1, 1]));
+    // seq 0 is missing and concealed, then arrives too late.
+    assert_eq!(jb.pop(), Some(vec![0, 0]));
+    jb.push(0, &chunk(2));
+    assert_eq!(jb.pop(), Some(vec![3, 3]));
+    assert_eq!((jb.lost(), jb.late()), (1, 1));
+    // running dry restarts buffering.
+    assert_eq!(jb.pop(), None);
+    jb.push(2, &chunk(4));
+    assert_eq!(jb.pop(), None);
+    jb.handle(&AudioMessage::Chunk { seq: 3, data: chunk(5) });
+    jb.handle(&AudioMessage::Chunk { seq: 4, data: chunk(6) });
+    assert_eq!(jb.pop(), Some(vec![4, 4]));
+    assert_eq!(jb.len(), 2);
+}
+
+#[test]
+fn test_arm_client() {
+    use super::arm::*;
+
+    roundtrip(ArmMessage::SetPositions { first: 1, duration_ms: 500, positions: vec![-1, 2] });
+    roundtrip(ArmMessage::SetVelocities { first: 0, velocities: vec![3] });
+    roundtrip(ArmMessage::Stop);
+    roundtrip(ArmMessage::State { first: 2, positions: vec![100, -100], velocities: vec![0, 5] });
+
+    let mut arm = ArmClient::new(Vec::new(), 6, 3);
+    assert!(arm.move_to(&[0.0, 1.0], Duration::from_secs(1)).is_err());
+    arm.handle(&ArmMessage::State { first: 0, positions: vec![0, 500, 1000], velocities: vec![0, 0, 0] });
+    assert!(arm.reached(&[0.0, 0.5, 1.0], 1e-9));
+    let d = arm.move_to_at(&[0.5, 0.5, 0.0], 2.0).unwrap();
+    assert_eq!(d, Duration::from_millis(500));
+    assert_eq!(ArmMessage::from_packet(&arm.sender()[0]),
+        Some(ArmMessage::SetPositions { first: 0, duration_ms: 500, positions: vec![500, 500, 0] }));
+    for v in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+        assert_eq!(arm.move_to_at(&[0.5, 0.5, 0.0], v).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+    assert!(arm.move_to_at(&[f64::INFINITY, 0.5, 0.0], 2.0).is_err());
+    assert!(arm.move_to_at(&[0.5, 0.5, 0.0], 1e-300).is_err());
+    assert_eq!(arm.sender().len(), 1);
+    arm.handle(&ArmMessage::State { first: 2, positions: vec![-250], velocities: vec![-1500] });
+    assert_eq!(arm.joints()[2], JointState { position: -0.25, velocity: -1.5 });
+    assert!(!arm.reached(&[0.5, 0.5, 0.0], 0.1));
+}
+
+#[test]
+fn test_gripper_client() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread;
+    use super::gripper::*;
+
+    roundtrip(GripperMessage::Open);
+    roundtrip(GripperMessage::Close { force_cn: 500 });
+    roundtrip(GripperMessage::SetPosition { position: 250 });
+    roundtrip(GripperMessage::SetForce { force_cn: 1 });
+    roundtrip(GripperMessage::State { position: 1000, force_cn: 0, moving: true });
+    roundtrip(GripperMessage::GraspDetected { position: 400, force_cn: 520 });
+    roundtrip(GripperMessage::Closed);
+
+    struct Noop;
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+    let waker = Arc::new(Noop).into();
+    let mut cx = Context::from_waker(&waker);
+
+    let mut g = GripperClient::new(Vec::new(), 7);
+    let monitor = g.monitor();
+    let mut fut = g.close_until_force(5.0).unwrap();
+    assert_eq!(GripperMessage::from_packet(&g.sender()[0]), Some(GripperMessage::Close { force_cn: 500 }));
+    assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Pending);
+    monitor.handle(&GripperMessage::State { position: 500, force_cn: 100, moving: true });
+    assert_eq!(monitor.state(), GripperState { position: 0.5, force: 1.0, moving: true });
+    assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Pending);
+    monitor.handle(&GripperMessage::GraspDetected { position: 400, force_cn: 520 });
+    assert_eq!(Pin::new(&mut fut).poll(&mut cx), Poll::Ready(Some(Grasp { position: 0.4, force: 5.2 })));
+
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        monitor.handle(&GripperMessage::Closed);
+    });
+    assert_eq!(g.close_until_force_blocking(5.0, Duration::from_secs(5)).unwrap(), None);
+    t.join().unwrap();
+    let err = g.close_until_force_blocking(5.0, Duration::from_millis(10)).unwrap_err();
+    assert_eq!(err.kind(), ::std::io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn test_stepper_client() {
+    use super::stepper::*;
+
+    roundtrip(StepperMessage::MoveTo { motor: 1, position: -200_000 });
+    roundtrip(StepperMessage::SetProfile { motor: 1, max_speed: 3200, accel: 800 });
+    roundtrip(StepperMessage::SetMicrosteps { motor: 0, microsteps: 16 });
+    roundtrip(StepperMessage::Home { motor: 2, forward: false, speed: 400 });
+    roundtrip(StepperMessage::Stop { motor: 2 });
+    roundtrip(StepperMessage::MoveComplete { motor: 1, position: 42 });
+    roundtrip(StepperMessage::HomeComplete { motor: 1 });
+
+    let sent = |s: &StepperClient<Vec<Packet>>| -> Vec<StepperMessage> {
+        s.sender().iter().map(|p| StepperMessage::from_packet(p).unwrap()).collect()
+    };
+    let mut s = StepperClient::new(Vec::new(), 4);
+    s.home(0, false, 400).unwrap();
+    s.set_profile(0, 1000, 500).unwrap();
+    s.move_to(0, 1600).unwrap();
+    s.move_to(0, 0).unwrap();
+    assert_eq!(sent(&s), vec![StepperMessage::Home { motor: 0, forward: false, speed: 400 }]);
+    assert_eq!((s.queued(0), s.position(0)), (3, None));
+    // other motors are independent.
+    s.move_to(1, 10).unwrap();
+    assert_eq!(sent(&s).len(), 2);
+
+    s.handle(&StepperMessage::HomeComplete { motor: 0 }).unwrap();
+    assert_eq!(&sent(&s)[2..], &[
+        StepperMessage::SetProfile { motor: 0, max_speed: 1000, accel: 500 },
+        StepperMessage::MoveTo { motor: 0, position: 1600 },
+    ]);
+    assert_eq!(s.position(0), Some(0));
+    s.handle(&StepperMessage::MoveComplete { motor: 0, position: 1600 }).unwrap();
+    assert_eq!(sent(&s)[4], StepperMessage::MoveTo { motor: 0, position: 0 });
+    assert!(!s.is_idle(0));
+    s.stop(0).unwrap();
+    assert!(s.is_idle(0));
+    assert_eq!(s.position(0), Some(1600));
+}
+
+#[test]
+fn test_line_reading() {
+    use super::line::*;
+
+    roundtrip(LineMessage { bits: 4, samples: vec![1, 15, 0, 7, 9] });
+    roundtrip(LineMessage { bits: 6, samples: vec![63, 0, 21, 42, 1, 2, 3] });
+    roundtrip(LineMessage { bits: 8, samples: vec![0, 128, 255] });
+    let pkt = LineMessage { bits: 4, samples: vec![1, 2, 3] }.to_packet(3);
+    assert_eq!(pkt.data, vec![0x01, 4, 3, 0x21, 0x03]);
+    assert_eq!(LineMessage::decode(&[0x01, 9, 1, 0, 0]), None);
+    assert_eq!(LineMessage::decode(&[0x01, 4, 3, 0x21]), None);
+    // bits out of range are clamped, and the count capped, as decode expects.
+    let msg = LineMessage { bits: 9, samples: vec![0xff, 0x80] };
+    assert_eq!(LineMessage::from_packet(&msg.to_packet(3)), Some(LineMessage { bits: 8, samples: vec![0xff, 0x80] }));
+    let msg = LineMessage { bits: 0, samples: vec![3, 17] };
+    assert_eq!(LineMessage::from_packet(&msg.to_packet(3)), Some(LineMessage { bits: 4, samples: vec![3, 1] }));
+    let msg = LineMessage { bits: 4, samples: vec![1; 300] };
+    assert_eq!(LineMessage::from_packet(&msg.to_packet(3)).unwrap().samples.len(), 255);
+
+    let mut est = LineEstimator::new().with_light_line();
+    assert_eq!(est.update(&LineMessage { bits: 4, samples: vec![0, 0, 0] }), None);
+    assert_eq!(est.update(&LineMessage { bits: 4, samples: vec![15, 15, 0] }), Some(-0.5));
+    assert_eq!(est.update(&LineMessage { bits: 4, samples: vec![0, 1, 0] }), None);
+    assert_eq!(est.last(), Some(-0.5));
+}
+
+#[test]
+fn test_touch_latch() {
+    use super::touch::*;
+
+    roundtrip(TouchMessage::Contact { switch: 3, pressed: true });
+    roundtrip(TouchMessage::SetDebounce { switch: 3, debounce_ms: 20 });
+
+    let ms = Duration::from_millis;
+    let mut latch = TouchLatch::new();
+    assert!(!latch.was_pressed_since(0, ms(0)));
+    latch.update(&TouchMessage::Contact { switch: 0, pressed: true }, ms(100));
+    latch.update(&TouchMessage::Contact { switch: 0, pressed: true }, ms(150));
+    assert!(latch.is_pressed(0) && latch.any_pressed());
+    latch.update(&TouchMessage::Contact { switch: 0, pressed: false }, ms(200));
+    assert!(!latch.is_pressed(0) && !latch.any_pressed());
+    assert!(latch.was_pressed_since(0, ms(100)));
+    assert!(!latch.was_pressed_since(0, ms(101)));
+    assert!(!latch.was_pressed_since(1, ms(0)));
+    latch.update(&TouchMessage::Contact { switch: 0, pressed: true }, ms(300));
+    assert_eq!((latch.presses(0), latch.last_press(0)), (2, Some(ms(300))));
+}
+
+#[test]
+fn test_env_monitor() {
+    use std::sync::{Arc, Mutex};
+    use super::env::*;
+
+    roundtrip(EnvMessage::Reading { sensor: 1, quantity: Quantity::Temperature, value: -1250 });
+    roundtrip(EnvMessage::SetAlarm { sensor: 1, quantity: Quantity::Pressure, low: 90_000, high: 110_000 });
+    roundtrip(EnvMessage::Alarm { sensor: 2, quantity: Quantity::Humidity, level: AlarmLevel::High, value: 9500 });
+    assert_eq!(Quantity::Temperature.to_fixed(36.6), 3660);
+
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let mut mon = EnvMonitor::new();
+    {
+        let fired = fired.clone();
+        mon.on_alarm(move |sensor, _, level, _| fired.lock().unwrap().push((sensor, level)));
+    }
+    let t = Duration::from_secs(1);
+    mon.update(&EnvMessage::Reading { sensor: 0, quantity: Quantity::Temperature, value: 4512 }, t);
+    assert_eq!(mon.get(0, Quantity::Temperature), Some(&EnvSample { value: 45.12, alarm: AlarmLevel::Clear, ts: t }));
+    assert_eq!(mon.get(0, Quantity::Humidity), None);
+    let hot = EnvMessage::Alarm { sensor: 0, quantity: Quantity::Temperature, level: AlarmLevel::High, value: 8000 };
+    mon.update(&hot, t);
+    mon.update(&hot, t);
+    assert_eq!(mon.alarms(), vec![(0, Quantity::Temperature, AlarmLevel::High)]);
+    mon.update(&EnvMessage::Alarm { sensor: 0, quantity: Quantity::Temperature, level: AlarmLevel::Clear, value: 7000 }, t);
+    assert!(mon.alarms().is_empty());
+    assert_eq!(*fired.lock().unwrap(), vec![(0, AlarmLevel::High), (0, AlarmLevel::Clear)]);
+}
+
+#[test]
+fn test_gnss_fix() {
+    use super::gnss::*;
+
+    let pos = GnssMessage::Position {
+        lat_e7: 473_977_420,
+        lon_e7: 85_455_940,
+        alt_cm: 48_812,
+        quality: FixQuality::Dgps,
+        sats: 9,
+        hdop_centi: 180,
+    };
+    roundtrip(pos);
+    roundtrip(GnssMessage::Velocity { north_cms: -120, east_cms: 35, up_cms: 0 });
+    assert_eq!(pos.to_packet(0).data.len(), 17);
+
+    let fix = GnssFix::from_message(&pos, Duration::from_secs(1)).unwrap();
+    assert!((fix.lat - 47.397742).abs() < 1e-9 && (fix.lon - 8.545594).abs() < 1e-9);
+    assert!((fix.alt - 488.12).abs() < 1e-9);
+    assert!((fix.horizontal_error() - 1.8).abs() < 1e-9);
+    let mut nofix = pos;
+    if let GnssMessage::Position { ref mut quality, .. } = nofix {
+        *quality = FixQuality::NoFix;
+    }
+    assert_eq!(GnssFix::from_message(&nofix, Duration::from_secs(1)), None);
+    let v = enu_velocity(&GnssMessage::Velocity { north_cms: -120, east_cms: 35, up_cms: 0 }).unwrap();
+    assert!((v.x - 0.35).abs() < 1e-9 && (v.y + 1.2).abs() < 1e-9);
+
+    let frame = LocalFrame::new(fix.lat, fix.lon);
+    // 0.001 degree of latitude is about 111m.
+    let (x, y) = frame.to_local(fix.lat + 0.001, fix.lon);
+    assert!(x.abs() < 1e-9 && (y - 111.19).abs() < 0.01);
+    let (lat, lon) = frame.to_geodetic(30.0, -40.0);
+    let (x, y) = frame.to_local(lat, lon);
+    assert!((x - 30.0).abs() < 1e-6 && (y + 40.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_lidar_scan() {
+    use super::lidar::*;
+
+    roundtrip(LidarMessage::Start { rpm: 600 });
+    roundtrip(LidarMessage::Stop);
+
+    // 360 one-degree ranges in 8 segments, starting at 270 degrees.
+    let segments: Vec<Segment> = (0..8)
+        .map(|i| Segment {
+            start_cdeg: ((27000 + i * 4500) % 36000) as u16,
+            increment_cdeg: 100,
+            ranges_mm: (0..45).map(|j| if j == 0 { 0 } else { 1000 + j as u16 }).collect(),
+        })
+        .collect();
+    assert!(segments[0].ranges_mm.len() <= MAX_SEGMENT_RANGES);
+    let msgs = scan_messages(3, &segments);
+    for m in &msgs {
+        assert!(m.to_packet(2).data.len() <= ::l0::comm::PACKET_MAX_DATA_LEN);
+        roundtrip(m.clone());
+    }
+    let mut rx = ScanReceiver::new();
+    let mut scan = None;
+    for m in msgs.iter().rev() {
+        scan = rx.push(m, Duration::from_millis(100));
+    }
+    let scan = scan.unwrap();
+    assert_eq!((scan.seq, scan.ranges.len(), scan.period), (3, 360, None));
+    assert!((scan.angle_min - 1.5 * ::std::f64::consts::PI).abs() < 1e-9);
+    assert!((scan.angle(90) - 2.0 * ::std::f64::consts::PI).abs() < 1e-9);
+    assert!(scan.ranges[45].is_nan());
+    assert!((scan.ranges[46] - 1.001).abs() < 1e-9);
+    let stats = scan.stats();
+    assert_eq!((stats.valid, stats.invalid), (352, 8));
+    assert!((stats.min - 1.001).abs() < 1e-9 && (stats.max - 1.044).abs() < 1e-9);
+    assert!((stats.mean - 1.0225).abs() < 1e-9);
+
+    let scan = rx.push(&scan_messages(4, &segments[..1])[0], Duration::from_millis(200)).unwrap();
+    assert_eq!((scan.ranges.len(), scan.period), (45, Some(Duration::from_millis(100))));
+}
+
+#[test]
+fn test_mode_observer() {
+    use std::sync::{Arc, Mutex};
+    use super::mode::*;
+
+    roundtrip(ModeMessage::Request { mode: Mode::Autonomous });
+    roundtrip(ModeMessage::Changed { from: Mode::Teleop, to: Mode::Fault, reason: 7 });
+    roundtrip(ModeMessage::Rejected { mode: Mode::Teleop, current: Mode::Estop });
+    assert_eq!(ModeMessage::decode(&[0x01, 9]), None);
+
+    assert!(Mode::Teleop.can_transition(Mode::Autonomous));
+    assert!(Mode::Disabled.can_transition(Mode::Estop));
+    assert!(!Mode::Estop.can_transition(Mode::Teleop));
+    assert!(Mode::Fault.can_transition(Mode::Disabled));
+    assert!(!Mode::Teleop.can_transition(Mode::Teleop));
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut obs = ModeObserver::new();
+    {
+        let seen = seen.clone();
+        obs.on_transition(None, Some(Mode::Estop), move |from, _, reason| seen.lock().unwrap().push((from, reason)));
+    }
+    assert_eq!(obs.request(Mode::Teleop), Some(ModeMessage::Request { mode: Mode::Teleop }));
+    obs.handle(&ModeMessage::Changed { from: Mode::Disabled, to: Mode::Teleop, reason: 0 });
+    obs.handle(&ModeMessage::Changed { from: Mode::Teleop, to: Mode::Estop, reason: 3 });
+    assert_eq!(obs.mode(), Mode::Estop);
+    assert_eq!(obs.request(Mode::Autonomous), None);
+    obs.handle(&ModeMessage::Rejected { mode: Mode::Disabled, current: Mode::Estop });
+    assert_eq!(obs.rejected(), Some(Mode::Disabled));
+    assert_eq!(*seen.lock().unwrap(), vec![(Mode::Teleop, 3)]);
+}
+
+#[test]
+fn test_imu_calibration() {
+    use super::calibration::*;
+
+    roundtrip(CalibrationMessage::StreamRaw { rate_hz: 100 });
+    roundtrip(CalibrationMessage::StopRaw);
+    roundtrip(CalibrationMessage::Write { sensor: Sensor::Mag, offset: [-5, 0, 7], scale_q14: 16384 });
+    roundtrip(CalibrationMessage::Written { sensor: Sensor::Accel, ok: true });
+
+    // points on a sphere of radius 2 around (1, -1, 0.5).
+    let dirs = [(1.0, 0.0, 0.0), (-1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, -1.0, 0.0), (0.0, 0.0, 1.0), (0.0, 0.0, -1.0)];
+    let sphere = |c: Vec3, r: f64| -> Vec<Vec3> {
+        dirs.iter().map(|d| Vec3::new(c.x + d.0 * r, c.y + d.1 * r, c.z + d.2 * r)).collect()
+    };
+    let (c, r) = fit_sphere(&sphere(Vec3::new(1.0, -1.0, 0.5), 2.0)).unwrap();
+    assert!((c.x - 1.0).abs() < 1e-9 && (c.y + 1.0).abs() < 1e-9 && (c.z - 0.5).abs() < 1e-9);
+    assert!((r - 2.0).abs() < 1e-9);
+    assert_eq!(fit_sphere(&[Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)]), None);
+
+    let mut cal = Calibrator::new(6);
+    assert_eq!(cal.start(50), CalibrationMessage::StreamRaw { rate_hz: 50 });
+    for _ in 0..6 {
+        cal.push(&ImuMessage::Motion { ts_ms: 0, accel: [0, 0, 1000], gyro: [10, -20, 5] });
+    }
+    assert_eq!(cal.step(), CalibrationStep::Accel);
+    let g = cal.calibration(Sensor::Gyro).unwrap();
+    assert!((g.offset.y + 0.02).abs() < 1e-9);
+    // accelerometer reading 2% high with an offset on x.
+    for p in sphere(Vec3::new(0.2, 0.0, 0.0), 1.02 * 9.80665) {
+        cal.push(&ImuMessage::Motion { ts_ms: 0, accel: p.to_fixed(ACCEL_LSB), gyro: [0; 3] });
+        // moving samples are ignored.
+        cal.push(&ImuMessage::Motion { ts_ms: 0, accel: [0; 3], gyro: [500, 0, 0] });
+    }
+    assert_eq!(cal.step(), CalibrationStep::Mag);
+    let a = cal.calibration(Sensor::Accel).unwrap();
+    assert!((a.scale - 1.0 / 1.02).abs() < 1e-3 && (a.offset.x - 0.2).abs() < 0.01);
+    for p in sphere(Vec3::new(-20.0, 5.0, 3.0), 45.0) {
+        cal.push(&ImuMessage::Mag { ts_ms: 0, mag: p.to_fixed(MAG_LSB) });
+    }
+    assert_eq!(cal.step(), CalibrationStep::Done);
+    let msgs = cal.write();
+    assert_eq!(msgs.len(), 4);
+    assert_eq!(msgs[2], CalibrationMessage::Write { sensor: Sensor::Mag, offset: [-200, 50, 30], scale_q14: 16384 });
+    assert_eq!(msgs[3], CalibrationMessage::StopRaw);
+}
+
+#[test]
+fn schema_describe() {
+    use super::schema::*;
+
+    let schema: Schema = "0x01=motor, 129=encoder".parse().unwrap();
+    assert_eq!(schema.get(0x81), Some(MessageSet::Encoder));
+    assert_eq!(schema.code_of(MessageSet::Motor), Some(0x01));
+    let pkt = MotorMessage::set_velocities(0, &[1.0, -1.0]).to_packet(0x01);
+    assert_eq!(schema.describe(&pkt).unwrap(), "SetVelocities { first: 0, velocities: [100, -100] }");
+    assert!(schema.validate(&pkt).is_ok());
+    let mut bad = pkt.clone();
+    bad.data.push(0);
+    assert!(schema.validate(&bad).is_err());
+    assert!(schema.validate(&Packet::new_with(0, 0x05)).is_ok());
+
+    assert!("0x01=warp".parse::<Schema>().is_err());
+    assert!("x=motor".parse::<Schema>().is_err());
+}
+
+// Checks each layout against the decoder: a payload of the layout's length
+// decodes for some field values, one byte more or less never does.
+#[test]
+fn test_layouts_match_decoders() {
+    for set in MessageSet::ALL.iter().cloned() {
+        for l in set.layouts() {
+            let decodes = |len: usize| len > 0 && (0..=255u8).any(|fill| {
+                let mut data: Vec<u8> = (0..len).map(|i| fill.wrapping_add(i as u8)).collect();
+                data[0] = l.op;
+                set.describe(&data).is_some()
+            });
+            match l.rest() {
+                Some(f) => assert!((0..4).any(|n| decodes(l.min_len() + n * f.ty.size())), "{} {}", set.name(), l.name),
+                None => {
+                    assert!(decodes(l.min_len()), "{} {}", set.name(), l.name);
+                    assert!(!decodes(l.min_len() - 1) && !decodes(l.min_len() + 1), "{} {}", set.name(), l.name);
+                },
+            }
+            assert_eq!(set.layouts().iter().filter(|o| o.op == l.op).count(), 1);
+        }
+    }
+}
+
+#[test]
+fn test_jitter_buffer() {
+    use std::time::{Duration, Instant};
+    use super::jitter::JitterBuffer;
+
+    let ms = Duration::from_millis;
+    let t = Instant::now();
+    let mut jb = JitterBuffer::new(ms(50), 8);
+    // samples 10 ms apart arrive in bursts, 3 out of order.
+    jb.push_at(ms(0), 0, t);
+    jb.push_at(ms(10), 1, t + ms(30));
+    jb.push_at(ms(30), 3, t + ms(30));
+    jb.push_at(ms(20), 2, t + ms(31));
+    assert_eq!(jb.pop_at(t + ms(49)), None);
+    assert_eq!(jb.pop_at(t + ms(50)), Some((ms(0), 0)));
+    assert_eq!(jb.pop_at(t + ms(55)), None);
+    assert_eq!(jb.pop_at(t + ms(60)), Some((ms(10), 1)));
+    let mut out = Vec::new();
+    while let Some((_, s)) = jb.pop_at(t + ms(80)) {
+        out.push(s);
+    }
+    assert_eq!(out, vec![2, 3]);
+
+    jb.push_at(ms(20), 2, t + ms(85));
+    assert_eq!(jb.late(), 1);
+    assert!(jb.is_empty());
+    for i in 4..14 {
+        jb.push_at(ms(i * 10), i, t + ms(90));
+    }
+    assert_eq!(jb.len(), 8);
+    assert_eq!(jb.pop_at(t + ms(200)), Some((ms(60), 6)));
+
+    jb.reset();
+    jb.push_at(ms(0), 0, t + ms(300));
+    assert_eq!(jb.playout(ms(0)), Some(t + ms(350)));
+}
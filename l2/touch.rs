@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use super::Message;
+use super::codec::*;
+use super::layout::{field, Layout, U16, U8};
+
+const OP_CONTACT: u8 = 0x01;
+const OP_SET_DEBOUNCE: u8 = 0x02;
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout::new("contact", OP_CONTACT, &[field("switch", U8), field("pressed", U8)]),
+    Layout::new("set_debounce", OP_SET_DEBOUNCE, &[field("switch", U8), field("debounce_ms", U16)]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchMessage {
+    // A bumper or limit switch changed state, after the device debounced it.
+    Contact { switch: u8, pressed: bool },
+    // How long a switch must be stable before Contact is reported.
+    SetDebounce { switch: u8, debounce_ms: u16 },
+}
+
+impl Message for TouchMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            TouchMessage::Contact { switch, pressed } => {
+                data.push(OP_CONTACT);
+                data.push(switch);
+                data.push(pressed as u8);
+            },
+            TouchMessage::SetDebounce { switch, debounce_ms } => {
+                data.push(OP_SET_DEBOUNCE);
+                data.push(switch);
+                put_u16(data, debounce_ms);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_CONTACT => TouchMessage::Contact { switch: r.u8()?, pressed: r.u8()? != 0 },
+            OP_SET_DEBOUNCE => TouchMessage::SetDebounce { switch: r.u8()?, debounce_ms: r.u16()? },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Switch {
+    pressed: bool,
+    last_press: Option<Duration>,
+    presses: u64,
+}
+
+// Remembers contact events so a brief bump is not missed by logic that
+// only polls now and then, e.g. a homing loop or a collision check.
+#[derive(Default)]
+pub struct TouchLatch {
+    switches: HashMap<u8, Switch>,
+}
+
+impl TouchLatch {
+    pub fn new() -> Self {
+        TouchLatch::default()
+    }
+
+    // ts is the host time the event was received.
+    pub fn update(&mut self, msg: &TouchMessage, ts: Duration) {
+        if let TouchMessage::Contact { switch, pressed } = *msg {
+            let s = self.switches.entry(switch).or_default();
+            if pressed && !s.pressed {
+                s.last_press = Some(ts);
+                s.presses += 1;
+            }
+            s.pressed = pressed;
+        }
+    }
+
+    pub fn is_pressed(&self, switch: u8) -> bool {
+        self.switches.get(&switch).is_some_and(|s| s.pressed)
+    }
+
+    pub fn last_press(&self, switch: u8) -> Option<Duration> {
+        self.switches.get(&switch).and_then(|s| s.last_press)
+    }
+
+    // True if the switch was pressed at or after since, even if it has
+    // been released again.
+    pub fn was_pressed_since(&self, switch: u8, since: Duration) -> bool {
+        self.last_press(switch).is_some_and(|ts| ts >= since)
+    }
+
+    // Total presses, to detect presses between two polls.
+    pub fn presses(&self, switch: u8) -> u64 {
+        self.switches.get(&switch).map_or(0, |s| s.presses)
+    }
+
+    // Any switch currently pressed, e.g. for collision reaction.
+    pub fn any_pressed(&self) -> bool {
+        self.switches.values().any(|s| s.pressed)
+    }
+}
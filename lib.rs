@@ -1 +1,83 @@
+// Without std only l0 is built, with the parser, frame encoding and
+// EmbeddedLink, and the C API, for firmware.
+#![cfg_attr(not(feature = "std"), no_std)]
+// Firmware builds must not panic, so nothing built without std may unwrap.
+#![cfg_attr(not(feature = "std"), deny(clippy::unwrap_used, clippy::expect_used, clippy::panic))]
+
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "web")]
+extern crate axum;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "critical-section")]
+extern crate critical_section;
+#[cfg(feature = "keyboard")]
+extern crate crossterm;
+#[cfg(feature = "web")]
+extern crate futures_util;
+#[cfg(feature = "defmt")]
+extern crate defmt;
+#[cfg(feature = "embedded")]
+extern crate embedded_hal_nb;
+#[cfg(feature = "gamepad")]
+extern crate gilrs;
+#[cfg(feature = "serde_json")]
+#[macro_use]
+extern crate serde_json;
+#[cfg(feature = "otel")]
+extern crate opentelemetry;
+#[cfg(feature = "prometheus")]
+extern crate prometheus;
+#[cfg(feature = "web")]
+extern crate tokio;
+// Modules shared with no_std builds, and pyo3's macros, name ::core, which
+// the 2015 edition resolves from the root; without std it is already there.
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "python")]
+extern crate serialport;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "rosbridge")]
+extern crate tungstenite;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
 pub mod l0;
+#[cfg(feature = "std")]
+pub mod l2;
+#[cfg(feature = "std")]
+pub mod kinematics;
+#[cfg(feature = "std")]
+pub mod bridge;
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod control;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fmt;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod nav;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod mission;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod safety;
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(feature = "std")]
+pub mod teleop;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
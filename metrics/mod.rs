@@ -0,0 +1,7 @@
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(feature = "prometheus")]
+pub mod prom;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,84 @@
+use std::borrow::Cow;
+use std::time::Duration;
+use opentelemetry::KeyValue;
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use opentelemetry::trace::{Span, Tracer};
+use l0::comm::LinkStats;
+
+// Exports the traffic of one link as OpenTelemetry instruments, with the
+// device as an attribute. Counters are fed from LinkStats snapshots, so
+// the endpoint doesn't depend on the exporter. Resyncs are also reported
+// as spans, to line them up with traces from the rest of the system.
+pub struct LinkMetrics {
+    attributes: Vec<KeyValue>,
+    packets_sent: Counter<u64>,
+    packets_received: Counter<u64>,
+    bytes_sent: Counter<u64>,
+    bytes_received: Counter<u64>,
+    resyncs: Counter<u64>,
+    rpc_duration: Histogram<f64>,
+    tracer: BoxedTracer,
+    last: LinkStats,
+}
+
+impl LinkMetrics {
+    pub fn new(meter: &Meter, device: &str) -> Self {
+        let counter = |name: &'static str, unit: &'static str, description: &'static str| {
+            meter.u64_counter(name).with_unit(unit).with_description(description).build()
+        };
+        LinkMetrics {
+            attributes: vec![KeyValue::new("device", device.to_string())],
+            packets_sent: counter("robo.link.packets_sent", "{packet}", "Packets sent to the device"),
+            packets_received: counter("robo.link.packets_received", "{packet}", "Packets received from the device"),
+            bytes_sent: counter("robo.link.bytes_sent", "By", "Bytes written to the transport"),
+            bytes_received: counter("robo.link.bytes_received", "By", "Bytes read from the transport"),
+            resyncs: counter("robo.link.resyncs", "{resync}", "Handshakes after the first"),
+            rpc_duration: meter.f64_histogram("robo.rpc.duration")
+                .with_unit("s")
+                .with_description("Time from request to response")
+                .build(),
+            tracer: global::tracer("robo"),
+            last: LinkStats::default(),
+        }
+    }
+
+    // Adds what changed since the previous snapshot. Counts going backwards
+    // mean a new endpoint, which is counted from zero.
+    pub fn record(&mut self, stats: &LinkStats) {
+        let last = if stats.packets_sent < self.last.packets_sent
+            || stats.packets_received < self.last.packets_received {
+            LinkStats::default()
+        } else {
+            self.last
+        };
+        let attrs = &self.attributes;
+        self.packets_sent.add(stats.packets_sent.saturating_sub(last.packets_sent), attrs);
+        self.packets_received.add(stats.packets_received.saturating_sub(last.packets_received), attrs);
+        self.bytes_sent.add(stats.bytes_sent.saturating_sub(last.bytes_sent), attrs);
+        self.bytes_received.add(stats.bytes_received.saturating_sub(last.bytes_received), attrs);
+        let resyncs = stats.resyncs.saturating_sub(last.resyncs);
+        if resyncs > 0 {
+            self.resyncs.add(resyncs, attrs);
+            let mut span = self.tracer.start("robo.link.resync");
+            span.set_attributes(attrs.iter().cloned());
+            span.set_attribute(KeyValue::new("count", resyncs as i64));
+            span.end();
+        }
+        self.last = *stats;
+    }
+
+    // One request/response exchange; method names the request, e.g. the
+    // message set and op.
+    pub fn record_rpc(&self, method: &str, duration: Duration, ok: bool) {
+        let mut attrs = self.attributes.clone();
+        attrs.push(KeyValue::new("method", Cow::Owned(method.to_string())));
+        attrs.push(KeyValue::new("status", if ok { "ok" } else { "error" }));
+        self.rpc_duration.record(duration.as_secs_f64(), &attrs);
+    }
+
+    // The snapshot recorded last.
+    pub fn last(&self) -> &LinkStats {
+        &self.last
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use prometheus::{Encoder, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use l0::comm::LinkStats;
+
+fn counter(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let c = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+    registry.register(Box::new(c.clone())).unwrap();
+    c
+}
+
+// Per-device link, queue and RPC metrics in Prometheus text format. Link
+// counters are fed from LinkStats snapshots; queue depths and RPC outcomes
+// are reported by whoever owns the queue or issues the request.
+pub struct Exporter {
+    registry: Registry,
+    packets: IntCounterVec,
+    bytes: IntCounterVec,
+    resyncs: IntCounterVec,
+    queue_depth: IntGaugeVec,
+    rpc_requests: IntCounterVec,
+    rpc_errors: IntCounterVec,
+    last: Mutex<HashMap<String, LinkStats>>,
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Exporter::new()
+    }
+}
+
+impl Exporter {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("robo".to_string()), None).unwrap();
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("queue_depth", "Packets waiting in a queue"), &["device", "queue"]).unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        Exporter {
+            packets: counter(&registry, "link_packets_total", "Packets on the link", &["device", "direction"]),
+            bytes: counter(&registry, "link_bytes_total", "Bytes on the link", &["device", "direction"]),
+            resyncs: counter(&registry, "link_resyncs_total", "Handshakes after the first", &["device"]),
+            rpc_requests: counter(&registry, "rpc_requests_total", "Requests sent", &["device", "method"]),
+            rpc_errors: counter(&registry, "rpc_errors_total", "Requests failed or timed out", &["device", "method"]),
+            queue_depth,
+            registry,
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    // Adds what changed since the device's previous snapshot, counting from
+    // zero when the counts went backwards.
+    pub fn record_link(&self, device: &str, stats: &LinkStats) {
+        let mut last = self.last.lock().unwrap();
+        let prev = last.entry(device.to_string()).or_default();
+        if stats.packets_sent < prev.packets_sent || stats.packets_received < prev.packets_received {
+            *prev = LinkStats::default();
+        }
+        let add = |c: &IntCounterVec, labels: &[&str], now: u64, before: u64| {
+            c.with_label_values(labels).inc_by(now.saturating_sub(before));
+        };
+        add(&self.packets, &[device, "tx"], stats.packets_sent, prev.packets_sent);
+        add(&self.packets, &[device, "rx"], stats.packets_received, prev.packets_received);
+        add(&self.bytes, &[device, "tx"], stats.bytes_sent, prev.bytes_sent);
+        add(&self.bytes, &[device, "rx"], stats.bytes_received, prev.bytes_received);
+        add(&self.resyncs, &[device], stats.resyncs, prev.resyncs);
+        *prev = *stats;
+    }
+
+    pub fn set_queue_depth(&self, device: &str, queue: &str, depth: usize) {
+        self.queue_depth.with_label_values(&[device, queue]).set(depth as i64);
+    }
+
+    pub fn record_rpc(&self, device: &str, method: &str, ok: bool) {
+        self.rpc_requests.with_label_values(&[device, method]).inc();
+        if !ok {
+            self.rpc_errors.with_label_values(&[device, method]).inc();
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    // Serves GET /metrics on addr from a background thread.
+    pub fn serve<A: ToSocketAddrs>(exporter: Arc<Exporter>, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let mut line = String::new();
+                if BufReader::new(&stream).read_line(&mut line).is_err() {
+                    continue;
+                }
+                let resp = if line.starts_with("GET /metrics ") {
+                    let body = exporter.render();
+                    format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                        Content-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+        Ok(local)
+    }
+}
@@ -0,0 +1,51 @@
+#![cfg(test)]
+
+#[cfg(feature = "otel")]
+#[test]
+fn otel_link_metrics() {
+    use std::time::Duration;
+    use opentelemetry::global;
+    use l0::comm::LinkStats;
+    use super::otel::*;
+
+    let mut metrics = LinkMetrics::new(&global::meter("robo"), "base");
+    let stats = LinkStats { packets_sent: 3, packets_received: 5, bytes_sent: 20, bytes_received: 40, resyncs: 1, overflows: 0, ..LinkStats::default() };
+    metrics.record(&stats);
+    assert_eq!(*metrics.last(), stats);
+    // a replaced endpoint starts over.
+    let stats = LinkStats { packets_sent: 1, ..LinkStats::default() };
+    metrics.record(&stats);
+    assert_eq!(*metrics.last(), stats);
+    metrics.record_rpc("arm.move_to", Duration::from_millis(12), true);
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn prometheus_exporter() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use l0::comm::LinkStats;
+    use super::prom::*;
+
+    let exporter = Arc::new(Exporter::new());
+    exporter.record_link("base", &LinkStats { packets_sent: 4, packets_received: 6, bytes_sent: 30, bytes_received: 50, resyncs: 1, overflows: 0, ..LinkStats::default() });
+    exporter.record_link("base", &LinkStats { packets_sent: 5, packets_received: 6, bytes_sent: 40, bytes_received: 50, resyncs: 1, overflows: 0, ..LinkStats::default() });
+    exporter.set_queue_depth("base", "tx", 3);
+    exporter.record_rpc("base", "arm", true);
+    exporter.record_rpc("base", "arm", false);
+    let text = exporter.render();
+    assert!(text.contains("robo_link_packets_total{device=\"base\",direction=\"tx\"} 5"), "{}", text);
+    assert!(text.contains("robo_link_bytes_total{device=\"base\",direction=\"tx\"} 40"));
+    assert!(text.contains("robo_link_resyncs_total{device=\"base\"} 1"));
+    assert!(text.contains("robo_queue_depth{device=\"base\",queue=\"tx\"} 3"));
+    assert!(text.contains("robo_rpc_errors_total{device=\"base\",method=\"arm\"} 1"));
+
+    let addr = Exporter::serve(exporter.clone(), "127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: robo\r\n\r\n").unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).unwrap();
+    assert!(resp.starts_with("HTTP/1.1 200 OK"));
+    assert!(resp.ends_with(&text));
+}
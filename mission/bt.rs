@@ -0,0 +1,165 @@
+use l0::comm::Packet;
+use l2::Sender;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+// A behavior tree node ticked with a context C, typically holding the
+// sender for commands and the latest telemetry. reset() halts a node that
+// was Running and is no longer ticked.
+pub trait Node<C> {
+    fn tick(&mut self, ctx: &mut C) -> Status;
+    fn reset(&mut self) {}
+}
+
+pub type BoxNode<C> = Box<dyn Node<C> + Send>;
+
+struct Action<F>(F);
+
+impl<C, F: FnMut(&mut C) -> Status> Node<C> for Action<F> {
+    fn tick(&mut self, ctx: &mut C) -> Status {
+        (self.0)(ctx)
+    }
+}
+
+struct Condition<F>(F);
+
+impl<C, F: Fn(&C) -> bool> Node<C> for Condition<F> {
+    fn tick(&mut self, ctx: &mut C) -> Status {
+        if (self.0)(ctx) { Status::Success } else { Status::Failure }
+    }
+}
+
+struct SendPacket(Packet);
+
+impl<C: Sender> Node<C> for SendPacket {
+    fn tick(&mut self, ctx: &mut C) -> Status {
+        match ctx.send(self.0.clone()) {
+            Ok(()) => Status::Success,
+            Err(_) => Status::Failure,
+        }
+    }
+}
+
+// Ticks children in order each tick, so earlier children (e.g. conditions)
+// are re-checked while a later one runs. Sequence stops at the first child
+// that doesn't succeed, Selector at the first that doesn't fail.
+struct Composite<C> {
+    children: Vec<BoxNode<C>>,
+    next_on: Status, // the status that moves on to the next child.
+    running: Option<usize>,
+}
+
+impl<C> Node<C> for Composite<C> {
+    fn tick(&mut self, ctx: &mut C) -> Status {
+        for i in 0..self.children.len() {
+            let status = self.children[i].tick(ctx);
+            if status == self.next_on {
+                continue;
+            }
+            if let Some(r) = self.running {
+                if r != i {
+                    self.children[r].reset();
+                }
+            }
+            self.running = if status == Status::Running { Some(i) } else { None };
+            return status;
+        }
+        self.running = None;
+        self.next_on
+    }
+
+    fn reset(&mut self) {
+        if let Some(r) = self.running.take() {
+            self.children[r].reset();
+        }
+    }
+}
+
+struct Invert<C>(BoxNode<C>);
+
+impl<C> Node<C> for Invert<C> {
+    fn tick(&mut self, ctx: &mut C) -> Status {
+        match self.0.tick(ctx) {
+            Status::Success => Status::Failure,
+            Status::Failure => Status::Success,
+            Status::Running => Status::Running,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+struct Retry<C> {
+    child: BoxNode<C>,
+    attempts: usize,
+    failed: usize,
+}
+
+impl<C> Node<C> for Retry<C> {
+    fn tick(&mut self, ctx: &mut C) -> Status {
+        match self.child.tick(ctx) {
+            Status::Failure => {
+                self.failed += 1;
+                if self.failed < self.attempts {
+                    Status::Running
+                } else {
+                    self.failed = 0;
+                    Status::Failure
+                }
+            },
+            status => {
+                if status == Status::Success {
+                    self.failed = 0;
+                }
+                status
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        self.failed = 0;
+        self.child.reset();
+    }
+}
+
+// Leaf running f on every tick.
+pub fn action<C, F>(f: F) -> BoxNode<C>
+    where F: FnMut(&mut C) -> Status + Send + 'static, C: 'static {
+    Box::new(Action(f))
+}
+
+// Leaf succeeding when f holds, e.g. a telemetry query.
+pub fn condition<C, F>(f: F) -> BoxNode<C>
+    where F: Fn(&C) -> bool + Send + 'static, C: 'static {
+    Box::new(Condition(f))
+}
+
+// Leaf sending pkt through the context, failing if the send does.
+pub fn send<C: Sender + 'static>(pkt: Packet) -> BoxNode<C> {
+    Box::new(SendPacket(pkt))
+}
+
+pub fn sequence<C: 'static>(children: Vec<BoxNode<C>>) -> BoxNode<C> {
+    Box::new(Composite { children, next_on: Status::Success, running: None })
+}
+
+pub fn selector<C: 'static>(children: Vec<BoxNode<C>>) -> BoxNode<C> {
+    Box::new(Composite { children, next_on: Status::Failure, running: None })
+}
+
+pub fn invert<C: 'static>(child: BoxNode<C>) -> BoxNode<C> {
+    Box::new(Invert(child))
+}
+
+// Re-runs child on following ticks until it succeeds or failed attempts
+// times.
+pub fn retry<C: 'static>(attempts: usize, child: BoxNode<C>) -> BoxNode<C> {
+    Box::new(Retry { child, attempts, failed: 0 })
+}
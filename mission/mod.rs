@@ -0,0 +1,8 @@
+mod sequencer;
+
+pub mod bt;
+
+pub use self::sequencer::*;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,366 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::time::Duration;
+use l0::comm::Packet;
+use l2::Sender;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    TimedOut,     // the last Expect timed out.
+    Flag(String), // set by the host with set_flag().
+    // data[index] of the packet that satisfied the last Expect.
+    EventByte { index: usize, value: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Send(Packet),
+    Wait(Duration),
+    // Waits for a packet with code whose data starts with prefix. On timeout
+    // the mission continues with TimedOut set.
+    Expect { code: u8, prefix: Vec<u8>, timeout: Duration },
+    // Jumps to step target if cond holds (or doesn't, when negated). A
+    // target one past the last step ends the mission.
+    Goto { target: usize, cond: Condition, negate: bool },
+}
+
+// A list of steps with a line based text form, one step per line:
+//
+//   send 0x10 01 02        packet code, then data bytes in hex
+//   wait 500               milliseconds
+//   expect 0x90 01 timeout 2000
+//   goto 0 if !timeout     also "if flag NAME", "if byte 1 == 0x05"
+//
+// Blank lines and anything after '#' are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mission {
+    pub steps: Vec<Step>,
+}
+
+fn invalid(line: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+fn parse_u8(s: &str) -> Option<u8> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn parse_bytes<'a, I: Iterator<Item = &'a str>>(toks: I) -> Option<Vec<u8>> {
+    toks.map(|t| u8::from_str_radix(t, 16).ok()).collect()
+}
+
+fn parse_step(toks: &[&str]) -> Option<Step> {
+    let ms = |s: &str| s.parse().ok().map(Duration::from_millis);
+    match toks[0] {
+        "send" => {
+            let mut pkt = Packet::new_with(0, parse_u8(toks.get(1)?)?);
+            pkt.data = parse_bytes(toks[2..].iter().cloned())?;
+            Some(Step::Send(pkt))
+        },
+        "wait" if toks.len() == 2 => Some(Step::Wait(ms(toks[1])?)),
+        "expect" if toks.len() >= 4 && toks[toks.len() - 2] == "timeout" => Some(Step::Expect {
+            code: parse_u8(toks[1])?,
+            prefix: parse_bytes(toks[2..toks.len() - 2].iter().cloned())?,
+            timeout: ms(toks[toks.len() - 1])?,
+        }),
+        "goto" => {
+            let target = toks.get(1)?.parse().ok()?;
+            if toks.len() == 2 {
+                return Some(Step::Goto { target, cond: Condition::Always, negate: false });
+            }
+            if toks[2] != "if" {
+                return None;
+            }
+            let (negate, first) = match toks.get(3)?.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, toks[3]),
+            };
+            let cond = match (first, &toks[4..]) {
+                ("timeout", []) => Condition::TimedOut,
+                ("flag", [name]) => Condition::Flag(name.to_string()),
+                ("byte", [index, "==", value]) => Condition::EventByte {
+                    index: index.parse().ok()?,
+                    value: parse_u8(value)?,
+                },
+                _ => return None,
+            };
+            Some(Step::Goto { target, cond, negate })
+        },
+        _ => None,
+    }
+}
+
+impl FromStr for Mission {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let mut steps = Vec::new();
+        let mut gotos = Vec::new();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let toks: Vec<&str> = line.split_whitespace().collect();
+            if toks.is_empty() {
+                continue;
+            }
+            let step = parse_step(&toks).ok_or_else(|| invalid(n + 1, "invalid step"))?;
+            if let Step::Goto { target, .. } = step {
+                gotos.push((n + 1, target));
+            }
+            steps.push(step);
+        }
+        if let Some(&(n, _)) = gotos.iter().find(|g| g.1 > steps.len()) {
+            return Err(invalid(n, "goto target out of range"));
+        }
+        Ok(Mission { steps })
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Step::Send(ref pkt) => {
+                write!(f, "send 0x{:02x}", pkt.code)?;
+                for b in &pkt.data {
+                    write!(f, " {:02x}", b)?;
+                }
+                Ok(())
+            },
+            Step::Wait(d) => write!(f, "wait {}", d.as_millis()),
+            Step::Expect { code, ref prefix, timeout } => {
+                write!(f, "expect 0x{:02x}", code)?;
+                for b in prefix {
+                    write!(f, " {:02x}", b)?;
+                }
+                write!(f, " timeout {}", timeout.as_millis())
+            },
+            Step::Goto { target, ref cond, negate } => {
+                write!(f, "goto {}", target)?;
+                let not = if negate { "!" } else { "" };
+                match *cond {
+                    Condition::Always => Ok(()),
+                    Condition::TimedOut => write!(f, " if {}timeout", not),
+                    Condition::Flag(ref name) => write!(f, " if {}flag {}", not, name),
+                    Condition::EventByte { index, value } => write!(f, " if {}byte {} == 0x{:02x}", not, index, value),
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Mission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for s in &self.steps {
+            writeln!(f, "{}", s)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequencerState {
+    Idle,
+    Running,
+    Paused,
+    Completed,
+    Aborted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequencerEvent {
+    StepStarted(usize),
+    Paused(usize),
+    Resumed(usize),
+    Completed,
+    Aborted(usize),
+}
+
+type EventCallback = Box<dyn FnMut(&SequencerEvent) + Send>;
+
+// Executes a mission, sending packets through sender and fed received
+// packets via handle(). poll() must be called regularly to advance timed
+// steps; all times are host timestamps.
+pub struct Sequencer<S> {
+    mission: Mission,
+    sender: S,
+    state: SequencerState,
+    step: usize,
+    deadline: Option<Duration>,
+    remaining: Option<Duration>, // of the current wait while paused.
+    timed_out: bool,
+    event: Option<Packet>,
+    flags: HashSet<String>,
+    callbacks: Vec<EventCallback>,
+}
+
+impl<S: Sender> Sequencer<S> {
+    pub fn new(mission: Mission, sender: S) -> Self {
+        Sequencer {
+            mission,
+            sender,
+            state: SequencerState::Idle,
+            step: 0,
+            deadline: None,
+            remaining: None,
+            timed_out: false,
+            event: None,
+            flags: HashSet::new(),
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn on_event<F>(&mut self, callback: F)
+        where F: FnMut(&SequencerEvent) + Send + 'static {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    pub fn state(&self) -> SequencerState {
+        self.state
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn sender(&self) -> &S {
+        &self.sender
+    }
+
+    // Steps done over the mission length.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.step, self.mission.steps.len())
+    }
+
+    pub fn set_flag(&mut self, name: &str, set: bool) {
+        if set {
+            self.flags.insert(name.to_string());
+        } else {
+            self.flags.remove(name);
+        }
+    }
+
+    pub fn start(&mut self, now: Duration) -> io::Result<()> {
+        self.step = 0;
+        self.deadline = None;
+        self.timed_out = false;
+        self.event = None;
+        self.state = SequencerState::Running;
+        self.enter(now)
+    }
+
+    pub fn pause(&mut self, now: Duration) {
+        if self.state == SequencerState::Running {
+            self.remaining = self.deadline.map(|d| d.saturating_sub(now));
+            self.state = SequencerState::Paused;
+            self.emit(SequencerEvent::Paused(self.step));
+        }
+    }
+
+    pub fn resume(&mut self, now: Duration) -> io::Result<()> {
+        if self.state != SequencerState::Paused {
+            return Ok(());
+        }
+        self.deadline = self.remaining.take().map(|r| now + r);
+        self.state = SequencerState::Running;
+        self.emit(SequencerEvent::Resumed(self.step));
+        self.poll(now)
+    }
+
+    pub fn abort(&mut self) {
+        if self.state == SequencerState::Running || self.state == SequencerState::Paused {
+            self.state = SequencerState::Aborted;
+            self.emit(SequencerEvent::Aborted(self.step));
+        }
+    }
+
+    // Completes a pending Expect if pkt matches it.
+    pub fn handle(&mut self, pkt: &Packet, now: Duration) -> io::Result<()> {
+        if self.state != SequencerState::Running {
+            return Ok(());
+        }
+        if let Some(&Step::Expect { code, ref prefix, .. }) = self.mission.steps.get(self.step) {
+            if pkt.code == code && pkt.data.starts_with(prefix) {
+                self.event = Some(pkt.clone());
+                self.timed_out = false;
+                return self.advance(self.step + 1, now);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn poll(&mut self, now: Duration) -> io::Result<()> {
+        if self.state != SequencerState::Running {
+            return Ok(());
+        }
+        match self.deadline {
+            Some(deadline) if now >= deadline => match self.mission.steps.get(self.step) {
+                Some(&Step::Wait(_)) => self.advance(self.step + 1, now),
+                Some(&Step::Expect { .. }) => {
+                    self.timed_out = true;
+                    self.advance(self.step + 1, now)
+                },
+                _ => self.advance(self.step, now), // yielded in a goto loop.
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn advance(&mut self, step: usize, now: Duration) -> io::Result<()> {
+        self.step = step;
+        self.deadline = None;
+        self.enter(now)
+    }
+
+    // Runs steps from the current one until one has to wait. A loop of
+    // gotos without a wait would never yield, so it is cut off after one
+    // pass over the mission and resumed by the next poll.
+    fn enter(&mut self, now: Duration) -> io::Result<()> {
+        for _ in 0..=self.mission.steps.len() {
+            let step = match self.mission.steps.get(self.step) {
+                Some(step) => step.clone(),
+                None => {
+                    self.state = SequencerState::Completed;
+                    self.emit(SequencerEvent::Completed);
+                    return Ok(());
+                },
+            };
+            self.emit(SequencerEvent::StepStarted(self.step));
+            match step {
+                Step::Send(pkt) => {
+                    self.sender.send(pkt)?;
+                    self.step += 1;
+                },
+                Step::Wait(d) | Step::Expect { timeout: d, .. } => {
+                    self.deadline = Some(now + d);
+                    return Ok(());
+                },
+                Step::Goto { target, cond, negate } => {
+                    self.step = if self.holds(&cond) != negate { target } else { self.step + 1 };
+                },
+            }
+        }
+        self.deadline = Some(now);
+        Ok(())
+    }
+
+    fn holds(&self, cond: &Condition) -> bool {
+        match *cond {
+            Condition::Always => true,
+            Condition::TimedOut => self.timed_out,
+            Condition::Flag(ref name) => self.flags.contains(name),
+            Condition::EventByte { index, value } =>
+                self.event.as_ref().and_then(|e| e.data.get(index)) == Some(&value),
+        }
+    }
+
+    fn emit(&mut self, event: SequencerEvent) {
+        for cb in self.callbacks.iter_mut() {
+            cb(&event);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+#![cfg(test)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use l0::comm::Packet;
+use super::*;
+
+const SCRIPT: &str = "
+# arm, then retry the ping until it answers
+send 0x10 01
+wait 100
+send 0x11 aa bb
+expect 0x91 aa timeout 500  # reply
+goto 2 if timeout
+goto 7 if byte 1 == 0x05
+send 0x12
+";
+
+fn ms(v: u64) -> Duration {
+    Duration::from_millis(v)
+}
+
+fn packet(code: u8, data: &[u8]) -> Packet {
+    let mut pkt = Packet::new_with(0, code);
+    pkt.data = data.to_vec();
+    pkt
+}
+
+#[test]
+fn test_mission_format() {
+    let mission: Mission = SCRIPT.parse().unwrap();
+    assert_eq!(mission.steps.len(), 7);
+    assert_eq!(mission.steps[3], Step::Expect { code: 0x91, prefix: vec![0xaa], timeout: ms(500) });
+    assert_eq!(mission.steps[5], Step::Goto {
+        target: 7,
+        cond: Condition::EventByte { index: 1, value: 5 },
+        negate: false,
+    });
+    assert_eq!(mission.to_string().parse::<Mission>().unwrap(), mission);
+    let flagged: Mission = "goto 0 if !flag go".parse().unwrap();
+    assert_eq!(flagged.to_string(), "goto 0 if !flag go\n");
+    assert!("goto 2".parse::<Mission>().is_err());
+    assert!("send 0x10\nbogus".parse::<Mission>().unwrap_err().to_string().contains("line 2"));
+}
+
+#[test]
+fn test_sequencer_run() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut seq = Sequencer::new(SCRIPT.parse().unwrap(), Vec::new());
+    {
+        let events = events.clone();
+        seq.on_event(move |e| events.lock().unwrap().push(*e));
+    }
+    seq.start(ms(0)).unwrap();
+    assert_eq!(seq.sender().len(), 1);
+    assert_eq!(seq.step(), 1);
+    seq.poll(ms(50)).unwrap();
+    seq.pause(ms(50));
+    seq.poll(ms(500)).unwrap();
+    assert_eq!(seq.state(), SequencerState::Paused);
+    // the rest of the wait runs after resuming.
+    seq.resume(ms(1000)).unwrap();
+    seq.poll(ms(1049)).unwrap();
+    assert_eq!(seq.step(), 1);
+    seq.poll(ms(1050)).unwrap();
+    assert_eq!(seq.step(), 3);
+    assert_eq!(seq.sender()[1], packet(0x11, &[0xaa, 0xbb]));
+    // no reply, so the ping is retried.
+    seq.handle(&packet(0x91, &[0xab]), ms(1100)).unwrap();
+    seq.poll(ms(1550)).unwrap();
+    assert_eq!((seq.step(), seq.sender().len()), (3, 3));
+    seq.handle(&packet(0x91, &[0xaa, 0x05]), ms(1600)).unwrap();
+    assert_eq!(seq.state(), SequencerState::Completed);
+    assert_eq!(seq.sender().len(), 3);
+
+    let events = events.lock().unwrap();
+    assert_eq!(events[..4], [
+        SequencerEvent::StepStarted(0),
+        SequencerEvent::StepStarted(1),
+        SequencerEvent::Paused(1),
+        SequencerEvent::Resumed(1),
+    ]);
+    assert_eq!(events.last(), Some(&SequencerEvent::Completed));
+}
+
+#[test]
+fn test_sequencer_flags_and_abort() {
+    let mission: Mission = "send 0x01\ngoto 0 if !flag done\nsend 0x02".parse().unwrap();
+    let mut seq = Sequencer::new(mission, Vec::new());
+    seq.start(ms(0)).unwrap();
+    // a busy loop yields after one pass.
+    assert_eq!(seq.state(), SequencerState::Running);
+    let sent = seq.sender().len();
+    assert!(sent >= 1);
+    seq.set_flag("done", true);
+    seq.poll(ms(1)).unwrap();
+    assert_eq!(seq.state(), SequencerState::Completed);
+    assert_eq!(seq.sender().last().unwrap().code, 0x02);
+
+    let mut seq = Sequencer::new("wait 100\nsend 0x01".parse().unwrap(), Vec::new());
+    seq.start(ms(0)).unwrap();
+    seq.abort();
+    seq.poll(ms(200)).unwrap();
+    assert_eq!(seq.state(), SequencerState::Aborted);
+    assert!(seq.sender().is_empty());
+}
+
+#[test]
+fn test_behavior_tree() {
+    use l2::Sender;
+    use super::bt::*;
+
+    #[derive(Default)]
+    struct Robot {
+        sent: Vec<Packet>,
+        obstacle: bool,
+        steps: u32,
+    }
+
+    impl Sender for Robot {
+        fn send(&mut self, pkt: Packet) -> ::std::io::Result<()> {
+            self.sent.push(pkt);
+            Ok(())
+        }
+    }
+
+    // drive forward for three ticks unless blocked, then stop.
+    let mut tree = selector(vec![
+        sequence(vec![
+            condition(|r: &Robot| !r.obstacle),
+            action(|r: &mut Robot| {
+                r.steps += 1;
+                if r.steps < 3 { Status::Running } else { Status::Success }
+            }),
+            send(packet(0x01, &[1])),
+        ]),
+        send(packet(0x02, &[])),
+    ]);
+    let mut robot = Robot::default();
+    assert_eq!(tree.tick(&mut robot), Status::Running);
+    robot.obstacle = true;
+    assert_eq!(tree.tick(&mut robot), Status::Success);
+    assert_eq!(robot.sent, vec![packet(0x02, &[])]);
+    robot.obstacle = false;
+    assert_eq!(tree.tick(&mut robot), Status::Running);
+    assert_eq!(tree.tick(&mut robot), Status::Success);
+    assert_eq!(robot.sent.last(), Some(&packet(0x01, &[1])));
+
+    let mut flaky = retry(3, invert(condition(|r: &Robot| r.obstacle)));
+    robot.obstacle = true;
+    assert_eq!(flaky.tick(&mut robot), Status::Running);
+    assert_eq!(flaky.tick(&mut robot), Status::Running);
+    assert_eq!(flaky.tick(&mut robot), Status::Failure);
+    robot.obstacle = false;
+    assert_eq!(flaky.tick(&mut robot), Status::Success);
+}
@@ -0,0 +1,137 @@
+use std::time::Duration;
+use l2::imu::{ImuMessage, ImuSample};
+use nav::{Pose2, normalize_angle};
+use nav::tf::Transform2;
+
+const GRAVITY: f64 = 9.80665;
+// Deviation from gravity (m/s^2) below which the robot may be still.
+const STILL_ACCEL: f64 = 0.2;
+
+type PublishCallback = Box<dyn FnMut(&Pose2, Duration) + Send>;
+
+struct Publisher {
+    period: Duration,
+    last: Option<Duration>,
+    callback: PublishCallback,
+}
+
+// Fuses wheel odometry with an IMU. The heading follows the integrated gyro
+// at short time scales and the odometry heading over time constant tau,
+// while translation comes from odometry along the fused heading.
+//
+// While the wheels are still and the accelerometer reads only gravity, the
+// gyro rate is taken as bias and subtracted, which removes most of the
+// heading drift of a cheap gyro.
+pub struct ComplementaryFilter {
+    tau: f64,
+    bias_alpha: f64,
+    pose: Pose2,
+    gyro_bias: f64,
+    gyro_delta: f64, // heading integrated since the last odometry update.
+    last_imu: Option<Duration>,
+    last_odom: Option<(Pose2, Duration)>,
+    still: bool,
+    publisher: Option<Publisher>,
+}
+
+impl ComplementaryFilter {
+    // tau is in seconds, larger trusts the gyro longer.
+    pub fn new(tau: f64) -> Self {
+        ComplementaryFilter {
+            tau,
+            bias_alpha: 0.01,
+            pose: Pose2::default(),
+            gyro_bias: 0.0,
+            gyro_delta: 0.0,
+            last_imu: None,
+            last_odom: None,
+            still: false,
+            publisher: None,
+        }
+    }
+
+    // Low-pass factor of the bias estimate per stationary sample, 0 disables
+    // bias estimation.
+    pub fn with_bias_estimation(mut self, alpha: f64) -> Self {
+        self.bias_alpha = alpha;
+        self
+    }
+
+    // Invokes callback with the pose and time at most once per period.
+    pub fn on_publish<F>(&mut self, period: Duration, callback: F)
+        where F: FnMut(&Pose2, Duration) + Send + 'static {
+        self.publisher = Some(Publisher { period, last: None, callback: Box::new(callback) });
+    }
+
+    pub fn pose(&self) -> &Pose2 {
+        &self.pose
+    }
+
+    pub fn gyro_bias(&self) -> f64 {
+        self.gyro_bias
+    }
+
+    pub fn reset(&mut self, pose: Pose2) {
+        self.pose = pose;
+        self.gyro_delta = 0.0;
+        self.last_odom = None;
+    }
+
+    // Integrates the z gyro of motion samples, using the device timestamps.
+    pub fn handle_imu(&mut self, msg: &ImuMessage, ts: Duration) -> bool {
+        let sample = match ImuSample::from_message(msg) {
+            Some(sample) => sample,
+            None => return false,
+        };
+        if let Some(last) = self.last_imu {
+            let dt = sample.ts.saturating_sub(last).as_secs_f64();
+            let rate = sample.gyro.z;
+            if self.still && (sample.accel.norm() - GRAVITY).abs() < STILL_ACCEL {
+                self.gyro_bias += self.bias_alpha * (rate - self.gyro_bias);
+            } else {
+                self.gyro_delta += (rate - self.gyro_bias) * dt;
+            }
+        }
+        self.last_imu = Some(sample.ts);
+        self.publish(ts);
+        true
+    }
+
+    // Feeds the pose from wheel odometry, e.g. nav::odometry::Odometry.
+    pub fn update_odometry(&mut self, odom: &Pose2, ts: Duration) {
+        let (last, last_ts) = match self.last_odom.replace((*odom, ts)) {
+            Some(last) => last,
+            None => return,
+        };
+        // the motion in the body frame, independent of the odometry heading.
+        let delta = Transform2::from(last).inverse() * Transform2::from(*odom);
+        self.still = delta.x == 0.0 && delta.y == 0.0 && delta.theta == 0.0;
+
+        let dt = ts.saturating_sub(last_ts).as_secs_f64();
+        let alpha = if self.tau > 0.0 { self.tau / (self.tau + dt) } else { 0.0 };
+        let dtheta = alpha * self.gyro_delta + (1.0 - alpha) * delta.theta;
+        self.gyro_delta = 0.0;
+
+        let (s, c) = self.pose.theta.sin_cos();
+        self.pose = Pose2::new(
+            self.pose.x + c * delta.x - s * delta.y,
+            self.pose.y + s * delta.x + c * delta.y,
+            normalize_angle(self.pose.theta + dtheta),
+        );
+        self.publish(ts);
+    }
+
+    fn publish(&mut self, ts: Duration) {
+        let pose = self.pose;
+        if let Some(ref mut p) = self.publisher {
+            let due = match p.last {
+                Some(last) => ts >= last + p.period,
+                None => true,
+            };
+            if due {
+                p.last = Some(ts);
+                (p.callback)(&pose, ts);
+            }
+        }
+    }
+}
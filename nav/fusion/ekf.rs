@@ -0,0 +1,243 @@
+use std::time::Duration;
+use nav::{Point2, Pose2, normalize_angle};
+use nav::odometry::{Covariance, PoseEstimate};
+use nav::tf::Transform2;
+
+// An observation of the (x, y, theta) state. Rows of the jacobian and the
+// residual correspond to the measured components.
+pub trait Measurement {
+    // z - h(x), with angles wrapped.
+    fn residual(&self, x: &Pose2) -> Vec<f64>;
+    // dh/dx, one row per component.
+    fn jacobian(&self, x: &Pose2) -> Vec<[f64; 3]>;
+    // Variance of each component.
+    fn noise(&self) -> Vec<f64>;
+}
+
+// Absolute position, e.g. GNSS projected into the local frame.
+pub struct PositionFix {
+    pub position: Point2,
+    pub variance: f64,
+}
+
+impl Measurement for PositionFix {
+    fn residual(&self, x: &Pose2) -> Vec<f64> {
+        vec![self.position.x - x.x, self.position.y - x.y]
+    }
+
+    fn jacobian(&self, _: &Pose2) -> Vec<[f64; 3]> {
+        vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+    }
+
+    fn noise(&self) -> Vec<f64> {
+        vec![self.variance, self.variance]
+    }
+}
+
+// Absolute heading, e.g. from a compass or IMU orientation.
+pub struct Heading {
+    pub theta: f64,
+    pub variance: f64,
+}
+
+impl Measurement for Heading {
+    fn residual(&self, x: &Pose2) -> Vec<f64> {
+        vec![normalize_angle(self.theta - x.theta)]
+    }
+
+    fn jacobian(&self, _: &Pose2) -> Vec<[f64; 3]> {
+        vec![[0.0, 0.0, 1.0]]
+    }
+
+    fn noise(&self) -> Vec<f64> {
+        vec![self.variance]
+    }
+}
+
+// Measured distance to a beacon at a known position, e.g. UWB.
+pub struct RangeBeacon {
+    pub beacon: Point2,
+    pub range: f64,
+    pub variance: f64,
+}
+
+impl Measurement for RangeBeacon {
+    fn residual(&self, x: &Pose2) -> Vec<f64> {
+        vec![self.range - x.position().distance(&self.beacon)]
+    }
+
+    fn jacobian(&self, x: &Pose2) -> Vec<[f64; 3]> {
+        let d = x.position().distance(&self.beacon).max(1e-9);
+        vec![[(x.x - self.beacon.x) / d, (x.y - self.beacon.y) / d, 0.0]]
+    }
+
+    fn noise(&self) -> Vec<f64> {
+        vec![self.variance]
+    }
+}
+
+// Full pose observation, e.g. from a map matcher.
+pub struct PoseFix {
+    pub pose: Pose2,
+    pub variance: [f64; 3],
+}
+
+impl Measurement for PoseFix {
+    fn residual(&self, x: &Pose2) -> Vec<f64> {
+        vec![self.pose.x - x.x, self.pose.y - x.y, normalize_angle(self.pose.theta - x.theta)]
+    }
+
+    fn jacobian(&self, _: &Pose2) -> Vec<[f64; 3]> {
+        vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+    }
+
+    fn noise(&self) -> Vec<f64> {
+        self.variance.to_vec()
+    }
+}
+
+// Inverts a small symmetric positive definite matrix by Gauss-Jordan
+// elimination, None if singular.
+fn invert(m: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = m.len();
+    let mut a: Vec<Vec<f64>> = m.to_vec();
+    let mut inv: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col] == 0.0 {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        let p = a[col][col];
+        for j in 0..n {
+            a[col][j] /= p;
+            inv[col][j] /= p;
+        }
+        for row in 0..n {
+            if row != col {
+                let f = a[row][col];
+                for j in 0..n {
+                    a[row][j] -= f * a[col][j];
+                    inv[row][j] -= f * inv[col][j];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+// Extended Kalman filter over a planar pose with a unicycle motion model.
+// Predict with velocity commands or odometry increments, then apply any
+// Measurement as it arrives.
+pub struct Ekf {
+    estimate: PoseEstimate,
+    process_noise: (f64, f64),
+}
+
+impl Ekf {
+    pub fn new(pose: Pose2, covariance: Covariance) -> Self {
+        Ekf {
+            estimate: PoseEstimate { pose, covariance, ts: Duration::from_secs(0) },
+            process_noise: (0.01, 0.01),
+        }
+    }
+
+    // Variance added per meter travelled and per radian turned.
+    pub fn with_process_noise(mut self, linear: f64, angular: f64) -> Self {
+        self.process_noise = (linear, angular);
+        self
+    }
+
+    pub fn pose(&self) -> &Pose2 {
+        &self.estimate.pose
+    }
+
+    pub fn covariance(&self) -> &Covariance {
+        &self.estimate.covariance
+    }
+
+    pub fn estimate(&self) -> &PoseEstimate {
+        &self.estimate
+    }
+
+    // Advances the state by driving at v and omega for dt.
+    pub fn predict(&mut self, v: f64, omega: f64, dt: Duration, ts: Duration) {
+        let dt = dt.as_secs_f64();
+        let (dist, turn) = (v * dt, omega * dt);
+        // the chord of the arc driven, at half the turn.
+        let chord = if turn.abs() < 1e-9 { dist } else { 2.0 * dist / turn * (turn / 2.0).sin() };
+        let (s, c) = (turn / 2.0).sin_cos();
+        self.predict_delta(&Transform2::new(chord * c, chord * s, turn), ts);
+    }
+
+    // Advances the state by a body frame increment, e.g. from odometry.
+    pub fn predict_delta(&mut self, delta: &Transform2, ts: Duration) {
+        let pose = self.estimate.pose;
+        let (s, c) = pose.theta.sin_cos();
+        let dx = c * delta.x - s * delta.y;
+        let dy = s * delta.x + c * delta.y;
+        self.estimate.pose = Pose2::new(pose.x + dx, pose.y + dy, normalize_angle(pose.theta + delta.theta));
+
+        let f = [[1.0, 0.0, -dy], [0.0, 1.0, dx], [0.0, 0.0, 1.0]];
+        let p = self.estimate.covariance;
+        let mut next = [[0.0; 3]; 3];
+        for (i, row) in next.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| (0..3).map(|l| f[i][k] * p[k][l] * f[j][l]).sum::<f64>()).sum();
+            }
+        }
+        let dist = delta.x.hypot(delta.y);
+        next[0][0] += self.process_noise.0 * dist;
+        next[1][1] += self.process_noise.0 * dist;
+        next[2][2] += self.process_noise.1 * delta.theta.abs();
+        self.estimate.covariance = next;
+        self.estimate.ts = ts;
+    }
+
+    // Applies a measurement, returning its normalized innovation squared
+    // (Mahalanobis distance) for outlier gating, or None if degenerate.
+    pub fn update<M: Measurement + ?Sized>(&mut self, m: &M, ts: Duration) -> Option<f64> {
+        let x = self.estimate.pose;
+        let y = m.residual(&x);
+        let h = m.jacobian(&x);
+        let r = m.noise();
+        let p = self.estimate.covariance;
+        let n = y.len();
+
+        // PH' (3 x n) and S = HPH' + R (n x n).
+        let ph: Vec<[f64; 3]> = h.iter()
+            .map(|hr| {
+                let mut col = [0.0; 3];
+                for (i, c) in col.iter_mut().enumerate() {
+                    *c = (0..3).map(|k| p[i][k] * hr[k]).sum();
+                }
+                col
+            })
+            .collect();
+        let s: Vec<Vec<f64>> = (0..n)
+            .map(|i| (0..n).map(|j| {
+                let v: f64 = (0..3).map(|k| h[i][k] * ph[j][k]).sum();
+                if i == j { v + r[i] } else { v }
+            }).collect())
+            .collect();
+        let s_inv = invert(&s)?;
+        let nis = (0..n).map(|i| (0..n).map(|j| y[i] * s_inv[i][j] * y[j]).sum::<f64>()).sum();
+
+        // K = PH' S^-1 (3 x n).
+        let k: Vec<Vec<f64>> = (0..3)
+            .map(|i| (0..n).map(|j| (0..n).map(|l| ph[l][i] * s_inv[l][j]).sum()).collect())
+            .collect();
+        let dx: Vec<f64> = (0..3).map(|i| (0..n).map(|j| k[i][j] * y[j]).sum()).collect();
+        self.estimate.pose = Pose2::new(x.x + dx[0], x.y + dx[1], normalize_angle(x.theta + dx[2]));
+        let mut next = p;
+        for (i, row) in next.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v -= (0..n).map(|l| k[i][l] * ph[l][j]).sum::<f64>();
+            }
+        }
+        self.estimate.covariance = next;
+        self.estimate.ts = ts;
+        Some(nis)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod complementary;
+pub mod ekf;
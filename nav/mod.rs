@@ -0,0 +1,55 @@
+use std::f64::consts::PI;
+
+pub mod fusion;
+pub mod odometry;
+pub mod planner;
+pub mod tf;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point2 {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point2 {
+    pub fn new(x: f64, y: f64) -> Self {
+        Point2 { x, y }
+    }
+
+    pub fn distance(&self, other: &Point2) -> f64 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
+}
+
+// Planar pose, theta is the heading in radians counter-clockwise from x.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pose2 {
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+}
+
+impl Pose2 {
+    pub fn new(x: f64, y: f64, theta: f64) -> Self {
+        Pose2 { x, y, theta }
+    }
+
+    pub fn position(&self) -> Point2 {
+        Point2::new(self.x, self.y)
+    }
+
+    // Expresses a world point in this pose's frame.
+    pub fn to_local(&self, p: &Point2) -> Point2 {
+        let (dx, dy) = (p.x - self.x, p.y - self.y);
+        let (s, c) = self.theta.sin_cos();
+        Point2::new(c * dx + s * dy, -s * dx + c * dy)
+    }
+}
+
+// Wraps an angle into [-pi, pi).
+pub fn normalize_angle(a: f64) -> f64 {
+    (a + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,256 @@
+use std::time::Duration;
+use kinematics::DriveKinematics;
+use l2::encoder::{EncoderMessage, EncoderTracker};
+use l2::gnss::{GnssFix, GnssMessage, LocalFrame};
+use l2::imu::{ImuMessage, Quaternion};
+use super::{Point2, Pose2, normalize_angle};
+
+pub type Covariance = [[f64; 3]; 3];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseEstimate {
+    pub pose: Pose2,
+    pub covariance: Covariance, // over (x, y, theta)
+    pub ts: Duration,
+}
+
+struct Wheel {
+    channel: u8,
+    tracker: EncoderTracker,
+    pending: Option<f64>,
+}
+
+struct Publisher {
+    period: Duration,
+    last: Option<Duration>,
+    callback: Box<dyn FnMut(&PoseEstimate) + Send>,
+}
+
+// Dead-reckoning pose estimate from wheel encoder telemetry, optionally
+// taking the heading from IMU orientation instead and correcting the
+// position with GNSS fixes.
+//
+// Encoder counts are buffered until every wheel has reported, then the
+// deltas are integrated together. A wheel reporting twice before the others
+// flushes what is buffered, treating the missing wheels as stationary.
+pub struct Odometry<K> {
+    kinematics: K,
+    ticks_per_rev: f64,
+    wheels: Vec<Wheel>,
+    noise: (f64, f64),
+    imu_yaw: bool,
+    yaw_offset: Option<f64>,
+    gnss: Option<LocalFrame>,
+    estimate: PoseEstimate,
+    publisher: Option<Publisher>,
+}
+
+impl<K: DriveKinematics> Odometry<K> {
+    // channels lists the encoder channel of each wheel, in kinematics order.
+    pub fn new(kinematics: K, channels: &[u8], ticks_per_rev: f64, counter_bits: u8) -> Self {
+        Odometry {
+            kinematics,
+            ticks_per_rev,
+            wheels: channels.iter()
+                .map(|ch| Wheel { channel: *ch, tracker: EncoderTracker::new(counter_bits, 1.0), pending: None })
+                .collect(),
+            noise: (0.01, 0.01),
+            imu_yaw: false,
+            yaw_offset: None,
+            gnss: None,
+            estimate: PoseEstimate {
+                pose: Pose2::default(),
+                covariance: [[0.0; 3]; 3],
+                ts: Duration::from_secs(0),
+            },
+            publisher: None,
+        }
+    }
+
+    // Variance added per meter travelled and per radian turned.
+    pub fn with_noise(mut self, linear: f64, angular: f64) -> Self {
+        self.noise = (linear, angular);
+        self
+    }
+
+    // Takes the heading from IMU orientation messages.
+    pub fn with_imu_yaw(mut self) -> Self {
+        self.imu_yaw = true;
+        self
+    }
+
+    // Corrects the position with GNSS fixes, projected into frame which must
+    // coincide with the odometry frame.
+    pub fn with_gnss(mut self, frame: LocalFrame) -> Self {
+        self.gnss = Some(frame);
+        self
+    }
+
+    // Invokes callback with the estimate at most once per period.
+    pub fn on_publish<F>(&mut self, period: Duration, callback: F)
+        where F: FnMut(&PoseEstimate) + Send + 'static {
+        self.publisher = Some(Publisher { period, last: None, callback: Box::new(callback) });
+    }
+
+    pub fn pose(&self) -> &Pose2 {
+        &self.estimate.pose
+    }
+
+    pub fn covariance(&self) -> &Covariance {
+        &self.estimate.covariance
+    }
+
+    pub fn estimate(&self) -> &PoseEstimate {
+        &self.estimate
+    }
+
+    pub fn reset(&mut self, pose: Pose2) {
+        self.estimate.pose = pose;
+        self.estimate.covariance = [[0.0; 3]; 3];
+        self.yaw_offset = None;
+    }
+
+    // Returns false if the message isn't a count from a known wheel.
+    pub fn handle_encoder(&mut self, msg: &EncoderMessage, ts: Duration) -> bool {
+        let (channel, count) = match *msg {
+            EncoderMessage::Count { channel, count } => (channel, count),
+            _ => return false,
+        };
+        let index = match self.wheels.iter().position(|w| w.channel == channel) {
+            Some(index) => index,
+            None => return false,
+        };
+        if self.wheels[index].pending.is_some() {
+            self.flush(ts);
+        }
+        let ticks = self.wheels[index].tracker.update(count, ts);
+        self.wheels[index].pending = Some(ticks as f64 / self.ticks_per_rev * 2.0 * ::std::f64::consts::PI);
+        if self.wheels.iter().all(|w| w.pending.is_some()) {
+            self.flush(ts);
+        }
+        true
+    }
+
+    fn flush(&mut self, ts: Duration) {
+        let deltas: Vec<f64> = self.wheels.iter_mut()
+            .map(|w| w.pending.take().unwrap_or(0.0))
+            .collect();
+        self.apply_wheel_deltas(&deltas, ts);
+    }
+
+    pub fn handle_imu(&mut self, msg: &ImuMessage, ts: Duration) -> bool {
+        match *msg {
+            ImuMessage::Orientation { quat, .. } if self.imu_yaw => {
+                self.set_yaw(Quaternion::from_fixed(quat).yaw(), ts);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Returns false unless the message is a fix and GNSS is configured.
+    pub fn handle_gnss(&mut self, msg: &GnssMessage, ts: Duration) -> bool {
+        let (frame, fix) = match (self.gnss, GnssFix::from_message(msg, ts)) {
+            (Some(frame), Some(fix)) => (frame, fix),
+            _ => return false,
+        };
+        let (x, y) = frame.to_local(fix.lat, fix.lon);
+        let err = fix.horizontal_error();
+        self.update_position(Point2::new(x, y), err * err, ts);
+        true
+    }
+
+    // Kalman update with an absolute position measurement of the given
+    // variance (m^2) per axis.
+    pub fn update_position(&mut self, p: Point2, variance: f64, ts: Duration) {
+        let c = self.estimate.covariance;
+        // S = H P H' + R, with H selecting x and y.
+        let s = [[c[0][0] + variance, c[0][1]], [c[1][0], c[1][1] + variance]];
+        let det = s[0][0] * s[1][1] - s[0][1] * s[1][0];
+        if det.abs() < f64::EPSILON {
+            return;
+        }
+        let inv = [[s[1][1] / det, -s[0][1] / det], [-s[1][0] / det, s[0][0] / det]];
+        let mut k = [[0.0; 2]; 3];
+        for (i, row) in k.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = c[i][0] * inv[0][j] + c[i][1] * inv[1][j];
+            }
+        }
+        let pose = self.estimate.pose;
+        let (ex, ey) = (p.x - pose.x, p.y - pose.y);
+        self.estimate.pose = Pose2::new(
+            pose.x + k[0][0] * ex + k[0][1] * ey,
+            pose.y + k[1][0] * ex + k[1][1] * ey,
+            normalize_angle(pose.theta + k[2][0] * ex + k[2][1] * ey),
+        );
+        // P = (I - K H) P
+        let mut next = c;
+        for i in 0..3 {
+            for j in 0..3 {
+                next[i][j] -= k[i][0] * c[0][j] + k[i][1] * c[1][j];
+            }
+        }
+        self.estimate.covariance = next;
+        self.advance(ts);
+    }
+
+    // Integrates wheel rotations (radians) in kinematics order.
+    pub fn apply_wheel_deltas(&mut self, deltas: &[f64], ts: Duration) {
+        let d = self.kinematics.twist(deltas);
+        let pose = self.estimate.pose;
+        let (s, c) = (pose.theta + d.omega / 2.0).sin_cos();
+        let dx = d.vx * c - d.vy * s;
+        let dy = d.vx * s + d.vy * c;
+        self.estimate.pose = Pose2::new(pose.x + dx, pose.y + dy, normalize_angle(pose.theta + d.omega));
+
+        // P = F P F' + Q with F the jacobian of the motion wrt the pose.
+        let f = [[1.0, 0.0, -dy], [0.0, 1.0, dx], [0.0, 0.0, 1.0]];
+        let p = self.estimate.covariance;
+        let mut fp = [[0.0; 3]; 3];
+        let mut next = [[0.0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                fp[i][j] = (0..3).map(|k| f[i][k] * p[k][j]).sum();
+            }
+        }
+        for i in 0..3 {
+            for j in 0..3 {
+                next[i][j] = (0..3).map(|k| fp[i][k] * f[j][k]).sum();
+            }
+        }
+        let dist = d.vx.hypot(d.vy);
+        next[0][0] += self.noise.0 * dist;
+        next[1][1] += self.noise.0 * dist;
+        next[2][2] += self.noise.1 * d.omega.abs();
+        self.estimate.covariance = next;
+        self.advance(ts);
+    }
+
+    // Overrides the heading with an absolute yaw; the first reading aligns
+    // the IMU frame with the current heading.
+    pub fn set_yaw(&mut self, yaw: f64, ts: Duration) {
+        let theta = self.estimate.pose.theta;
+        let offset = *self.yaw_offset.get_or_insert(yaw - theta);
+        self.estimate.pose.theta = normalize_angle(yaw - offset);
+        for i in 0..3 {
+            self.estimate.covariance[i][2] = 0.0;
+            self.estimate.covariance[2][i] = 0.0;
+        }
+        self.advance(ts);
+    }
+
+    fn advance(&mut self, ts: Duration) {
+        self.estimate.ts = ts;
+        if let Some(ref mut p) = self.publisher {
+            let due = match p.last {
+                Some(last) => ts >= last + p.period,
+                None => true,
+            };
+            if due {
+                p.last = Some(ts);
+                (p.callback)(&self.estimate);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use super::Point2;
+
+// Occupancy grid with square cells, cell (0, 0) at origin and x/y growing
+// along the world axes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    resolution: f64,
+    origin: Point2,
+    occupied: Vec<bool>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, resolution: f64, origin: Point2) -> Self {
+        Grid {
+            width,
+            height,
+            resolution,
+            origin,
+            occupied: vec![false; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set_occupied(&mut self, cell: (usize, usize), occupied: bool) {
+        if cell.0 < self.width && cell.1 < self.height {
+            self.occupied[cell.1 * self.width + cell.0] = occupied;
+        }
+    }
+
+    // Cells outside the grid count as occupied.
+    pub fn is_occupied(&self, cell: (usize, usize)) -> bool {
+        cell.0 >= self.width || cell.1 >= self.height || self.occupied[cell.1 * self.width + cell.0]
+    }
+
+    pub fn cell(&self, p: &Point2) -> Option<(usize, usize)> {
+        let x = ((p.x - self.origin.x) / self.resolution).floor();
+        let y = ((p.y - self.origin.y) / self.resolution).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn center(&self, cell: (usize, usize)) -> Point2 {
+        Point2::new(
+            self.origin.x + (cell.0 as f64 + 0.5) * self.resolution,
+            self.origin.y + (cell.1 as f64 + 0.5) * self.resolution,
+        )
+    }
+
+    // Marks every cell within radius of an occupied cell, so a robot of
+    // that radius can be planned as a point.
+    pub fn inflate(&self, radius: f64) -> Grid {
+        let r = (radius / self.resolution).ceil() as isize;
+        let mut out = self.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.is_occupied((x, y)) {
+                    continue;
+                }
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        if dx * dx + dy * dy > r * r {
+                            continue;
+                        }
+                        let (cx, cy) = (x as isize + dx, y as isize + dy);
+                        if cx >= 0 && cy >= 0 {
+                            out.set_occupied((cx as usize, cy as usize), true);
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(PartialEq)]
+struct Open {
+    f: f64,
+    index: usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, smallest f first.
+        other.f.partial_cmp(&self.f).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heuristic {
+    Dijkstra,  // no heuristic, explores uniformly.
+    Euclidean, // A*.
+}
+
+// Plans 8-connected paths over a grid, not cutting corners of occupied
+// cells.
+pub struct Planner {
+    heuristic: Heuristic,
+}
+
+impl Default for Planner {
+    fn default() -> Self {
+        Planner::new()
+    }
+}
+
+impl Planner {
+    pub fn new() -> Self {
+        Planner { heuristic: Heuristic::Euclidean }
+    }
+
+    pub fn with_heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    // Cells from start to goal inclusive, None if unreachable.
+    pub fn plan_cells(&self, grid: &Grid, start: (usize, usize), goal: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        if grid.is_occupied(start) || grid.is_occupied(goal) {
+            return None;
+        }
+        let w = grid.width;
+        let h = |i: usize| match self.heuristic {
+            Heuristic::Dijkstra => 0.0,
+            Heuristic::Euclidean => {
+                let (x, y) = ((i % w) as f64, (i / w) as f64);
+                (x - goal.0 as f64).hypot(y - goal.1 as f64)
+            },
+        };
+        let n = grid.width * grid.height;
+        let mut cost = vec![f64::INFINITY; n];
+        let mut came_from = vec![usize::MAX; n];
+        let mut open = BinaryHeap::new();
+        let (s, g) = (start.1 * w + start.0, goal.1 * w + goal.0);
+        cost[s] = 0.0;
+        open.push(Open { f: h(s), index: s });
+        while let Some(Open { f, index }) = open.pop() {
+            if index == g {
+                let mut path = vec![goal];
+                let mut i = g;
+                while i != s {
+                    i = came_from[i];
+                    path.push((i % w, i / w));
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if f > cost[index] + h(index) {
+                continue; // stale entry.
+            }
+            let (x, y) = ((index % w) as isize, (index / w) as isize);
+            for dy in -1..=1isize {
+                for dx in -1..=1isize {
+                    if dx == 0 && dy == 0 {
+                        continue;
+                    }
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || grid.is_occupied((nx as usize, ny as usize)) {
+                        continue;
+                    }
+                    if dx != 0 && dy != 0 &&
+                        (grid.is_occupied((nx as usize, y as usize)) || grid.is_occupied((x as usize, ny as usize))) {
+                        continue;
+                    }
+                    let next = ny as usize * w + nx as usize;
+                    let c = cost[index] + if dx != 0 && dy != 0 { ::std::f64::consts::SQRT_2 } else { 1.0 };
+                    if c < cost[next] {
+                        cost[next] = c;
+                        came_from[next] = index;
+                        open.push(Open { f: c + h(next), index: next });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // World waypoints for Follower: the start and goal points, with the
+    // centers of the cells where the path turns in between.
+    pub fn plan(&self, grid: &Grid, start: &Point2, goal: &Point2) -> Option<Vec<Point2>> {
+        let cells = self.plan_cells(grid, grid.cell(start)?, grid.cell(goal)?)?;
+        let mut waypoints = vec![*start];
+        for i in 1..cells.len().saturating_sub(1) {
+            let (a, b, c) = (cells[i - 1], cells[i], cells[i + 1]);
+            let d1 = (b.0 as isize - a.0 as isize, b.1 as isize - a.1 as isize);
+            let d2 = (c.0 as isize - b.0 as isize, c.1 as isize - b.1 as isize);
+            if d1 != d2 {
+                waypoints.push(grid.center(b));
+            }
+        }
+        waypoints.push(*goal);
+        Some(waypoints)
+    }
+}
@@ -0,0 +1,285 @@
+#![cfg(test)]
+
+use std::f64::consts::PI;
+use std::time::Duration;
+use kinematics::diff_drive::DiffDrive;
+use l2::encoder::EncoderMessage;
+use l2::imu::{ImuMessage, Quaternion};
+use super::*;
+use super::odometry::*;
+
+fn assert_near(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+#[test]
+fn test_pose_to_local() {
+    let pose = Pose2::new(1.0, 1.0, PI / 2.0);
+    let p = pose.to_local(&Point2::new(1.0, 3.0));
+    assert_near(p.x, 2.0);
+    assert_near(p.y, 0.0);
+    assert_near(Point2::new(0.0, 0.0).distance(&Point2::new(3.0, 4.0)), 5.0);
+}
+
+#[test]
+fn test_normalize_angle() {
+    assert_near(normalize_angle(3.0 * PI / 2.0), -PI / 2.0);
+    assert_near(normalize_angle(-3.0 * PI / 2.0), PI / 2.0);
+    assert_near(normalize_angle(0.5), 0.5);
+}
+
+fn diff_odometry() -> Odometry<DiffDrive> {
+    // 2*pi ticks per revolution, so one tick is one radian of wheel rotation.
+    Odometry::new(DiffDrive::new(1.0, 0.5), &[0, 1], 2.0 * PI, 16)
+}
+
+fn both(odom: &mut Odometry<DiffDrive>, l: u32, r: u32, ms: u64) {
+    let ts = Duration::from_millis(ms);
+    assert!(odom.handle_encoder(&EncoderMessage::Count { channel: 0, count: l }, ts));
+    assert!(odom.handle_encoder(&EncoderMessage::Count { channel: 1, count: r }, ts));
+}
+
+#[test]
+fn test_odometry_straight() {
+    let mut odom = diff_odometry();
+    both(&mut odom, 0, 0, 0);
+    both(&mut odom, 4, 4, 100);
+    assert_near(odom.pose().x, 2.0);
+    assert_near(odom.pose().y, 0.0);
+    assert_near(odom.pose().theta, 0.0);
+    assert!(odom.covariance()[0][0] > 0.0);
+    assert!(!odom.handle_encoder(&EncoderMessage::Count { channel: 5, count: 0 }, Duration::from_millis(100)));
+}
+
+#[test]
+fn test_odometry_rotate_in_place() {
+    let mut odom = diff_odometry();
+    odom.apply_wheel_deltas(&[-PI / 2.0, PI / 2.0], Duration::from_millis(0));
+    assert_near(odom.pose().x, 0.0);
+    assert_near(odom.pose().theta, PI / 2.0);
+    odom.apply_wheel_deltas(&[2.0, 2.0], Duration::from_millis(0));
+    assert_near(odom.pose().x, 0.0);
+    assert_near(odom.pose().y, 1.0);
+    // heading uncertainty turns into lateral uncertainty.
+    let p = odom.covariance();
+    assert!(p[0][0] > p[1][1]);
+}
+
+#[test]
+fn test_odometry_imu_yaw_and_publish() {
+    use std::sync::{Arc, Mutex};
+
+    let published = Arc::new(Mutex::new(Vec::new()));
+    let p = published.clone();
+    let mut odom = diff_odometry().with_imu_yaw();
+    odom.on_publish(Duration::from_millis(100), move |e| p.lock().unwrap().push(e.ts));
+
+    let h = (0.5f64).sqrt();
+    let q = Quaternion { w: h, x: 0.0, y: 0.0, z: h };
+    let msg = ImuMessage::Orientation { ts_ms: 0, quat: q.to_fixed() };
+    assert!(odom.handle_imu(&msg, Duration::from_millis(0)));
+    assert_near(odom.pose().theta, 0.0);
+    let msg = ImuMessage::Orientation { ts_ms: 0, quat: [16384, 0, 0, 0] };
+    odom.handle_imu(&msg, Duration::from_millis(50));
+    assert!((odom.pose().theta + PI / 2.0).abs() < 1e-3);
+    odom.handle_imu(&msg, Duration::from_millis(120));
+    assert_eq!(*published.lock().unwrap(), vec![Duration::from_millis(0), Duration::from_millis(120)]);
+}
+
+#[test]
+fn test_odometry_gnss() {
+    use l2::gnss::*;
+
+    let frame = LocalFrame::new(47.0, 8.0);
+    let mut odom = diff_odometry().with_gnss(frame);
+    odom.apply_wheel_deltas(&[20.0, 20.0], Duration::from_secs(1));
+    assert_near(odom.pose().x, 10.0);
+    let var = odom.covariance()[0][0];
+    // a fix 1m north and 2m east of the estimate, as uncertain as it.
+    let (lat, lon) = frame.to_geodetic(12.0, 1.0);
+    let msg = GnssMessage::Position {
+        lat_e7: (lat / DEGREE_LSB).round() as i32,
+        lon_e7: (lon / DEGREE_LSB).round() as i32,
+        alt_cm: 0,
+        quality: FixQuality::RtkFixed,
+        sats: 12,
+        hdop_centi: 100,
+    };
+    let err = GnssFix::from_message(&msg, Duration::from_secs(2)).unwrap().horizontal_error();
+    odom.update_position(Point2::new(12.0, 1.0), var, Duration::from_secs(2));
+    assert_near(odom.pose().x, 11.0);
+    assert_near(odom.pose().y, 0.5);
+    assert_near(odom.covariance()[0][0], var / 2.0);
+    assert!(odom.handle_gnss(&msg, Duration::from_secs(3)));
+    assert!((odom.pose().x - 12.0).abs() < 0.02);
+    assert!(odom.covariance()[0][0] < err * err);
+    assert!(!odom.handle_gnss(&GnssMessage::Velocity { north_cms: 0, east_cms: 0, up_cms: 0 }, Duration::from_secs(3)));
+}
+
+#[test]
+fn test_transform2() {
+    use super::tf::*;
+
+    let t = Transform2::new(1.0, 2.0, PI / 2.0);
+    let p = t.apply(&Point2::new(1.0, 0.0));
+    assert_near(p.x, 1.0);
+    assert_near(p.y, 3.0);
+    let id = t * t.inverse();
+    assert_near(id.x, 0.0);
+    assert_near(id.y, 0.0);
+    assert_near(id.theta, 0.0);
+    let back = t.inverse().apply(&p);
+    assert_near(back.x, 1.0);
+    assert_near(back.y, 0.0);
+    let pose = t.apply_pose(&Pose2::new(1.0, 0.0, PI / 2.0));
+    assert_near(pose.x, 1.0);
+    assert_near(pose.y, 3.0);
+    assert_near(pose.theta, -PI);
+}
+
+#[test]
+fn test_frame_tree() {
+    use super::tf::*;
+
+    let mut tree = FrameTree::new();
+    assert!(tree.set("base", "odom", Transform2::new(2.0, 0.0, PI / 2.0)));
+    assert!(tree.set("lidar", "base", Transform2::new(0.5, 0.0, 0.0)));
+    assert!(tree.set("camera", "base", Transform2::new(0.0, 0.1, PI)));
+    assert!(!tree.set("odom", "lidar", Transform2::identity()));
+    assert_eq!(tree.parent("lidar"), Some("base"));
+
+    // a reading 1m ahead of the lidar is 1.5m ahead of the base.
+    let p = tree.transform_point("odom", "lidar", &Point2::new(1.0, 0.0)).unwrap();
+    assert_near(p.x, 2.0);
+    assert_near(p.y, 1.5);
+    let p = tree.transform_point("camera", "lidar", &Point2::new(1.0, 0.0)).unwrap();
+    assert_near(p.x, -1.5);
+    assert_near(p.y, 0.1);
+    let t = tree.lookup("lidar", "odom").unwrap() * tree.lookup("odom", "lidar").unwrap();
+    assert_near(t.x, 0.0);
+    assert_near(t.theta, 0.0);
+    assert_eq!(tree.lookup("map", "lidar"), None);
+    assert_eq!(tree.lookup("odom", "odom"), Some(Transform2::identity()));
+}
+
+#[test]
+fn test_grid_planner() {
+    use super::planner::*;
+
+    // a wall at x = 2 with a gap at the top.
+    let mut grid = Grid::new(5, 4, 0.5, Point2::new(-1.0, 0.0));
+    for y in 0..3 {
+        grid.set_occupied((2, y), true);
+    }
+    assert_eq!(grid.cell(&Point2::new(-0.9, 0.1)), Some((0, 0)));
+    assert_eq!(grid.cell(&Point2::new(-1.1, 0.1)), None);
+    assert_near(grid.center((1, 1)).x, -0.25);
+
+    let astar = Planner::new();
+    let path = astar.plan_cells(&grid, (0, 0), (4, 0)).unwrap();
+    assert_eq!(path.first(), Some(&(0, 0)));
+    assert_eq!(path.last(), Some(&(4, 0)));
+    assert!(path.contains(&(2, 3)));
+    let dijkstra = Planner::new().with_heuristic(Heuristic::Dijkstra).plan_cells(&grid, (0, 0), (4, 0)).unwrap();
+    assert_eq!(dijkstra.len(), path.len());
+
+    let start = Point2::new(-0.75, 0.25);
+    let goal = Point2::new(1.25, 0.25);
+    let waypoints = astar.plan(&grid, &start, &goal).unwrap();
+    assert_eq!(waypoints.first(), Some(&start));
+    assert_eq!(waypoints.last(), Some(&goal));
+    assert!(waypoints.len() < path.len());
+
+    grid.set_occupied((2, 3), true);
+    assert_eq!(astar.plan_cells(&grid, (0, 0), (4, 0)), None);
+    let inflated = Grid::new(5, 4, 0.5, Point2::new(0.0, 0.0)).inflate(0.5);
+    assert!(!inflated.is_occupied((0, 0)));
+    let mut g = Grid::new(5, 4, 0.5, Point2::new(0.0, 0.0));
+    g.set_occupied((2, 2), true);
+    let g = g.inflate(0.5);
+    assert!(g.is_occupied((1, 2)) && g.is_occupied((2, 3)) && !g.is_occupied((1, 1)));
+}
+
+#[test]
+fn test_complementary_filter() {
+    use std::sync::{Arc, Mutex};
+    use l2::imu::*;
+    use super::fusion::complementary::*;
+
+    let motion = |ts_ms: u32, gyro_z: f64| ImuSample {
+        accel: Vec3::new(0.0, 0.0, 9.80665),
+        gyro: Vec3::new(0.0, 0.0, gyro_z),
+        ts: Duration::from_millis(ts_ms as u64),
+    }.to_message();
+    let ms = |v: u64| Duration::from_millis(v);
+
+    let published = Arc::new(Mutex::new(0));
+    let mut f = ComplementaryFilter::new(1e6).with_bias_estimation(0.5);
+    {
+        let published = published.clone();
+        f.on_publish(ms(500), move |_, _| *published.lock().unwrap() += 1);
+    }
+    // standing still with a biased gyro.
+    f.update_odometry(&Pose2::default(), ms(0));
+    f.update_odometry(&Pose2::default(), ms(10));
+    for i in 0..20 {
+        assert!(f.handle_imu(&motion(i * 10, 0.05), ms(i as u64 * 10)));
+    }
+    assert!((f.gyro_bias() - 0.05).abs() < 1e-4);
+    assert_near(f.pose().theta, 0.0);
+
+    // odometry says straight, the gyro says turning at 1 rad/s: trust the gyro.
+    f.update_odometry(&Pose2::new(1.0, 0.0, 0.0), ms(200));
+    for i in 20..30 {
+        f.handle_imu(&motion(i * 10, 1.05), ms(i as u64 * 10));
+    }
+    f.update_odometry(&Pose2::new(2.0, 0.0, 0.0), ms(300));
+    assert!((f.pose().theta - 0.1).abs() < 1e-3);
+    assert_near(f.pose().x, 2.0);
+    // translation follows the fused heading.
+    f.update_odometry(&Pose2::new(3.0, 0.0, 0.0), ms(400));
+    assert!((f.pose().y - 0.1f64.sin()).abs() < 1e-3);
+    assert!(!f.handle_imu(&ImuMessage::Mag { ts_ms: 0, mag: [0; 3] }, ms(0)));
+    assert_eq!(*published.lock().unwrap(), 1);
+
+    // with a short time constant the odometry heading wins.
+    let mut f = ComplementaryFilter::new(0.0);
+    f.update_odometry(&Pose2::default(), ms(0));
+    f.handle_imu(&motion(0, 1.0), ms(0));
+    f.handle_imu(&motion(100, 1.0), ms(100));
+    f.update_odometry(&Pose2::new(0.0, 0.0, 0.5), ms(100));
+    assert_near(f.pose().theta, 0.5);
+}
+
+#[test]
+fn test_ekf() {
+    use super::fusion::ekf::*;
+
+    let t = Duration::from_secs(0);
+    let mut ekf = Ekf::new(Pose2::default(), [[0.0; 3]; 3]).with_process_noise(0.1, 0.1);
+    // a quarter circle of radius 1.
+    ekf.predict(PI / 2.0, PI / 2.0, Duration::from_secs(1), t);
+    assert!((ekf.pose().x - 1.0).abs() < 1e-9 && (ekf.pose().y - 1.0).abs() < 1e-9);
+    assert_near(ekf.pose().theta, PI / 2.0);
+    assert!(ekf.covariance()[0][0] > 0.0);
+
+    let before = ekf.covariance()[0][0];
+    let nis = ekf.update(&PositionFix { position: Point2::new(1.2, 1.0), variance: 0.01 }, t).unwrap();
+    assert!(nis > 0.0);
+    assert!(ekf.pose().x > 1.0 && ekf.pose().x < 1.2);
+    assert!(ekf.covariance()[0][0] < before);
+
+    // a precise beacon range pulls the estimate onto the circle around it.
+    let beacon = Point2::new(0.0, 1.0);
+    for _ in 0..5 {
+        ekf.update(&RangeBeacon { beacon, range: 1.0, variance: 1e-6 }, t);
+    }
+    assert!((ekf.pose().position().distance(&beacon) - 1.0).abs() < 1e-3);
+
+    ekf.update(&Heading { theta: -PI + 0.1, variance: 1e-9 }, t);
+    assert!((ekf.pose().theta - (-PI + 0.1)).abs() < 1e-6);
+    ekf.update(&PoseFix { pose: Pose2::new(3.0, 4.0, 0.5), variance: [1e-12; 3] }, t);
+    assert!((ekf.pose().x - 3.0).abs() < 1e-4 && (ekf.pose().theta - 0.5).abs() < 1e-2);
+    let p = ekf.covariance();
+    assert!(p[0][0] < 1e-9 && p[2][2] < 1e-9);
+}
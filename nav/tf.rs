@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::ops::Mul;
+use super::{Point2, normalize_angle};
+
+pub use super::Pose2;
+
+// Rigid 2D transform: a rotation by theta followed by a translation. The
+// transform from frame A to frame B is the pose of A in B.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Transform2 {
+    pub x: f64,
+    pub y: f64,
+    pub theta: f64,
+}
+
+impl Transform2 {
+    pub fn new(x: f64, y: f64, theta: f64) -> Self {
+        Transform2 { x, y, theta }
+    }
+
+    pub fn identity() -> Self {
+        Transform2::default()
+    }
+
+    pub fn inverse(&self) -> Self {
+        let (s, c) = self.theta.sin_cos();
+        Transform2::new(-c * self.x - s * self.y, s * self.x - c * self.y, normalize_angle(-self.theta))
+    }
+
+    // self after other: maps through other, then through self.
+    pub fn compose(&self, other: &Transform2) -> Self {
+        let p = self.apply(&Point2::new(other.x, other.y));
+        Transform2::new(p.x, p.y, normalize_angle(self.theta + other.theta))
+    }
+
+    pub fn apply(&self, p: &Point2) -> Point2 {
+        let (s, c) = self.theta.sin_cos();
+        Point2::new(self.x + c * p.x - s * p.y, self.y + s * p.x + c * p.y)
+    }
+
+    pub fn apply_pose(&self, p: &Pose2) -> Pose2 {
+        self.compose(&Transform2::from(*p)).into()
+    }
+}
+
+impl Mul for Transform2 {
+    type Output = Transform2;
+
+    fn mul(self, rhs: Transform2) -> Transform2 {
+        self.compose(&rhs)
+    }
+}
+
+impl From<Pose2> for Transform2 {
+    fn from(p: Pose2) -> Self {
+        Transform2::new(p.x, p.y, p.theta)
+    }
+}
+
+impl From<Transform2> for Pose2 {
+    fn from(t: Transform2) -> Self {
+        Pose2::new(t.x, t.y, t.theta)
+    }
+}
+
+// Named frames, each attached to a parent by the transform from the frame
+// to its parent, e.g. odom -> base -> lidar.
+#[derive(Debug, Clone, Default)]
+pub struct FrameTree {
+    parents: HashMap<String, (String, Transform2)>,
+}
+
+impl FrameTree {
+    pub fn new() -> Self {
+        FrameTree::default()
+    }
+
+    // Attaches or moves frame under parent. Returns false, changing nothing,
+    // if that would create a cycle.
+    pub fn set(&mut self, frame: &str, parent: &str, t: Transform2) -> bool {
+        if frame == parent || self.chain(parent).iter().any(|(f, _)| f == frame) {
+            return false;
+        }
+        self.parents.insert(frame.to_string(), (parent.to_string(), t));
+        true
+    }
+
+    pub fn parent(&self, frame: &str) -> Option<&str> {
+        self.parents.get(frame).map(|(p, _)| p.as_str())
+    }
+
+    // The transform mapping coordinates in source into target, if both are
+    // in the same tree.
+    pub fn lookup(&self, target: &str, source: &str) -> Option<Transform2> {
+        let up = self.chain(source);
+        let down = self.chain(target);
+        // up and down end at their roots; find the first common frame.
+        let common = up.iter().map(|(f, _)| f).find(|f| down.iter().any(|(g, _)| g == *f))?;
+        let to_common = |chain: &[(String, Transform2)]| {
+            chain.iter()
+                .take_while(|(f, _)| f != common)
+                .fold(Transform2::identity(), |acc, (_, t)| *t * acc)
+        };
+        Some(to_common(&down).inverse() * to_common(&up))
+    }
+
+    pub fn transform_point(&self, target: &str, source: &str, p: &Point2) -> Option<Point2> {
+        self.lookup(target, source).map(|t| t.apply(p))
+    }
+
+    // Frame followed by its ancestors, each with the transform to its
+    // parent (identity for the root).
+    fn chain(&self, frame: &str) -> Vec<(String, Transform2)> {
+        let mut chain = Vec::new();
+        let mut f = frame.to_string();
+        loop {
+            match self.parents.get(&f) {
+                Some((parent, t)) => {
+                    chain.push((f, *t));
+                    f = parent.clone();
+                },
+                None => {
+                    chain.push((f, Transform2::identity()));
+                    return chain;
+                },
+            }
+        }
+    }
+}
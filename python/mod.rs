@@ -0,0 +1,392 @@
+// Python bindings, built as the `robo` extension module:
+//
+//   maturin develop
+//
+//   import robo
+//   link = robo.Link("/dev/ttyUSB0", schema="0x01=motor,0x83=power")
+//   link.send_message("motor", {"message": "set_velocities", "first": 0, "velocities": [100, 100]})
+//   for pkt in link.poll():
+//       print(link.decode(pkt))
+//
+// Messages are dicts keyed by field name, with "message" naming the op;
+// their layouts come from l2::layout.
+
+// The pyfunction and pymethods expansions convert PyErr into itself.
+#![allow(clippy::useless_conversion)]
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyIOError, PyKeyError, PyTimeoutError, PyValueError};
+use pyo3::types::{PyBytes, PyDict, PyList};
+use l0::comm::{Endpoint, LinkStats, Packet, Parser, SyncStateReader, PACKET_MAX_DATA_LEN};
+use l2::layout::{Count, Field, FieldType, Layout};
+use l2::schema::{MessageSet, Schema};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+
+pub trait Stream: io::Read + io::Write + Send {}
+
+impl<T: io::Read + io::Write + Send> Stream for T {}
+
+type Transport = Box<dyn Stream>;
+
+fn io_error(e: io::Error) -> PyErr {
+    match e.kind() {
+        io::ErrorKind::TimedOut => PyTimeoutError::new_err(e.to_string()),
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => PyValueError::new_err(e.to_string()),
+        _ => PyIOError::new_err(e.to_string()),
+    }
+}
+
+// A timeout in seconds, which must be one a deadline can be set from.
+fn timeout_secs(secs: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(secs).ok()
+        .filter(|d| Instant::now().checked_add(*d).is_some())
+        .ok_or_else(|| PyValueError::new_err(format!("invalid timeout {}", secs)))
+}
+
+#[pyclass(name = "Packet", module = "robo")]
+#[derive(Clone)]
+pub struct PyPacket {
+    pkt: Packet,
+}
+
+#[pymethods]
+impl PyPacket {
+    #[new]
+    #[pyo3(signature = (code, data = Vec::new(), seq = 0))]
+    fn new(code: u8, data: Vec<u8>, seq: u8) -> PyResult<Self> {
+        if data.len() > PACKET_MAX_DATA_LEN {
+            return Err(PyValueError::new_err("payload too long"));
+        }
+        let mut pkt = Packet::new_with(seq, code);
+        pkt.data = data;
+        Ok(PyPacket { pkt })
+    }
+
+    #[getter]
+    fn seq(&self) -> u8 {
+        self.pkt.seq
+    }
+
+    #[getter]
+    fn code(&self) -> u8 {
+        self.pkt.code
+    }
+
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.pkt.data)
+    }
+
+    // The packet as it goes on the wire.
+    fn encode<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let mut buf = Vec::new();
+        self.pkt.encode(&mut buf).expect("write to vec");
+        PyBytes::new_bound(py, &buf)
+    }
+
+    fn __eq__(&self, other: &PyPacket) -> bool {
+        self.pkt == other.pkt
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Packet(code=0x{:02x}, data={:02x?}, seq={})", self.pkt.code, self.pkt.data, self.pkt.seq)
+    }
+}
+
+// The l0 parser. Sync responses it asks for collect until taken with
+// responses(), so the caller decides how to write them.
+#[pyclass(name = "Parser", module = "robo")]
+pub struct PyParser {
+    parser: Parser,
+    ready: bool,
+    responses: Vec<u8>,
+}
+
+#[pymethods]
+impl PyParser {
+    #[new]
+    #[pyo3(signature = (promiscuous = false))]
+    fn new(promiscuous: bool) -> Self {
+        let parser = if promiscuous { Parser::promiscuous() } else { Parser::new() };
+        PyParser { parser, ready: promiscuous, responses: Vec::new() }
+    }
+
+    #[getter]
+    fn ready(&self) -> bool {
+        self.ready
+    }
+
+    // Parses bytes, returning the complete packets.
+    fn feed(&mut self, data: &[u8]) -> Vec<PyPacket> {
+        let mut pkts = Vec::new();
+        for r in self.parser.parse_iter(data.iter().cloned()) {
+            self.ready = r.is_synced();
+            self.responses.extend(r.needs_sync_reply());
+            pkts.extend(r.into_iter().map(|pkt| PyPacket { pkt }));
+        }
+        pkts
+    }
+
+    fn timeout(&mut self) {
+        let r = self.parser.timeout();
+        self.ready = r.state.is_ready();
+    }
+
+    // Sync bytes the parser asked to send since the last call; each is
+    // followed on the wire by the sender's seq.
+    fn responses<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let b = PyBytes::new_bound(py, &self.responses);
+        self.responses.clear();
+        b
+    }
+}
+
+fn message_set(name: &str) -> PyResult<MessageSet> {
+    name.parse().map_err(io_error)
+}
+
+fn get_int(r: &[u8], pos: &mut usize, ty: &FieldType) -> Option<i64> {
+    let n = ty.size();
+    let b = r.get(*pos..*pos + n)?;
+    *pos += n;
+    let u = b.iter().rev().fold(0u64, |acc, b| acc << 8 | *b as u64);
+    Some(match *ty {
+        FieldType::I8 => u as i8 as i64,
+        FieldType::I16 => u as i16 as i64,
+        FieldType::I32 => u as i32 as i64,
+        _ => u as i64,
+    })
+}
+
+fn decode_value(py: Python, data: &[u8], pos: &mut usize, ty: &FieldType) -> PyResult<PyObject> {
+    match *ty {
+        FieldType::Struct(_, fields) => Ok(decode_fields(py, data, pos, fields)?.into_any().unbind()),
+        _ => get_int(data, pos, ty).map(|v| v.into_py(py)).ok_or_else(|| PyValueError::new_err("payload too short")),
+    }
+}
+
+fn decode_fields<'py>(py: Python<'py>, data: &[u8], pos: &mut usize, fields: &[Field]) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    for f in fields {
+        let value = match f.count {
+            Count::One => decode_value(py, data, pos, &f.ty)?,
+            Count::Rest if f.ty == FieldType::U8 => {
+                let rest = PyBytes::new_bound(py, &data[*pos..]);
+                *pos = data.len();
+                rest.into_any().unbind()
+            },
+            _ => {
+                let list = PyList::empty_bound(py);
+                while match f.count {
+                    Count::Fixed(n) => list.len() < n,
+                    _ => *pos < data.len(),
+                } {
+                    list.append(decode_value(py, data, pos, &f.ty)?)?;
+                }
+                list.into_any().unbind()
+            },
+        };
+        dict.set_item(f.name, value)?;
+    }
+    Ok(dict)
+}
+
+fn encode_value(data: &mut Vec<u8>, value: &Bound<PyAny>, ty: &FieldType) -> PyResult<()> {
+    match *ty {
+        FieldType::Struct(_, fields) => encode_fields(data, value.downcast::<PyDict>()?, fields),
+        _ => {
+            let v: i64 = value.extract()?;
+            data.extend_from_slice(&v.to_le_bytes()[..ty.size()]);
+            Ok(())
+        },
+    }
+}
+
+fn encode_fields(data: &mut Vec<u8>, msg: &Bound<PyDict>, fields: &[Field]) -> PyResult<()> {
+    for f in fields {
+        let value = msg.get_item(f.name)?.ok_or_else(|| PyKeyError::new_err(f.name))?;
+        match f.count {
+            Count::One => encode_value(data, &value, &f.ty)?,
+            _ if f.ty == FieldType::U8 && value.is_instance_of::<PyBytes>() => {
+                data.extend_from_slice(value.downcast::<PyBytes>()?.as_bytes());
+            },
+            _ => for item in value.iter()? {
+                encode_value(data, &item?, &f.ty)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+fn layout_of(set: MessageSet, name: &str) -> PyResult<&'static Layout> {
+    set.layouts().iter().find(|l| l.name == name)
+        .ok_or_else(|| PyValueError::new_err(format!("no {} message {:?}", set.name(), name)))
+}
+
+// Decodes a payload of the named message set into a dict.
+#[pyfunction]
+fn decode<'py>(py: Python<'py>, set: &str, data: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+    let set = message_set(set)?;
+    let layout = data.first().and_then(|op| set.layouts().iter().find(|l| l.op == *op));
+    let layout = match layout {
+        Some(l) if set.describe(data).is_some() => l,
+        _ => return Err(PyValueError::new_err(format!("payload is not a valid {} message", set.name()))),
+    };
+    let mut pos = 1;
+    let dict = decode_fields(py, data, &mut pos, layout.fields)?;
+    dict.set_item("message", layout.name)?;
+    Ok(dict)
+}
+
+// Encodes a message dict of the named set into a payload.
+#[pyfunction]
+fn encode<'py>(py: Python<'py>, set: &str, msg: &Bound<'py, PyDict>) -> PyResult<Bound<'py, PyBytes>> {
+    let set = message_set(set)?;
+    let name: String = msg.get_item("message")?.ok_or_else(|| PyKeyError::new_err("message"))?.extract()?;
+    let layout = layout_of(set, &name)?;
+    let mut data = vec![layout.op];
+    encode_fields(&mut data, msg, layout.fields)?;
+    if set.describe(&data).is_none() {
+        return Err(PyValueError::new_err(format!("not a valid {} {} message", set.name(), name)));
+    }
+    Ok(PyBytes::new_bound(py, &data))
+}
+
+fn open(port: &str, baud: u32) -> io::Result<Transport> {
+    if let Some(addr) = port.strip_prefix("tcp://") {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        return Ok(Box::new(stream));
+    }
+    Ok(Box::new(serialport::new(port, baud).timeout(READ_TIMEOUT).open()?))
+}
+
+fn stats_dict<'py>(py: Python<'py>, stats: &LinkStats) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("packets_sent", stats.packets_sent)?;
+    dict.set_item("packets_received", stats.packets_received)?;
+    dict.set_item("bytes_sent", stats.bytes_sent)?;
+    dict.set_item("bytes_received", stats.bytes_received)?;
+    dict.set_item("resyncs", stats.resyncs)?;
+    dict.set_item("overflows", stats.overflows)?;
+    Ok(dict)
+}
+
+// A link to a device over a serial port or tcp://HOST:PORT. The
+// constructor waits for the handshake.
+#[pyclass(name = "Link", module = "robo")]
+pub struct PyLink {
+    ep: Endpoint<Transport>,
+    schema: Schema,
+    queue: VecDeque<Packet>,
+}
+
+impl PyLink {
+    fn fill(&mut self) -> io::Result<()> {
+        let pkts = self.ep.poll()?;
+        self.queue.extend(pkts);
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl PyLink {
+    #[new]
+    #[pyo3(signature = (port, baud = 115200, schema = "", timeout = 1.0))]
+    fn new(py: Python, port: &str, baud: u32, schema: &str, timeout: f64) -> PyResult<Self> {
+        let schema = schema.parse().map_err(io_error)?;
+        let timeout = timeout_secs(timeout)?;
+        let ep = py.allow_threads(|| -> io::Result<Endpoint<Transport>> {
+            let mut ep = Endpoint::named(open(port, baud)?, port)?;
+            let deadline = Instant::now() + timeout;
+            while !ep.is_ready() {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "no handshake from the device"));
+                }
+                ep.poll()?;
+                thread::sleep(Duration::from_millis(1));
+            }
+            Ok(ep)
+        }).map_err(io_error)?;
+        Ok(PyLink { ep, schema, queue: VecDeque::new() })
+    }
+
+    #[getter]
+    fn ready(&self) -> bool {
+        self.ep.is_ready()
+    }
+
+    #[getter]
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        stats_dict(py, self.ep.stats())
+    }
+
+    // Packets received so far, without waiting.
+    fn poll(&mut self) -> PyResult<Vec<PyPacket>> {
+        self.fill().map_err(io_error)?;
+        Ok(self.queue.drain(..).map(|pkt| PyPacket { pkt }).collect())
+    }
+
+    // Waits for the next packet, with the given code if one is given.
+    // Packets skipped over stay queued for poll.
+    #[pyo3(signature = (code = None, timeout = 1.0))]
+    fn recv(&mut self, py: Python, code: Option<u8>, timeout: f64) -> PyResult<Option<PyPacket>> {
+        let deadline = Instant::now() + timeout_secs(timeout)?;
+        py.allow_threads(|| -> io::Result<Option<PyPacket>> {
+            loop {
+                if let Some(i) = self.queue.iter().position(|p| code.is_none_or(|c| c == p.code)) {
+                    return Ok(self.queue.remove(i).map(|pkt| PyPacket { pkt }));
+                }
+                if Instant::now() >= deadline {
+                    return Ok(None);
+                }
+                self.fill()?;
+                thread::sleep(Duration::from_millis(1));
+            }
+        }).map_err(io_error)
+    }
+
+    #[pyo3(signature = (code, data = Vec::new()))]
+    fn send(&mut self, code: u8, data: Vec<u8>) -> PyResult<()> {
+        let pkt = PyPacket::new(code, data, 0)?.pkt;
+        self.schema.validate(&pkt).map_err(io_error)?;
+        self.ep.send(pkt).map_err(io_error)
+    }
+
+    // Encodes the message and sends it on the code the schema assigns to
+    // the set.
+    fn send_message(&mut self, py: Python, set: &str, msg: &Bound<PyDict>) -> PyResult<()> {
+        let code = self.schema.code_of(message_set(set)?)
+            .ok_or_else(|| PyValueError::new_err(format!("no code for {} in the schema", set)))?;
+        let data = encode(py, set, msg)?;
+        self.send(code, data.as_bytes().to_vec())
+    }
+
+    // The message set name and decoded message, None when the schema has
+    // nothing for the code.
+    fn decode<'py>(&self, py: Python<'py>, pkt: &PyPacket) -> PyResult<Option<(&'static str, Bound<'py, PyDict>)>> {
+        match self.schema.get(pkt.pkt.code) {
+            Some(set) => Ok(Some((set.name(), decode(py, set.name(), &pkt.pkt.data)?))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[pymodule]
+fn robo(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<PyPacket>()?;
+    m.add_class::<PyParser>()?;
+    m.add_class::<PyLink>()?;
+    m.add_function(wrap_pyfunction!(self::decode, m)?)?;
+    m.add_function(wrap_pyfunction!(self::encode, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
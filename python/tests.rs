@@ -0,0 +1,89 @@
+#![cfg(test)]
+
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use l0::comm::{Endpoint, Packet};
+use super::*;
+
+fn module(py: Python) -> Bound<PyModule> {
+    let m = PyModule::new_bound(py, "robo").unwrap();
+    robo(&m).unwrap();
+    m
+}
+
+#[test]
+fn messages_and_parser() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let robo = module(py);
+        let locals = PyDict::new_bound(py);
+        locals.set_item("robo", robo).unwrap();
+        py.run_bound(r#"
+data = robo.encode("power", {"message": "status", "voltage_mv": 12400, "current_ma": -300, "soc_pct": 80})
+assert data == bytes([1, 0x70, 0x30, 0xd4, 0xfe, 80]), data
+msg = robo.decode("power", data)
+assert msg == {"message": "status", "voltage_mv": 12400, "current_ma": -300, "soc_pct": 80}, msg
+
+frame = {"message": "frame", "strip": 1, "offset": 0, "runs": [{"count": 2, "color": {"r": 255, "g": 0, "b": 0}}]}
+assert robo.decode("led", robo.encode("led", frame)) == frame
+
+try:
+    robo.encode("power", {"message": "status", "voltage_mv": 1})
+    assert False
+except KeyError:
+    pass
+
+p = robo.Parser()
+assert p.feed(bytes([0xff, 1])) == []
+assert p.ready and p.responses() == bytes([0xfe])
+pkts = p.feed(robo.Packet(3, b"\x01\x02", seq=1).encode())
+assert len(pkts) == 1 and pkts[0].code == 3 and pkts[0].data == b"\x01\x02", pkts
+"#, None, Some(&locals)).unwrap();
+    });
+}
+
+#[test]
+fn link_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let device = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+        let mut ep = Endpoint::new(stream).unwrap();
+        loop {
+            for pkt in ep.poll().unwrap() {
+                if pkt.code == 0x01 {
+                    let mut reply = Packet::new_with(0, 0x83);
+                    reply.data = vec![1, 0x70, 0x30, 0x2c, 0x01, 80];
+                    ep.send(reply).unwrap();
+                    return;
+                }
+            }
+        }
+    });
+
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let locals = PyDict::new_bound(py);
+        locals.set_item("robo", module(py)).unwrap();
+        locals.set_item("port", format!("tcp://{}", addr)).unwrap();
+        py.run_bound(r#"
+link = robo.Link(port, schema="0x01=motor,0x83=power")
+assert link.ready
+link.send_message("motor", {"message": "stop"})
+pkt = link.recv(0x83, timeout=2.0)
+assert link.decode(pkt) == ("power", {"message": "status", "voltage_mv": 12400, "current_ma": 300, "soc_pct": 80})
+assert link.stats["packets_sent"] == 1
+for t in (-1.0, float("nan"), float("inf"), 1e300):
+    try:
+        link.recv(0x83, timeout=t)
+        assert False
+    except ValueError:
+        pass
+"#, None, Some(&locals)).unwrap();
+    });
+    device.join().unwrap();
+}
@@ -0,0 +1,140 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use l0::comm::Packet;
+use l2::{Message, Sender};
+use l2::codec::*;
+use l2::queue::{Priority, TxQueue};
+
+const OP_ENGAGE: u8 = 0x01;
+const OP_CLEAR: u8 = 0x02;
+const OP_STATE: u8 = 0x03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstopMessage {
+    Engage { reason: u8 },
+    Clear,
+    State { engaged: bool, reason: u8 }, // device report, e.g. a physical button.
+}
+
+impl Message for EstopMessage {
+    fn encode(&self, data: &mut Vec<u8>) {
+        match *self {
+            EstopMessage::Engage { reason } => {
+                data.push(OP_ENGAGE);
+                data.push(reason);
+            },
+            EstopMessage::Clear => data.push(OP_CLEAR),
+            EstopMessage::State { engaged, reason } => {
+                data.push(OP_STATE);
+                data.push(engaged as u8);
+                data.push(reason);
+            },
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader::new(data);
+        let msg = match r.u8()? {
+            OP_ENGAGE => EstopMessage::Engage { reason: r.u8()? },
+            OP_CLEAR => EstopMessage::Clear,
+            OP_STATE => EstopMessage::State { engaged: r.u8()? != 0, reason: r.u8()? },
+            _ => return None,
+        };
+        r.finish(msg)
+    }
+}
+
+// Latched e-stop state shared by every handle. Once engaged it stays engaged
+// until cleared explicitly; motion packets sent through a MotionGate are
+// rejected meanwhile.
+#[derive(Clone)]
+pub struct Estop {
+    engaged: Arc<Mutex<Option<u8>>>,
+    code: u8,
+    motion_codes: Arc<Vec<u8>>,
+}
+
+impl Estop {
+    // code carries EstopMessage, motion_codes are the packet codes of every
+    // message set that moves the robot.
+    pub fn new(code: u8, motion_codes: &[u8]) -> Self {
+        Estop {
+            engaged: Arc::new(Mutex::new(None)),
+            code,
+            motion_codes: Arc::new(motion_codes.to_vec()),
+        }
+    }
+
+    pub fn is_engaged(&self) -> bool {
+        self.reason().is_some()
+    }
+
+    pub fn reason(&self) -> Option<u8> {
+        *self.engaged.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_motion(&self, pkt: &Packet) -> bool {
+        self.motion_codes.contains(&pkt.code)
+    }
+
+    // Latches the e-stop, discards queued motion packets and queues the
+    // engage message ahead of everything else.
+    pub fn engage(&self, reason: u8, queue: &mut TxQueue) {
+        self.latch(reason);
+        queue.retain(|pkt| !self.is_motion(pkt));
+        queue.push(EstopMessage::Engage { reason }.to_packet(self.code), Priority::Urgent);
+    }
+
+    pub fn clear(&self, queue: &mut TxQueue) {
+        *self.engaged.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        queue.push(EstopMessage::Clear.to_packet(self.code), Priority::Urgent);
+    }
+
+    // Latches on an engaged state reported by the device. A device reporting
+    // clear doesn't release the host latch.
+    pub fn handle(&self, msg: &EstopMessage) {
+        if let EstopMessage::State { engaged: true, reason } = *msg {
+            self.latch(reason);
+        }
+    }
+
+    pub fn gate<S: Sender>(&self, sender: S) -> MotionGate<S> {
+        MotionGate { estop: self.clone(), sender }
+    }
+
+    fn latch(&self, reason: u8) {
+        let mut engaged = self.engaged.lock().unwrap_or_else(|e| e.into_inner());
+        if engaged.is_none() {
+            *engaged = Some(reason);
+        }
+    }
+}
+
+// Sender that refuses motion packets while the e-stop is engaged.
+pub struct MotionGate<S> {
+    estop: Estop,
+    sender: S,
+}
+
+impl<S> MotionGate<S> {
+    pub fn get_ref(&self) -> &S {
+        &self.sender
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.sender
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sender
+    }
+}
+
+impl<S: Sender> Sender for MotionGate<S> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        if self.estop.is_engaged() && self.estop.is_motion(&pkt) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "e-stop engaged"));
+        }
+        self.sender.send(pkt)
+    }
+}
@@ -0,0 +1,4 @@
+pub mod estop;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,57 @@
+#![cfg(test)]
+
+use std::io;
+use l0::comm::Packet;
+use l2::{Message, Sender};
+use l2::motor::MotorMessage;
+use l2::queue::TxQueue;
+use super::estop::*;
+
+const ESTOP: u8 = 0x01;
+const MOTOR: u8 = 0x02;
+const OTHER: u8 = 0x03;
+
+#[test]
+fn test_estop_roundtrip() {
+    for msg in &[EstopMessage::Engage { reason: 3 }, EstopMessage::Clear, EstopMessage::State { engaged: true, reason: 1 }] {
+        assert_eq!(EstopMessage::from_packet(&msg.to_packet(ESTOP)).as_ref(), Some(msg));
+    }
+}
+
+#[test]
+fn test_estop_bypasses_queue() {
+    let estop = Estop::new(ESTOP, &[MOTOR]);
+    let mut q = TxQueue::new();
+    q.send(MotorMessage::Stop.to_packet(MOTOR)).unwrap();
+    q.send(Packet::new_with(0, OTHER)).unwrap();
+    estop.engage(7, &mut q);
+    assert!(estop.is_engaged());
+    assert_eq!(estop.reason(), Some(7));
+    assert_eq!(q.len(), 2);
+    let first = q.pop().unwrap();
+    assert_eq!(EstopMessage::from_packet(&first), Some(EstopMessage::Engage { reason: 7 }));
+    assert_eq!(q.pop().unwrap().code, OTHER);
+}
+
+#[test]
+fn test_estop_latch_blocks_motion() {
+    let estop = Estop::new(ESTOP, &[MOTOR]);
+    let mut q = TxQueue::new();
+    let mut gate = estop.gate(Vec::<Packet>::new());
+    gate.send(Packet::new_with(0, MOTOR)).unwrap();
+
+    estop.clone().handle(&EstopMessage::State { engaged: true, reason: 2 });
+    let err = gate.send(Packet::new_with(0, MOTOR)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    gate.send(Packet::new_with(0, OTHER)).unwrap();
+
+    // the device reporting clear doesn't release the latch.
+    estop.handle(&EstopMessage::State { engaged: false, reason: 0 });
+    assert!(estop.is_engaged());
+    estop.engage(5, &mut q);
+    assert_eq!(estop.reason(), Some(2));
+
+    estop.clear(&mut q);
+    gate.send(Packet::new_with(0, MOTOR)).unwrap();
+    assert_eq!(gate.get_ref().len(), 3);
+}
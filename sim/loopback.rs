@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+type Pipe = Arc<Mutex<VecDeque<u8>>>;
+
+// One end of an in-memory full-duplex byte stream, standing in for a serial
+// port. Reads never block; with nothing buffered they fail with WouldBlock.
+#[derive(Clone)]
+pub struct Loopback {
+    rx: Pipe,
+    tx: Pipe,
+}
+
+impl Loopback {
+    pub fn pair() -> (Loopback, Loopback) {
+        let a: Pipe = Arc::new(Mutex::new(VecDeque::new()));
+        let b: Pipe = Arc::new(Mutex::new(VecDeque::new()));
+        (Loopback { rx: a.clone(), tx: b.clone() }, Loopback { rx: b, tx: a })
+    }
+
+    // Bytes waiting to be read from this end.
+    pub fn available(&self) -> usize {
+        self.rx.lock().unwrap().len()
+    }
+}
+
+impl io::Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut rx = self.rx.lock().unwrap();
+        if rx.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock, "no data"));
+        }
+        let n = buf.len().min(rx.len());
+        for (b, v) in buf.iter_mut().zip(rx.drain(..n)) {
+            *b = v;
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx.lock().unwrap().extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
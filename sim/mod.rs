@@ -0,0 +1,10 @@
+mod loopback;
+mod robot;
+mod session;
+
+pub use self::loopback::*;
+pub use self::robot::*;
+pub use self::session::*;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,213 @@
+use std::f64::consts::PI;
+use std::io;
+use std::io::{Read, Write};
+use std::time::Duration;
+use l0::comm::{Endpoint, Packet};
+use kinematics::Twist;
+use kinematics::diff_drive::DiffDrive;
+use l2::Message;
+use l2::encoder::EncoderMessage;
+use l2::imu::{ImuSample, Vec3};
+use l2::motor::{MotorMessage, VELOCITY_LSB};
+use l2::power::PowerMessage;
+use nav::{Pose2, normalize_angle};
+
+const GRAVITY: f64 = 9.80665;
+
+// The device profile and physical parameters of the simulated robot. Motor
+// commands are requests, telemetry goes out as events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimConfig {
+    pub motor_code: u8,
+    pub encoder_code: u8,
+    pub imu_code: u8,
+    pub power_code: u8,
+    pub ticks_per_rev: f64,
+    pub telemetry_period: Duration,
+    pub max_wheel_speed: f64, // rad/s, commands are clamped to this.
+    pub capacity_ah: f64,
+    pub full_voltage: f64,
+    pub empty_voltage: f64,
+    pub internal_resistance: f64, // ohms
+    pub idle_current: f64,        // amps
+    pub motor_current: f64,       // amps per rad/s of each wheel
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            motor_code: 0x01,
+            encoder_code: 0x81,
+            imu_code: 0x82,
+            power_code: 0x83,
+            ticks_per_rev: 1024.0,
+            telemetry_period: Duration::from_millis(20),
+            max_wheel_speed: 30.0,
+            capacity_ah: 2.0,
+            full_voltage: 12.6,
+            empty_voltage: 10.5,
+            internal_resistance: 0.1,
+            idle_current: 0.2,
+            motor_current: 0.05,
+        }
+    }
+}
+
+// A virtual differential-drive robot speaking the wire protocol. It obeys
+// motor velocity commands and reports encoder counts, IMU motion and battery
+// status every telemetry period. Time only advances through step, so tests
+// run as fast as they like and are repeatable.
+pub struct SimRobot<T> {
+    endpoint: Endpoint<T>,
+    kinematics: DiffDrive,
+    config: SimConfig,
+    commands: [f64; 2],
+    wheels: [f64; 2], // accumulated rotation in radians.
+    pose: Pose2,
+    twist: Twist,
+    accel: f64,
+    charge_ah: f64,
+    current: f64,
+    time: Duration,
+    last_telemetry: Option<Duration>,
+}
+
+impl<T: Read + Write> SimRobot<T> {
+    pub fn new(transport: T, kinematics: DiffDrive) -> io::Result<Self> {
+        let config = SimConfig::default();
+        Ok(SimRobot {
+            endpoint: Endpoint::named(transport, "sim")?,
+            kinematics,
+            config,
+            commands: [0.0; 2],
+            wheels: [0.0; 2],
+            pose: Pose2::default(),
+            twist: Twist::zero(),
+            accel: 0.0,
+            charge_ah: config.capacity_ah,
+            current: 0.0,
+            time: Duration::from_secs(0),
+            last_telemetry: None,
+        })
+    }
+
+    pub fn with_config(mut self, config: SimConfig) -> Self {
+        self.charge_ah = self.charge_ah.min(config.capacity_ah);
+        self.config = config;
+        self
+    }
+
+    pub fn endpoint(&mut self) -> &mut Endpoint<T> {
+        &mut self.endpoint
+    }
+
+    // Ground truth, for comparing against estimates.
+    pub fn pose(&self) -> &Pose2 {
+        &self.pose
+    }
+
+    pub fn set_pose(&mut self, pose: Pose2) {
+        self.pose = pose;
+    }
+
+    pub fn twist(&self) -> &Twist {
+        &self.twist
+    }
+
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    // State of charge in percent.
+    pub fn soc(&self) -> f64 {
+        self.charge_ah / self.config.capacity_ah * 100.0
+    }
+
+    pub fn set_soc(&mut self, soc: f64) {
+        self.charge_ah = soc.clamp(0.0, 100.0) / 100.0 * self.config.capacity_ah;
+    }
+
+    pub fn voltage(&self) -> f64 {
+        let c = &self.config;
+        c.empty_voltage + (c.full_voltage - c.empty_voltage) * self.soc() / 100.0
+            - self.current * c.internal_resistance
+    }
+
+    fn handle(&mut self, pkt: &Packet) {
+        if pkt.code != self.config.motor_code {
+            return;
+        }
+        match MotorMessage::from_packet(pkt) {
+            Some(MotorMessage::SetVelocities { first, velocities }) => {
+                let max = self.config.max_wheel_speed;
+                for (i, v) in velocities.iter().enumerate() {
+                    if let Some(cmd) = self.commands.get_mut(first as usize + i) {
+                        *cmd = (*v as f64 * VELOCITY_LSB).clamp(-max, max);
+                    }
+                }
+            },
+            Some(MotorMessage::Stop) => self.commands = [0.0; 2],
+            None => (),
+        }
+    }
+
+    // Handles pending commands, then advances the simulation by dt and sends
+    // telemetry if due.
+    pub fn step(&mut self, dt: Duration) -> io::Result<()> {
+        for pkt in self.endpoint.poll()? {
+            self.handle(&pkt);
+        }
+        let secs = dt.as_secs_f64();
+        // a flat battery stalls the motors.
+        let speeds = if self.charge_ah > 0.0 { self.commands } else { [0.0; 2] };
+        let twist = self.kinematics.twist_lr(speeds[0], speeds[1]);
+        if secs > 0.0 {
+            self.accel = (twist.vx - self.twist.vx) / secs;
+        }
+        let (s, c) = (self.pose.theta + twist.omega * secs / 2.0).sin_cos();
+        self.pose = Pose2::new(
+            self.pose.x + twist.vx * secs * c,
+            self.pose.y + twist.vx * secs * s,
+            normalize_angle(self.pose.theta + twist.omega * secs),
+        );
+        for (w, v) in self.wheels.iter_mut().zip(speeds.iter()) {
+            *w += v * secs;
+        }
+        self.twist = twist;
+        self.current = self.config.idle_current
+            + self.config.motor_current * (speeds[0].abs() + speeds[1].abs());
+        self.charge_ah = (self.charge_ah - self.current * secs / 3600.0).max(0.0);
+        self.time += dt;
+
+        let due = match self.last_telemetry {
+            Some(last) => self.time >= last + self.config.telemetry_period,
+            None => true,
+        };
+        if due && self.endpoint.is_ready() {
+            self.last_telemetry = Some(self.time);
+            self.send_telemetry()?;
+        }
+        Ok(())
+    }
+
+    fn send_telemetry(&mut self) -> io::Result<()> {
+        let c = self.config;
+        for (channel, w) in self.wheels.iter().enumerate() {
+            let count = (w / (2.0 * PI) * c.ticks_per_rev).round() as i64 as u32;
+            let msg = EncoderMessage::Count { channel: channel as u8, count };
+            self.endpoint.send(msg.to_packet(c.encoder_code))?;
+        }
+        let sample = ImuSample {
+            accel: Vec3::new(self.accel, self.twist.vx * self.twist.omega, GRAVITY),
+            gyro: Vec3::new(0.0, 0.0, self.twist.omega),
+            ts: self.time,
+        };
+        self.endpoint.send(sample.to_message().to_packet(c.imu_code))?;
+        let status = PowerMessage::Status {
+            voltage_mv: (self.voltage() * 1000.0).round() as u16,
+            current_ma: (self.current * 1000.0).round() as i16,
+            soc_pct: self.soc().round() as u8,
+        };
+        self.endpoint.send(status.to_packet(c.power_code))
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+use l0::comm::{ParseResult, Parser};
+
+const MAGIC: &[u8; 4] = b"RSES";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    Rx(Vec<u8>), // what one read returned.
+    Tx(Vec<u8>), // what one write accepted.
+    Timeout,     // a read that returned nothing.
+}
+
+// ts is relative to the start of the session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionRecord {
+    pub ts: Duration,
+    pub event: SessionEvent,
+}
+
+// The raw traffic of a link in both directions, in the order the
+// application saw it. Unlike a wire log it keeps the read boundaries and
+// empty reads, which is what the application's behaviour depends on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    pub records: Vec<SessionRecord>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session::default()
+    }
+
+    pub fn push(&mut self, ts: Duration, event: SessionEvent) {
+        self.records.push(SessionRecord { ts, event });
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.records.last().map_or(Duration::from_secs(0), |r| r.ts)
+    }
+
+    // All bytes received, concatenated.
+    pub fn rx_bytes(&self) -> Vec<u8> {
+        self.records.iter().filter_map(|r| match r.event {
+            SessionEvent::Rx(ref data) => Some(&data[..]),
+            _ => None,
+        }).flat_map(|d| d.iter().cloned()).collect()
+    }
+
+    // All bytes sent, concatenated.
+    pub fn tx_bytes(&self) -> Vec<u8> {
+        self.records.iter().filter_map(|r| match r.event {
+            SessionEvent::Tx(ref data) => Some(&data[..]),
+            _ => None,
+        }).flat_map(|d| d.iter().cloned()).collect()
+    }
+
+    // Binary format: magic and version, then per record a kind byte ('r',
+    // 't' or 'o'), the timestamp in microseconds as a little-endian u64 and
+    // the data length as a little-endian u32 followed by the data.
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        for r in &self.records {
+            let (kind, data): (u8, &[u8]) = match r.event {
+                SessionEvent::Rx(ref data) => (b'r', data),
+                SessionEvent::Tx(ref data) => (b't', data),
+                SessionEvent::Timeout => (b'o', &[]),
+            };
+            out.write_all(&[kind])?;
+            out.write_all(&(r.ts.as_micros() as u64).to_le_bytes())?;
+            out.write_all(&(data.len() as u32).to_le_bytes())?;
+            out.write_all(data)?;
+        }
+        out.flush()
+    }
+
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a session recording"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported session version"));
+        }
+        let mut session = Session::new();
+        let mut kind = [0u8; 1];
+        loop {
+            match input.read(&mut kind) {
+                Ok(0) => return Ok(session),
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            let mut ts = [0u8; 8];
+            let mut len = [0u8; 4];
+            input.read_exact(&mut ts)?;
+            input.read_exact(&mut len)?;
+            let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+            input.read_exact(&mut data)?;
+            let event = match kind[0] {
+                b'r' => SessionEvent::Rx(data),
+                b't' => SessionEvent::Tx(data),
+                b'o' if data.is_empty() => SessionEvent::Timeout,
+                _ => return Err(invalid("bad session record")),
+            };
+            session.push(Duration::from_micros(u64::from_le_bytes(ts)), event);
+        }
+    }
+
+    // Runs the received bytes and timeouts through parser in order, handing
+    // each result to f with the time it happened.
+    pub fn replay_parser<F>(&self, parser: &mut Parser, mut f: F)
+        where F: FnMut(Duration, ParseResult) {
+        for r in &self.records {
+            match r.event {
+                SessionEvent::Rx(ref data) => for b in data {
+                    f(r.ts, parser.parse(*b));
+                },
+                SessionEvent::Timeout => f(r.ts, parser.timeout()),
+                SessionEvent::Tx(_) => (),
+            }
+        }
+    }
+}
+
+// Wraps a transport, recording everything read from and written to it.
+pub struct Recorder<T> {
+    inner: T,
+    start: Instant,
+    session: Session,
+}
+
+impl<T> Recorder<T> {
+    pub fn new(inner: T) -> Self {
+        Recorder { inner, start: Instant::now(), session: Session::new() }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_session(self) -> Session {
+        self.session
+    }
+
+    fn push(&mut self, event: SessionEvent) {
+        let ts = self.start.elapsed();
+        self.session.push(ts, event);
+    }
+}
+
+impl<T: Read> Read for Recorder<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(0) => {
+                self.push(SessionEvent::Timeout);
+                Ok(0)
+            },
+            Ok(n) => {
+                self.push(SessionEvent::Rx(buf[..n].to_vec()));
+                Ok(n)
+            },
+            Err(e) => {
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut {
+                    self.push(SessionEvent::Timeout);
+                }
+                Err(e)
+            },
+        }
+    }
+}
+
+impl<T: Write> Write for Recorder<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.push(SessionEvent::Tx(buf[..n].to_vec()));
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    // Received data becomes readable at its recorded time divided by the
+    // factor; infinity delivers it as soon as it is read.
+    Speed(f64),
+    // Every read returns exactly what the recorded one did, timeouts
+    // included, without looking at the clock.
+    Deterministic,
+}
+
+// A transport that plays back the received side of a session. Writes are
+// kept so they can be checked against what was sent originally.
+pub struct Replayer {
+    session: Session,
+    next: usize,
+    pending: VecDeque<u8>,
+    timing: Timing,
+    start: Option<Instant>,
+    written: Vec<u8>,
+}
+
+impl Replayer {
+    // Plays back in real time.
+    pub fn new(session: Session) -> Self {
+        Replayer {
+            session,
+            next: 0,
+            pending: VecDeque::new(),
+            timing: Timing::Speed(1.0),
+            start: None,
+            written: Vec::new(),
+        }
+    }
+
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn deterministic(self) -> Self {
+        self.with_timing(Timing::Deterministic)
+    }
+
+    // True once everything received has been read.
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty() && self.session.records[self.next..].iter()
+            .all(|r| match r.event {
+                SessionEvent::Rx(_) => false,
+                SessionEvent::Timeout => self.timing != Timing::Deterministic,
+                SessionEvent::Tx(_) => true,
+            })
+    }
+
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    // The offset of the first byte written that differs from what was sent
+    // in the session, None if the writes so far match.
+    pub fn divergence(&self) -> Option<usize> {
+        let sent = self.session.tx_bytes();
+        self.written.iter().enumerate()
+            .find(|&(i, b)| sent.get(i) != Some(b))
+            .map(|(i, _)| i)
+    }
+
+    fn due(&mut self, ts: Duration) -> bool {
+        match self.timing {
+            Timing::Deterministic => true,
+            Timing::Speed(speed) => {
+                let start = *self.start.get_or_insert_with(Instant::now);
+                speed.is_infinite() || start.elapsed().as_secs_f64() * speed >= ts.as_secs_f64()
+            },
+        }
+    }
+}
+
+impl Read for Replayer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            loop {
+                let r = match self.session.records.get(self.next) {
+                    Some(r) => r.clone(),
+                    None => return Err(io::Error::new(io::ErrorKind::WouldBlock, "end of session")),
+                };
+                if !self.due(r.ts) {
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "no data"));
+                }
+                self.next += 1;
+                match r.event {
+                    SessionEvent::Rx(data) => {
+                        self.pending.extend(data);
+                        break;
+                    },
+                    SessionEvent::Timeout if self.timing == Timing::Deterministic => {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "recorded timeout"));
+                    },
+                    _ => (),
+                }
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        for (b, v) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *b = v;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Replayer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+#![cfg(test)]
+
+use std::time::Duration;
+use kinematics::{DriveClient, Twist};
+use kinematics::diff_drive::DiffDrive;
+use l0::comm::{Endpoint, Packet, Parser};
+use l2::Message;
+use l2::encoder::EncoderMessage;
+use l2::power::PowerMessage;
+use nav::odometry::Odometry;
+use super::*;
+
+const DT: Duration = Duration::from_millis(10);
+
+fn assert_near(a: f64, b: f64, tolerance: f64) {
+    assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+}
+
+#[test]
+fn robot_drives_and_reports() {
+    let kinematics = DiffDrive::new(0.3, 0.05);
+    let (a, b) = Loopback::pair();
+    let mut robot = SimRobot::new(b, kinematics).unwrap();
+    let mut host = Endpoint::new(a).unwrap();
+    let mut odom = Odometry::new(kinematics, &[0, 1], 1024.0, 32);
+    let mut power = None;
+
+    for i in 0..201 {
+        if i == 1 {
+            DriveClient::new(kinematics, &mut host, 0x01).drive(&Twist::new(0.5, 0.0)).unwrap();
+        }
+        robot.step(DT).unwrap();
+        for pkt in host.poll().unwrap() {
+            match pkt.code {
+                0x81 => {
+                    odom.handle_encoder(&EncoderMessage::from_packet(&pkt).unwrap(), robot.time());
+                },
+                0x83 => power = PowerMessage::from_packet(&pkt),
+                _ => (),
+            }
+        }
+    }
+    assert_near(robot.pose().x, 1.0, 1e-9);
+    assert_near(odom.pose().x, robot.pose().x, 0.01);
+    assert_near(odom.pose().y, 0.0, 1e-6);
+    match power {
+        Some(PowerMessage::Status { voltage_mv, current_ma, soc_pct }) => {
+            assert_eq!(current_ma, 1200);
+            assert_eq!(soc_pct, 100);
+            assert!(voltage_mv < 12600 && voltage_mv > 12400, "{}", voltage_mv);
+        },
+        other => panic!("{:?}", other),
+    }
+
+    // turning in place on a flat battery goes nowhere.
+    robot.set_soc(0.0);
+    DriveClient::new(kinematics, &mut host, 0x01).drive(&Twist::new(0.0, 1.0)).unwrap();
+    robot.step(DT).unwrap();
+    assert_eq!(*robot.twist(), Twist::zero());
+}
+
+// Records a host talking to a simulated robot, then replays the robot's
+// side into a fresh host.
+fn record_session() -> (Session, Vec<Packet>) {
+    let kinematics = DiffDrive::new(0.3, 0.05);
+    let (a, b) = Loopback::pair();
+    let mut robot = SimRobot::new(b, kinematics).unwrap();
+    let mut host = Endpoint::new(Recorder::new(a)).unwrap();
+    let mut received = Vec::new();
+    for i in 0..20 {
+        if i == 1 {
+            DriveClient::new(kinematics, &mut host, 0x01).drive(&Twist::new(0.5, 0.0)).unwrap();
+        }
+        robot.step(DT).unwrap();
+        received.extend(host.poll().unwrap());
+    }
+    (host.transport().session().clone(), received)
+}
+
+#[test]
+fn session_replays_deterministically() {
+    let (session, received) = record_session();
+    assert!(!received.is_empty());
+    assert!(session.records.iter().any(|r| r.event == SessionEvent::Timeout));
+
+    let mut buf = Vec::new();
+    session.write_to(&mut buf).unwrap();
+    let session = Session::read_from(&buf[..]).unwrap();
+
+    let kinematics = DiffDrive::new(0.3, 0.05);
+    let mut host = Endpoint::new(Replayer::new(session.clone()).deterministic()).unwrap();
+    let mut replayed = Vec::new();
+    for i in 0..20 {
+        if i == 1 {
+            DriveClient::new(kinematics, &mut host, 0x01).drive(&Twist::new(0.5, 0.0)).unwrap();
+        }
+        replayed.extend(host.poll().unwrap());
+    }
+    assert_eq!(replayed, received);
+    assert!(host.transport().is_finished());
+    assert_eq!(host.transport().divergence(), None);
+    assert_eq!(host.transport().written(), &session.tx_bytes()[..]);
+
+    let mut parser = Parser::new();
+    let mut packets = 0;
+    session.replay_parser(&mut parser, |_, r| packets += r.packet.is_some() as usize);
+    assert_eq!(packets, received.len());
+}
+
+#[test]
+fn session_replays_accelerated() {
+    let mut session = Session::new();
+    session.push(Duration::from_millis(0), SessionEvent::Rx(vec![0xff, 1]));
+    session.push(Duration::from_millis(500), SessionEvent::Rx(vec![1, 0x12, 7]));
+    let mut replayer = Replayer::new(session).with_timing(Timing::Speed(100.0));
+    let mut host = Endpoint::new(&mut replayer).unwrap();
+    assert!(host.poll().unwrap().is_empty());
+    assert!(host.is_ready());
+    ::std::thread::sleep(Duration::from_millis(10));
+    let pkts = host.poll().unwrap();
+    assert_eq!(pkts.len(), 1);
+    assert_eq!(pkts[0].data, vec![7]);
+    host.send(Packet::new_with(0, 2)).unwrap();
+    drop(host);
+    assert!(replayer.is_finished());
+    assert_eq!(replayer.divergence(), Some(0));
+}
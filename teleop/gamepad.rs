@@ -0,0 +1,100 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use kinematics::{DriveClient, DriveKinematics, Twist};
+use l2::Sender;
+use super::{rate_period, AxisMapping, Mapping, TwistAxis};
+
+pub type GamepadMapping<T> = Mapping<Axis, Button, T>;
+
+// Left stick drives forward/backward, right stick turns.
+pub fn default_mapping<T>(max_linear: f64, max_angular: f64) -> GamepadMapping<T> {
+    Mapping::new()
+        .axis(AxisMapping::new(Axis::LeftStickY, TwistAxis::Vx, max_linear).with_deadband(0.1).with_expo(0.3))
+        .axis(AxisMapping::new(Axis::RightStickX, TwistAxis::Omega, -max_angular).with_deadband(0.1).with_expo(0.3))
+}
+
+// Reads the first gamepad that produces input and publishes the mapped
+// twist at a fixed rate.
+pub struct GamepadTeleop<T> {
+    gilrs: Gilrs,
+    mapping: GamepadMapping<T>,
+    active: Option<GamepadId>,
+    period: Duration,
+}
+
+impl<T: Clone> GamepadTeleop<T> {
+    pub fn new(mapping: GamepadMapping<T>, rate_hz: f64) -> io::Result<Self> {
+        let period = rate_period(rate_hz)?;
+        let gilrs = Gilrs::new().map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(GamepadTeleop {
+            gilrs,
+            mapping,
+            active: None,
+            period,
+        })
+    }
+
+    // Drains pending input events, returns the actions of pressed buttons.
+    pub fn poll(&mut self) -> Vec<T> {
+        let mut actions = Vec::new();
+        while let Some(ev) = self.gilrs.next_event() {
+            match ev.event {
+                EventType::Disconnected if self.active == Some(ev.id) => self.active = None,
+                EventType::ButtonPressed(button, _) => {
+                    self.active.get_or_insert(ev.id);
+                    if self.active == Some(ev.id) {
+                        if let Some(action) = self.mapping.action(&button) {
+                            actions.push(action.clone());
+                        }
+                    }
+                },
+                EventType::AxisChanged(..) => {
+                    self.active.get_or_insert(ev.id);
+                },
+                _ => (),
+            }
+        }
+        actions
+    }
+
+    // Zero while no gamepad is active.
+    pub fn twist(&self) -> Twist {
+        match self.active {
+            Some(id) => {
+                let pad = self.gilrs.gamepad(id);
+                self.mapping.twist(|axis| pad.value(*axis) as f64)
+            },
+            None => Twist::zero(),
+        }
+    }
+
+    pub fn tick<K, S>(&mut self, client: &mut DriveClient<K, S>) -> io::Result<Vec<T>>
+        where K: DriveKinematics, S: Sender {
+        let actions = self.poll();
+        client.drive(&self.twist())?;
+        Ok(actions)
+    }
+
+    // Publishes at the configured rate until on_action returns false, then
+    // stops the robot.
+    pub fn run<K, S, F>(&mut self, client: &mut DriveClient<K, S>, mut on_action: F) -> io::Result<()>
+        where K: DriveKinematics, S: Sender, F: FnMut(&T) -> bool {
+        let mut next = Instant::now();
+        loop {
+            for action in self.tick(client)? {
+                if !on_action(&action) {
+                    return client.stop();
+                }
+            }
+            next += self.period;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            } else {
+                next = now;
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::io;
+use std::time::{Duration, Instant};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use control::ramp::Ramp;
+use kinematics::{DriveClient, DriveKinematics};
+use l2::Sender;
+use super::{rate_period, Wasd};
+
+// Drives from the terminal: WASD to move, space or x to stop, Esc, q or
+// Ctrl-C to quit. Commands pass through a ramp so key presses don't step
+// the motors.
+pub struct KeyboardTeleop {
+    wasd: Wasd,
+    ramp: Ramp,
+    period: Duration,
+}
+
+impl KeyboardTeleop {
+    pub fn new(wasd: Wasd, ramp: Ramp, rate_hz: f64) -> io::Result<Self> {
+        Ok(KeyboardTeleop {
+            wasd,
+            ramp,
+            period: rate_period(rate_hz)?,
+        })
+    }
+
+    // Runs until quit, with the terminal in raw mode meanwhile. The robot is
+    // stopped on exit.
+    pub fn run<K, S>(&mut self, client: &mut DriveClient<K, S>) -> io::Result<()>
+        where K: DriveKinematics, S: Sender {
+        terminal::enable_raw_mode()?;
+        let result = self.run_raw(client);
+        let stopped = client.stop();
+        terminal::disable_raw_mode()?;
+        result.and(stopped)
+    }
+
+    fn run_raw<K, S>(&mut self, client: &mut DriveClient<K, S>) -> io::Result<()>
+        where K: DriveKinematics, S: Sender {
+        let start = Instant::now();
+        let mut next = start;
+        loop {
+            let now = Instant::now();
+            if now >= next {
+                let target = self.wasd.target(now - start);
+                self.ramp.drive(&target, self.period, client)?;
+                next += self.period;
+                continue;
+            }
+            if !event::poll(next - now)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char(c) => {
+                        self.wasd.press(c, start.elapsed());
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+}
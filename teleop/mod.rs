@@ -0,0 +1,177 @@
+use std::io;
+use std::time::Duration;
+use kinematics::Twist;
+
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+#[cfg(feature = "keyboard")]
+pub mod keyboard;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwistAxis {
+    Vx,
+    Vy,
+    Omega,
+}
+
+// The command period for a teleop loop running at rate_hz, which must be
+// positive and finite.
+pub fn rate_period(rate_hz: f64) -> io::Result<Duration> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "rate must be positive and finite");
+    if !(rate_hz.is_finite() && rate_hz > 0.0) {
+        return Err(invalid());
+    }
+    Duration::try_from_secs_f64(1.0 / rate_hz).map_err(|_| invalid())
+}
+
+// Zeroes inputs within width of center and rescales the rest back to the
+// full [-1, 1] range, so there's no step at the deadband edge.
+pub fn deadband(v: f64, width: f64) -> f64 {
+    if v.abs() <= width || width >= 1.0 {
+        0.0
+    } else {
+        (v.abs() - width) / (1.0 - width) * v.signum()
+    }
+}
+
+// Blends a linear (expo = 0) and cubic (expo = 1) response for finer control
+// around center.
+pub fn expo(v: f64, expo: f64) -> f64 {
+    (1.0 - expo) * v + expo * v * v * v
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisMapping<A> {
+    pub input: A,
+    pub target: TwistAxis,
+    pub scale: f64, // twist value at full deflection, negative to invert.
+    pub deadband: f64,
+    pub expo: f64,
+}
+
+impl<A> AxisMapping<A> {
+    pub fn new(input: A, target: TwistAxis, scale: f64) -> Self {
+        AxisMapping { input, target, scale, deadband: 0.0, expo: 0.0 }
+    }
+
+    pub fn with_deadband(mut self, width: f64) -> Self {
+        self.deadband = width;
+        self
+    }
+
+    pub fn with_expo(mut self, e: f64) -> Self {
+        self.expo = e;
+        self
+    }
+
+    pub fn apply(&self, raw: f64) -> f64 {
+        expo(deadband(raw.clamp(-1.0, 1.0), self.deadband), self.expo) * self.scale
+    }
+}
+
+// Maps input axes of type A to twist axes, and buttons of type B to
+// application defined actions T.
+#[derive(Debug, Clone)]
+pub struct Mapping<A, B, T> {
+    pub axes: Vec<AxisMapping<A>>,
+    pub buttons: Vec<(B, T)>,
+}
+
+impl<A, B: PartialEq, T> Default for Mapping<A, B, T> {
+    fn default() -> Self {
+        Mapping::new()
+    }
+}
+
+impl<A, B: PartialEq, T> Mapping<A, B, T> {
+    pub fn new() -> Self {
+        Mapping { axes: Vec::new(), buttons: Vec::new() }
+    }
+
+    pub fn axis(mut self, m: AxisMapping<A>) -> Self {
+        self.axes.push(m);
+        self
+    }
+
+    pub fn button(mut self, button: B, action: T) -> Self {
+        self.buttons.push((button, action));
+        self
+    }
+
+    // Sums the contributions of all mapped axes, read gives the raw input.
+    pub fn twist<F: Fn(&A) -> f64>(&self, read: F) -> Twist {
+        let mut t = Twist::zero();
+        for m in &self.axes {
+            let v = m.apply(read(&m.input));
+            match m.target {
+                TwistAxis::Vx => t.vx += v,
+                TwistAxis::Vy => t.vy += v,
+                TwistAxis::Omega => t.omega += v,
+            }
+        }
+        t
+    }
+
+    pub fn action(&self, button: &B) -> Option<&T> {
+        self.buttons.iter().find(|b| b.0 == *button).map(|b| &b.1)
+    }
+}
+
+// WASD driving from key presses. Terminals report key repeats but usually
+// not releases, so each axis returns to zero when its keys haven't been seen
+// for the hold time.
+#[derive(Debug, Clone)]
+pub struct Wasd {
+    max_linear: f64,
+    max_angular: f64,
+    hold: Duration,
+    linear: Option<(f64, Duration)>,
+    angular: Option<(f64, Duration)>,
+}
+
+impl Wasd {
+    pub fn new(max_linear: f64, max_angular: f64) -> Self {
+        Wasd {
+            max_linear,
+            max_angular,
+            hold: Duration::from_millis(600),
+            linear: None,
+            angular: None,
+        }
+    }
+
+    // Longer than the terminal's initial key repeat delay.
+    pub fn with_hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    // Handles a key at ts, returns false if the key isn't bound.
+    pub fn press(&mut self, key: char, ts: Duration) -> bool {
+        match key.to_ascii_lowercase() {
+            'w' => self.linear = Some((self.max_linear, ts)),
+            's' => self.linear = Some((-self.max_linear, ts)),
+            'a' => self.angular = Some((self.max_angular, ts)),
+            'd' => self.angular = Some((-self.max_angular, ts)),
+            ' ' | 'x' => self.stop(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn stop(&mut self) {
+        self.linear = None;
+        self.angular = None;
+    }
+
+    pub fn target(&self, ts: Duration) -> Twist {
+        let held = |axis: Option<(f64, Duration)>| match axis {
+            Some((v, at)) if ts < at + self.hold => v,
+            _ => 0.0,
+        };
+        Twist::new(held(self.linear), held(self.angular))
+    }
+}
+
+#[cfg(test)]
+mod tests;
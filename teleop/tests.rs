@@ -0,0 +1,72 @@
+#![cfg(test)]
+
+use std::time::Duration;
+use kinematics::Twist;
+use super::*;
+
+fn assert_near(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+}
+
+#[test]
+fn test_deadband() {
+    assert_eq!(deadband(0.05, 0.1), 0.0);
+    assert_near(deadband(0.55, 0.1), 0.5);
+    assert_near(deadband(-1.0, 0.1), -1.0);
+    assert_eq!(deadband(1.0, 1.0), 0.0);
+}
+
+#[test]
+fn test_expo() {
+    assert_near(expo(0.5, 0.0), 0.5);
+    assert_near(expo(0.5, 1.0), 0.125);
+    assert_near(expo(-1.0, 0.7), -1.0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Input {
+    X,
+    Y,
+}
+
+#[test]
+fn test_mapping() {
+    let m = Mapping::new()
+        .axis(AxisMapping::new(Input::Y, TwistAxis::Vx, 2.0).with_deadband(0.1))
+        .axis(AxisMapping::new(Input::X, TwistAxis::Omega, -1.0).with_expo(1.0))
+        .button('a', "horn");
+    let t = m.twist(|i| match *i {
+        Input::X => 0.5,
+        Input::Y => 2.0,
+    });
+    assert_near(t.vx, 2.0);
+    assert_near(t.vy, 0.0);
+    assert_near(t.omega, -0.125);
+    assert_eq!(m.action(&'a'), Some(&"horn"));
+    assert_eq!(m.action(&'b'), None);
+}
+
+#[test]
+fn test_wasd() {
+    let ms = Duration::from_millis;
+    let mut k = Wasd::new(1.0, 2.0).with_hold(ms(500));
+    assert_eq!(k.target(ms(0)), Twist::zero());
+    assert!(k.press('w', ms(0)));
+    assert!(k.press('A', ms(300)));
+    assert_eq!(k.target(ms(400)), Twist::new(1.0, 2.0));
+    assert_eq!(k.target(ms(600)), Twist::new(0.0, 2.0));
+    assert!(k.press('s', ms(600)));
+    assert!(k.press('d', ms(600)));
+    assert_eq!(k.target(ms(700)), Twist::new(-1.0, -2.0));
+    assert!(k.press(' ', ms(700)));
+    assert_eq!(k.target(ms(700)), Twist::zero());
+    assert!(!k.press('z', ms(700)));
+}
+
+#[test]
+fn test_rate_period() {
+    assert_eq!(rate_period(20.0).unwrap(), Duration::from_millis(50));
+    for rate in [0.0, -5.0, f64::NAN, f64::INFINITY, 1e-300] {
+        assert_eq!(rate_period(rate).unwrap_err().kind(), ::std::io::ErrorKind::InvalidInput);
+    }
+}
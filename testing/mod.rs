@@ -0,0 +1,75 @@
+// Helpers for integration tests: assert_packet! for checking single
+// packets, and Tap for recording traffic and checking its order.
+mod tap;
+
+pub use self::tap::*;
+
+use fmt::display::HexDump;
+use l0::comm::Packet;
+
+// Checks fields of a packet, naming the first mismatch and dumping the
+// packet on failure:
+//
+//   assert_packet!(pkt, code = 0x12, len = 2, payload[0] == 3);
+//   assert_packet!(pkt, seq = 4, payload = [1, 2]);
+#[macro_export]
+macro_rules! assert_packet {
+    (@check $p:ident $(,)*) => {};
+    (@check $p:ident, code = $v:expr, $($rest:tt)*) => {
+        $crate::testing::check_field($p, "code", $p.code as usize, $v as usize);
+        assert_packet!(@check $p, $($rest)*);
+    };
+    (@check $p:ident, seq = $v:expr, $($rest:tt)*) => {
+        $crate::testing::check_field($p, "seq", $p.seq as usize, $v as usize);
+        assert_packet!(@check $p, $($rest)*);
+    };
+    (@check $p:ident, len = $v:expr, $($rest:tt)*) => {
+        $crate::testing::check_field($p, "len", $p.data.len(), $v as usize);
+        assert_packet!(@check $p, $($rest)*);
+    };
+    (@check $p:ident, payload = $v:expr, $($rest:tt)*) => {
+        $crate::testing::check_payload($p, &$v[..]);
+        assert_packet!(@check $p, $($rest)*);
+    };
+    (@check $p:ident, payload[$i:expr] == $v:expr, $($rest:tt)*) => {
+        $crate::testing::check_payload_byte($p, $i, $v);
+        assert_packet!(@check $p, $($rest)*);
+    };
+    ($pkt:expr, $($rest:tt)*) => {{
+        let pkt: &$crate::l0::comm::Packet = &$pkt;
+        assert_packet!(@check pkt, $($rest)*,);
+    }};
+}
+
+fn mismatch(pkt: &Packet, what: String) -> ! {
+    panic!("packet mismatch: {}\n{}", what, HexDump::packet(pkt))
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn check_field(pkt: &Packet, name: &str, actual: usize, expected: usize) {
+    if actual != expected {
+        mismatch(pkt, format!("{} is 0x{:02x}, expected 0x{:02x}", name, actual, expected));
+    }
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn check_payload(pkt: &Packet, expected: &[u8]) {
+    if pkt.data != expected {
+        mismatch(pkt, format!("payload is {:02x?}, expected {:02x?}", pkt.data, expected));
+    }
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn check_payload_byte(pkt: &Packet, index: usize, expected: u8) {
+    match pkt.data.get(index) {
+        Some(b) if *b == expected => (),
+        Some(b) => mismatch(pkt, format!("payload[{}] is 0x{:02x}, expected 0x{:02x}", index, b, expected)),
+        None => mismatch(pkt, format!("payload[{}] is missing, len is {}", index, pkt.data.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use l0::comm::Packet;
+use l2::Sender;
+
+// Describes a packet expected in a trace; unset fields match anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketMatcher {
+    code: Option<u8>,
+    prefix: Vec<u8>,
+    len: Option<usize>,
+}
+
+impl PacketMatcher {
+    pub fn any() -> Self {
+        PacketMatcher::default()
+    }
+
+    pub fn code(code: u8) -> Self {
+        PacketMatcher { code: Some(code), ..PacketMatcher::default() }
+    }
+
+    // The payload starts with prefix.
+    pub fn prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefix = prefix.to_vec();
+        self
+    }
+
+    pub fn len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+
+    pub fn matches(&self, pkt: &Packet) -> bool {
+        self.code.is_none_or(|c| c == pkt.code)
+            && self.len.is_none_or(|n| n == pkt.data.len())
+            && pkt.data.starts_with(&self.prefix)
+    }
+}
+
+impl fmt::Display for PacketMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "code=0x{:02x}", code)?,
+            None => write!(f, "code=*")?,
+        }
+        if let Some(len) = self.len {
+            write!(f, " len={}", len)?;
+        }
+        if !self.prefix.is_empty() {
+            write!(f, " prefix={:02x?}", self.prefix)?;
+        }
+        Ok(())
+    }
+}
+
+// Records packets for later checks. Clones share the recording, so a test
+// can keep one while the code under test sends through another.
+#[derive(Debug, Clone, Default)]
+pub struct Tap {
+    packets: Arc<Mutex<Vec<Packet>>>,
+}
+
+// A sender that records what passes through to the inner one.
+pub struct Tapped<S> {
+    tap: Tap,
+    inner: S,
+}
+
+impl Tap {
+    pub fn new() -> Self {
+        Tap::default()
+    }
+
+    pub fn wrap<S: Sender>(&self, inner: S) -> Tapped<S> {
+        Tapped { tap: self.clone(), inner }
+    }
+
+    pub fn record(&self, pkt: &Packet) {
+        self.packets.lock().unwrap().push(pkt.clone());
+    }
+
+    pub fn packets(&self) -> Vec<Packet> {
+        self.packets.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.packets.lock().unwrap().clear();
+    }
+
+    // Panics unless the recorded packets contain ones matching expected,
+    // in order; other packets may come in between.
+    #[track_caller]
+    pub fn expect_sequence(&self, expected: &[PacketMatcher]) {
+        expect_sequence(&self.packets(), expected)
+    }
+}
+
+impl Sender for Tap {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.record(&pkt);
+        Ok(())
+    }
+}
+
+impl<S> Tapped<S> {
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Sender> Sender for Tapped<S> {
+    fn send(&mut self, pkt: Packet) -> io::Result<()> {
+        self.tap.record(&pkt);
+        self.inner.send(pkt)
+    }
+}
+
+// See Tap::expect_sequence. The failure lists which matchers were found
+// and the packets they were searched in.
+#[track_caller]
+pub fn expect_sequence(packets: &[Packet], expected: &[PacketMatcher]) {
+    let mut found = Vec::new();
+    let mut from = 0;
+    for m in expected {
+        match packets[from..].iter().position(|p| m.matches(p)) {
+            Some(i) => {
+                found.push(from + i);
+                from += i + 1;
+            },
+            None => break,
+        }
+    }
+    if found.len() == expected.len() {
+        return;
+    }
+    let mut msg = format!("expected sequence not found, matched {} of {}:\n", found.len(), expected.len());
+    for (i, m) in expected.iter().enumerate() {
+        match found.get(i) {
+            Some(at) => msg += &format!("  ok   {} at [{}]\n", m, at),
+            None if i == found.len() => msg += &format!("  miss {}\n", m),
+            None => msg += &format!("       {}\n", m),
+        }
+    }
+    msg += "packets:\n";
+    for (i, p) in packets.iter().enumerate() {
+        msg += &format!("  [{}] seq={} code=0x{:02x} len={} {:02x?}\n", i, p.seq, p.code, p.data.len(), p.data);
+    }
+    panic!("{}", msg);
+}
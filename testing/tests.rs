@@ -0,0 +1,46 @@
+#![cfg(test)]
+
+use std::panic;
+use l0::comm::Packet;
+use l2::Sender;
+use super::*;
+
+fn panic_message<F: FnOnce() + panic::UnwindSafe>(f: F) -> String {
+    let err = panic::catch_unwind(f).unwrap_err();
+    err.downcast_ref::<String>().cloned().unwrap_or_default()
+}
+
+#[test]
+fn assert_packet_fields() {
+    let pkt = Packet { seq: 4, code: 0x12, data: vec![3, 7] };
+    assert_packet!(pkt, code = 0x12, seq = 4, len = 2, payload[0] == 3, payload[1] == 7);
+    assert_packet!(pkt, payload = [3, 7],);
+
+    let msg = panic_message(|| assert_packet!(Packet { seq: 4, code: 0x12, data: vec![3, 7] }, code = 0x12, payload[1] == 8));
+    assert!(msg.starts_with("packet mismatch: payload[1] is 0x07, expected 0x08\n04 "), "{}", msg);
+    let msg = panic_message(|| assert_packet!(Packet::new_with(1, 2), payload[0] == 1));
+    assert!(msg.starts_with("packet mismatch: payload[0] is missing, len is 0"), "{}", msg);
+}
+
+#[test]
+fn tap_expect_sequence() {
+    let tap = Tap::new();
+    let mut out = Vec::new();
+    {
+        let mut sender = tap.wrap(&mut out);
+        for (code, data) in &[(0x01u8, vec![1u8, 2]), (0x81, vec![]), (0x01, vec![3])] {
+            sender.send(Packet { seq: 0, code: *code, data: data.clone() }).unwrap();
+        }
+    }
+    assert_eq!(out.len(), 3);
+    tap.expect_sequence(&[PacketMatcher::code(0x01).prefix(&[1]), PacketMatcher::code(0x01).len(1)]);
+    tap.expect_sequence(&[PacketMatcher::any(), PacketMatcher::code(0x81)]);
+
+    let t = tap.clone();
+    let msg = panic_message(move || t.expect_sequence(&[PacketMatcher::code(0x81), PacketMatcher::code(0x81)]));
+    assert!(msg.starts_with("expected sequence not found, matched 1 of 2:\n  ok   code=0x81 at [1]\n  miss code=0x81\n"),
+        "{}", msg);
+    assert!(msg.contains("  [0] seq=0 code=0x01 len=2 [01, 02]\n"), "{}", msg);
+    tap.clear();
+    assert!(tap.packets().is_empty());
+}
@@ -0,0 +1,161 @@
+// wasm-bindgen bindings for browser tools, built with
+//
+//   cargo rustc --lib --release --target wasm32-unknown-unknown --crate-type cdylib --features wasm
+//   wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/robo.wasm
+//
+// so pages reading a device through Web Serial or WebUSB can parse, decode
+// and craft packets client-side. Messages cross as JSON text in the form
+// of fmt::json.
+
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+use fmt::display::HexDump;
+use fmt::json::{decode_message, encode_message};
+use l0::comm::{self, SyncStateReader, PACKET_MAX_DATA_LEN};
+use l2::schema::{MessageSet, Schema as CodeSchema};
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub seq: u8,
+    pub code: u8,
+    data: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Packet {
+    #[wasm_bindgen(constructor)]
+    pub fn new(code: u8, data: Vec<u8>, seq: u8) -> Result<Packet, JsError> {
+        if data.len() > PACKET_MAX_DATA_LEN {
+            return Err(JsError::new("payload too long"));
+        }
+        Ok(Packet { seq, code, data })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    // The packet as it goes on the wire.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.packet().encode(&mut buf).expect("write to vec");
+        buf
+    }
+
+    #[wasm_bindgen(js_name = hexDump)]
+    pub fn hex_dump(&self, schema: Option<Schema>) -> String {
+        let pkt = self.packet();
+        match schema {
+            Some(s) => HexDump::packet(&pkt).with_schema(&s.inner).to_string(),
+            None => HexDump::packet(&pkt).to_string(),
+        }
+    }
+}
+
+impl Packet {
+    fn packet(&self) -> comm::Packet {
+        comm::Packet { seq: self.seq, code: self.code, data: self.data.clone() }
+    }
+}
+
+// The l0 parser. Sync bytes it asks to send collect until taken with
+// responses(); each goes on the wire followed by the sender's seq.
+#[wasm_bindgen]
+pub struct Parser {
+    parser: comm::Parser,
+    ready: bool,
+    responses: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Parser {
+    #[wasm_bindgen(constructor)]
+    pub fn new(promiscuous: bool) -> Parser {
+        let parser = if promiscuous { comm::Parser::promiscuous() } else { comm::Parser::new() };
+        Parser { parser, ready: promiscuous, responses: Vec::new() }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ready(&self) -> bool {
+        self.ready
+    }
+
+    // Parses bytes, returning the complete packets.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Packet> {
+        let mut pkts = Vec::new();
+        for r in self.parser.parse_iter(data.iter().cloned()) {
+            self.ready = r.is_synced();
+            self.responses.extend(r.needs_sync_reply());
+            if let Some(pkt) = r.into_packet() {
+                pkts.push(Packet { seq: pkt.seq, code: pkt.code, data: pkt.data });
+            }
+        }
+        pkts
+    }
+
+    pub fn timeout(&mut self) {
+        self.ready = self.parser.timeout().state.is_ready();
+    }
+
+    pub fn responses(&mut self) -> Vec<u8> {
+        self.responses.split_off(0)
+    }
+}
+
+// A device profile in the text form of l2::schema, e.g.
+// "0x01=motor,0x81=encoder".
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct Schema {
+    inner: CodeSchema,
+}
+
+#[wasm_bindgen]
+impl Schema {
+    #[wasm_bindgen(constructor)]
+    pub fn new(text: &str) -> Result<Schema, JsError> {
+        text.parse().map(|inner| Schema { inner }).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = codeOf)]
+    pub fn code_of(&self, set: &str) -> Option<u8> {
+        self.inner.code_of(set.parse().ok()?)
+    }
+
+    // The message set name carried by code.
+    #[wasm_bindgen(js_name = setOf)]
+    pub fn set_of(&self, code: u8) -> Option<String> {
+        self.inner.get(code).map(|s| s.name().to_string())
+    }
+
+    // The payload as a JSON message, undefined when the code has no set or
+    // the payload doesn't decode.
+    pub fn decode(&self, code: u8, data: &[u8]) -> Option<String> {
+        decode_message(self.inner.get(code)?, data).map(|v| v.to_string())
+    }
+
+    // Encodes a JSON message of the set assigned to code.
+    pub fn encode(&self, code: u8, msg: &str) -> Result<Vec<u8>, JsError> {
+        let set = self.inner.get(code).ok_or_else(|| JsError::new("no message set for the code"))?;
+        encode(set.name(), msg)
+    }
+}
+
+// Encodes a JSON message of the named set into a payload.
+#[wasm_bindgen]
+pub fn encode(set: &str, msg: &str) -> Result<Vec<u8>, JsError> {
+    let set: MessageSet = set.parse().map_err(|e: ::std::io::Error| JsError::new(&e.to_string()))?;
+    let msg: Value = serde_json::from_str(msg).map_err(|e| JsError::new(&e.to_string()))?;
+    encode_message(set, &msg).map_err(|e| JsError::new(&e.to_string()))
+}
+
+// Decodes a payload of the named set into a JSON message.
+#[wasm_bindgen]
+pub fn decode(set: &str, data: &[u8]) -> Option<String> {
+    decode_message(set.parse().ok()?, data).map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests;
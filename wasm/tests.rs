@@ -0,0 +1,28 @@
+#![cfg(test)]
+
+use super::*;
+
+// JsError can only be built inside a JS runtime, so only paths that
+// succeed run natively.
+#[test]
+fn parse_decode_and_encode() {
+    let schema = Schema::new("0x01=motor,0x83=power").unwrap();
+    assert_eq!(schema.code_of("power"), Some(0x83));
+    assert_eq!(schema.set_of(0x01).as_deref(), Some("motor"));
+
+    let payload = schema.encode(0x83, r#"{"message":"status","voltage_mv":12400,"current_ma":-300,"soc_pct":80}"#).unwrap();
+    let pkt = Packet::new(0x83, payload, 1).unwrap();
+
+    let mut parser = Parser::new(false);
+    assert!(parser.feed(&[0xff, 1]).is_empty());
+    assert!(parser.ready());
+    assert_eq!(parser.responses(), vec![0xfe]);
+    let pkts = parser.feed(&pkt.encode());
+    assert_eq!(pkts, vec![pkt.clone()]);
+
+    let msg: Value = serde_json::from_str(&schema.decode(0x83, &pkts[0].data()).unwrap()).unwrap();
+    assert_eq!(msg, json!({"message": "status", "voltage_mv": 12400, "current_ma": -300, "soc_pct": 80}));
+    assert_eq!(schema.decode(0x02, &pkts[0].data()), None);
+    assert_eq!(decode("motor", &[2]).as_deref(), Some(r#"{"message":"stop"}"#));
+    assert!(pkt.hex_dump(Some(schema)).contains("power Status"));
+}